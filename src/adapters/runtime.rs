@@ -1,3 +1,4 @@
+use crate::channels::{self, ChannelError};
 use crate::types::{Attachment, InboundMessage, OutboundMessage, RouteInfo};
 
 use anyhow::Context;
@@ -32,9 +33,11 @@ struct RuntimeSendRequest {
     account_id: Option<String>,
     attachments: Vec<Attachment>,
     peer_id: Option<String>,
+    peer_kind: Option<String>,
     reply_to: Option<String>,
     text: Option<String>,
     thread_id: Option<String>,
+    metadata: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,6 +45,7 @@ pub struct RuntimeSendResponse {
     pub message_id: Option<String>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn ingest(
     client: &reqwest::Client,
     runtime_url: &str,
@@ -96,9 +100,11 @@ pub async fn send(
             .or_else(|| outbound.account_id.clone()),
         attachments: outbound.attachments.clone(),
         peer_id: route.peer_id.clone().or_else(|| outbound.peer_id.clone()),
+        peer_kind: route.peer_kind.clone(),
         reply_to: outbound.reply_to.clone(),
         text: outbound.text.clone(),
         thread_id: route.thread_id.clone(),
+        metadata: outbound.metadata.clone(),
     };
 
     let response = client
@@ -109,12 +115,20 @@ pub async fn send(
         .json(&request)
         .send()
         .await
-        .with_context(|| format!("failed to call embedded adapter send for {channel}"))?;
+        .map_err(|err| channels::transport_error(channel, err))?;
+    let response = channels::check_rate_limit(channel, response).await?;
 
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("embedded adapter send failed for {channel}: {status} {body}");
+        let channel = channel.to_string();
+        let code = format!("embedded adapter send failed: {status} {body}");
+        return Err(if status.is_server_error() {
+            ChannelError::Transient { channel, code }
+        } else {
+            ChannelError::Rejected { channel, code }
+        }
+        .into());
     }
 
     response
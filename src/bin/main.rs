@@ -1,16 +1,24 @@
+use agent_ping::config::load_config;
 use agent_ping::create_app;
-use tracing::info;
+use agent_ping::logging::{init_tracing, LogFormat};
+use tracing::{debug, info};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    let logging = load_config().logging;
+    let format = LogFormat::parse(&logging.format).unwrap_or(LogFormat::Pretty);
+    init_tracing(format, &logging.level)?;
 
     let (state, app) = create_app().await?;
-    let addr = format!("{}:{}", state.config.server.host, state.config.server.port);
+    let config = state.config();
+    debug!("config: {:?}", config.redacted());
+    let addr = format!("{}:{}", config.server.host, config.server.port);
     info!("agent-ping listening on {addr}");
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
@@ -0,0 +1,71 @@
+use super::ChannelError;
+use crate::config::CustomChannelConfig;
+use crate::types::{OutboundMessage, RouteInfo};
+use anyhow::Result;
+use reqwest::Client;
+
+/// Builds the JSON body forwarded to a custom channel's `forward_url`.
+pub fn custom_send_payload(
+    session_key: &str,
+    route: &RouteInfo,
+    outbound: &OutboundMessage,
+) -> serde_json::Value {
+    serde_json::json!({
+        "channel": route.channel,
+        "session_key": session_key,
+        "account_id": route.account_id,
+        "peer_id": route.peer_id,
+        "peer_kind": route.peer_kind,
+        "thread_id": route.thread_id,
+        "text": outbound.text,
+        "attachments": outbound.attachments,
+        "reply_to": outbound.reply_to,
+        "metadata": outbound.metadata,
+    })
+}
+
+/// Classifies a failed forward by HTTP status, using the body's `error` string
+/// as the code when there is one.
+pub fn custom_error(channel: &str, status: u16, body: Option<&serde_json::Value>) -> ChannelError {
+    let code = body
+        .and_then(|body| body.get("error"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("http {status}"));
+    let channel = channel.to_string();
+    match status {
+        401 | 403 => ChannelError::Auth { channel, code },
+        404 => ChannelError::InvalidRecipient { channel, code },
+        400..=499 => ChannelError::Rejected { channel, code },
+        _ => ChannelError::Transient { channel, code },
+    }
+}
+
+/// POSTs a send to the custom channel and returns the `message_id` from the
+/// response body, if any.
+pub async fn send_custom_message(
+    client: &Client,
+    channel: &str,
+    config: &CustomChannelConfig,
+    payload: &serde_json::Value,
+) -> Result<Option<String>> {
+    let mut req = client.post(&config.forward_url).json(payload);
+    for (name, value) in &config.headers {
+        req = req.header(name.as_str(), value.as_str());
+    }
+    let resp = req
+        .send()
+        .await
+        .map_err(|err| super::transport_error(channel, err))?;
+    let resp = super::check_rate_limit(channel, resp).await?;
+    if !resp.status().is_success() {
+        let status = resp.status().as_u16();
+        let body = resp.json::<serde_json::Value>().await.ok();
+        return Err(custom_error(channel, status, body.as_ref()).into());
+    }
+    let value: serde_json::Value = resp.json().await.unwrap_or_default();
+    Ok(value
+        .get("message_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string()))
+}
@@ -1,3 +1,225 @@
+pub mod custom;
 pub mod slack;
 pub mod telegram;
 pub mod whatsapp;
+
+use bytes::Bytes;
+use reqwest::header::RETRY_AFTER;
+use reqwest::multipart::Part;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const MAX_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChannelCapabilities {
+    pub send: bool,
+    pub threads: bool,
+    pub edits: bool,
+    pub deletes: bool,
+    pub reactions: bool,
+    pub typing: bool,
+    pub templates: bool,
+}
+
+pub fn capabilities(channel: &str) -> Option<ChannelCapabilities> {
+    let none = ChannelCapabilities {
+        send: false,
+        threads: false,
+        edits: false,
+        deletes: false,
+        reactions: false,
+        typing: false,
+        templates: false,
+    };
+    match channel {
+        "slack" => Some(ChannelCapabilities {
+            send: true,
+            threads: true,
+            edits: true,
+            deletes: true,
+            reactions: true,
+            ..none
+        }),
+        "telegram" => Some(ChannelCapabilities {
+            send: true,
+            edits: true,
+            deletes: true,
+            reactions: true,
+            ..none
+        }),
+        "whatsapp" => Some(ChannelCapabilities { send: true, ..none }),
+        "teams" => Some(none),
+        _ => None,
+    }
+}
+
+/// Starts downloading `request` and returns its body as a streamed multipart
+/// part. A failed request or an error status is classified like a channel
+/// call, so a provider outage is retried and a bad URL is not.
+pub async fn download_part(
+    channel: &str,
+    request: RequestBuilder,
+    filename: String,
+) -> anyhow::Result<Part> {
+    let resp = fetch_media(channel, request).await?;
+    let length = resp.content_length();
+    let body = reqwest::Body::wrap_stream(resp.bytes_stream());
+    let part = match length {
+        Some(length) => Part::stream_with_length(body, length),
+        None => Part::stream(body),
+    };
+    Ok(part.file_name(filename))
+}
+
+/// Downloads `request` into memory, for media that is posted to more than one
+/// place. Errors are classified the same way as in [`download_part`].
+pub async fn download_bytes(channel: &str, request: RequestBuilder) -> anyhow::Result<Bytes> {
+    let resp = fetch_media(channel, request).await?;
+    resp.bytes()
+        .await
+        .map_err(|err| transport_error(channel, err))
+}
+
+/// Wraps media already downloaded with [`download_bytes`] as a multipart part.
+pub fn bytes_part(body: Bytes, filename: String) -> Part {
+    let length = body.len() as u64;
+    Part::stream_with_length(body, length).file_name(filename)
+}
+
+async fn fetch_media(channel: &str, request: RequestBuilder) -> anyhow::Result<Response> {
+    let resp = request
+        .send()
+        .await
+        .map_err(|err| transport_error(channel, err))?;
+    resp.error_for_status().map_err(|err| {
+        let server_error = err.status().is_some_and(|status| status.is_server_error());
+        let channel = channel.to_string();
+        let code = err.without_url().to_string();
+        if server_error {
+            ChannelError::Transient { channel, code }.into()
+        } else {
+            ChannelError::Rejected { channel, code }.into()
+        }
+    })
+}
+
+/// A provider failure classified from its error response, so retries and
+/// client-facing error codes don't depend on the provider's raw body.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ChannelError {
+    #[error("{channel} rejected the bot credentials ({code})")]
+    Auth { channel: String, code: String },
+    #[error("{channel} rate limited, retry after {}s", retry_after.as_secs())]
+    RateLimited {
+        channel: String,
+        retry_after: Duration,
+    },
+    #[error("{channel} recipient is invalid ({code})")]
+    InvalidRecipient { channel: String, code: String },
+    #[error("{channel} is temporarily unavailable ({code})")]
+    Transient { channel: String, code: String },
+    #[error("{channel} rejected the request ({code})")]
+    Rejected { channel: String, code: String },
+}
+
+impl ChannelError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ChannelError::RateLimited { .. } | ChannelError::Transient { .. }
+        )
+    }
+}
+
+/// A call that got no usable response from `channel` (connect, timeout,
+/// decoding), as a transient failure. The URL is dropped from the message
+/// since some providers put credentials in it.
+pub fn transport_error(channel: &str, err: reqwest::Error) -> anyhow::Error {
+    ChannelError::Transient {
+        channel: channel.to_string(),
+        code: err.without_url().to_string(),
+    }
+    .into()
+}
+
+/// Whether a failed channel call is worth retrying. Only classified channel
+/// errors can be: transport failures and provider 5xx responses are
+/// `Transient`, while anything unclassified (a missing token, a bad payload)
+/// fails the same way on every attempt.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ChannelError>()
+        .is_some_and(ChannelError::is_retryable)
+}
+
+pub fn parse_retry_after(header: Option<&str>, body: Option<&Value>) -> Duration {
+    header
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .or_else(|| {
+            body.and_then(|body| body.pointer("/parameters/retry_after"))
+                .and_then(|value| value.as_u64())
+        })
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(1))
+}
+
+pub async fn check_rate_limit(channel: &str, resp: Response) -> anyhow::Result<Response> {
+    if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(resp);
+    }
+    let header = resp
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let body = resp.json::<Value>().await.ok();
+    Err(ChannelError::RateLimited {
+        channel: channel.to_string(),
+        retry_after: parse_retry_after(header.as_deref(), body.as_ref()),
+    }
+    .into())
+}
+
+#[derive(Debug, Clone)]
+pub struct SendLimiter {
+    semaphore: Arc<Semaphore>,
+    permits: u32,
+}
+
+impl SendLimiter {
+    pub fn new(permits: usize) -> SendLimiter {
+        let permits = permits.clamp(1, Semaphore::MAX_PERMITS) as u32;
+        SendLimiter {
+            semaphore: Arc::new(Semaphore::new(permits as usize)),
+            permits,
+        }
+    }
+
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.semaphore.clone().acquire_owned().await.ok()
+    }
+
+    pub fn permits(&self) -> u32 {
+        self.permits
+    }
+
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    pub fn pause(&self, duration: Duration) {
+        let semaphore = self.semaphore.clone();
+        let permits = self.permits;
+        let duration = duration.min(MAX_RATE_LIMIT_PAUSE);
+        tokio::spawn(async move {
+            if let Ok(all) = semaphore.acquire_many_owned(permits).await {
+                tokio::time::sleep(duration).await;
+                drop(all);
+            }
+        });
+    }
+}
@@ -1,9 +1,132 @@
-use crate::types::{Attachment, InboundMessage};
+use super::ChannelError;
+use crate::types::{Attachment, InboundEdit, InboundMessage, InboundReaction};
 use anyhow::Result;
 use chrono::Utc;
 use reqwest::Client;
 use serde_json::Value;
 
+/// Oldest `X-Slack-Request-Timestamp` accepted, in seconds, so a captured
+/// request cannot be replayed later.
+pub const SLACK_SIGNATURE_MAX_AGE_SECS: u64 = 300;
+
+/// Checks an `X-Slack-Signature` header: `v0=` followed by the hex
+/// HMAC-SHA256 of `v0:{timestamp}:{body}` keyed by the signing secret. The
+/// comparison is constant time, and timestamps more than five minutes from
+/// `now` (Unix seconds) are rejected.
+pub fn verify_slack_signature(
+    signing_secret: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: i64,
+) -> bool {
+    use hmac::{Hmac, Mac};
+
+    let Ok(sent_at) = timestamp.trim().parse::<i64>() else {
+        return false;
+    };
+    let age = now.checked_sub(sent_at).map(i64::unsigned_abs);
+    if age.is_none_or(|age| age > SLACK_SIGNATURE_MAX_AGE_SECS) {
+        return false;
+    }
+    let Some(expected) = signature
+        .trim()
+        .strip_prefix("v0=")
+        .and_then(|hex| hex::decode(hex).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<sha2::Sha256>::new_from_slice(signing_secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(format!("v0:{}:", timestamp.trim()).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Network and decoding failures (including the HTML error pages Slack serves
+/// with a 5xx) are transient.
+fn transport(err: reqwest::Error) -> anyhow::Error {
+    super::transport_error("slack", err)
+}
+
+/// Classifies a Slack Web API `{"ok": false, "error": ...}` response.
+pub fn slack_error(value: &Value) -> ChannelError {
+    let code = value
+        .get("error")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown_error")
+        .to_string();
+    let channel = "slack".to_string();
+    match code.as_str() {
+        "not_authed"
+        | "invalid_auth"
+        | "account_inactive"
+        | "token_revoked"
+        | "token_expired"
+        | "no_permission"
+        | "missing_scope"
+        | "not_allowed_token_type" => ChannelError::Auth { channel, code },
+        "ratelimited" | "rate_limited" => ChannelError::RateLimited {
+            channel,
+            retry_after: super::parse_retry_after(None, Some(value)),
+        },
+        "channel_not_found" | "not_in_channel" | "is_archived" | "user_not_found"
+        | "user_disabled" | "cannot_dm_bot" => ChannelError::InvalidRecipient { channel, code },
+        "internal_error" | "fatal_error" | "service_unavailable" | "request_timeout" => {
+            ChannelError::Transient { channel, code }
+        }
+        _ => ChannelError::Rejected { channel, code },
+    }
+}
+
+pub fn slack_message_payload(
+    channel: &str,
+    text: &str,
+    thread_ts: Option<&str>,
+    metadata: Option<&Value>,
+) -> Value {
+    let mut payload = serde_json::json!({
+        "channel": channel,
+        "text": text,
+    });
+    if let Some(ts) = thread_ts {
+        payload["thread_ts"] = Value::String(ts.to_string());
+    }
+    if let Some(metadata) = metadata.filter(|v| v.is_object()) {
+        payload["metadata"] = serde_json::json!({
+            "event_type": "agent_ping_message",
+            "event_payload": metadata,
+        });
+    }
+    payload
+}
+
+/// Sets the per-message bot appearance on a `chat.postMessage` payload. An
+/// icon starting with `http://` or `https://` becomes `icon_url`; anything else
+/// is treated as an emoji name.
+pub fn apply_slack_sender(payload: &mut Value, name: Option<&str>, icon: Option<&str>) {
+    if let Some(name) = name.map(str::trim).filter(|name| !name.is_empty()) {
+        payload["username"] = Value::String(name.to_string());
+    }
+    if let Some(icon) = icon.map(str::trim).filter(|icon| !icon.is_empty()) {
+        if icon.starts_with("http://") || icon.starts_with("https://") {
+            payload["icon_url"] = Value::String(icon.to_string());
+        } else {
+            payload["icon_emoji"] = Value::String(format!(":{}:", icon.trim_matches(':')));
+        }
+    }
+}
+
+/// Sets `reply_broadcast` so a threaded reply also shows in the channel. A
+/// payload without `thread_ts` is left alone.
+pub fn apply_slack_reply_broadcast(payload: &mut Value, broadcast: bool) {
+    if broadcast && payload.get("thread_ts").is_some() {
+        payload["reply_broadcast"] = Value::Bool(true);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn send_slack_message(
     client: &Client,
     token: &str,
@@ -11,27 +134,34 @@ pub async fn send_slack_message(
     text: Option<&str>,
     thread_ts: Option<&str>,
     attachments: &[Attachment],
-) -> Result<String> {
+    metadata: Option<&Value>,
+    sender_name: Option<&str>,
+    sender_icon: Option<&str>,
+    reply_broadcast: bool,
+) -> Result<Option<String>> {
+    let mut message_ts = None;
     if let Some(body) = text {
-        let mut payload = serde_json::json!({
-            "channel": channel,
-            "text": body,
-        });
-        if let Some(ts) = thread_ts {
-            payload["thread_ts"] = serde_json::Value::String(ts.to_string());
-        }
+        let mut payload = slack_message_payload(channel, body, thread_ts, metadata);
+        apply_slack_sender(&mut payload, sender_name, sender_icon);
+        apply_slack_reply_broadcast(&mut payload, reply_broadcast);
 
         let resp = client
             .post("https://slack.com/api/chat.postMessage")
             .bearer_auth(token)
             .json(&payload)
             .send()
-            .await?;
+            .await
+            .map_err(transport)?;
+        let resp = super::check_rate_limit("slack", resp).await?;
 
-        let value: Value = resp.json().await?;
+        let value: Value = resp.json().await.map_err(transport)?;
         if !value.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
-            return Err(anyhow::anyhow!("slack send failed: {}", value));
+            return Err(slack_error(&value).into());
         }
+        message_ts = value
+            .get("ts")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
     }
 
     for attachment in attachments {
@@ -39,12 +169,9 @@ pub async fn send_slack_message(
             .filename
             .clone()
             .unwrap_or_else(|| "file".to_string());
-        let bytes = client.get(&attachment.url).send().await?.bytes().await?;
+        let part = super::download_part("slack", client.get(&attachment.url), filename).await?;
         let mut form = reqwest::multipart::Form::new()
-            .part(
-                "file",
-                reqwest::multipart::Part::bytes(bytes.to_vec()).file_name(filename),
-            )
+            .part("file", part)
             .text("channels", channel.to_string());
 
         if let Some(ts) = thread_ts {
@@ -56,14 +183,187 @@ pub async fn send_slack_message(
             .bearer_auth(token)
             .multipart(form)
             .send()
-            .await?;
-        let value: Value = resp.json().await?;
+            .await
+            .map_err(transport)?;
+        let resp = super::check_rate_limit("slack", resp).await?;
+        let value: Value = resp.json().await.map_err(transport)?;
         if !value.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
-            return Err(anyhow::anyhow!("slack upload failed: {}", value));
+            return Err(slack_error(&value).into());
         }
     }
 
-    Ok("ok".to_string())
+    Ok(message_ts)
+}
+
+pub fn slack_update_payload(channel: &str, ts: &str, text: &str) -> Value {
+    serde_json::json!({
+        "channel": channel,
+        "ts": ts,
+        "text": text,
+    })
+}
+
+pub async fn edit_slack_message(
+    client: &Client,
+    token: &str,
+    channel: &str,
+    ts: &str,
+    text: &str,
+) -> Result<()> {
+    let resp = client
+        .post("https://slack.com/api/chat.update")
+        .bearer_auth(token)
+        .json(&slack_update_payload(channel, ts, text))
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if !value.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err(slack_error(&value).into());
+    }
+    Ok(())
+}
+
+pub fn slack_delete_payload(channel: &str, ts: &str) -> Value {
+    serde_json::json!({
+        "channel": channel,
+        "ts": ts,
+    })
+}
+
+pub async fn delete_slack_message(
+    client: &Client,
+    token: &str,
+    channel: &str,
+    ts: &str,
+) -> Result<()> {
+    let resp = client
+        .post("https://slack.com/api/chat.delete")
+        .bearer_auth(token)
+        .json(&slack_delete_payload(channel, ts))
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if !value.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err(slack_error(&value).into());
+    }
+    Ok(())
+}
+
+pub fn slack_reaction_payload(channel: &str, ts: &str, name: &str) -> Value {
+    serde_json::json!({
+        "channel": channel,
+        "timestamp": ts,
+        "name": name.trim().trim_matches(':'),
+    })
+}
+
+pub async fn add_slack_reaction(
+    client: &Client,
+    token: &str,
+    channel: &str,
+    ts: &str,
+    name: &str,
+) -> Result<()> {
+    let resp = client
+        .post("https://slack.com/api/reactions.add")
+        .bearer_auth(token)
+        .json(&slack_reaction_payload(channel, ts, name))
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if !value.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err(slack_error(&value).into());
+    }
+    Ok(())
+}
+
+pub fn parse_slack_reaction(payload: &Value) -> Option<InboundReaction> {
+    if payload.get("type")?.as_str()? != "event_callback" {
+        return None;
+    }
+    let event = payload.get("event")?;
+    let added = match event.get("type")?.as_str()? {
+        "reaction_added" => true,
+        "reaction_removed" => false,
+        _ => return None,
+    };
+    let item = event.get("item")?;
+    if item.get("type")?.as_str()? != "message" {
+        return None;
+    }
+    Some(InboundReaction {
+        channel: "slack".to_string(),
+        account_id: payload
+            .get("team_id")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        peer_id: item.get("channel")?.as_str()?.to_string(),
+        message_id: item.get("ts")?.as_str()?.to_string(),
+        user_id: event
+            .get("user")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        reaction: event.get("reaction")?.as_str()?.to_string(),
+        added,
+    })
+}
+
+pub fn parse_slack_message_change(payload: &Value) -> Option<InboundEdit> {
+    if payload.get("type")?.as_str()? != "event_callback" {
+        return None;
+    }
+    let event = payload.get("event")?;
+    if event.get("type")?.as_str()? != "message" {
+        return None;
+    }
+    let (message_id, text, deleted) = match event.get("subtype")?.as_str()? {
+        "message_changed" => {
+            let message = event.get("message")?;
+            let text = message
+                .get("text")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            (message.get("ts")?.as_str()?, text, false)
+        }
+        "message_deleted" => {
+            let ts = event
+                .get("deleted_ts")
+                .or_else(|| event.get("previous_message")?.get("ts"))?;
+            (ts.as_str()?, None, true)
+        }
+        _ => return None,
+    };
+    Some(InboundEdit {
+        channel: "slack".to_string(),
+        account_id: payload
+            .get("team_id")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        peer_id: event.get("channel")?.as_str()?.to_string(),
+        message_id: message_id.to_string(),
+        text,
+        deleted,
+    })
+}
+
+/// Whether `event` was posted by the app receiving it: Slack lists the app's
+/// bot user in the payload's `authorizations`, and echoes the bot's own posts
+/// back as messages from that user.
+fn is_own_message(payload: &Value, event: &Value) -> bool {
+    let Some(user) = event.get("user").and_then(|v| v.as_str()) else {
+        return false;
+    };
+    payload
+        .get("authorizations")
+        .and_then(|v| v.as_array())
+        .is_some_and(|authorizations| {
+            authorizations
+                .iter()
+                .any(|auth| auth.get("user_id").and_then(|v| v.as_str()) == Some(user))
+        })
 }
 
 pub fn parse_slack_event(payload: &Value) -> Option<InboundMessage> {
@@ -76,7 +376,7 @@ pub fn parse_slack_event(payload: &Value) -> Option<InboundMessage> {
     if event.get("type")?.as_str()? != "message" {
         return None;
     }
-    if event.get("subtype").is_some() {
+    if event.get("subtype").is_some() || is_own_message(payload, event) {
         return None;
     }
 
@@ -1,13 +1,23 @@
-use crate::types::{Attachment, InboundMessage};
+use super::ChannelError;
+use crate::types::{Attachment, InboundMessage, InboundReaction};
 use anyhow::Result;
 use reqwest::Client;
 use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tokio::time::sleep;
 
+/// Bot API URLs carry the token, so transport errors leave this module with
+/// the URL stripped.
+fn transport(err: reqwest::Error) -> anyhow::Error {
+    super::transport_error("telegram", err)
+}
+
 pub async fn start_telegram_poller(
     token: String,
     tx: tokio::sync::mpsc::Sender<InboundMessage>,
     interval_seconds: u64,
+    started: Arc<AtomicBool>,
 ) {
     let client = Client::new();
     let mut offset: i64 = 0;
@@ -21,6 +31,7 @@ pub async fn start_telegram_poller(
         if let Ok(resp) = resp {
             if let Ok(value) = resp.json::<Value>().await {
                 if value.get("ok").and_then(|v| v.as_bool()) == Some(true) {
+                    started.store(true, Ordering::SeqCst);
                     if let Some(results) = value.get("result").and_then(|v| v.as_array()) {
                         for update in results {
                             if let Some(update_id) = update.get("update_id").and_then(|v| v.as_i64())
@@ -52,7 +63,7 @@ pub fn parse_telegram_update(update: &Value) -> Option<InboundMessage> {
 
     let peer_kind = match chat.get("type").and_then(|v| v.as_str()) {
         Some("private") => "dm",
-        Some("channel") => "channel",
+        Some(kind @ ("supergroup" | "channel")) => kind,
         _ => "group",
     };
 
@@ -112,58 +123,324 @@ pub fn parse_telegram_update(update: &Value) -> Option<InboundMessage> {
     })
 }
 
-pub async fn send_telegram_message(
-    client: &Client,
-    token: &str,
+pub fn parse_telegram_reaction(update: &Value) -> Vec<InboundReaction> {
+    let Some(update) = update.get("message_reaction") else {
+        return Vec::new();
+    };
+    let Some(chat_id) = update
+        .get("chat")
+        .and_then(|v| v.get("id"))
+        .and_then(|v| v.as_i64())
+    else {
+        return Vec::new();
+    };
+    let Some(message_id) = update.get("message_id").and_then(|v| v.as_i64()) else {
+        return Vec::new();
+    };
+    let user_id = update
+        .get("user")
+        .and_then(|v| v.get("id"))
+        .and_then(|v| v.as_i64())
+        .map(|v| v.to_string());
+    let emojis = |key: &str| -> Vec<String> {
+        update
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.get("emoji").and_then(|v| v.as_str()))
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let old = emojis("old_reaction");
+    let new = emojis("new_reaction");
+
+    let removed = old.iter().filter(|e| !new.contains(e)).map(|e| (e, false));
+    let added = new.iter().filter(|e| !old.contains(e)).map(|e| (e, true));
+    removed
+        .chain(added)
+        .map(|(emoji, added)| InboundReaction {
+            channel: "telegram".to_string(),
+            account_id: None,
+            peer_id: chat_id.to_string(),
+            message_id: message_id.to_string(),
+            user_id: user_id.clone(),
+            reaction: emoji.clone(),
+            added,
+        })
+        .collect()
+}
+
+const TELEGRAM_CAPTION_LIMIT: usize = 1024;
+
+/// Classifies a Bot API `{"ok": false, "error_code": ..., "description": ...}`
+/// response. Telegram answers 404 for an unknown bot token and 403 when the bot
+/// was blocked or removed from the chat.
+pub fn telegram_error(value: &Value) -> ChannelError {
+    let error_code = value.get("error_code").and_then(|v| v.as_i64());
+    let code = value
+        .get("description")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown error")
+        .to_string();
+    let channel = "telegram".to_string();
+    let lower = code.to_ascii_lowercase();
+    match error_code {
+        Some(401 | 404) => ChannelError::Auth { channel, code },
+        Some(403) => ChannelError::InvalidRecipient { channel, code },
+        Some(429) => ChannelError::RateLimited {
+            channel,
+            retry_after: super::parse_retry_after(None, Some(value)),
+        },
+        Some(400)
+            if lower.contains("chat not found")
+                || lower.contains("user not found")
+                || lower.contains("peer_id_invalid") =>
+        {
+            ChannelError::InvalidRecipient { channel, code }
+        }
+        Some(400..=499) => ChannelError::Rejected { channel, code },
+        _ => ChannelError::Transient { channel, code },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelegramSendStep<'a> {
+    pub method: &'static str,
+    pub payload: Value,
+    pub attachment: Option<&'a Attachment>,
+}
+
+pub fn telegram_send_steps<'a>(
     chat_id: &str,
     text: Option<&str>,
     reply_to: Option<&str>,
-    attachments: &[Attachment],
-) -> Result<String> {
-    if let Some(body) = text {
-        let url = format!("https://api.telegram.org/bot{}/sendMessage", token);
+    attachments: &'a [Attachment],
+    caption_mode: bool,
+) -> Vec<TelegramSendStep<'a>> {
+    let media: Vec<&Attachment> = attachments
+        .iter()
+        .filter(|attachment| !attachment.url.starts_with("telegram://file/"))
+        .collect();
+    let caption = text.filter(|body| {
+        caption_mode && !media.is_empty() && body.chars().count() <= TELEGRAM_CAPTION_LIMIT
+    });
+
+    let mut steps = Vec::new();
+    if let (Some(body), None) = (text, caption) {
         let mut payload = serde_json::json!({
             "chat_id": chat_id,
             "text": body,
         });
         if let Some(reply) = reply_to {
             if let Ok(mid) = reply.parse::<i64>() {
-                payload["reply_to_message_id"] = serde_json::Value::Number(mid.into());
+                payload["reply_to_message_id"] = Value::Number(mid.into());
             }
         }
-        let resp = client.post(&url).json(&payload).send().await?;
-        let value: Value = resp.json().await?;
-        if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
-            return Err(anyhow::anyhow!("telegram send failed: {}", value));
+        steps.push(TelegramSendStep {
+            method: "sendMessage",
+            payload,
+            attachment: None,
+        });
+    }
+    for (index, attachment) in media.into_iter().enumerate() {
+        let mut payload = serde_json::json!({ "chat_id": chat_id });
+        if let Some(reply) = reply_to {
+            payload["reply_to_message_id"] = Value::String(reply.to_string());
         }
+        if let (0, Some(body)) = (index, caption) {
+            payload["caption"] = Value::String(body.to_string());
+        }
+        steps.push(TelegramSendStep {
+            method: "sendDocument",
+            payload,
+            attachment: Some(attachment),
+        });
     }
+    steps
+}
 
-    for attachment in attachments {
-        if attachment.url.starts_with("telegram://file/") {
+pub async fn send_telegram_message(
+    client: &Client,
+    token: &str,
+    chat_id: &str,
+    text: Option<&str>,
+    reply_to: Option<&str>,
+    attachments: &[Attachment],
+    caption_mode: bool,
+) -> Result<Option<String>> {
+    let mut message_id = None;
+    for step in telegram_send_steps(chat_id, text, reply_to, attachments, caption_mode) {
+        let url = format!("https://api.telegram.org/bot{}/{}", token, step.method);
+        let Some(attachment) = step.attachment else {
+            let resp = client
+                .post(&url)
+                .json(&step.payload)
+                .send()
+                .await
+                .map_err(transport)?;
+            let resp = super::check_rate_limit("telegram", resp).await?;
+            let value: Value = resp.json().await.map_err(transport)?;
+            if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+                return Err(telegram_error(&value).into());
+            }
+            message_id = value
+                .pointer("/result/message_id")
+                .and_then(|v| v.as_i64())
+                .map(|id| id.to_string());
             continue;
-        }
-        let bytes = client.get(&attachment.url).send().await?.bytes().await?;
+        };
+
         let filename = attachment
             .filename
             .clone()
             .unwrap_or_else(|| "file".to_string());
-        let url = format!("https://api.telegram.org/bot{}/sendDocument", token);
-        let mut form = reqwest::multipart::Form::new()
-            .text("chat_id", chat_id.to_string())
-            .part(
-                "document",
-                reqwest::multipart::Part::bytes(bytes.to_vec()).file_name(filename),
-            );
-        if let Some(reply) = reply_to {
-            form = form.text("reply_to_message_id", reply.to_string());
+        let part = super::download_part("telegram", client.get(&attachment.url), filename).await?;
+        let mut form = reqwest::multipart::Form::new();
+        for (name, value) in step.payload.as_object().into_iter().flatten() {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+            form = form.text(name.clone(), value);
         }
-        let resp = client.post(&url).multipart(form).send().await?;
-        let value: Value = resp.json().await?;
+        let form = form.part("document", part);
+        let resp = client
+            .post(&url)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(transport)?;
+        let resp = super::check_rate_limit("telegram", resp).await?;
+        let value: Value = resp.json().await.map_err(transport)?;
         if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
-            return Err(anyhow::anyhow!("telegram document failed: {}", value));
+            return Err(telegram_error(&value).into());
+        }
+        if step.payload.get("caption").is_some() {
+            message_id = value
+                .pointer("/result/message_id")
+                .and_then(|v| v.as_i64())
+                .map(|id| id.to_string());
         }
     }
-    Ok("ok".to_string())
+    Ok(message_id)
+}
+
+pub fn telegram_edit_request(
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+    text: &str,
+) -> Result<(String, Value)> {
+    let message_id = message_id
+        .parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("invalid telegram message id: {message_id}"))?;
+    let url = format!("https://api.telegram.org/bot{}/editMessageText", token);
+    let payload = serde_json::json!({
+        "chat_id": chat_id,
+        "message_id": message_id,
+        "text": text,
+    });
+    Ok((url, payload))
+}
+
+pub async fn edit_telegram_message(
+    client: &Client,
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+    text: &str,
+) -> Result<()> {
+    let (url, payload) = telegram_edit_request(token, chat_id, message_id, text)?;
+    let resp = client
+        .post(&url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+        return Err(telegram_error(&value).into());
+    }
+    Ok(())
+}
+
+pub fn telegram_delete_request(
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+) -> Result<(String, Value)> {
+    let message_id = message_id
+        .parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("invalid telegram message id: {message_id}"))?;
+    let url = format!("https://api.telegram.org/bot{}/deleteMessage", token);
+    let payload = serde_json::json!({
+        "chat_id": chat_id,
+        "message_id": message_id,
+    });
+    Ok((url, payload))
+}
+
+pub async fn delete_telegram_message(
+    client: &Client,
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+) -> Result<()> {
+    let (url, payload) = telegram_delete_request(token, chat_id, message_id)?;
+    let resp = client
+        .post(&url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+        return Err(telegram_error(&value).into());
+    }
+    Ok(())
+}
+
+pub fn telegram_reaction_request(
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+    emoji: &str,
+) -> Result<(String, Value)> {
+    let message_id = message_id
+        .parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("invalid telegram message id: {message_id}"))?;
+    let url = format!("https://api.telegram.org/bot{}/setMessageReaction", token);
+    let payload = serde_json::json!({
+        "chat_id": chat_id,
+        "message_id": message_id,
+        "reaction": [{"type": "emoji", "emoji": emoji}],
+    });
+    Ok((url, payload))
+}
+
+pub async fn add_telegram_reaction(
+    client: &Client,
+    token: &str,
+    chat_id: &str,
+    message_id: &str,
+    emoji: &str,
+) -> Result<()> {
+    let (url, payload) = telegram_reaction_request(token, chat_id, message_id, emoji)?;
+    let resp = client
+        .post(&url)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
+    if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+        return Err(telegram_error(&value).into());
+    }
+    Ok(())
 }
 
 pub async fn resolve_telegram_file_url(
@@ -176,8 +453,9 @@ pub async fn resolve_telegram_file_url(
         .post(&url)
         .json(&serde_json::json!({"file_id": file_id}))
         .send()
-        .await?;
-    let value: Value = resp.json().await?;
+        .await
+        .map_err(transport)?;
+    let value: Value = resp.json().await.map_err(transport)?;
     if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
         return Ok(None);
     }
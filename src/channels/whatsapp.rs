@@ -1,5 +1,7 @@
+use super::ChannelError;
 use crate::types::{Attachment, InboundMessage};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -10,30 +12,187 @@ pub struct WhatsAppInboundPayload {
     pub thread_id: Option<String>,
     pub attachments: Option<Vec<Attachment>>,
     pub sender_name: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<serde_json::Value>,
 }
 
-pub async fn send_whatsapp_message(
-    client: &Client,
-    sidecar_url: &str,
+pub fn whatsapp_send_payload(
     to: &str,
     text: Option<&str>,
     attachments: &[Attachment],
-) -> Result<String> {
-    let payload = serde_json::json!({
+    caption_mode: bool,
+    metadata: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let mut payload = serde_json::json!({
         "to": to,
-        "text": text,
         "attachments": attachments,
     });
+    if let Some(metadata) = metadata {
+        payload["metadata"] = metadata.clone();
+    }
+    match (caption_mode, text, attachments.is_empty()) {
+        (true, Some(body), false) => {
+            payload["attachments"][0]["caption"] = serde_json::Value::String(body.to_string());
+        }
+        (_, Some(body), _) => payload["text"] = serde_json::Value::String(body.to_string()),
+        _ => {}
+    }
+    payload
+}
+
+/// Builds the sidecar requests for one send. With `split_attachments` each
+/// attachment goes in its own request, after a text-only request unless the
+/// text is used as the first attachment's caption.
+pub fn whatsapp_send_payloads(
+    to: &str,
+    text: Option<&str>,
+    attachments: &[Attachment],
+    caption_mode: bool,
+    metadata: Option<&serde_json::Value>,
+    split_attachments: bool,
+) -> Vec<serde_json::Value> {
+    if !split_attachments || attachments.len() <= 1 {
+        return vec![whatsapp_send_payload(
+            to,
+            text,
+            attachments,
+            caption_mode,
+            metadata,
+        )];
+    }
+    let mut payloads = Vec::new();
+    let caption = text.filter(|_| caption_mode);
+    if let (Some(body), false) = (text, caption_mode) {
+        payloads.push(whatsapp_send_payload(to, Some(body), &[], false, metadata));
+    }
+    for (index, attachment) in attachments.iter().enumerate() {
+        let caption = caption.filter(|_| index == 0);
+        payloads.push(whatsapp_send_payload(
+            to,
+            caption,
+            std::slice::from_ref(attachment),
+            true,
+            metadata,
+        ));
+    }
+    payloads
+}
+
+/// Classifies a failed sidecar response by HTTP status, using the body's
+/// `error` string as the code when there is one.
+pub fn whatsapp_error(status: u16, body: Option<&serde_json::Value>) -> ChannelError {
+    let code = body
+        .and_then(|body| body.get("error"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("http {status}"));
+    let channel = "whatsapp".to_string();
+    match status {
+        401 | 403 => ChannelError::Auth { channel, code },
+        404 => ChannelError::InvalidRecipient { channel, code },
+        400..=499 => ChannelError::Rejected { channel, code },
+        _ => ChannelError::Transient { channel, code },
+    }
+}
+
+/// Posts each sidecar request in turn. When a later request fails after
+/// earlier ones were delivered, the error is `Rejected` with a
+/// `partially_delivered` code: retrying would resend the delivered parts.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_whatsapp_message(
+    client: &Client,
+    sidecar_url: &str,
+    to: &str,
+    text: Option<&str>,
+    attachments: &[Attachment],
+    caption_mode: bool,
+    metadata: Option<&serde_json::Value>,
+    peer_kind: Option<&str>,
+    split_attachments: bool,
+) -> Result<Option<String>> {
+    let payloads = whatsapp_send_payloads(
+        to,
+        text,
+        attachments,
+        caption_mode,
+        metadata,
+        split_attachments,
+    );
+    let total = payloads.len();
+    let mut message_id = None;
+    for (delivered, mut payload) in payloads.into_iter().enumerate() {
+        if let Some(kind) = peer_kind {
+            payload["peer_kind"] = serde_json::Value::String(kind.to_string());
+        }
+        let value = match post_send(client, sidecar_url, &payload).await {
+            Ok(value) => value,
+            Err(err) if delivered > 0 => {
+                return Err(ChannelError::Rejected {
+                    channel: "whatsapp".to_string(),
+                    code: format!("partially_delivered {delivered}/{total}: {err}"),
+                }
+                .into());
+            }
+            Err(err) => return Err(err),
+        };
+        if message_id.is_none() {
+            message_id = value
+                .get("message_id")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+        }
+    }
+    Ok(message_id)
+}
+
+async fn post_send(
+    client: &Client,
+    sidecar_url: &str,
+    payload: &serde_json::Value,
+) -> Result<serde_json::Value> {
     let resp = client
         .post(format!("{}/send", sidecar_url))
-        .json(&payload)
+        .json(payload)
         .send()
-        .await?;
+        .await
+        .map_err(|err| super::transport_error("whatsapp", err))?;
+    let resp = super::check_rate_limit("whatsapp", resp).await?;
     if !resp.status().is_success() {
-        let body = resp.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("whatsapp sidecar error: {}", body));
+        let status = resp.status().as_u16();
+        let body = resp.json::<serde_json::Value>().await.ok();
+        return Err(whatsapp_error(status, body.as_ref()).into());
+    }
+    Ok(resp.json().await.unwrap_or_default())
+}
+
+/// WhatsApp accepts free-form messages only this long after the user's last message.
+pub const MESSAGING_WINDOW_HOURS: i64 = 24;
+
+/// Whether the customer service window is open at `now`, and when it closes.
+/// With no inbound message on record the window is closed and has no expiry.
+pub fn messaging_window(
+    last_inbound_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> (bool, Option<DateTime<Utc>>) {
+    match last_inbound_at {
+        Some(last) => {
+            let expires_at = last + chrono::Duration::hours(MESSAGING_WINDOW_HOURS);
+            (now < expires_at, Some(expires_at))
+        }
+        None => (false, None),
+    }
+}
+
+pub fn normalize_phone_number(raw: &str) -> String {
+    let trimmed = raw.trim();
+    if trimmed.contains('@') {
+        return trimmed.to_string();
+    }
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return trimmed.to_string();
     }
-    Ok("ok".to_string())
+    format!("+{digits}")
 }
 
 pub fn normalize_whatsapp_inbound(payload: WhatsAppInboundPayload) -> InboundMessage {
@@ -44,13 +203,17 @@ pub fn normalize_whatsapp_inbound(payload: WhatsAppInboundPayload) -> InboundMes
             .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
         channel: "whatsapp".to_string(),
         account_id: None,
-        peer_id: payload.peer_id,
+        peer_id: normalize_phone_number(&payload.peer_id),
         peer_kind: "dm".to_string(),
         thread_id: payload.thread_id,
         message_id: payload.message_id,
         sender_name: payload.sender_name,
         text: payload.text,
         attachments: payload.attachments.unwrap_or_default(),
-        timestamp: None,
+        timestamp: payload.timestamp.and_then(|value| match value {
+            serde_json::Value::String(s) => Some(s),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }),
     }
 }
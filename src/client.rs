@@ -0,0 +1,253 @@
+use crate::db::{MessageRecord, SessionRecord};
+use crate::ws::{WsCommand, WsEvent};
+use crate::{SendByIdentityRequest, SendMessageRequest, SendMessageResponse};
+use futures::{SinkExt, StreamExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("{status}: {message}")]
+    Api {
+        status: StatusCode,
+        code: Option<String>,
+        message: String,
+        request_id: Option<String>,
+    },
+    #[error(transparent)]
+    Ws(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BulkSendResult {
+    Sent(SendMessageResponse),
+    Failed { error: String, code: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkSendResponse {
+    results: Vec<BulkSendResult>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    base_url: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>, token: Option<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn send_message(
+        &self,
+        req: &SendMessageRequest,
+    ) -> Result<SendMessageResponse, ClientError> {
+        self.json(
+            self.request(reqwest::Method::POST, "/v1/messages/send")
+                .json(req),
+        )
+        .await
+    }
+
+    pub async fn send_by_identity(
+        &self,
+        req: &SendByIdentityRequest,
+    ) -> Result<SendMessageResponse, ClientError> {
+        self.json(
+            self.request(reqwest::Method::POST, "/v1/messages/send-by-identity")
+                .json(req),
+        )
+        .await
+    }
+
+    pub async fn send_bulk(
+        &self,
+        messages: &[SendMessageRequest],
+    ) -> Result<Vec<BulkSendResult>, ClientError> {
+        let response: BulkSendResponse = self
+            .json(
+                self.request(reqwest::Method::POST, "/v1/messages/send-bulk")
+                    .json(&json!({ "messages": messages })),
+            )
+            .await?;
+        Ok(response.results)
+    }
+
+    pub async fn list_sessions(
+        &self,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<SessionRecord>, ClientError> {
+        self.json(
+            self.request(reqwest::Method::GET, "/v1/sessions")
+                .query(&page_query(limit, offset, None)),
+        )
+        .await
+    }
+
+    pub async fn get_session(
+        &self,
+        session_key: &str,
+    ) -> Result<Option<SessionRecord>, ClientError> {
+        let path = format!("/v1/sessions/{}", encode(session_key));
+        let resp = self.request(reqwest::Method::GET, &path).send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        decode(resp).await.map(Some)
+    }
+
+    /// Sessions for `session_keys` by key; unknown keys are left out.
+    pub async fn get_sessions(
+        &self,
+        session_keys: &[String],
+    ) -> Result<HashMap<String, SessionRecord>, ClientError> {
+        self.json(
+            self.request(reqwest::Method::POST, "/v1/sessions/batch")
+                .json(&json!({ "session_keys": session_keys })),
+        )
+        .await
+    }
+
+    pub async fn list_messages(
+        &self,
+        session_key: &str,
+        after: Option<&str>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<MessageRecord>, ClientError> {
+        let path = format!("/v1/sessions/{}/messages", encode(session_key));
+        self.json(
+            self.request(reqwest::Method::GET, &path)
+                .query(&page_query(limit, offset, after)),
+        )
+        .await
+    }
+
+    pub async fn subscribe(&self, events: &[&str]) -> Result<Subscription, ClientError> {
+        let url = format!(
+            "{}/v1/ws",
+            self.base_url
+                .replacen("https://", "wss://", 1)
+                .replacen("http://", "ws://", 1)
+        );
+        let mut request = url.into_client_request()?;
+        if let Some(token) = self.token.as_deref() {
+            if let Ok(value) = token.parse() {
+                request.headers_mut().insert("X-Agent-Ping-Token", value);
+            }
+        }
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+        let connect = WsCommand::Connect {
+            token: self.token.clone(),
+        };
+        socket
+            .send(Message::Text(serde_json::to_string(&connect)?))
+            .await?;
+        let subscribe = WsCommand::Subscribe {
+            events: Some(events.iter().map(|event| event.to_string()).collect()),
+        };
+        socket
+            .send(Message::Text(serde_json::to_string(&subscribe)?))
+            .await?;
+        Ok(Subscription { socket })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let builder = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match self.token.as_deref() {
+            Some(token) => builder.header("X-Agent-Ping-Token", token),
+            None => builder,
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T, ClientError> {
+        decode(builder.send().await?).await
+    }
+}
+
+pub struct Subscription {
+    socket: WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+}
+
+impl Subscription {
+    pub async fn next_event(&mut self) -> Option<Result<WsEvent, ClientError>> {
+        while let Some(msg) = self.socket.next().await {
+            match msg {
+                Ok(Message::Text(text)) => {
+                    return Some(serde_json::from_str(&text).map_err(ClientError::from))
+                }
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+        None
+    }
+}
+
+async fn decode<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T, ClientError> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp.json().await?);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let value: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+    let field = |name: &str| {
+        value
+            .get("error")
+            .and_then(|error| error.get(name))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+    Err(ClientError::Api {
+        status,
+        code: field("code"),
+        request_id: field("request_id"),
+        message: field("message").unwrap_or(body),
+    })
+}
+
+fn encode(segment: &str) -> String {
+    utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string()
+}
+
+fn page_query(
+    limit: Option<i64>,
+    offset: Option<i64>,
+    after: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    if let Some(limit) = limit {
+        query.push(("limit", limit.to_string()));
+    }
+    if let Some(offset) = offset {
+        query.push(("offset", offset.to_string()));
+    }
+    if let Some(after) = after {
+        query.push(("after", after.to_string()));
+    }
+    query
+}
@@ -1,8 +1,10 @@
+use crate::types::RouteInfo;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -15,23 +17,151 @@ pub struct Config {
     pub queue: QueueConfig,
     pub channels: ChannelsConfig,
     pub bindings: Vec<Binding>,
+    #[serde(default)]
+    pub bridges: Vec<Bridge>,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub ws: WsConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default = "default_true")]
+    pub compression: bool,
+    #[serde(default)]
+    pub base_path: String,
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Serve `/v1/status` behind token auth instead of publicly.
+    #[serde(default)]
+    pub status_requires_auth: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ServerConfig {
+    pub fn route_prefix(&self) -> String {
+        let trimmed = self.base_path.trim().trim_end_matches('/');
+        if trimmed.is_empty() || trimmed.starts_with('/') {
+            trimmed.to_string()
+        } else {
+            format!("/{trimmed}")
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_backend_batch_size() -> usize {
+    1
+}
+
+fn default_backend_concurrency() -> usize {
+    1
+}
+
+fn default_max_body_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_max_webhook_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_max_concurrent_sends() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AuthConfig {
-    pub token: Option<String>,
+    #[serde(default, alias = "token", deserialize_with = "deserialize_tokens")]
+    pub tokens: Vec<String>,
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    pub fn accepts(&self, candidate: Option<&str>) -> bool {
+        if self.tokens.is_empty() {
+            return true;
+        }
+        match candidate {
+            Some(candidate) => self.tokens.iter().any(|token| token == candidate),
+            None => false,
+        }
+    }
+}
+
+fn deserialize_tokens<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let raw = Option::<OneOrMany>::deserialize(deserializer)?;
+    let tokens = match raw {
+        None => Vec::new(),
+        Some(OneOrMany::One(token)) => vec![token],
+        Some(OneOrMany::Many(tokens)) => tokens,
+    };
+    Ok(parse_token_list(tokens.iter().map(String::as_str)))
+}
+
+fn parse_token_list<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    values
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub url: Option<String>,
     pub sqlite_path: String,
+    #[serde(default)]
+    pub statement_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub read_url: Option<String>,
+    #[serde(default)]
+    pub maintenance_interval_hours: Option<u64>,
+    /// `persistent` (the default) or `memory` for an in-process SQLite database
+    /// that is lost on restart.
+    #[serde(default = "default_database_mode")]
+    pub mode: String,
+    /// Close pooled connections idle for longer than this; `0` keeps them open.
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Replace pooled connections older than this; `0` keeps them indefinitely.
+    #[serde(default = "default_max_lifetime_secs")]
+    pub max_lifetime_secs: u64,
+    /// Ping a pooled connection before handing it out.
+    #[serde(default = "default_true")]
+    pub test_before_acquire: bool,
+}
+
+fn default_database_mode() -> String {
+    "persistent".to_string()
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    300
+}
+
+fn default_max_lifetime_secs() -> u64 {
+    1800
 }
 
 impl Default for DatabaseConfig {
@@ -39,6 +169,13 @@ impl Default for DatabaseConfig {
         Self {
             url: None,
             sqlite_path: "~/.agent-ping/state.sqlite".to_string(),
+            statement_timeout_ms: None,
+            read_url: None,
+            maintenance_interval_hours: None,
+            mode: default_database_mode(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            max_lifetime_secs: default_max_lifetime_secs(),
+            test_before_acquire: true,
         }
     }
 }
@@ -48,7 +185,53 @@ pub struct BackendConfig {
     pub webhook_url: Option<String>,
     pub media_upload_url: Option<String>,
     pub route_resolve_url: Option<String>,
+    /// Receives inbound audio and returns `{"text": ...}` to append to the message.
+    #[serde(default)]
+    pub transcription_url: Option<String>,
     pub api_token: Option<String>,
+    /// Extra headers sent with every request to the backend.
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// Outbox rows posted per webhook call; above 1 the body is a JSON array.
+    #[serde(default = "default_backend_batch_size")]
+    pub batch_size: usize,
+    /// How long to wait for a batch to fill before posting a partial one.
+    #[serde(default)]
+    pub batch_max_wait_ms: u64,
+    /// Webhook calls the outbox keeps in flight at once when posting single rows.
+    #[serde(default = "default_backend_concurrency")]
+    pub concurrency: usize,
+    /// Which inbound messages are forwarded to the webhook; all by default.
+    #[serde(default)]
+    pub forward_filter: ForwardFilter,
+    /// Delete delivered and dead outbox rows older than this; `0` keeps them.
+    #[serde(default)]
+    pub outbox_retention_hours: u64,
+    /// Shape of the JSON posted to the webhook, with `{{field}}` placeholders
+    /// for inbound payload fields; the default shape when unset.
+    #[serde(default)]
+    pub payload_template: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ForwardFilter {
+    /// Channels to forward; empty forwards every channel.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Peer kinds (`dm`, `group`, `channel`, `thread`) to forward; empty forwards every kind.
+    #[serde(default)]
+    pub peer_kinds: Vec<String>,
+    /// Skip messages without text, such as attachment-only messages.
+    #[serde(default)]
+    pub require_text: bool,
+}
+
+impl ForwardFilter {
+    pub fn allows(&self, channel: &str, peer_kind: &str, text: Option<&str>) -> bool {
+        (self.channels.is_empty() || self.channels.iter().any(|c| c == channel))
+            && (self.peer_kinds.is_empty() || self.peer_kinds.iter().any(|k| k == peer_kind))
+            && (!self.require_text || text.is_some_and(|text| !text.trim().is_empty()))
+    }
 }
 
 impl Default for BackendConfig {
@@ -57,28 +240,48 @@ impl Default for BackendConfig {
             webhook_url: None,
             media_upload_url: None,
             route_resolve_url: None,
+            transcription_url: None,
             api_token: None,
+            extra_headers: HashMap::new(),
+            batch_size: default_backend_batch_size(),
+            batch_max_wait_ms: 0,
+            concurrency: default_backend_concurrency(),
+            forward_filter: ForwardFilter::default(),
+            outbox_retention_hours: 0,
+            payload_template: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AdapterRuntimeConfig {
     pub runtime_url: Option<String>,
 }
 
-impl Default for AdapterRuntimeConfig {
-    fn default() -> Self {
-        Self { runtime_url: None }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionConfig {
     pub agent_id: String,
     pub dm_scope: String,
     pub main_key: String,
     pub identity_links: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub key_template: Option<String>,
+    /// Scope per `peer_kind` (same values as `dm_scope`); `dm` here overrides `dm_scope`.
+    #[serde(default)]
+    pub scope_by_kind: HashMap<String, String>,
+    /// Keep the case of peer ids in session keys instead of lowercasing them.
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Use `identity_links` canonical keys in session keys exactly as configured
+    /// (trimmed) instead of lowercasing them.
+    #[serde(default)]
+    pub preserve_canonical_case: bool,
+    /// Cap on stored sessions; the least recently updated are evicted past it. `0` is unlimited.
+    #[serde(default)]
+    pub max_sessions: u64,
+    /// Evict sessions not updated for this many days. `0` disables idle eviction.
+    #[serde(default)]
+    pub idle_ttl_days: u64,
 }
 
 impl Default for SessionConfig {
@@ -88,6 +291,12 @@ impl Default for SessionConfig {
             dm_scope: "main".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            key_template: None,
+            scope_by_kind: HashMap::new(),
+            case_sensitive: false,
+            preserve_canonical_case: false,
+            max_sessions: 0,
+            idle_ttl_days: 0,
         }
     }
 }
@@ -98,6 +307,24 @@ pub struct QueueConfig {
     pub debounce_ms: u64,
     pub cap: usize,
     pub drop: String,
+    #[serde(default)]
+    pub max_content_bytes: Option<usize>,
+    #[serde(default = "default_on_oversize")]
+    pub on_oversize: String,
+    /// Most attachments kept per inbound message; `drop` picks which go.
+    #[serde(default)]
+    pub max_attachments: Option<usize>,
+    /// Strip control, zero-width and bidi override characters from inbound
+    /// text and normalize it to NFC before it is stored.
+    #[serde(default)]
+    pub sanitize_inbound: bool,
+    /// With `sanitize_inbound`, also collapse runs of spaces and blank lines.
+    #[serde(default)]
+    pub collapse_whitespace: bool,
+}
+
+fn default_on_oversize() -> String {
+    "truncate".to_string()
 }
 
 impl Default for QueueConfig {
@@ -107,27 +334,93 @@ impl Default for QueueConfig {
             debounce_ms: 1000,
             cap: 20,
             drop: "summarize".to_string(),
+            max_content_bytes: None,
+            on_oversize: default_on_oversize(),
+            max_attachments: None,
+            sanitize_inbound: false,
+            collapse_whitespace: false,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default = "default_log_format")]
+    pub format: String,
+    #[serde(default = "default_log_level")]
+    pub level: String,
+}
+
+fn default_log_format() -> String {
+    "pretty".to_string()
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: default_log_format(),
+            level: default_log_level(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsConfig {
+    /// Sustained commands per second a client may send; 0 disables the limit.
+    #[serde(default = "default_ws_command_rate")]
+    pub command_rate: u32,
+    /// Commands a client may send in a burst before the rate applies.
+    #[serde(default = "default_ws_command_burst")]
+    pub command_burst: u32,
+    /// Largest client frame or message accepted before the socket is dropped.
+    #[serde(default = "default_ws_max_frame_bytes")]
+    pub max_frame_bytes: usize,
+}
+
+fn default_ws_command_rate() -> u32 {
+    10
+}
+
+fn default_ws_command_burst() -> u32 {
+    20
+}
+
+fn default_ws_max_frame_bytes() -> usize {
+    64 * 1024
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            command_rate: default_ws_command_rate(),
+            command_burst: default_ws_command_burst(),
+            max_frame_bytes: default_ws_max_frame_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChannelsConfig {
     pub slack: SlackConfig,
     pub telegram: TelegramConfig,
     pub whatsapp: WhatsAppConfig,
     pub teams: TeamsConfig,
+    /// Channels without native support, keyed by channel name. Inbound messages
+    /// arrive on `POST /v1/inbound`; sends are POSTed to `forward_url`.
+    #[serde(default)]
+    pub custom: HashMap<String, CustomChannelConfig>,
 }
 
-impl Default for ChannelsConfig {
-    fn default() -> Self {
-        Self {
-            slack: SlackConfig::default(),
-            telegram: TelegramConfig::default(),
-            whatsapp: WhatsAppConfig::default(),
-            teams: TeamsConfig::default(),
-        }
-    }
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CustomChannelConfig {
+    pub forward_url: String,
+    /// Extra headers sent with every forwarded send, e.g. an API key.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,6 +432,30 @@ pub struct SlackConfig {
     pub mode: String,
     pub transport: String,
     pub webhook_path: String,
+    #[serde(default)]
+    pub always_thread: bool,
+    #[serde(default)]
+    pub inbound_reactions: bool,
+    #[serde(default)]
+    pub track_edits: bool,
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub default_agent: Option<String>,
+    #[serde(default = "default_max_webhook_bytes")]
+    pub max_webhook_bytes: usize,
+    #[serde(default = "default_max_concurrent_sends")]
+    pub max_concurrent_sends: usize,
+    /// Overrides `queue.debounce_ms` for this channel's inbound messages.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// Send inbound audio attachments to `backend.transcription_url`.
+    #[serde(default)]
+    pub transcribe_audio: bool,
+    /// Key sessions by `{team_id}:{channel_id}` so workspaces sharing an app
+    /// don't collide on channel ids.
+    #[serde(default)]
+    pub qualify_peer_ids: bool,
 }
 
 impl Default for SlackConfig {
@@ -151,6 +468,16 @@ impl Default for SlackConfig {
             mode: "http".to_string(),
             transport: "native".to_string(),
             webhook_path: "/v1/channels/slack/events".to_string(),
+            always_thread: false,
+            inbound_reactions: false,
+            track_edits: false,
+            allowed_ips: Vec::new(),
+            default_agent: None,
+            max_webhook_bytes: default_max_webhook_bytes(),
+            max_concurrent_sends: default_max_concurrent_sends(),
+            debounce_ms: None,
+            transcribe_audio: false,
+            qualify_peer_ids: false,
         }
     }
 }
@@ -162,6 +489,25 @@ pub struct TelegramConfig {
     pub transport: String,
     pub webhook_path: String,
     pub poll_interval_seconds: u64,
+    #[serde(default)]
+    pub inbound_reactions: bool,
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub default_agent: Option<String>,
+    #[serde(default = "default_max_webhook_bytes")]
+    pub max_webhook_bytes: usize,
+    #[serde(default = "default_max_concurrent_sends")]
+    pub max_concurrent_sends: usize,
+    /// Overrides `queue.debounce_ms` for this channel's inbound messages.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// Send inbound audio attachments to `backend.transcription_url`.
+    #[serde(default)]
+    pub transcribe_audio: bool,
+    /// Prepend a quote of the replied-to message to the text of replies.
+    #[serde(default)]
+    pub reply_quote: bool,
 }
 
 impl Default for TelegramConfig {
@@ -172,6 +518,14 @@ impl Default for TelegramConfig {
             transport: "native".to_string(),
             webhook_path: "/v1/channels/telegram/webhook".to_string(),
             poll_interval_seconds: 2,
+            inbound_reactions: false,
+            allowed_ips: Vec::new(),
+            default_agent: None,
+            max_webhook_bytes: default_max_webhook_bytes(),
+            max_concurrent_sends: default_max_concurrent_sends(),
+            debounce_ms: None,
+            transcribe_audio: false,
+            reply_quote: false,
         }
     }
 }
@@ -182,6 +536,29 @@ pub struct WhatsAppConfig {
     pub sidecar_url: String,
     pub transport: String,
     pub inbound_path: String,
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub default_agent: Option<String>,
+    #[serde(default = "default_max_webhook_bytes")]
+    pub max_webhook_bytes: usize,
+    #[serde(default = "default_max_concurrent_sends")]
+    pub max_concurrent_sends: usize,
+    /// Overrides `queue.debounce_ms` for this channel's inbound messages.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// Send inbound audio attachments to `backend.transcription_url`.
+    #[serde(default)]
+    pub transcribe_audio: bool,
+    /// Send each outbound attachment as its own sidecar request.
+    #[serde(default)]
+    pub split_attachments: bool,
+    /// Prepend a quote of the replied-to message to the text of replies.
+    #[serde(default)]
+    pub reply_quote: bool,
+    /// Reject sends to peers outside WhatsApp's 24h messaging window.
+    #[serde(default)]
+    pub enforce_messaging_window: bool,
 }
 
 impl Default for WhatsAppConfig {
@@ -191,6 +568,15 @@ impl Default for WhatsAppConfig {
             sidecar_url: "http://127.0.0.1:4040".to_string(),
             transport: "native".to_string(),
             inbound_path: "/v1/channels/whatsapp/inbound".to_string(),
+            allowed_ips: Vec::new(),
+            default_agent: None,
+            max_webhook_bytes: default_max_webhook_bytes(),
+            max_concurrent_sends: default_max_concurrent_sends(),
+            debounce_ms: None,
+            transcribe_audio: false,
+            split_attachments: false,
+            enforce_messaging_window: false,
+            reply_quote: false,
         }
     }
 }
@@ -200,6 +586,20 @@ pub struct TeamsConfig {
     pub enabled: bool,
     pub transport: String,
     pub webhook_path: String,
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    #[serde(default)]
+    pub default_agent: Option<String>,
+    #[serde(default = "default_max_webhook_bytes")]
+    pub max_webhook_bytes: usize,
+    #[serde(default = "default_max_concurrent_sends")]
+    pub max_concurrent_sends: usize,
+    /// Overrides `queue.debounce_ms` for this channel's inbound messages.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// Send inbound audio attachments to `backend.transcription_url`.
+    #[serde(default)]
+    pub transcribe_audio: bool,
 }
 
 impl Default for TeamsConfig {
@@ -208,31 +608,56 @@ impl Default for TeamsConfig {
             enabled: false,
             transport: "native".to_string(),
             webhook_path: "/v1/channels/teams/webhook".to_string(),
+            allowed_ips: Vec::new(),
+            default_agent: None,
+            max_webhook_bytes: default_max_webhook_bytes(),
+            max_concurrent_sends: default_max_concurrent_sends(),
+            debounce_ms: None,
+            transcribe_audio: false,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Binding {
+    #[serde(default)]
     pub channel: String,
     pub account_id: Option<String>,
     pub peer_id: Option<String>,
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Canonical identity from `session.identity_links`; matches every linked peer.
+    #[serde(default)]
+    pub canonical_id: Option<String>,
     pub business_profile_id: Option<String>,
     pub user_id: Option<String>,
     pub agent_id: Option<String>,
+    /// Backend webhook for inbound messages matched to this binding, in place
+    /// of `backend.webhook_url`.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
 }
 
-impl Default for Binding {
-    fn default() -> Self {
-        Self {
-            channel: String::new(),
-            account_id: None,
-            peer_id: None,
-            business_profile_id: None,
-            user_id: None,
-            agent_id: None,
-        }
-    }
+/// Inbound messages selected by a bridge. Unset fields match anything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteMatch {
+    pub channel: String,
+    #[serde(default)]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    pub peer_id: Option<String>,
+    #[serde(default)]
+    pub peer_kind: Option<String>,
+    #[serde(default)]
+    pub thread_id: Option<String>,
+}
+
+/// Mirrors inbound messages matching `from` to the `to` route as outbound
+/// sends, e.g. a Telegram DM relayed into a Slack channel for operators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bridge {
+    pub from: RouteMatch,
+    pub to: RouteInfo,
 }
 
 impl Default for Config {
@@ -241,30 +666,61 @@ impl Default for Config {
             server: ServerConfig {
                 host: "0.0.0.0".to_string(),
                 port: 8091,
+                trusted_proxies: Vec::new(),
+                compression: true,
+                base_path: String::new(),
+                max_body_bytes: default_max_body_bytes(),
+                status_requires_auth: false,
             },
-            auth: AuthConfig { token: None },
+            auth: AuthConfig::default(),
             database: DatabaseConfig {
                 url: None,
                 sqlite_path: "~/.agent-ping/state.sqlite".to_string(),
+                statement_timeout_ms: None,
+                read_url: None,
+                maintenance_interval_hours: None,
+                mode: default_database_mode(),
+                idle_timeout_secs: default_idle_timeout_secs(),
+                max_lifetime_secs: default_max_lifetime_secs(),
+                test_before_acquire: true,
             },
             adapters: AdapterRuntimeConfig { runtime_url: None },
             backend: BackendConfig {
                 webhook_url: None,
                 media_upload_url: None,
                 route_resolve_url: None,
+                transcription_url: None,
                 api_token: None,
+                extra_headers: HashMap::new(),
+                batch_size: default_backend_batch_size(),
+                batch_max_wait_ms: 0,
+                concurrency: default_backend_concurrency(),
+                forward_filter: ForwardFilter::default(),
+                outbox_retention_hours: 0,
+                payload_template: None,
             },
             session: SessionConfig {
                 agent_id: "main".to_string(),
                 dm_scope: "main".to_string(),
                 main_key: "main".to_string(),
                 identity_links: HashMap::new(),
+                key_template: None,
+                scope_by_kind: HashMap::new(),
+                case_sensitive: false,
+                preserve_canonical_case: false,
+                max_sessions: 0,
+                idle_ttl_days: 0,
             },
             queue: QueueConfig {
                 mode: "collect".to_string(),
                 debounce_ms: 1000,
                 cap: 20,
                 drop: "summarize".to_string(),
+                max_content_bytes: None,
+                on_oversize: default_on_oversize(),
+                max_attachments: None,
+                sanitize_inbound: false,
+                collapse_whitespace: false,
             },
             channels: ChannelsConfig {
                 slack: SlackConfig {
@@ -275,6 +731,16 @@ impl Default for Config {
                     mode: "http".to_string(),
                     transport: "native".to_string(),
                     webhook_path: "/v1/channels/slack/events".to_string(),
+                    always_thread: false,
+                    inbound_reactions: false,
+                    allowed_ips: Vec::new(),
+                    default_agent: None,
+                    max_webhook_bytes: default_max_webhook_bytes(),
+                    max_concurrent_sends: default_max_concurrent_sends(),
+                    debounce_ms: None,
+                    track_edits: false,
+                    transcribe_audio: false,
+                    qualify_peer_ids: false,
                 },
                 telegram: TelegramConfig {
                     enabled: false,
@@ -282,21 +748,303 @@ impl Default for Config {
                     transport: "native".to_string(),
                     webhook_path: "/v1/channels/telegram/webhook".to_string(),
                     poll_interval_seconds: 2,
+                    inbound_reactions: false,
+                    allowed_ips: Vec::new(),
+                    default_agent: None,
+                    max_webhook_bytes: default_max_webhook_bytes(),
+                    max_concurrent_sends: default_max_concurrent_sends(),
+                    debounce_ms: None,
+                    transcribe_audio: false,
+                    reply_quote: false,
                 },
                 whatsapp: WhatsAppConfig {
                     enabled: false,
                     sidecar_url: "http://127.0.0.1:4040".to_string(),
                     transport: "native".to_string(),
                     inbound_path: "/v1/channels/whatsapp/inbound".to_string(),
+                    allowed_ips: Vec::new(),
+                    default_agent: None,
+                    max_webhook_bytes: default_max_webhook_bytes(),
+                    max_concurrent_sends: default_max_concurrent_sends(),
+                    debounce_ms: None,
+                    transcribe_audio: false,
+                    split_attachments: false,
+                    enforce_messaging_window: false,
+                    reply_quote: false,
                 },
                 teams: TeamsConfig {
                     enabled: false,
                     transport: "native".to_string(),
                     webhook_path: "/v1/channels/teams/webhook".to_string(),
+                    allowed_ips: Vec::new(),
+                    default_agent: None,
+                    max_webhook_bytes: default_max_webhook_bytes(),
+                    max_concurrent_sends: default_max_concurrent_sends(),
+                    debounce_ms: None,
+                    transcribe_audio: false,
                 },
+                custom: HashMap::new(),
             },
             bindings: Vec::new(),
+            bridges: Vec::new(),
+            logging: LoggingConfig::default(),
+            ws: WsConfig::default(),
+        }
+    }
+}
+
+const REDACTED: &str = "***";
+
+impl Config {
+    pub fn redacted(&self) -> Config {
+        let mut cfg = self.clone();
+        cfg.auth.tokens = cfg
+            .auth
+            .tokens
+            .iter()
+            .map(|_| REDACTED.to_string())
+            .collect();
+        cfg.database.url = cfg.database.url.as_deref().map(redact_url_credentials);
+        cfg.database.read_url = cfg.database.read_url.as_deref().map(redact_url_credentials);
+        redact_secret(&mut cfg.backend.api_token);
+        for value in cfg.backend.extra_headers.values_mut() {
+            *value = REDACTED.to_string();
+        }
+        redact_secret(&mut cfg.channels.slack.bot_token);
+        redact_secret(&mut cfg.channels.slack.signing_secret);
+        redact_secret(&mut cfg.channels.slack.app_token);
+        redact_secret(&mut cfg.channels.telegram.bot_token);
+        for custom in cfg.channels.custom.values_mut() {
+            for value in custom.headers.values_mut() {
+                *value = REDACTED.to_string();
+            }
+        }
+        cfg
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let known_scope = |scope: &str| {
+            matches!(
+                scope,
+                "main" | "per-peer" | "per-channel-peer" | "per-account-channel-peer"
+            )
+        };
+        if !known_scope(&self.session.dm_scope) {
+            anyhow::bail!("unknown session.dm_scope {:?}", self.session.dm_scope);
+        }
+        if !matches!(self.database.mode.as_str(), "persistent" | "memory") {
+            anyhow::bail!("unknown database.mode {:?}", self.database.mode);
+        }
+        if self.database.mode == "memory" && self.database.read_url.is_some() {
+            anyhow::bail!("database.read_url cannot be used with database.mode \"memory\"");
+        }
+        for (kind, scope) in &self.session.scope_by_kind {
+            if !known_scope(scope) {
+                anyhow::bail!("unknown session.scope_by_kind.{kind} {scope:?}");
+            }
+        }
+        if !matches!(self.queue.on_oversize.as_str(), "truncate" | "reject") {
+            anyhow::bail!("unknown queue.on_oversize {:?}", self.queue.on_oversize);
+        }
+        if self.server.max_body_bytes == 0 {
+            anyhow::bail!("server.max_body_bytes must be greater than 0");
+        }
+        let channels = &self.channels;
+        for (name, limit) in [
+            ("slack", channels.slack.max_webhook_bytes),
+            ("telegram", channels.telegram.max_webhook_bytes),
+            ("whatsapp", channels.whatsapp.max_webhook_bytes),
+            ("teams", channels.teams.max_webhook_bytes),
+        ] {
+            if limit == 0 {
+                anyhow::bail!("channels.{name}.max_webhook_bytes must be greater than 0");
+            }
+        }
+        for (name, permits) in [
+            ("slack", channels.slack.max_concurrent_sends),
+            ("telegram", channels.telegram.max_concurrent_sends),
+            ("whatsapp", channels.whatsapp.max_concurrent_sends),
+            ("teams", channels.teams.max_concurrent_sends),
+        ] {
+            if permits == 0 {
+                anyhow::bail!("channels.{name}.max_concurrent_sends must be greater than 0");
+            }
+        }
+        if !matches!(self.logging.format.as_str(), "pretty" | "json") {
+            anyhow::bail!("unknown logging.format {:?}", self.logging.format);
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.logging.level)
+            .with_context(|| format!("invalid logging.level {:?}", self.logging.level))?;
+        if self.ws.command_rate > 0 && self.ws.command_burst == 0 {
+            anyhow::bail!("ws.command_burst must be greater than 0");
+        }
+        if self.ws.max_frame_bytes == 0 {
+            anyhow::bail!("ws.max_frame_bytes must be greater than 0");
+        }
+        if self.backend.batch_size == 0 || self.backend.batch_size > 500 {
+            anyhow::bail!("backend.batch_size must be between 1 and 500");
+        }
+        if self.backend.concurrency == 0 || self.backend.concurrency > 64 {
+            anyhow::bail!("backend.concurrency must be between 1 and 64");
+        }
+        for (name, value) in &self.backend.extra_headers {
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid backend.extra_headers name {name:?}"))?;
+            reqwest::header::HeaderValue::from_str(value)
+                .with_context(|| format!("invalid backend.extra_headers value for {name:?}"))?;
+        }
+        // Peer kinds are open-ended (Telegram `supergroup`, custom channel
+        // kinds), so only entries that could never match are rejected.
+        if self
+            .backend
+            .forward_filter
+            .peer_kinds
+            .iter()
+            .any(|kind| kind.trim().is_empty())
+        {
+            anyhow::bail!("backend.forward_filter.peer_kinds entries must not be empty");
+        }
+        if let Some(template) = &self.backend.payload_template {
+            validate_payload_template(template).context("invalid backend.payload_template")?;
         }
+        for (name, custom) in &self.channels.custom {
+            if name.trim().is_empty() || name != &name.trim().to_lowercase() {
+                anyhow::bail!("channels.custom name {name:?} must be non-empty and lowercase");
+            }
+            if matches!(name.as_str(), "slack" | "telegram" | "whatsapp" | "teams") {
+                anyhow::bail!("channels.custom.{name} shadows a built-in channel");
+            }
+            if !custom.forward_url.starts_with("http://")
+                && !custom.forward_url.starts_with("https://")
+            {
+                anyhow::bail!("channels.custom.{name}.forward_url must be an http(s) URL");
+            }
+            for (header, value) in &custom.headers {
+                reqwest::header::HeaderName::from_bytes(header.as_bytes()).with_context(|| {
+                    format!("invalid channels.custom.{name}.headers name {header:?}")
+                })?;
+                reqwest::header::HeaderValue::from_str(value).with_context(|| {
+                    format!("invalid channels.custom.{name}.headers value for {header:?}")
+                })?;
+            }
+        }
+        for binding in &self.bindings {
+            if binding.channel.trim().is_empty() && binding.canonical_id.is_none() {
+                anyhow::bail!("binding is missing a channel or canonical_id");
+            }
+            if let Some(url) = binding.webhook_url.as_deref() {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    anyhow::bail!("binding webhook_url {url:?} must be an http(s) URL");
+                }
+            }
+        }
+        for bridge in &self.bridges {
+            if bridge.from.channel.trim().is_empty() {
+                anyhow::bail!("bridge is missing from.channel");
+            }
+            let to_peer = bridge
+                .to
+                .peer_id
+                .as_deref()
+                .map(str::trim)
+                .unwrap_or_default();
+            if bridge.to.channel.trim().is_empty() || to_peer.is_empty() {
+                anyhow::bail!(
+                    "bridge from {:?} needs a to.channel and to.peer_id",
+                    bridge.from.channel
+                );
+            }
+        }
+        let channels = &self.channels;
+        for (name, transport, path, allowed_ips) in [
+            (
+                "slack",
+                &channels.slack.transport,
+                &channels.slack.webhook_path,
+                &channels.slack.allowed_ips,
+            ),
+            (
+                "telegram",
+                &channels.telegram.transport,
+                &channels.telegram.webhook_path,
+                &channels.telegram.allowed_ips,
+            ),
+            (
+                "whatsapp",
+                &channels.whatsapp.transport,
+                &channels.whatsapp.inbound_path,
+                &channels.whatsapp.allowed_ips,
+            ),
+            (
+                "teams",
+                &channels.teams.transport,
+                &channels.teams.webhook_path,
+                &channels.teams.allowed_ips,
+            ),
+        ] {
+            if !matches!(transport.as_str(), "native" | "embedded" | "echo") {
+                anyhow::bail!("unknown channels.{name}.transport {transport:?}");
+            }
+            if !path.starts_with('/') {
+                anyhow::bail!("channels.{name} path {path:?} must start with '/'");
+            }
+            if let Some(bad) = allowed_ips
+                .iter()
+                .find(|value| crate::ipfilter::Cidr::parse(value).is_none())
+            {
+                anyhow::bail!("invalid channels.{name}.allowed_ips entry {bad:?}");
+            }
+        }
+        if let Some(bad) = self
+            .server
+            .trusted_proxies
+            .iter()
+            .find(|value| crate::ipfilter::Cidr::parse(value).is_none())
+        {
+            anyhow::bail!("invalid server.trusted_proxies entry {bad:?}");
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every `{{placeholder}}` in a payload template names a known
+/// inbound payload field.
+fn validate_payload_template(template: &serde_json::Value) -> anyhow::Result<()> {
+    match template {
+        serde_json::Value::String(text) => {
+            for name in crate::outbox::template_placeholders(text)? {
+                if !crate::outbox::PAYLOAD_TEMPLATE_FIELDS.contains(&name) {
+                    anyhow::bail!("unknown placeholder {{{{{name}}}}}");
+                }
+            }
+            Ok(())
+        }
+        serde_json::Value::Array(items) => items.iter().try_for_each(validate_payload_template),
+        serde_json::Value::Object(fields) => {
+            fields.values().try_for_each(validate_payload_template)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn redact_secret(value: &mut Option<String>) {
+    if value.is_some() {
+        *value = Some(REDACTED.to_string());
+    }
+}
+
+fn redact_url_credentials(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+    match authority.rsplit_once('@') {
+        Some((userinfo, host)) => {
+            let user = userinfo.split(':').next().unwrap_or_default();
+            format!("{scheme}://{user}:{REDACTED}@{host}{path}")
+        }
+        None => url.to_string(),
     }
 }
 
@@ -310,10 +1058,7 @@ pub fn expand_tilde(path: &str) -> PathBuf {
 }
 
 pub fn load_config() -> Config {
-    let config_path = env::var("AGENT_PING_CONFIG")
-        .ok()
-        .map(PathBuf::from)
-        .unwrap_or_else(|| expand_tilde("~/.agent-ping/agent-ping.json"));
+    let config_path = resolve_config_path();
 
     let mut cfg = Config::default();
 
@@ -325,10 +1070,72 @@ pub fn load_config() -> Config {
         }
     }
 
+    match apply_overrides(cfg.clone()) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            eprintln!("WARN: {err:#}");
+            apply_env_overrides(cfg)
+        }
+    }
+}
+
+pub fn try_load_config() -> anyhow::Result<Config> {
+    let config_path = resolve_config_path();
+
+    let mut cfg = Config::default();
+
+    if config_path.exists() {
+        let raw = fs::read_to_string(&config_path)
+            .with_context(|| format!("reading {}", config_path.display()))?;
+        cfg = serde_json::from_str::<Config>(&raw)
+            .with_context(|| format!("parsing {}", config_path.display()))?;
+    }
+
+    let cfg = apply_overrides(cfg)?;
+    cfg.validate()?;
+    Ok(cfg)
+}
+
+fn apply_overrides(mut cfg: Config) -> anyhow::Result<Config> {
+    if let Ok(dir) = env::var("AGENT_PING_CONFIG_DIR") {
+        if !dir.trim().is_empty() {
+            cfg = load_config_dir(cfg, Path::new(dir.trim()))?;
+        }
+    }
+    Ok(apply_env_overrides(cfg))
+}
+
+fn apply_env_overrides(mut cfg: Config) -> Config {
     // Override from environment
-    if let Ok(token) = env::var("AGENT_PING_TOKEN") {
-        if !token.trim().is_empty() {
-            cfg.auth.token = Some(token);
+    if let Ok(value) = env::var("AGENT_PING_TOKEN") {
+        let tokens = parse_token_list(value.split(','));
+        if !tokens.is_empty() {
+            cfg.auth.tokens = tokens;
+        }
+    }
+
+    if let Some(compression) = env::var("AGENT_PING_SERVER_COMPRESSION")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.server.compression = compression;
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_LOG_FORMAT") {
+        if !value.trim().is_empty() {
+            cfg.logging.format = value.trim().to_lowercase();
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_LOG_LEVEL") {
+        if !value.trim().is_empty() {
+            cfg.logging.level = value.trim().to_string();
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_SERVER_BASE_PATH") {
+        if !value.trim().is_empty() {
+            cfg.server.base_path = value;
         }
     }
 
@@ -344,6 +1151,43 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Ok(value) = env::var("AGENT_PING_DATABASE_MODE") {
+        if !value.trim().is_empty() {
+            cfg.database.mode = value.trim().to_lowercase();
+        }
+    }
+
+    if let Ok(url) = env::var("AGENT_PING_DATABASE_READ_URL") {
+        if !url.trim().is_empty() {
+            cfg.database.read_url = Some(url);
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_DATABASE_STATEMENT_TIMEOUT_MS") {
+        if let Ok(ms) = value.trim().parse::<u64>() {
+            cfg.database.statement_timeout_ms = Some(ms);
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_DATABASE_IDLE_TIMEOUT_SECS") {
+        if let Ok(secs) = value.trim().parse::<u64>() {
+            cfg.database.idle_timeout_secs = secs;
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_DATABASE_MAX_LIFETIME_SECS") {
+        if let Ok(secs) = value.trim().parse::<u64>() {
+            cfg.database.max_lifetime_secs = secs;
+        }
+    }
+
+    if let Some(test) = env::var("AGENT_PING_DATABASE_TEST_BEFORE_ACQUIRE")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.database.test_before_acquire = test;
+    }
+
     if let Ok(url) = env::var("AGENT_PING_BACKEND_WEBHOOK_URL") {
         if !url.trim().is_empty() {
             cfg.backend.webhook_url = Some(url);
@@ -356,6 +1200,12 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Ok(url) = env::var("AGENT_PING_BACKEND_TRANSCRIPTION_URL") {
+        if !url.trim().is_empty() {
+            cfg.backend.transcription_url = Some(url);
+        }
+    }
+
     if let Ok(url) = env::var("AGENT_PING_BACKEND_ROUTE_RESOLVE_URL") {
         if !url.trim().is_empty() {
             cfg.backend.route_resolve_url = Some(url);
@@ -368,6 +1218,36 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Ok(value) = env::var("AGENT_PING_BACKEND_HEADERS_JSON") {
+        if let Some(headers) =
+            parse_json_env::<HashMap<String, String>>(&value, "AGENT_PING_BACKEND_HEADERS_JSON")
+        {
+            cfg.backend.extra_headers = headers;
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_BACKEND_FORWARD_FILTER_JSON") {
+        if let Some(filter) =
+            parse_json_env::<ForwardFilter>(&value, "AGENT_PING_BACKEND_FORWARD_FILTER_JSON")
+        {
+            cfg.backend.forward_filter = filter;
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_BACKEND_PAYLOAD_TEMPLATE_JSON") {
+        if let Some(template) =
+            parse_json_env::<serde_json::Value>(&value, "AGENT_PING_BACKEND_PAYLOAD_TEMPLATE_JSON")
+        {
+            cfg.backend.payload_template = Some(template);
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_BACKEND_OUTBOX_RETENTION_HOURS") {
+        if let Ok(hours) = value.trim().parse::<u64>() {
+            cfg.backend.outbox_retention_hours = hours;
+        }
+    }
+
     if let Ok(url) = env::var("AGENT_PING_ADAPTER_RUNTIME_URL") {
         if !url.trim().is_empty() {
             cfg.adapters.runtime_url = Some(url);
@@ -392,6 +1272,41 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Some(case_sensitive) = env::var("AGENT_PING_SESSION_CASE_SENSITIVE")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.session.case_sensitive = case_sensitive;
+    }
+
+    if let Some(preserve) = env::var("AGENT_PING_SESSION_PRESERVE_CANONICAL_CASE")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.session.preserve_canonical_case = preserve;
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_SESSION_MAX_SESSIONS") {
+        if let Ok(max) = value.trim().parse::<u64>() {
+            cfg.session.max_sessions = max;
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_SESSION_IDLE_TTL_DAYS") {
+        if let Ok(days) = value.trim().parse::<u64>() {
+            cfg.session.idle_ttl_days = days;
+        }
+    }
+
+    if let Ok(value) = env::var("AGENT_PING_SESSION_SCOPE_BY_KIND_JSON") {
+        if let Some(scope_by_kind) = parse_json_env::<HashMap<String, String>>(
+            &value,
+            "AGENT_PING_SESSION_SCOPE_BY_KIND_JSON",
+        ) {
+            cfg.session.scope_by_kind = scope_by_kind;
+        }
+    }
+
     if let Ok(value) = env::var("AGENT_PING_IDENTITY_LINKS_JSON") {
         if let Some(identity_links) =
             parse_json_env::<HashMap<String, Vec<String>>>(&value, "AGENT_PING_IDENTITY_LINKS_JSON")
@@ -409,6 +1324,95 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Ok(value) = env::var("AGENT_PING_BRIDGES_JSON") {
+        if let Some(bridges) = parse_json_env::<Vec<Bridge>>(&value, "AGENT_PING_BRIDGES_JSON") {
+            cfg.bridges = bridges;
+        }
+    }
+
+    if let Some(enabled) = env::var("AGENT_PING_SLACK_ENABLED")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.slack.enabled = enabled;
+    }
+    if let Ok(value) = env::var("AGENT_PING_SLACK_BOT_TOKEN") {
+        if !value.trim().is_empty() {
+            cfg.channels.slack.bot_token = Some(value.trim().to_string());
+        }
+    }
+    if let Ok(value) = env::var("AGENT_PING_SLACK_SIGNING_SECRET") {
+        if !value.trim().is_empty() {
+            cfg.channels.slack.signing_secret = Some(value.trim().to_string());
+        }
+    }
+    if let Ok(value) = env::var("AGENT_PING_SLACK_APP_TOKEN") {
+        if !value.trim().is_empty() {
+            cfg.channels.slack.app_token = Some(value.trim().to_string());
+        }
+    }
+    if let Some(qualify) = env::var("AGENT_PING_SLACK_QUALIFY_PEER_IDS")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.slack.qualify_peer_ids = qualify;
+    }
+
+    if let Some(enabled) = env::var("AGENT_PING_TELEGRAM_ENABLED")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.telegram.enabled = enabled;
+    }
+    if let Ok(value) = env::var("AGENT_PING_TELEGRAM_BOT_TOKEN") {
+        if !value.trim().is_empty() {
+            cfg.channels.telegram.bot_token = Some(value.trim().to_string());
+        }
+    }
+    if let Some(quote) = env::var("AGENT_PING_TELEGRAM_REPLY_QUOTE")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.telegram.reply_quote = quote;
+    }
+
+    if let Some(enabled) = env::var("AGENT_PING_WHATSAPP_ENABLED")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.whatsapp.enabled = enabled;
+    }
+    if let Ok(value) = env::var("AGENT_PING_WHATSAPP_SIDECAR_URL") {
+        if !value.trim().is_empty() {
+            cfg.channels.whatsapp.sidecar_url = value.trim().to_string();
+        }
+    }
+    if let Some(split) = env::var("AGENT_PING_WHATSAPP_SPLIT_ATTACHMENTS")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.whatsapp.split_attachments = split;
+    }
+    if let Some(enforce) = env::var("AGENT_PING_WHATSAPP_ENFORCE_MESSAGING_WINDOW")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.whatsapp.enforce_messaging_window = enforce;
+    }
+    if let Some(quote) = env::var("AGENT_PING_WHATSAPP_REPLY_QUOTE")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.whatsapp.reply_quote = quote;
+    }
+
+    if let Some(enabled) = env::var("AGENT_PING_TEAMS_ENABLED")
+        .ok()
+        .and_then(|v| parse_bool_env(&v))
+    {
+        cfg.channels.teams.enabled = enabled;
+    }
+
     if let Ok(value) = env::var("AGENT_PING_CHANNEL_SLACK_TRANSPORT") {
         if !value.trim().is_empty() {
             cfg.channels.slack.transport = value;
@@ -416,7 +1420,7 @@ pub fn load_config() -> Config {
     }
     if let Ok(value) = env::var("AGENT_PING_CHANNEL_SLACK_MODE") {
         let trimmed = value.trim();
-        if matches!(trimmed, "native" | "embedded") {
+        if matches!(trimmed, "native" | "embedded" | "echo") {
             cfg.channels.slack.transport = trimmed.to_string();
         } else if !trimmed.is_empty() {
             cfg.channels.slack.mode = trimmed.to_string();
@@ -456,9 +1460,82 @@ pub fn load_config() -> Config {
         }
     }
 
+    if let Ok(value) = env::var("AGENT_PING_CUSTOM_CHANNELS_JSON") {
+        if let Some(custom) = parse_json_env::<HashMap<String, CustomChannelConfig>>(
+            &value,
+            "AGENT_PING_CUSTOM_CHANNELS_JSON",
+        ) {
+            cfg.channels.custom = custom;
+        }
+    }
+
     cfg
 }
 
+/// Overlays the `*.json` fragments in `dir` onto `base` in filename order.
+/// Unparseable fragments are skipped with a warning; an unreadable directory or
+/// a merged config that no longer deserializes is an error.
+pub fn load_config_dir(base: Config, dir: &Path) -> anyhow::Result<Config> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("reading AGENT_PING_CONFIG_DIR {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    entries.sort();
+
+    let mut merged = serde_json::to_value(&base).context("serializing base config")?;
+    for path in entries {
+        let fragment = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|raw| {
+                serde_json::from_str::<serde_json::Value>(&raw).map_err(|err| err.to_string())
+            });
+        match fragment {
+            Ok(mut fragment) => {
+                normalize_aliases(&mut fragment);
+                merge_json(&mut merged, fragment)
+            }
+            Err(err) => eprintln!("WARN: skipping config fragment {}: {err}", path.display()),
+        }
+    }
+
+    serde_json::from_value::<Config>(merged)
+        .with_context(|| format!("invalid merged config from {}", dir.display()))
+}
+
+/// Renames aliased keys in a config fragment to their canonical names, so
+/// merging `auth.token` into a base that has `auth.tokens` does not leave both.
+fn normalize_aliases(fragment: &mut serde_json::Value) {
+    let Some(auth) = fragment
+        .get_mut("auth")
+        .and_then(|auth| auth.as_object_mut())
+    else {
+        return;
+    };
+    if let Some(token) = auth.remove("token") {
+        auth.entry("tokens").or_insert(token);
+    }
+}
+
+pub fn merge_json(target: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (target, overlay) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(target), serde_json::Value::Array(overlay)) => {
+            target.extend(overlay);
+        }
+        (target, overlay) => *target = overlay,
+    }
+}
+
 fn parse_json_env<T>(raw: &str, env_name: &str) -> Option<T>
 where
     T: for<'de> Deserialize<'de>,
@@ -477,6 +1554,14 @@ where
     }
 }
 
+pub fn parse_bool_env(raw: &str) -> Option<bool> {
+    match raw.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
 fn normalize_binding(mut binding: Binding) -> Option<Binding> {
     binding.channel = binding.channel.trim().to_lowercase();
     binding.account_id = binding
@@ -487,6 +1572,10 @@ fn normalize_binding(mut binding: Binding) -> Option<Binding> {
         .peer_id
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty());
+    binding.thread_id = binding
+        .thread_id
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
     binding.business_profile_id = binding
         .business_profile_id
         .map(|value| value.trim().to_string())
@@ -499,6 +1588,10 @@ fn normalize_binding(mut binding: Binding) -> Option<Binding> {
         .agent_id
         .map(|value| value.trim().to_string())
         .filter(|value| !value.is_empty());
+    binding.webhook_url = binding
+        .webhook_url
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
 
     if binding.channel.is_empty() {
         return None;
@@ -514,22 +1607,59 @@ pub fn resolve_config_path() -> PathBuf {
         .unwrap_or_else(|| expand_tilde("~/.agent-ping/agent-ping.json"))
 }
 
+pub fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)
+    }
+    #[cfg(not(unix))]
+    {
+        fs::create_dir_all(path)
+    }
+}
+
+pub fn create_private_file(path: &Path) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    match options.open(path) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
 pub fn ensure_config_dir() {
     let path = resolve_config_path();
     if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+        let _ = create_private_dir(parent);
     }
 }
 
+/// Database URL used by `database.mode = "memory"`.
+pub const MEMORY_DATABASE_URL: &str = "sqlite::memory:";
+
 pub fn resolve_database_url(cfg: &Config) -> String {
+    if cfg.database.mode == "memory" {
+        return MEMORY_DATABASE_URL.to_string();
+    }
     if let Some(url) = cfg.database.url.as_ref() {
         return url.to_string();
     }
 
     let path = expand_tilde(&cfg.database.sqlite_path);
     if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+        let _ = create_private_dir(parent);
     }
+    let _ = create_private_file(&path);
     format!("sqlite://{}", path.to_string_lossy())
 }
 
@@ -561,6 +1691,13 @@ mod tests {
             database: DatabaseConfig {
                 url: Some("postgres://localhost/testdb".to_string()),
                 sqlite_path: "~/.agent-ping/state.sqlite".to_string(),
+                statement_timeout_ms: None,
+                read_url: None,
+                maintenance_interval_hours: None,
+                mode: default_database_mode(),
+                idle_timeout_secs: default_idle_timeout_secs(),
+                max_lifetime_secs: default_max_lifetime_secs(),
+                test_before_acquire: true,
             },
             ..Config::default()
         };
@@ -574,6 +1711,13 @@ mod tests {
             database: DatabaseConfig {
                 url: None,
                 sqlite_path: "~/test/data.db".to_string(),
+                statement_timeout_ms: None,
+                read_url: None,
+                maintenance_interval_hours: None,
+                mode: default_database_mode(),
+                idle_timeout_secs: default_idle_timeout_secs(),
+                max_lifetime_secs: default_max_lifetime_secs(),
+                test_before_acquire: true,
             },
             ..Config::default()
         };
@@ -603,7 +1747,7 @@ mod tests {
         let cfg = Config::default();
         assert_eq!(cfg.server.port, 8091);
         assert_eq!(cfg.server.host, "0.0.0.0");
-        assert!(cfg.auth.token.is_none());
+        assert!(cfg.auth.tokens.is_empty());
         assert!(cfg.bindings.is_empty());
     }
 
@@ -693,11 +1837,40 @@ mod tests {
         assert!(binding.channel.is_empty());
         assert!(binding.account_id.is_none());
         assert!(binding.peer_id.is_none());
+        assert!(binding.thread_id.is_none());
         assert!(binding.business_profile_id.is_none());
         assert!(binding.user_id.is_none());
         assert!(binding.agent_id.is_none());
     }
 
+    #[test]
+    fn test_auth_config_accepts_any_configured_token() {
+        let auth = AuthConfig {
+            tokens: vec!["old-token".to_string(), "new-token".to_string()],
+        };
+        assert!(auth.is_enabled());
+        assert!(auth.accepts(Some("old-token")));
+        assert!(auth.accepts(Some("new-token")));
+        assert!(!auth.accepts(Some("other-token")));
+        assert!(!auth.accepts(None));
+        assert!(AuthConfig::default().accepts(None));
+    }
+
+    #[test]
+    fn test_auth_config_deserialize_token_alias_and_list() {
+        let single: AuthConfig = serde_json::from_str(r#"{"token":"abc"}"#).unwrap();
+        assert_eq!(single.tokens, vec!["abc".to_string()]);
+
+        let many: AuthConfig = serde_json::from_str(r#"{"tokens":["abc"," def ",""]}"#).unwrap();
+        assert_eq!(many.tokens, vec!["abc".to_string(), "def".to_string()]);
+
+        let null: AuthConfig = serde_json::from_str(r#"{"token":null}"#).unwrap();
+        assert!(null.tokens.is_empty());
+
+        let missing: AuthConfig = serde_json::from_str("{}").unwrap();
+        assert!(missing.tokens.is_empty());
+    }
+
     #[test]
     fn test_load_config_env_identity_links_json() {
         std::env::set_var(
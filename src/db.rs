@@ -1,6 +1,10 @@
+use crate::config::{DatabaseConfig, MEMORY_DATABASE_URL};
 use anyhow::Result;
 use chrono::{DateTime, Utc, TimeZone};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use sqlx::any::{AnyPoolOptions, AnyRow};
 use sqlx::{AnyPool, Row};
 use std::borrow::Cow;
 use uuid::Uuid;
@@ -20,6 +24,65 @@ pub fn db_kind_from_url(url: &str) -> DbKind {
     }
 }
 
+pub fn connection_setup_sql(kind: DbKind, statement_timeout_ms: Option<u64>) -> Option<String> {
+    let ms = statement_timeout_ms?;
+    Some(match kind {
+        DbKind::Postgres => format!("SET statement_timeout = {}", ms),
+        DbKind::Sqlite => format!("PRAGMA busy_timeout = {}", ms),
+    })
+}
+
+/// Pool settings from `database.*`: idle and lifetime limits (`0` disables
+/// either) and whether connections are pinged before use.
+pub fn pool_options(config: &DatabaseConfig) -> AnyPoolOptions {
+    let secs = |secs: u64| (secs > 0).then(|| std::time::Duration::from_secs(secs));
+    AnyPoolOptions::new()
+        .idle_timeout(secs(config.idle_timeout_secs))
+        .max_lifetime(secs(config.max_lifetime_secs))
+        .test_before_acquire(config.test_before_acquire)
+}
+
+pub async fn connect(url: &str, config: &DatabaseConfig) -> Result<AnyPool> {
+    let setup = connection_setup_sql(db_kind_from_url(url), config.statement_timeout_ms);
+    let mut options = pool_options(config);
+    if url == MEMORY_DATABASE_URL {
+        // Each in-memory SQLite connection is its own database, so keep exactly
+        // one open for the life of the process.
+        options = options
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None);
+    }
+    let pool = options
+        .after_connect(move |conn, _meta| {
+            let setup = setup.clone();
+            Box::pin(async move {
+                if let Some(sql) = setup {
+                    sqlx::query(&sql).execute(conn).await?;
+                }
+                Ok(())
+            })
+        })
+        .connect(url)
+        .await?;
+    Ok(pool)
+}
+
+pub fn maintenance_sql(kind: DbKind) -> &'static [&'static str] {
+    match kind {
+        DbKind::Postgres => &["VACUUM (ANALYZE)"],
+        DbKind::Sqlite => &["VACUUM", "ANALYZE", "PRAGMA optimize"],
+    }
+}
+
+pub async fn maintain(pool: &AnyPool, kind: DbKind) -> Result<()> {
+    for sql in maintenance_sql(kind) {
+        sqlx::query(sql).execute(pool).await?;
+    }
+    Ok(())
+}
+
 pub fn rewrite_sql<'a>(sql: &'a str, kind: DbKind) -> Cow<'a, str> {
     match kind {
         DbKind::Sqlite => Cow::Borrowed(sql),
@@ -49,16 +112,23 @@ pub struct SessionRecord {
     pub last_route: Option<serde_json::Value>,
     pub dm_scope: String,
     pub identity_links: Option<serde_json::Value>,
-    #[serde(skip)]
+    #[serde(default = "Utc::now")]
     pub created_at: DateTime<Utc>,
-    #[serde(skip)]
+    #[serde(default = "Utc::now")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
 }
 
+/// Direction of messages appended by the API rather than sent or received on a
+/// channel; they are never dispatched.
+pub const SYSTEM_DIRECTION: &str = "system";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageRecord {
     pub id: String,
     pub session_key: String,
+    /// `inbound`, `outbound`, or [`SYSTEM_DIRECTION`].
     pub direction: String,
     pub channel: String,
     pub account_id: Option<String>,
@@ -67,8 +137,21 @@ pub struct MessageRecord {
     pub attachments: Option<serde_json::Value>,
     pub status: String,
     pub dedupe_key: Option<String>,
-    #[serde(skip)]
+    pub provider_message_id: Option<String>,
+    #[serde(default = "Utc::now")]
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+pub const OUTBOX_MAX_RETRIES: i32 = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxStats {
+    pub pending: i64,
+    pub failed: i64,
+    pub dead: i64,
+    pub oldest_pending_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +165,54 @@ pub struct OutboxRecord {
     pub last_error: Option<String>,
     #[serde(skip)]
     pub created_at: DateTime<Utc>,
+    /// Overrides `backend.webhook_url` for this row, from the matched binding.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// The inbound message this row forwards, kept apart from `payload` since
+    /// `backend.payload_template` may leave it out.
+    #[serde(default)]
+    pub inbound_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundFailureRecord {
+    pub id: String,
+    pub payload: serde_json::Value,
+    /// The session the event was stored under, when processing got that far.
+    pub session_key: Option<String>,
+    pub status: String,
+    pub retry_count: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A failed channel send waiting to be retried. `payload` holds the resolved
+/// `route` and the original `outbound` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundQueueRecord {
+    pub message_id: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub retry_count: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An inbound attachment that could not be rehosted at `backend.media_upload_url`.
+/// `attachment` holds the original attachment as received from the channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaFailureRecord {
+    pub id: String,
+    pub message_id: String,
+    pub channel: String,
+    pub session_key: String,
+    pub attachment: serde_json::Value,
+    pub status: String,
+    pub retry_count: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 fn i64_to_datetime(ts: i64) -> DateTime<Utc> {
@@ -92,19 +223,56 @@ fn datetime_to_i64(dt: DateTime<Utc>) -> i64 {
     dt.timestamp()
 }
 
+/// Column type for JSON documents: `JSONB` on Postgres so they can be indexed
+/// and queried (`last_route->>'channel'`), `TEXT` on SQLite.
+fn json_column(kind: DbKind) -> &'static str {
+    match kind {
+        DbKind::Postgres => "JSONB",
+        DbKind::Sqlite => "TEXT",
+    }
+}
+
+/// Placeholder for binding a serialized JSON document to a `json_column`.
+fn json_param(kind: DbKind) -> &'static str {
+    match kind {
+        DbKind::Postgres => "CAST(? AS JSONB)",
+        DbKind::Sqlite => "?",
+    }
+}
+
+/// Selects a `json_column` as text, since the Any driver cannot decode JSONB.
+fn json_select(kind: DbKind, column: &str) -> String {
+    match kind {
+        DbKind::Postgres => format!("{column}::text AS {column}"),
+        DbKind::Sqlite => column.to_string(),
+    }
+}
+
+fn session_columns(kind: DbKind) -> String {
+    format!(
+        "session_key, agent_id, business_profile_id, user_id, {}, dm_scope, {}, created_at, updated_at, metadata",
+        json_select(kind, "last_route"),
+        json_select(kind, "identity_links"),
+    )
+}
+
 pub async fn init_db(pool: &AnyPool, kind: DbKind) -> Result<()> {
-    let stmts = vec![
+    let sessions_table = format!(
         r#"CREATE TABLE IF NOT EXISTS sessions (
             session_key TEXT PRIMARY KEY,
             agent_id TEXT NOT NULL,
             business_profile_id TEXT,
             user_id TEXT,
-            last_route TEXT,
+            last_route {json},
             dm_scope TEXT NOT NULL,
-            identity_links TEXT,
+            identity_links {json},
             created_at INTEGER NOT NULL,
             updated_at INTEGER NOT NULL
         )"#,
+        json = json_column(kind),
+    );
+    let stmts = vec![
+        sessions_table.as_str(),
         r#"CREATE TABLE IF NOT EXISTS messages (
             id TEXT PRIMARY KEY,
             session_key TEXT NOT NULL,
@@ -116,10 +284,10 @@ pub async fn init_db(pool: &AnyPool, kind: DbKind) -> Result<()> {
             attachments TEXT,
             status TEXT NOT NULL,
             dedupe_key TEXT,
+            provider_message_id TEXT,
             created_at INTEGER NOT NULL
         )"#,
         r#"CREATE INDEX IF NOT EXISTS idx_messages_session ON messages(session_key, created_at)"#,
-        r#"CREATE INDEX IF NOT EXISTS idx_messages_dedupe ON messages(dedupe_key)"#,
         r#"CREATE TABLE IF NOT EXISTS deliveries (
             id TEXT PRIMARY KEY,
             message_id TEXT NOT NULL,
@@ -139,6 +307,38 @@ pub async fn init_db(pool: &AnyPool, kind: DbKind) -> Result<()> {
             created_at INTEGER NOT NULL
         )"#,
         r#"CREATE INDEX IF NOT EXISTS idx_outbox_status ON inbound_outbox(status, next_attempt_at)"#,
+        r#"CREATE TABLE IF NOT EXISTS inbound_failures (
+            id TEXT PRIMARY KEY,
+            payload TEXT NOT NULL,
+            status TEXT NOT NULL,
+            retry_count INTEGER NOT NULL,
+            next_attempt_at INTEGER NOT NULL,
+            last_error TEXT,
+            created_at INTEGER NOT NULL
+        )"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_inbound_failures_status ON inbound_failures(status, next_attempt_at)"#,
+        r#"CREATE TABLE IF NOT EXISTS outbound_queue (
+            message_id TEXT PRIMARY KEY,
+            payload TEXT NOT NULL,
+            status TEXT NOT NULL,
+            retry_count INTEGER NOT NULL,
+            next_attempt_at INTEGER NOT NULL,
+            last_error TEXT,
+            created_at INTEGER NOT NULL
+        )"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_outbound_queue_status ON outbound_queue(status, next_attempt_at)"#,
+        r#"CREATE TABLE IF NOT EXISTS media_failures (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL,
+            channel TEXT NOT NULL,
+            session_key TEXT NOT NULL,
+            attachment TEXT NOT NULL,
+            status TEXT NOT NULL,
+            retry_count INTEGER NOT NULL,
+            last_error TEXT,
+            created_at INTEGER NOT NULL
+        )"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_media_failures_status ON media_failures(status, created_at)"#,
         r#"CREATE TABLE IF NOT EXISTS pairing_requests (
             id TEXT PRIMARY KEY,
             channel TEXT NOT NULL,
@@ -155,14 +355,109 @@ pub async fn init_db(pool: &AnyPool, kind: DbKind) -> Result<()> {
         sqlx::query(sql.as_ref()).execute(pool).await?;
     }
 
+    migrate_unique_dedupe(pool, kind).await?;
+    ensure_column(pool, kind, "messages", "provider_message_id", "TEXT").await?;
+    ensure_column(pool, kind, "messages", "metadata", "TEXT").await?;
+    ensure_column(pool, kind, "sessions", "metadata", "TEXT").await?;
+    ensure_column(pool, kind, "inbound_outbox", "webhook_url", "TEXT").await?;
+    ensure_column(pool, kind, "inbound_outbox", "inbound_id", "TEXT").await?;
+    ensure_column(pool, kind, "inbound_failures", "session_key", "TEXT").await?;
+    ensure_column(pool, kind, "media_failures", "next_attempt_at", "INTEGER").await?;
+    if kind == DbKind::Postgres {
+        migrate_to_jsonb(pool, "sessions", "last_route").await?;
+        migrate_to_jsonb(pool, "sessions", "identity_links").await?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the non-unique dedupe index of older versions with a unique one,
+/// first clearing the key on all but the earliest row of each duplicate (by
+/// `created_at`, then `id`). Runs once: nothing happens when the unique index
+/// already exists.
+async fn migrate_unique_dedupe(pool: &AnyPool, kind: DbKind) -> Result<()> {
+    let exists_sql = match kind {
+        DbKind::Postgres => {
+            "SELECT 1 FROM pg_indexes WHERE schemaname = current_schema() AND indexname = $1"
+        }
+        DbKind::Sqlite => "SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?",
+    };
+    let exists = sqlx::query(exists_sql)
+        .bind("idx_messages_dedupe_unique")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    if exists {
+        return Ok(());
+    }
+    let stmts = [
+        r#"DROP INDEX IF EXISTS idx_messages_dedupe"#,
+        r#"UPDATE messages SET dedupe_key = NULL
+           WHERE dedupe_key IS NOT NULL
+             AND EXISTS (
+               SELECT 1 FROM messages earlier
+               WHERE earlier.dedupe_key = messages.dedupe_key
+                 AND (earlier.created_at < messages.created_at
+                      OR (earlier.created_at = messages.created_at AND earlier.id < messages.id)))"#,
+        r#"CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_dedupe_unique ON messages(dedupe_key)"#,
+    ];
+    for stmt in stmts {
+        sqlx::query(stmt).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// Converts a Postgres `TEXT` column created by an older version to `JSONB`.
+async fn migrate_to_jsonb(pool: &AnyPool, table: &str, column: &str) -> Result<()> {
+    let row = sqlx::query(
+        "SELECT data_type::text AS data_type FROM information_schema.columns
+         WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+    let data_type: Option<String> = row.map(|row| row.try_get("data_type")).transpose()?;
+    if data_type.as_deref() == Some("text") {
+        let sql = format!("ALTER TABLE {table} ALTER COLUMN {column} TYPE JSONB USING {column}::jsonb");
+        sqlx::query(&sql).execute(pool).await?;
+    }
+    Ok(())
+}
+
+async fn ensure_column(
+    pool: &AnyPool,
+    kind: DbKind,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<()> {
+    match kind {
+        DbKind::Postgres => {
+            let sql = format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {column} {decl}");
+            sqlx::query(&sql).execute(pool).await?;
+        }
+        DbKind::Sqlite => {
+            let exists = sqlx::query("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")
+                .bind(table)
+                .bind(column)
+                .fetch_optional(pool)
+                .await?
+                .is_some();
+            if !exists {
+                let sql = format!("ALTER TABLE {table} ADD COLUMN {column} {decl}");
+                sqlx::query(&sql).execute(pool).await?;
+            }
+        }
+    }
     Ok(())
 }
 
 pub async fn upsert_session(pool: &AnyPool, kind: DbKind, record: &SessionRecord) -> Result<()> {
-    let sql = rewrite_sql(
+    let sql = format!(
         r#"INSERT INTO sessions (
-            session_key, agent_id, business_profile_id, user_id, last_route, dm_scope, identity_links, created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            session_key, agent_id, business_profile_id, user_id, last_route, dm_scope, identity_links, created_at, updated_at, metadata
+        ) VALUES (?, ?, ?, ?, {json}, ?, {json}, ?, ?, ?)
         ON CONFLICT(session_key) DO UPDATE SET
             agent_id=excluded.agent_id,
             business_profile_id=excluded.business_profile_id,
@@ -170,9 +465,11 @@ pub async fn upsert_session(pool: &AnyPool, kind: DbKind, record: &SessionRecord
             last_route=excluded.last_route,
             dm_scope=excluded.dm_scope,
             identity_links=excluded.identity_links,
-            updated_at=excluded.updated_at"#,
-        kind,
+            updated_at=excluded.updated_at,
+            metadata=COALESCE(excluded.metadata, sessions.metadata)"#,
+        json = json_param(kind),
     );
+    let sql = rewrite_sql(&sql, kind);
     sqlx::query(sql.as_ref())
         .bind(&record.session_key)
         .bind(&record.agent_id)
@@ -183,19 +480,50 @@ pub async fn upsert_session(pool: &AnyPool, kind: DbKind, record: &SessionRecord
         .bind(record.identity_links.as_ref().map(|v| v.to_string()))
         .bind(datetime_to_i64(record.created_at))
         .bind(datetime_to_i64(record.updated_at))
+        .bind(record.metadata.as_ref().map(|v| v.to_string()))
         .execute(pool)
         .await?;
     Ok(())
 }
 
-pub async fn insert_message(pool: &AnyPool, kind: DbKind, record: &MessageRecord) -> Result<()> {
+pub async fn set_session_route(
+    pool: &AnyPool,
+    kind: DbKind,
+    session_key: &str,
+    route: &serde_json::Value,
+    updated_at: DateTime<Utc>,
+) -> Result<bool> {
+    let sql = format!(
+        "UPDATE sessions SET last_route = {}, updated_at = ? WHERE session_key = ?",
+        json_param(kind)
+    );
+    let sql = rewrite_sql(&sql, kind);
+    let result = sqlx::query(sql.as_ref())
+        .bind(route.to_string())
+        .bind(datetime_to_i64(updated_at))
+        .bind(session_key)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Inserts `record`, returning `false` when its dedupe key is already stored.
+/// Takes any executor so it can share a transaction with `insert_outbox`.
+pub async fn insert_message<'e, E>(
+    executor: E,
+    kind: DbKind,
+    record: &MessageRecord,
+) -> Result<bool>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
     let sql = rewrite_sql(
         r#"INSERT INTO messages (
-            id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, created_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         kind,
     );
-    sqlx::query(sql.as_ref())
+    let result = sqlx::query(sql.as_ref())
         .bind(&record.id)
         .bind(&record.session_key)
         .bind(&record.direction)
@@ -206,10 +534,20 @@ pub async fn insert_message(pool: &AnyPool, kind: DbKind, record: &MessageRecord
         .bind(record.attachments.as_ref().map(|v| v.to_string()))
         .bind(&record.status)
         .bind(record.dedupe_key.as_deref())
+        .bind(record.provider_message_id.as_deref())
         .bind(datetime_to_i64(record.created_at))
-        .execute(pool)
-        .await?;
-    Ok(())
+        .bind(record.metadata.as_ref().map(|v| v.to_string()))
+        .execute(executor)
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(sqlx::Error::Database(err))
+            if err.is_unique_violation() && record.dedupe_key.is_some() =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err.into()),
+    }
 }
 
 pub async fn message_dedupe_exists(pool: &AnyPool, kind: DbKind, dedupe_key: &str) -> Result<bool> {
@@ -221,105 +559,420 @@ pub async fn message_dedupe_exists(pool: &AnyPool, kind: DbKind, dedupe_key: &st
     Ok(row.is_some())
 }
 
+/// Maps a row selected with `session_columns`.
+fn session_from_row(row: &AnyRow) -> Result<SessionRecord> {
+    let last_route: Option<String> = row.try_get("last_route")?;
+    let identity_links: Option<String> = row.try_get("identity_links")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    let updated_at: i64 = row.try_get("updated_at")?;
+    let metadata: Option<String> = row.try_get("metadata")?;
+    Ok(SessionRecord {
+        session_key: row.try_get("session_key")?,
+        agent_id: row.try_get("agent_id")?,
+        business_profile_id: row.try_get("business_profile_id")?,
+        user_id: row.try_get("user_id")?,
+        last_route: last_route.and_then(|v| serde_json::from_str(&v).ok()),
+        dm_scope: row.try_get("dm_scope")?,
+        identity_links: identity_links.and_then(|v| serde_json::from_str(&v).ok()),
+        created_at: i64_to_datetime(created_at),
+        updated_at: i64_to_datetime(updated_at),
+        metadata: metadata.and_then(|v| serde_json::from_str(&v).ok()),
+    })
+}
+
 pub async fn list_sessions(pool: &AnyPool, kind: DbKind, limit: i64, offset: i64) -> Result<Vec<SessionRecord>> {
-    let sql = rewrite_sql(
-        r#"SELECT session_key, agent_id, business_profile_id, user_id, last_route, dm_scope, identity_links, created_at, updated_at
-           FROM sessions ORDER BY updated_at DESC LIMIT ? OFFSET ?"#,
-        kind,
+    let sql = format!(
+        "SELECT {} FROM sessions ORDER BY updated_at DESC LIMIT ? OFFSET ?",
+        session_columns(kind)
     );
+    let sql = rewrite_sql(&sql, kind);
     let rows = sqlx::query(sql.as_ref())
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
         .await?;
+    rows.iter().map(session_from_row).collect()
+}
 
-    let mut result = Vec::new();
-    for row in rows {
-        let last_route: Option<String> = row.try_get("last_route")?;
-        let identity_links: Option<String> = row.try_get("identity_links")?;
-        let created_at: i64 = row.try_get("created_at")?;
-        let updated_at: i64 = row.try_get("updated_at")?;
-        result.push(SessionRecord {
-            session_key: row.try_get("session_key")?,
-            agent_id: row.try_get("agent_id")?,
-            business_profile_id: row.try_get("business_profile_id")?,
-            user_id: row.try_get("user_id")?,
-            last_route: last_route.and_then(|v| serde_json::from_str(&v).ok()),
-            dm_scope: row.try_get("dm_scope")?,
-            identity_links: identity_links.and_then(|v| serde_json::from_str(&v).ok()),
-            created_at: i64_to_datetime(created_at),
-            updated_at: i64_to_datetime(updated_at),
-        });
+pub async fn get_session(pool: &AnyPool, kind: DbKind, session_key: &str) -> Result<Option<SessionRecord>> {
+    let sql = format!("SELECT {} FROM sessions WHERE session_key = ?", session_columns(kind));
+    let sql = rewrite_sql(&sql, kind);
+    let row = sqlx::query(sql.as_ref())
+        .bind(session_key)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(session_from_row).transpose()
+}
+
+/// Sessions among `session_keys` in one `IN (...)` query; missing keys are
+/// skipped. Callers bound the list, since every key is a bind parameter.
+pub async fn get_sessions_by_keys(
+    pool: &AnyPool,
+    kind: DbKind,
+    session_keys: &[String],
+) -> Result<Vec<SessionRecord>> {
+    if session_keys.is_empty() {
+        return Ok(Vec::new());
     }
-    Ok(result)
+    let placeholders = session_keys
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(",");
+    let sql = format!(
+        "SELECT {} FROM sessions WHERE session_key IN ({})",
+        session_columns(kind),
+        placeholders
+    );
+    let sql = rewrite_sql(&sql, kind);
+    let mut query = sqlx::query(sql.as_ref());
+    for key in session_keys {
+        query = query.bind(key);
+    }
+    let rows = query.fetch_all(pool).await?;
+    rows.iter().map(session_from_row).collect()
 }
 
-pub async fn get_session(pool: &AnyPool, kind: DbKind, session_key: &str) -> Result<Option<SessionRecord>> {
+const IDENTITY_SCAN_BATCH: i64 = 100;
+
+/// Most recently updated session whose stored `identity_links` name
+/// `canonical_id` and that `is_linked` accepts. Sessions carry the whole link
+/// map, so the caller checks that the session's own route is one of the ids
+/// linked to `canonical_id`.
+pub async fn find_session_by_identity(
+    pool: &AnyPool,
+    kind: DbKind,
+    canonical_id: &str,
+    is_linked: impl Fn(&SessionRecord) -> bool,
+) -> Result<Option<SessionRecord>> {
+    let canonical = canonical_id.trim().to_lowercase();
+    if canonical.is_empty() {
+        return Ok(None);
+    }
+    let sql = format!(
+        "SELECT {} FROM sessions WHERE LOWER(CAST(identity_links AS TEXT)) LIKE ?
+         ORDER BY updated_at DESC, session_key LIMIT ? OFFSET ?",
+        session_columns(kind)
+    );
+    let sql = rewrite_sql(&sql, kind);
+    let pattern = format!("%{}%", serde_json::Value::String(canonical));
+    let mut offset = 0;
+    loop {
+        let rows = sqlx::query(sql.as_ref())
+            .bind(&pattern)
+            .bind(IDENTITY_SCAN_BATCH)
+            .bind(offset)
+            .fetch_all(pool)
+            .await?;
+        for row in &rows {
+            let session = session_from_row(row)?;
+            if is_linked(&session) {
+                return Ok(Some(session));
+            }
+        }
+        if (rows.len() as i64) < IDENTITY_SCAN_BATCH {
+            return Ok(None);
+        }
+        offset += IDENTITY_SCAN_BATCH;
+    }
+}
+
+pub async fn list_messages(
+    pool: &AnyPool,
+    kind: DbKind,
+    session_key: &str,
+    after: Option<DateTime<Utc>>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<MessageRecord>> {
+    let base_sql = if after.is_some() {
+        r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+           FROM messages WHERE session_key = ? AND created_at > ? ORDER BY created_at ASC LIMIT ? OFFSET ?"#
+    } else {
+        r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+           FROM messages WHERE session_key = ? ORDER BY created_at DESC LIMIT ? OFFSET ?"#
+    };
+    let sql = rewrite_sql(base_sql, kind);
+    let mut query = sqlx::query(sql.as_ref()).bind(session_key);
+    if let Some(after) = after {
+        query = query.bind(datetime_to_i64(after));
+    }
+    let rows = query.bind(limit).bind(offset).fetch_all(pool).await?;
+
+    rows.iter().map(message_from_row).collect()
+}
+
+pub fn stream_messages(
+    pool: AnyPool,
+    kind: DbKind,
+    session_key: String,
+) -> mpsc::Receiver<Result<MessageRecord>> {
+    let (mut tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let sql = rewrite_sql(
+            r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+               FROM messages WHERE session_key = ? ORDER BY created_at ASC"#,
+            kind,
+        );
+        let mut rows = sqlx::query(sql.as_ref()).bind(&session_key).fetch(&pool);
+        while let Some(row) = rows.next().await {
+            let item = row
+                .map_err(anyhow::Error::from)
+                .and_then(|row| message_from_row(&row));
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn message_from_row(row: &AnyRow) -> Result<MessageRecord> {
+    let attachments: Option<String> = row.try_get("attachments")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    let metadata: Option<String> = row.try_get("metadata")?;
+    Ok(MessageRecord {
+        id: row.try_get("id")?,
+        session_key: row.try_get("session_key")?,
+        direction: row.try_get("direction")?,
+        channel: row.try_get("channel")?,
+        account_id: row.try_get("account_id")?,
+        peer_id: row.try_get("peer_id")?,
+        content: row.try_get("content")?,
+        attachments: attachments.and_then(|v| serde_json::from_str(&v).ok()),
+        status: row.try_get("status")?,
+        dedupe_key: row.try_get("dedupe_key")?,
+        provider_message_id: row.try_get("provider_message_id")?,
+        created_at: i64_to_datetime(created_at),
+        metadata: metadata.and_then(|v| serde_json::from_str(&v).ok()),
+    })
+}
+
+pub async fn get_message(pool: &AnyPool, kind: DbKind, id: &str) -> Result<Option<MessageRecord>> {
     let sql = rewrite_sql(
-        r#"SELECT session_key, agent_id, business_profile_id, user_id, last_route, dm_scope, identity_links, created_at, updated_at
-           FROM sessions WHERE session_key = ?"#,
+        r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+           FROM messages WHERE id = ?"#,
         kind,
     );
     let row = sqlx::query(sql.as_ref())
-        .bind(session_key)
+        .bind(id)
         .fetch_optional(pool)
         .await?;
+    row.as_ref().map(message_from_row).transpose()
+}
+
+/// Deletes a session with its messages, their deliveries, queued send retries
+/// and any pending media and inbound failures in one transaction, so no retry
+/// worker replays traffic for it afterwards. Returns whether the session existed.
+pub async fn delete_session(pool: &AnyPool, kind: DbKind, session_key: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    for stmt in [
+        "DELETE FROM deliveries WHERE message_id IN (SELECT id FROM messages WHERE session_key = ?)",
+        "DELETE FROM outbound_queue WHERE message_id IN (SELECT id FROM messages WHERE session_key = ?)",
+        "DELETE FROM media_failures WHERE session_key = ?",
+        "DELETE FROM inbound_failures WHERE session_key = ?",
+        "DELETE FROM messages WHERE session_key = ?",
+    ] {
+        let sql = rewrite_sql(stmt, kind);
+        sqlx::query(sql.as_ref()).bind(session_key).execute(&mut *tx).await?;
+    }
+    let sql = rewrite_sql("DELETE FROM sessions WHERE session_key = ?", kind);
+    let result = sqlx::query(sql.as_ref())
+        .bind(session_key)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
 
-    if let Some(row) = row {
-        let last_route: Option<String> = row.try_get("last_route")?;
-        let identity_links: Option<String> = row.try_get("identity_links")?;
-        let created_at: i64 = row.try_get("created_at")?;
-        let updated_at: i64 = row.try_get("updated_at")?;
-        return Ok(Some(SessionRecord {
-            session_key: row.try_get("session_key")?,
-            agent_id: row.try_get("agent_id")?,
-            business_profile_id: row.try_get("business_profile_id")?,
-            user_id: row.try_get("user_id")?,
-            last_route: last_route.and_then(|v| serde_json::from_str(&v).ok()),
-            dm_scope: row.try_get("dm_scope")?,
-            identity_links: identity_links.and_then(|v| serde_json::from_str(&v).ok()),
-            created_at: i64_to_datetime(created_at),
-            updated_at: i64_to_datetime(updated_at),
-        }));
+/// Deletes sessions last updated before `idle_before` and, when `max_sessions`
+/// is non-zero, the least recently updated sessions beyond that cap. Returns the
+/// evicted session keys.
+pub async fn evict_sessions(
+    pool: &AnyPool,
+    kind: DbKind,
+    max_sessions: u64,
+    idle_before: Option<DateTime<Utc>>,
+) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    if let Some(cutoff) = idle_before {
+        let sql = rewrite_sql(
+            "SELECT session_key FROM sessions WHERE updated_at < ?",
+            kind,
+        );
+        let rows = sqlx::query(sql.as_ref())
+            .bind(datetime_to_i64(cutoff))
+            .fetch_all(pool)
+            .await?;
+        for row in rows {
+            keys.push(row.try_get::<String, _>("session_key")?);
+        }
+    }
+    if max_sessions > 0 {
+        let row = sqlx::query("SELECT COUNT(*) AS total FROM sessions")
+            .fetch_one(pool)
+            .await?;
+        let remaining = row.try_get::<i64, _>("total")? - keys.len() as i64;
+        let excess = remaining - max_sessions as i64;
+        if excess > 0 {
+            let sql = rewrite_sql(
+                "SELECT session_key FROM sessions ORDER BY updated_at ASC, session_key ASC LIMIT ?",
+                kind,
+            );
+            let rows = sqlx::query(sql.as_ref())
+                .bind(excess + keys.len() as i64)
+                .fetch_all(pool)
+                .await?;
+            for row in rows {
+                let key = row.try_get::<String, _>("session_key")?;
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+    let mut evicted = Vec::new();
+    for key in keys {
+        if delete_session(pool, kind, &key).await? {
+            evicted.push(key);
+        }
     }
-    Ok(None)
+    Ok(evicted)
 }
 
-pub async fn list_messages(pool: &AnyPool, kind: DbKind, session_key: &str, limit: i64, offset: i64) -> Result<Vec<MessageRecord>> {
+pub async fn set_message_provider_id(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    provider_message_id: &str,
+) -> Result<()> {
     let sql = rewrite_sql(
-        r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, created_at
-           FROM messages WHERE session_key = ? ORDER BY created_at DESC LIMIT ? OFFSET ?"#,
+        "UPDATE messages SET provider_message_id = ? WHERE id = ?",
         kind,
     );
-    let rows = sqlx::query(sql.as_ref())
-        .bind(session_key)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(pool)
+    sqlx::query(sql.as_ref())
+        .bind(provider_message_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn update_message_attachments(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    attachments: &serde_json::Value,
+) -> Result<()> {
+    let sql = rewrite_sql("UPDATE messages SET attachments = ? WHERE id = ?", kind);
+    sqlx::query(sql.as_ref())
+        .bind(attachments.to_string())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn update_message_content(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    content: &str,
+) -> Result<()> {
+    let sql = rewrite_sql("UPDATE messages SET content = ? WHERE id = ?", kind);
+    sqlx::query(sql.as_ref())
+        .bind(content)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn find_message_by_provider_id(
+    pool: &AnyPool,
+    kind: DbKind,
+    channel: &str,
+    peer_id: &str,
+    provider_message_id: &str,
+) -> Result<Option<MessageRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, session_key, direction, channel, account_id, peer_id, content, attachments, status, dedupe_key, provider_message_id, created_at, metadata
+           FROM messages WHERE channel = ? AND peer_id = ? AND provider_message_id = ?
+           ORDER BY created_at DESC LIMIT 1"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(channel)
+        .bind(peer_id)
+        .bind(provider_message_id)
+        .fetch_optional(pool)
         .await?;
+    row.as_ref().map(message_from_row).transpose()
+}
+
+pub async fn set_message_status(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    status: &str,
+) -> Result<()> {
+    let sql = rewrite_sql("UPDATE messages SET status = ? WHERE id = ?", kind);
+    sqlx::query(sql.as_ref())
+        .bind(status)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
-    let mut result = Vec::new();
-    for row in rows {
-        let attachments: Option<String> = row.try_get("attachments")?;
-        let created_at: i64 = row.try_get("created_at")?;
-        result.push(MessageRecord {
-            id: row.try_get("id")?,
-            session_key: row.try_get("session_key")?,
-            direction: row.try_get("direction")?,
-            channel: row.try_get("channel")?,
-            account_id: row.try_get("account_id")?,
-            peer_id: row.try_get("peer_id")?,
-            content: row.try_get("content")?,
-            attachments: attachments.and_then(|v| serde_json::from_str(&v).ok()),
-            status: row.try_get("status")?,
-            dedupe_key: row.try_get("dedupe_key")?,
-            created_at: i64_to_datetime(created_at),
-        });
+pub async fn get_message_created_at(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let sql = rewrite_sql("SELECT created_at FROM messages WHERE id = ?", kind);
+    let row = sqlx::query(sql.as_ref())
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    match row {
+        Some(row) => Ok(Some(i64_to_datetime(row.try_get("created_at")?))),
+        None => Ok(None),
     }
-    Ok(result)
 }
 
-pub async fn insert_outbox(pool: &AnyPool, kind: DbKind, payload: serde_json::Value, next_attempt_at: DateTime<Utc>) -> Result<OutboxRecord> {
+/// When `session_key` last received an inbound message on `channel`.
+pub async fn last_inbound_at(
+    pool: &AnyPool,
+    kind: DbKind,
+    session_key: &str,
+    channel: &str,
+) -> Result<Option<DateTime<Utc>>> {
+    let sql = rewrite_sql(
+        "SELECT MAX(created_at) AS last_at FROM messages \
+         WHERE session_key = ? AND channel = ? AND direction = 'inbound'",
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(session_key)
+        .bind(channel)
+        .fetch_one(pool)
+        .await?;
+    let last_at: Option<i64> = row.try_get("last_at")?;
+    Ok(last_at.map(i64_to_datetime))
+}
+
+pub async fn insert_outbox<'e, E>(
+    executor: E,
+    kind: DbKind,
+    payload: serde_json::Value,
+    next_attempt_at: DateTime<Utc>,
+    webhook_url: Option<&str>,
+    inbound_id: Option<&str>,
+) -> Result<OutboxRecord>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
     let record = OutboxRecord {
         id: Uuid::new_v4().to_string(),
         payload: payload.clone(),
@@ -328,10 +981,12 @@ pub async fn insert_outbox(pool: &AnyPool, kind: DbKind, payload: serde_json::Va
         next_attempt_at,
         last_error: None,
         created_at: Utc::now(),
+        webhook_url: webhook_url.map(str::to_string),
+        inbound_id: inbound_id.map(str::to_string),
     };
     let sql = rewrite_sql(
-        r#"INSERT INTO inbound_outbox (id, payload, status, retry_count, next_attempt_at, last_error, created_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+        r#"INSERT INTO inbound_outbox (id, payload, status, retry_count, next_attempt_at, last_error, created_at, webhook_url, inbound_id)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         kind,
     );
     sqlx::query(sql.as_ref())
@@ -342,7 +997,9 @@ pub async fn insert_outbox(pool: &AnyPool, kind: DbKind, payload: serde_json::Va
         .bind(datetime_to_i64(record.next_attempt_at))
         .bind(record.last_error.as_deref())
         .bind(datetime_to_i64(record.created_at))
-        .execute(pool)
+        .bind(record.webhook_url.as_deref())
+        .bind(record.inbound_id.as_deref())
+        .execute(executor)
         .await?;
     Ok(record)
 }
@@ -350,7 +1007,7 @@ pub async fn insert_outbox(pool: &AnyPool, kind: DbKind, payload: serde_json::Va
 pub async fn claim_outbox_batch(pool: &AnyPool, kind: DbKind, now: DateTime<Utc>, limit: i64) -> Result<Vec<OutboxRecord>> {
     let now_i64 = datetime_to_i64(now);
     let sql = rewrite_sql(
-        r#"SELECT id, payload, status, retry_count, next_attempt_at, last_error, created_at
+        r#"SELECT id, payload, status, retry_count, next_attempt_at, last_error, created_at, webhook_url, inbound_id
            FROM inbound_outbox
            WHERE status IN ('pending','failed') AND next_attempt_at <= ?
            ORDER BY created_at ASC
@@ -363,28 +1020,17 @@ pub async fn claim_outbox_batch(pool: &AnyPool, kind: DbKind, now: DateTime<Utc>
         .fetch_all(pool)
         .await?;
 
-    let mut result = Vec::new();
-    for row in rows {
-        let payload: String = row.try_get("payload")?;
-        let next_attempt_at: i64 = row.try_get("next_attempt_at")?;
-        let created_at: i64 = row.try_get("created_at")?;
-        result.push(OutboxRecord {
-            id: row.try_get("id")?,
-            payload: serde_json::from_str(&payload).unwrap_or_else(|_| serde_json::json!({})),
-            status: row.try_get("status")?,
-            retry_count: row.try_get::<i64, _>("retry_count")? as i32,
-            next_attempt_at: i64_to_datetime(next_attempt_at),
-            last_error: row.try_get("last_error")?,
-            created_at: i64_to_datetime(created_at),
-        });
-    }
+    let result = rows
+        .iter()
+        .map(outbox_from_row)
+        .collect::<Result<Vec<_>>>()?;
 
     if !result.is_empty() {
         let ids: Vec<String> = result.iter().map(|r| r.id.clone()).collect();
         let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-        let base_sql = format!("UPDATE inbound_outbox SET status='sending', last_error=NULL WHERE id IN ({})", placeholders);
+        let base_sql = format!("UPDATE inbound_outbox SET status='sending', last_error=NULL, next_attempt_at=? WHERE id IN ({})", placeholders);
         let update_sql = rewrite_sql(&base_sql, kind);
-        let mut query = sqlx::query(update_sql.as_ref());
+        let mut query = sqlx::query(update_sql.as_ref()).bind(now_i64);
         for id in ids {
             query = query.bind(id);
         }
@@ -394,6 +1040,104 @@ pub async fn claim_outbox_batch(pool: &AnyPool, kind: DbKind, now: DateTime<Utc>
     Ok(result)
 }
 
+pub async fn reclaim_stale_sending(
+    pool: &AnyPool,
+    kind: DbKind,
+    older_than: DateTime<Utc>,
+) -> Result<u64> {
+    let sql = rewrite_sql(
+        "UPDATE inbound_outbox SET status='pending' WHERE status='sending' AND next_attempt_at <= ?",
+        kind,
+    );
+    let result = sqlx::query(sql.as_ref())
+        .bind(datetime_to_i64(older_than))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+fn outbox_from_row(row: &AnyRow) -> Result<OutboxRecord> {
+    let payload: String = row.try_get("payload")?;
+    let next_attempt_at: i64 = row.try_get("next_attempt_at")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    Ok(OutboxRecord {
+        id: row.try_get("id")?,
+        payload: serde_json::from_str(&payload).unwrap_or_else(|_| serde_json::json!({})),
+        status: row.try_get("status")?,
+        retry_count: row.try_get::<i64, _>("retry_count")? as i32,
+        next_attempt_at: i64_to_datetime(next_attempt_at),
+        last_error: row.try_get("last_error")?,
+        created_at: i64_to_datetime(created_at),
+        webhook_url: row.try_get("webhook_url")?,
+        inbound_id: row.try_get("inbound_id")?,
+    })
+}
+
+pub async fn get_outbox_by_id(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+) -> Result<Option<OutboxRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, payload, status, retry_count, next_attempt_at, last_error, created_at, webhook_url, inbound_id
+           FROM inbound_outbox WHERE id = ?"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(outbox_from_row).transpose()
+}
+
+/// Marks an outbox row delivered if its status is still one of `from`, so an
+/// ack and the worker cannot overwrite each other's result. Returns whether a
+/// row changed.
+pub async fn settle_outbox_delivered(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    from: &[&str],
+) -> Result<bool> {
+    let placeholders = from.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let base_sql =
+        format!("UPDATE inbound_outbox SET status='delivered' WHERE id = ? AND status IN ({placeholders})");
+    let sql = rewrite_sql(&base_sql, kind);
+    let mut query = sqlx::query(sql.as_ref()).bind(id);
+    for status in from {
+        query = query.bind(*status);
+    }
+    Ok(query.execute(pool).await?.rows_affected() > 0)
+}
+
+/// Like `mark_outbox_failed`, but only for a row whose status is still one of
+/// `from`. Returns whether a row changed.
+pub async fn settle_outbox_failed(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    from: &[&str],
+    retry_count: i32,
+    next_attempt_at: DateTime<Utc>,
+    error: &str,
+) -> Result<bool> {
+    let placeholders = from.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let base_sql = format!(
+        "UPDATE inbound_outbox SET status='failed', retry_count=?, next_attempt_at=?, last_error=?
+         WHERE id=? AND status IN ({placeholders})"
+    );
+    let sql = rewrite_sql(&base_sql, kind);
+    let mut query = sqlx::query(sql.as_ref())
+        .bind(retry_count)
+        .bind(datetime_to_i64(next_attempt_at))
+        .bind(error)
+        .bind(id);
+    for status in from {
+        query = query.bind(*status);
+    }
+    Ok(query.execute(pool).await?.rows_affected() > 0)
+}
+
 pub async fn mark_outbox_delivered(pool: &AnyPool, kind: DbKind, id: &str) -> Result<()> {
     let sql = rewrite_sql("UPDATE inbound_outbox SET status='delivered' WHERE id = ?", kind);
     sqlx::query(sql.as_ref()).bind(id).execute(pool).await?;
@@ -413,4 +1157,650 @@ pub async fn mark_outbox_failed(pool: &AnyPool, kind: DbKind, id: &str, retry_co
         .execute(pool)
         .await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Deletes outbox rows created before `cutoff` whose status is in `statuses`.
+/// `dead` selects failed rows that have used up their retries.
+pub async fn prune_outbox(
+    pool: &AnyPool,
+    kind: DbKind,
+    cutoff: DateTime<Utc>,
+    statuses: &[&str],
+) -> Result<u64> {
+    if statuses.is_empty() {
+        return Ok(0);
+    }
+    let conditions = statuses
+        .iter()
+        .map(|status| match *status {
+            "dead" => "(status = 'failed' AND retry_count >= ?)",
+            _ => "status = ?",
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    let base_sql = format!("DELETE FROM inbound_outbox WHERE created_at < ? AND ({conditions})");
+    let sql = rewrite_sql(&base_sql, kind);
+    let mut query = sqlx::query(sql.as_ref()).bind(datetime_to_i64(cutoff));
+    for status in statuses {
+        query = match *status {
+            "dead" => query.bind(OUTBOX_MAX_RETRIES),
+            other => query.bind(other),
+        };
+    }
+    Ok(query.execute(pool).await?.rows_affected())
+}
+
+pub async fn outbox_stats(pool: &AnyPool, kind: DbKind) -> Result<OutboxStats> {
+    let sql = rewrite_sql(
+        r#"SELECT
+            COUNT(CASE WHEN status IN ('pending','sending') THEN 1 END) AS pending,
+            COUNT(CASE WHEN status = 'failed' AND retry_count < ? THEN 1 END) AS failed,
+            COUNT(CASE WHEN status = 'failed' AND retry_count >= ? THEN 1 END) AS dead,
+            MIN(CASE WHEN status IN ('pending','sending') OR (status = 'failed' AND retry_count < ?)
+                THEN created_at END) AS oldest_pending_at
+           FROM inbound_outbox"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(OUTBOX_MAX_RETRIES)
+        .bind(OUTBOX_MAX_RETRIES)
+        .bind(OUTBOX_MAX_RETRIES)
+        .fetch_one(pool)
+        .await?;
+    let oldest: Option<i64> = row.try_get("oldest_pending_at")?;
+    Ok(OutboxStats {
+        pending: row.try_get("pending")?,
+        failed: row.try_get("failed")?,
+        dead: row.try_get("dead")?,
+        oldest_pending_at: oldest.map(i64_to_datetime),
+    })
+}
+
+/// A table of rows that are retried in place: `status` moves from a waiting
+/// status to `claimed` while an attempt runs, with `next_attempt_at` doubling
+/// as the claim's lease.
+struct RetryTable {
+    name: &'static str,
+    key: &'static str,
+    claimed: &'static str,
+}
+
+const INBOUND_FAILURES: RetryTable = RetryTable {
+    name: "inbound_failures",
+    key: "id",
+    claimed: "retrying",
+};
+
+const OUTBOUND_QUEUE: RetryTable = RetryTable {
+    name: "outbound_queue",
+    key: "message_id",
+    claimed: "sending",
+};
+
+const MEDIA_FAILURES: RetryTable = RetryTable {
+    name: "media_failures",
+    key: "id",
+    claimed: "retrying",
+};
+
+/// Moves a row in `statuses`, or a claimed row whose lease has run out, to the
+/// table's claimed status until `lease_until`. Returns whether this caller got it.
+async fn claim_retry_row(
+    pool: &AnyPool,
+    kind: DbKind,
+    table: &RetryTable,
+    id: &str,
+    statuses: &[&str],
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<bool> {
+    let RetryTable { name, key, claimed } = table;
+    let placeholders = statuses.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let base_sql = format!(
+        "UPDATE {name} SET status='{claimed}', next_attempt_at=?
+         WHERE {key}=? AND (status IN ({placeholders})
+             OR (status='{claimed}' AND next_attempt_at <= ?))"
+    );
+    let sql = rewrite_sql(&base_sql, kind);
+    let mut query = sqlx::query(sql.as_ref())
+        .bind(datetime_to_i64(lease_until))
+        .bind(id);
+    for status in statuses {
+        query = query.bind(*status);
+    }
+    let result = query.bind(datetime_to_i64(now)).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Sets a row's final status and clears its last error.
+async fn settle_retry_row(
+    pool: &AnyPool,
+    kind: DbKind,
+    table: &RetryTable,
+    id: &str,
+    status: &str,
+) -> Result<()> {
+    let RetryTable { name, key, .. } = table;
+    let base_sql = format!("UPDATE {name} SET status=?, last_error=NULL WHERE {key} = ?");
+    let sql = rewrite_sql(&base_sql, kind);
+    sqlx::query(sql.as_ref())
+        .bind(status)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records a failed attempt and when the row is next due.
+#[allow(clippy::too_many_arguments)]
+async fn reschedule_retry_row(
+    pool: &AnyPool,
+    kind: DbKind,
+    table: &RetryTable,
+    id: &str,
+    status: &str,
+    retry_count: i32,
+    next_attempt_at: DateTime<Utc>,
+    error: &str,
+) -> Result<()> {
+    let RetryTable { name, key, .. } = table;
+    let base_sql = format!(
+        "UPDATE {name} SET status=?, retry_count=?, next_attempt_at=?, last_error=? WHERE {key}=?"
+    );
+    let sql = rewrite_sql(&base_sql, kind);
+    sqlx::query(sql.as_ref())
+        .bind(status)
+        .bind(retry_count)
+        .bind(datetime_to_i64(next_attempt_at))
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn insert_inbound_failure(
+    pool: &AnyPool,
+    kind: DbKind,
+    payload: serde_json::Value,
+    session_key: Option<&str>,
+    error: &str,
+    next_attempt_at: DateTime<Utc>,
+) -> Result<InboundFailureRecord> {
+    let record = InboundFailureRecord {
+        id: Uuid::new_v4().to_string(),
+        payload,
+        session_key: session_key.map(str::to_string),
+        status: "pending".to_string(),
+        retry_count: 0,
+        next_attempt_at,
+        last_error: Some(error.to_string()),
+        created_at: Utc::now(),
+    };
+    let sql = rewrite_sql(
+        r#"INSERT INTO inbound_failures (id, payload, session_key, status, retry_count, next_attempt_at, last_error, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+        kind,
+    );
+    sqlx::query(sql.as_ref())
+        .bind(&record.id)
+        .bind(record.payload.to_string())
+        .bind(record.session_key.as_deref())
+        .bind(&record.status)
+        .bind(record.retry_count)
+        .bind(datetime_to_i64(record.next_attempt_at))
+        .bind(record.last_error.as_deref())
+        .bind(datetime_to_i64(record.created_at))
+        .execute(pool)
+        .await?;
+    Ok(record)
+}
+
+fn inbound_failure_from_row(row: &AnyRow) -> Result<InboundFailureRecord> {
+    let payload: String = row.try_get("payload")?;
+    let next_attempt_at: i64 = row.try_get("next_attempt_at")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    Ok(InboundFailureRecord {
+        id: row.try_get("id")?,
+        payload: serde_json::from_str(&payload).unwrap_or_else(|_| serde_json::json!({})),
+        session_key: row.try_get("session_key")?,
+        status: row.try_get("status")?,
+        retry_count: row.try_get::<i64, _>("retry_count")? as i32,
+        next_attempt_at: i64_to_datetime(next_attempt_at),
+        last_error: row.try_get("last_error")?,
+        created_at: i64_to_datetime(created_at),
+    })
+}
+
+pub async fn list_inbound_failures(
+    pool: &AnyPool,
+    kind: DbKind,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<InboundFailureRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, payload, session_key, status, retry_count, next_attempt_at, last_error, created_at
+           FROM inbound_failures WHERE status <> 'resolved'
+           ORDER BY created_at ASC LIMIT ? OFFSET ?"#,
+        kind,
+    );
+    let rows = sqlx::query(sql.as_ref())
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(inbound_failure_from_row).collect()
+}
+
+pub async fn get_inbound_failure(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+) -> Result<Option<InboundFailureRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, payload, session_key, status, retry_count, next_attempt_at, last_error, created_at
+           FROM inbound_failures WHERE id = ?"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(inbound_failure_from_row).transpose()
+}
+
+pub async fn due_inbound_failures(
+    pool: &AnyPool,
+    kind: DbKind,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<InboundFailureRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, payload, session_key, status, retry_count, next_attempt_at, last_error, created_at
+           FROM inbound_failures WHERE status IN ('pending','retrying') AND next_attempt_at <= ?
+           ORDER BY created_at ASC LIMIT ?"#,
+        kind,
+    );
+    let rows = sqlx::query(sql.as_ref())
+        .bind(datetime_to_i64(now))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(inbound_failure_from_row).collect()
+}
+
+/// Claims an inbound failure for one replay, moving it to `retrying` until
+/// `lease_until`, so the retry worker and a manual retry never replay it twice.
+/// A `retrying` row whose lease has expired can be claimed again.
+pub async fn claim_inbound_failure(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    statuses: &[&str],
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<bool> {
+    claim_retry_row(
+        pool,
+        kind,
+        &INBOUND_FAILURES,
+        id,
+        statuses,
+        now,
+        lease_until,
+    )
+    .await
+}
+
+pub async fn mark_inbound_failure_resolved(pool: &AnyPool, kind: DbKind, id: &str) -> Result<()> {
+    settle_retry_row(pool, kind, &INBOUND_FAILURES, id, "resolved").await
+}
+
+pub async fn mark_inbound_failure_retry(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    status: &str,
+    retry_count: i32,
+    next_attempt_at: DateTime<Utc>,
+    error: &str,
+) -> Result<()> {
+    let table = &INBOUND_FAILURES;
+    reschedule_retry_row(
+        pool,
+        kind,
+        table,
+        id,
+        status,
+        retry_count,
+        next_attempt_at,
+        error,
+    )
+    .await
+}
+
+/// Takes any executor so it can share a transaction with `insert_message`.
+pub async fn insert_media_failure<'e, E>(
+    executor: E,
+    kind: DbKind,
+    message_id: &str,
+    channel: &str,
+    session_key: &str,
+    attachment: serde_json::Value,
+    error: &str,
+) -> Result<MediaFailureRecord>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    let record = MediaFailureRecord {
+        id: Uuid::new_v4().to_string(),
+        message_id: message_id.to_string(),
+        channel: channel.to_string(),
+        session_key: session_key.to_string(),
+        attachment,
+        status: "pending".to_string(),
+        retry_count: 0,
+        last_error: Some(error.to_string()),
+        created_at: Utc::now(),
+    };
+    let sql = rewrite_sql(
+        r#"INSERT INTO media_failures (id, message_id, channel, session_key, attachment, status, retry_count, last_error, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+        kind,
+    );
+    sqlx::query(sql.as_ref())
+        .bind(&record.id)
+        .bind(&record.message_id)
+        .bind(&record.channel)
+        .bind(&record.session_key)
+        .bind(record.attachment.to_string())
+        .bind(&record.status)
+        .bind(record.retry_count)
+        .bind(record.last_error.as_deref())
+        .bind(datetime_to_i64(record.created_at))
+        .execute(executor)
+        .await?;
+    Ok(record)
+}
+
+fn media_failure_from_row(row: &AnyRow) -> Result<MediaFailureRecord> {
+    let attachment: String = row.try_get("attachment")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    Ok(MediaFailureRecord {
+        id: row.try_get("id")?,
+        message_id: row.try_get("message_id")?,
+        channel: row.try_get("channel")?,
+        session_key: row.try_get("session_key")?,
+        attachment: serde_json::from_str(&attachment).unwrap_or_else(|_| serde_json::json!({})),
+        status: row.try_get("status")?,
+        retry_count: row.try_get::<i64, _>("retry_count")? as i32,
+        last_error: row.try_get("last_error")?,
+        created_at: i64_to_datetime(created_at),
+    })
+}
+
+pub async fn list_media_failures(
+    pool: &AnyPool,
+    kind: DbKind,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<MediaFailureRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, message_id, channel, session_key, attachment, status, retry_count, last_error, created_at
+           FROM media_failures WHERE status <> 'resolved'
+           ORDER BY created_at ASC LIMIT ? OFFSET ?"#,
+        kind,
+    );
+    let rows = sqlx::query(sql.as_ref())
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(media_failure_from_row).collect()
+}
+
+pub async fn get_media_failure(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+) -> Result<Option<MediaFailureRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT id, message_id, channel, session_key, attachment, status, retry_count, last_error, created_at
+           FROM media_failures WHERE id = ?"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(media_failure_from_row).transpose()
+}
+
+/// Claims a media failure for one upload attempt, moving it to `retrying`
+/// until `lease_until` so concurrent retries never upload it twice.
+pub async fn claim_media_failure(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<bool> {
+    claim_retry_row(
+        pool,
+        kind,
+        &MEDIA_FAILURES,
+        id,
+        &["pending"],
+        now,
+        lease_until,
+    )
+    .await
+}
+
+pub async fn mark_media_failure_resolved(pool: &AnyPool, kind: DbKind, id: &str) -> Result<()> {
+    settle_retry_row(pool, kind, &MEDIA_FAILURES, id, "resolved").await
+}
+
+/// Releases a claimed media failure back to `pending` with the attempt's error.
+pub async fn mark_media_failure_retry(
+    pool: &AnyPool,
+    kind: DbKind,
+    id: &str,
+    retry_count: i32,
+    error: &str,
+) -> Result<()> {
+    let table = &MEDIA_FAILURES;
+    reschedule_retry_row(
+        pool,
+        kind,
+        table,
+        id,
+        "pending",
+        retry_count,
+        Utc::now(),
+        error,
+    )
+    .await
+}
+
+pub async fn insert_outbound_queue(
+    pool: &AnyPool,
+    kind: DbKind,
+    message_id: &str,
+    payload: serde_json::Value,
+    error: Option<&str>,
+    next_attempt_at: DateTime<Utc>,
+) -> Result<OutboundQueueRecord> {
+    let record = OutboundQueueRecord {
+        message_id: message_id.to_string(),
+        payload,
+        status: "pending".to_string(),
+        retry_count: 0,
+        next_attempt_at,
+        last_error: error.map(str::to_string),
+        created_at: Utc::now(),
+    };
+    let sql = rewrite_sql(
+        r#"INSERT INTO outbound_queue (message_id, payload, status, retry_count, next_attempt_at, last_error, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+        kind,
+    );
+    sqlx::query(sql.as_ref())
+        .bind(&record.message_id)
+        .bind(record.payload.to_string())
+        .bind(&record.status)
+        .bind(record.retry_count)
+        .bind(datetime_to_i64(record.next_attempt_at))
+        .bind(record.last_error.as_deref())
+        .bind(datetime_to_i64(record.created_at))
+        .execute(pool)
+        .await?;
+    Ok(record)
+}
+
+fn outbound_queue_from_row(row: &AnyRow) -> Result<OutboundQueueRecord> {
+    let payload: String = row.try_get("payload")?;
+    let next_attempt_at: i64 = row.try_get("next_attempt_at")?;
+    let created_at: i64 = row.try_get("created_at")?;
+    Ok(OutboundQueueRecord {
+        message_id: row.try_get("message_id")?,
+        payload: serde_json::from_str(&payload).unwrap_or_else(|_| serde_json::json!({})),
+        status: row.try_get("status")?,
+        retry_count: row.try_get::<i64, _>("retry_count")? as i32,
+        next_attempt_at: i64_to_datetime(next_attempt_at),
+        last_error: row.try_get("last_error")?,
+        created_at: i64_to_datetime(created_at),
+    })
+}
+
+pub async fn get_outbound_queue(
+    pool: &AnyPool,
+    kind: DbKind,
+    message_id: &str,
+) -> Result<Option<OutboundQueueRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT message_id, payload, status, retry_count, next_attempt_at, last_error, created_at
+           FROM outbound_queue WHERE message_id = ?"#,
+        kind,
+    );
+    let row = sqlx::query(sql.as_ref())
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(outbound_queue_from_row).transpose()
+}
+
+pub async fn due_outbound_queue(
+    pool: &AnyPool,
+    kind: DbKind,
+    now: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<OutboundQueueRecord>> {
+    let sql = rewrite_sql(
+        r#"SELECT message_id, payload, status, retry_count, next_attempt_at, last_error, created_at
+           FROM outbound_queue WHERE status IN ('pending','sending') AND next_attempt_at <= ?
+           ORDER BY created_at ASC LIMIT ?"#,
+        kind,
+    );
+    let rows = sqlx::query(sql.as_ref())
+        .bind(datetime_to_i64(now))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(outbound_queue_from_row).collect()
+}
+
+/// Claims an outbound queue row for one send attempt, moving it to `sending`
+/// until `lease_until`. Only a row in `statuses`, or a `sending` row whose
+/// lease has run out, can be claimed. Returns whether this caller got it.
+pub async fn claim_outbound_queue(
+    pool: &AnyPool,
+    kind: DbKind,
+    message_id: &str,
+    statuses: &[&str],
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<bool> {
+    claim_retry_row(
+        pool,
+        kind,
+        &OUTBOUND_QUEUE,
+        message_id,
+        statuses,
+        now,
+        lease_until,
+    )
+    .await
+}
+
+pub async fn mark_outbound_queue_sent(
+    pool: &AnyPool,
+    kind: DbKind,
+    message_id: &str,
+) -> Result<()> {
+    settle_retry_row(pool, kind, &OUTBOUND_QUEUE, message_id, "sent").await
+}
+
+pub async fn mark_outbound_queue_retry(
+    pool: &AnyPool,
+    kind: DbKind,
+    message_id: &str,
+    status: &str,
+    retry_count: i32,
+    next_attempt_at: DateTime<Utc>,
+    error: &str,
+) -> Result<()> {
+    let table = &OUTBOUND_QUEUE;
+    let id = message_id;
+    reschedule_retry_row(
+        pool,
+        kind,
+        table,
+        id,
+        status,
+        retry_count,
+        next_attempt_at,
+        error,
+    )
+    .await
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramRow {
+    pub bucket_start: DateTime<Utc>,
+    pub channel: String,
+    pub direction: String,
+    pub count: i64,
+}
+
+/// Counts messages created at or after `since`, grouped into `bucket_seconds`
+/// buckets aligned to the Unix epoch, per channel and direction.
+pub async fn message_histogram(
+    pool: &AnyPool,
+    kind: DbKind,
+    since: DateTime<Utc>,
+    bucket_seconds: i64,
+) -> Result<Vec<HistogramRow>> {
+    let sql = rewrite_sql(
+        r#"SELECT created_at - (created_at % ?) AS bucket, channel, direction, COUNT(1) AS count
+           FROM messages WHERE created_at >= ?
+           GROUP BY bucket, channel, direction
+           ORDER BY bucket ASC, channel ASC, direction ASC"#,
+        kind,
+    );
+    let rows = sqlx::query(sql.as_ref())
+        .bind(bucket_seconds)
+        .bind(datetime_to_i64(since))
+        .fetch_all(pool)
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(HistogramRow {
+                bucket_start: i64_to_datetime(row.try_get::<i64, _>("bucket")?),
+                channel: row.try_get("channel")?,
+                direction: row.try_get("direction")?,
+                count: row.try_get("count")?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+use crate::channels::ChannelError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+tokio::task_local! {
+    /// Id of the request being handled, set by the request-id middleware.
+    pub static REQUEST_ID: String;
+}
+
+/// Error response body shared by every handler:
+/// `{"error": {"code", "message", "request_id", ...details}}`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: String,
+    message: String,
+    details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ApiError {
+    /// An error whose code is derived from `status` (e.g. `not_found`).
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code: status_code_name(status).to_string(),
+            message: message.into(),
+            details: serde_json::Map::new(),
+        }
+    }
+
+    pub fn internal(err: impl std::fmt::Display) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = code.into();
+        self
+    }
+
+    /// Adds a field next to `code` and `message` in the error object.
+    pub fn with_detail(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn to_json(&self, request_id: Option<&str>) -> serde_json::Value {
+        let mut error = self.details.clone();
+        error.insert("code".to_string(), json!(self.code));
+        error.insert("message".to_string(), json!(self.message));
+        error.insert("request_id".to_string(), json!(request_id));
+        json!({"error": error})
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(|id| id.clone()).ok();
+        (self.status, Json(self.to_json(request_id.as_deref()))).into_response()
+    }
+}
+
+/// Snake-case error code for a bare HTTP status.
+pub fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::NOT_IMPLEMENTED => "not_implemented",
+        StatusCode::BAD_GATEWAY => "bad_gateway",
+        StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+        status if status.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error("unknown session")]
+    UnknownSession,
+    #[error("no route for session")]
+    NoRoute,
+    #[error("unsupported channel: {0}")]
+    UnsupportedChannel(String),
+    #[error("{0} peer missing")]
+    MissingPeer(String),
+    #[error("unknown message")]
+    UnknownMessage,
+    #[error("editing is not supported on {0}")]
+    EditUnsupported(String),
+    #[error("deleting is not supported on {0}")]
+    DeleteUnsupported(String),
+    #[error("reactions are not supported on {0}")]
+    ReactionUnsupported(String),
+    #[error("message has no failed send to retry")]
+    NotResendable,
+    #[error("whatsapp messaging window is closed; send an approved template instead")]
+    WindowClosed,
+    #[error("{source}")]
+    Queued {
+        message_id: String,
+        source: anyhow::Error,
+    },
+    /// The channel is missing local configuration (a bot token, a runtime
+    /// URL) that the send needs. Retrying cannot help until it is configured.
+    #[error("{0}")]
+    NotConfigured(String),
+    /// A failure outside the channel, such as the database.
+    #[error(transparent)]
+    Internal(anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl SendError {
+    /// The classified provider failure behind a failed channel call, if any.
+    pub fn channel_error(&self) -> Option<&ChannelError> {
+        match self {
+            SendError::Other(err) | SendError::Queued { source: err, .. } => err.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self.channel_error() {
+            Some(ChannelError::Auth { .. }) => return StatusCode::BAD_GATEWAY,
+            Some(ChannelError::RateLimited { .. }) => return StatusCode::TOO_MANY_REQUESTS,
+            Some(ChannelError::InvalidRecipient { .. }) => return StatusCode::UNPROCESSABLE_ENTITY,
+            _ => {}
+        }
+        match self {
+            SendError::UnknownSession | SendError::UnknownMessage => StatusCode::NOT_FOUND,
+            SendError::NoRoute
+            | SendError::UnsupportedChannel(_)
+            | SendError::MissingPeer(_)
+            | SendError::EditUnsupported(_)
+            | SendError::DeleteUnsupported(_)
+            | SendError::ReactionUnsupported(_)
+            | SendError::WindowClosed => StatusCode::UNPROCESSABLE_ENTITY,
+            SendError::NotResendable => StatusCode::CONFLICT,
+            SendError::NotConfigured(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SendError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SendError::Queued { .. } => StatusCode::ACCEPTED,
+            SendError::Other(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self.channel_error() {
+            Some(ChannelError::Auth { .. }) => return "channel_auth_failed",
+            Some(ChannelError::RateLimited { .. }) => return "rate_limited",
+            Some(ChannelError::InvalidRecipient { .. }) => return "invalid_recipient",
+            _ => {}
+        }
+        match self {
+            SendError::UnknownSession => "unknown_session",
+            SendError::NoRoute => "no_route",
+            SendError::UnsupportedChannel(_) => "unsupported_channel",
+            SendError::MissingPeer(_) => "missing_peer",
+            SendError::UnknownMessage => "unknown_message",
+            SendError::EditUnsupported(_) => "edit_unsupported",
+            SendError::DeleteUnsupported(_) => "delete_unsupported",
+            SendError::ReactionUnsupported(_) => "reaction_unsupported",
+            SendError::NotResendable => "not_resendable",
+            SendError::WindowClosed => "window_closed",
+            SendError::NotConfigured(_) => "not_configured",
+            SendError::Internal(_) => "internal_error",
+            SendError::Queued { .. } => "queued",
+            SendError::Other(_) => "send_failed",
+        }
+    }
+
+    /// The per-message result body. A queued send is not an error for the
+    /// caller: it reports `"status": "queued"` and its `message_id`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = json!({"error": self.to_string(), "code": self.code()});
+        if let SendError::Queued { message_id, .. } = self {
+            value["message_id"] = json!(message_id);
+            value["status"] = json!("queued");
+        }
+        value
+    }
+}
+
+impl From<SendError> for ApiError {
+    fn from(err: SendError) -> Self {
+        let api = ApiError::new(err.status(), err.to_string()).with_code(err.code());
+        match err {
+            SendError::Queued { message_id, .. } => api.with_detail("message_id", message_id),
+            _ => api,
+        }
+    }
+}
+
+impl IntoResponse for SendError {
+    fn into_response(self) -> Response {
+        if let SendError::Queued { .. } = self {
+            return (StatusCode::ACCEPTED, Json(self.to_json())).into_response();
+        }
+        ApiError::from(self).into_response()
+    }
+}
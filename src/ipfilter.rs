@@ -0,0 +1,172 @@
+use std::net::IpAddr;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(value: &str) -> Option<Cidr> {
+        let value = value.trim();
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr.trim(), Some(prefix.trim().parse::<u8>().ok()?)),
+            None => (value, None),
+        };
+        let network = normalize(addr.parse::<IpAddr>().ok()?);
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return None;
+        }
+        Some(Cidr { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, normalize(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+pub fn parse_cidrs(values: &[String]) -> Vec<Cidr> {
+    values
+        .iter()
+        .filter_map(|value| {
+            let cidr = Cidr::parse(value);
+            if cidr.is_none() {
+                warn!("ignoring invalid CIDR {value:?}");
+            }
+            cidr
+        })
+        .collect()
+}
+
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[Cidr]) -> IpAddr {
+    let trusted = |ip: IpAddr| trusted_proxies.iter().any(|cidr| cidr.contains(ip));
+    let mut client = peer;
+    if !trusted(peer) {
+        return client;
+    }
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted(ip) {
+            break;
+        }
+    }
+    client
+}
+
+pub fn is_allowed(allowlist: &[Cidr], ip: IpAddr) -> bool {
+    allowlist.is_empty() || allowlist.iter().any(|cidr| cidr.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn cidrs(values: &[&str]) -> Vec<Cidr> {
+        parse_cidrs(&values.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_cidr_parse_rejects_invalid() {
+        assert!(Cidr::parse("10.0.0.0/33").is_none());
+        assert!(Cidr::parse("not-an-ip").is_none());
+        assert!(Cidr::parse("10.0.0.0/x").is_none());
+        assert!(Cidr::parse("::1/129").is_none());
+    }
+
+    #[test]
+    fn test_cidr_contains_ipv4() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains(ip("10.1.255.7")));
+        assert!(!cidr.contains(ip("10.2.0.1")));
+        assert!(Cidr::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("203.0.113.9")));
+        assert!(Cidr::parse("203.0.113.9")
+            .unwrap()
+            .contains(ip("203.0.113.9")));
+        assert!(!Cidr::parse("203.0.113.9")
+            .unwrap()
+            .contains(ip("203.0.113.10")));
+    }
+
+    #[test]
+    fn test_cidr_contains_ipv6_and_mapped() {
+        let cidr = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(cidr.contains(ip("2001:db8::1")));
+        assert!(!cidr.contains(ip("2001:db9::1")));
+        assert!(!cidr.contains(ip("10.0.0.1")));
+        assert!(Cidr::parse("10.0.0.0/8")
+            .unwrap()
+            .contains(ip("::ffff:10.9.8.7")));
+    }
+
+    #[test]
+    fn test_client_ip_ignores_forwarded_from_untrusted_peer() {
+        let trusted = cidrs(&["10.0.0.0/8"]);
+        assert_eq!(
+            client_ip(ip("198.51.100.4"), Some("203.0.113.9"), &trusted),
+            ip("198.51.100.4")
+        );
+        assert_eq!(
+            client_ip(ip("198.51.100.4"), Some("203.0.113.9"), &[]),
+            ip("198.51.100.4")
+        );
+    }
+
+    #[test]
+    fn test_client_ip_walks_trusted_proxy_chain() {
+        let trusted = cidrs(&["10.0.0.0/8"]);
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), Some("203.0.113.9, 10.0.0.5"), &trusted),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            client_ip(
+                ip("10.0.0.2"),
+                Some("1.1.1.1, 203.0.113.9, 10.0.0.5"),
+                &trusted
+            ),
+            ip("203.0.113.9")
+        );
+        assert_eq!(client_ip(ip("10.0.0.2"), None, &trusted), ip("10.0.0.2"));
+        assert_eq!(
+            client_ip(ip("10.0.0.2"), Some("garbage"), &trusted),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_is_allowed_empty_allows_all() {
+        assert!(is_allowed(&[], ip("203.0.113.9")));
+        let allow = cidrs(&["192.0.2.0/24"]);
+        assert!(is_allowed(&allow, ip("192.0.2.10")));
+        assert!(!is_allowed(&allow, ip("203.0.113.9")));
+    }
+}
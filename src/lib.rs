@@ -1,7 +1,13 @@
 pub mod adapters;
 pub mod channels;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
 pub mod db;
+pub mod error;
+pub mod ipfilter;
+pub mod logging;
+pub mod openapi;
 pub mod outbox;
 pub mod session;
 pub mod types;
@@ -10,38 +16,138 @@ pub mod ws;
 pub use config::Config;
 
 use self::channels::{
-    slack as slack_channel, telegram as telegram_channel, whatsapp as whatsapp_channel,
+    custom as custom_channel, slack as slack_channel, telegram as telegram_channel,
+    whatsapp as whatsapp_channel,
 };
-use self::config::{load_config, resolve_database_url};
+use self::config::{load_config, resolve_database_url, try_load_config};
 use self::db::DbKind;
-use self::types::{Attachment, InboundMessage, OutboundMessage, RouteInfo};
+use self::error::{ApiError, SendError};
+use self::types::{
+    Attachment, InboundEdit, InboundMessage, InboundReaction, OutboundMessage, RouteInfo,
+};
 
 use axum::{
     body::{Body, Bytes},
-    extract::{Path, Query, RawQuery, State, WebSocketUpgrade},
+    extract::{ConnectInfo, DefaultBodyLimit, Path, Query, RawQuery, State, WebSocketUpgrade},
     http::{HeaderMap, Method, StatusCode},
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{get, patch, post, put},
     Json, Router,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::AnyPool;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 use tokio::sync::{broadcast, mpsc};
-use tracing::error;
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+use tracing::{debug, error, info, warn};
 
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Config,
+    pub config: Arc<RwLock<Arc<Config>>>,
     pub pool: AnyPool,
+    pub read_pool: AnyPool,
     pub http: reqwest::Client,
     pub ws_tx: broadcast::Sender<ws::WsEvent>,
+    pub ws_clients: Arc<AtomicUsize>,
     pub db_kind: DbKind,
+    pub channel_health: Arc<RwLock<HashMap<String, ChannelHealth>>>,
+    pub maintenance_running: Arc<AtomicBool>,
+    /// Per-channel send limiters, rebuilt on reload when their permits change.
+    pub send_limits: Arc<RwLock<HashMap<String, channels::SendLimiter>>>,
+    /// Set once the database is initialized and channel pollers have started.
+    pub ready: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChannelHealth {
+    pub last_inbound_at: Option<DateTime<Utc>>,
+    /// Seconds between the provider timestamp of the last inbound message that
+    /// carried one and its ingestion.
+    pub last_lag_seconds: Option<f64>,
+    pub last_error: Option<String>,
+}
+
+impl AppState {
+    pub async fn for_tests(config: Config) -> AppState {
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .expect("in-memory sqlite");
+        db::init_db(&pool, DbKind::Sqlite)
+            .await
+            .expect("init in-memory sqlite");
+        let (ws_tx, _) = broadcast::channel(100);
+        let send_limits = Arc::new(RwLock::new(build_send_limits(&config)));
+        AppState {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            read_pool: pool.clone(),
+            pool,
+            http: reqwest::Client::new(),
+            ws_tx,
+            ws_clients: Arc::new(AtomicUsize::new(0)),
+            db_kind: DbKind::Sqlite,
+            channel_health: Arc::new(RwLock::new(HashMap::new())),
+            maintenance_running: Arc::new(AtomicBool::new(false)),
+            send_limits,
+            ready: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn send_limiter(&self, channel: &str) -> Option<channels::SendLimiter> {
+        match self.send_limits.read() {
+            Ok(limits) => limits.get(channel).cloned(),
+            Err(poisoned) => poisoned.into_inner().get(channel).cloned(),
+        }
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn replace_config(&self, config: Config) {
+        match self.config.write() {
+            Ok(mut current) => *current = Arc::new(config),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(config),
+        }
+    }
+
+    fn record_inbound(&self, channel: &str, sent_at: Option<DateTime<Utc>>) {
+        let now = Utc::now();
+        if let Ok(mut health) = self.channel_health.write() {
+            let entry = health.entry(channel.to_string()).or_default();
+            entry.last_inbound_at = Some(now);
+            if let Some(sent_at) = sent_at {
+                entry.last_lag_seconds = Some(ingest_lag_seconds(now, sent_at));
+            }
+        }
+    }
+
+    fn record_channel_error(&self, channel: &str, error: Option<String>) {
+        if let Ok(mut health) = self.channel_health.write() {
+            health.entry(channel.to_string()).or_default().last_error = error;
+        }
+    }
+}
+
+/// Time from the provider timestamp to ingestion, floored at zero to absorb
+/// clock skew between the provider and this host.
+fn ingest_lag_seconds(now: DateTime<Utc>, sent_at: DateTime<Utc>) -> f64 {
+    (now - sent_at).num_milliseconds().max(0) as f64 / 1000.0
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SendMessageRequest {
     pub session_key: String,
     pub text: Option<String>,
@@ -50,14 +156,113 @@ pub struct SendMessageRequest {
     pub account_id: Option<String>,
     pub peer_id: Option<String>,
     pub reply_to: Option<String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    #[serde(default)]
+    pub caption_mode: bool,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    /// Resolve and validate the send without delivering or storing it.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub peer_kind: Option<String>,
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    #[serde(default)]
+    pub sender_icon: Option<String>,
+    #[serde(default)]
+    pub reply_broadcast: bool,
 }
 
-#[derive(Debug, Serialize)]
+impl SendMessageRequest {
+    fn batch_key(&self) -> String {
+        if let Some(key) = self
+            .idempotency_key
+            .as_deref()
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+        {
+            return format!("key:{key}");
+        }
+        let attachments: Vec<&str> = self
+            .attachments
+            .iter()
+            .flatten()
+            .map(|att| att.url.as_str())
+            .collect();
+        json!([
+            self.session_key.trim(),
+            self.channel.as_deref().map(session::normalize_token),
+            self.account_id.as_deref().map(str::trim),
+            self.peer_id.as_deref().map(str::trim),
+            self.reply_to.as_deref().map(str::trim),
+            self.peer_kind.as_deref().map(str::trim),
+            self.thread_id.as_deref().map(str::trim),
+            self.sender_name.as_deref().map(str::trim),
+            self.sender_icon.as_deref().map(str::trim),
+            self.reply_broadcast,
+            self.text.as_deref().map(str::trim),
+            attachments,
+            self.dry_run,
+        ])
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendMessageResponse {
     pub message_id: String,
     pub status: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SendByIdentityRequest {
+    pub canonical_id: String,
+    pub text: Option<String>,
+    pub attachments: Option<Vec<Attachment>>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemMessageRequest {
+    pub text: String,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionBatchRequest {
+    pub session_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CreateSessionRequest {
+    pub session_key: Option<String>,
+    pub agent_id: Option<String>,
+    pub business_profile_id: Option<String>,
+    pub user_id: Option<String>,
+    pub identity_links: Option<serde_json::Value>,
+    pub metadata: Option<serde_json::Value>,
+    pub channel: Option<String>,
+    pub account_id: Option<String>,
+    pub peer_id: Option<String>,
+    pub peer_kind: Option<String>,
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub upsert: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct BulkSendRequest {
     pub messages: Vec<SendMessageRequest>,
@@ -77,6 +282,22 @@ pub struct HealthResponse {
 pub struct StatusResponse {
     pub sessions: i64,
     pub messages: i64,
+    pub outbox_pending: i64,
+    pub outbox_failed: i64,
+    pub outbox_dead: i64,
+    pub oldest_pending_age_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelStatus {
+    pub enabled: bool,
+    pub configured: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_inbound_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_lag_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -85,42 +306,98 @@ pub struct Pagination {
     pub offset: Option<i64>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub window: Option<String>,
+    pub bucket: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub after: Option<String>,
+}
+
 pub async fn create_app() -> anyhow::Result<(AppState, Router)> {
+    create_app_with_config(load_config()).await
+}
+
+pub async fn create_app_with_config(config: Config) -> anyhow::Result<(AppState, Router)> {
     sqlx::any::install_default_drivers();
 
-    let config = load_config();
     let db_url = resolve_database_url(&config);
     let db_kind = db::db_kind_from_url(&db_url);
-    let pool = AnyPool::connect(&db_url).await?;
+    let pool = db::connect(&db_url, &config.database).await?;
     db::init_db(&pool, db_kind).await?;
+    let read_pool = match config.database.read_url.as_deref() {
+        Some(_) if config.database.mode == "memory" => {
+            anyhow::bail!("database.read_url cannot be used with database.mode \"memory\"");
+        }
+        Some(read_url) => {
+            if db::db_kind_from_url(read_url) != db_kind {
+                anyhow::bail!(
+                    "database.read_url must use the same backend as the primary database"
+                );
+            }
+            db::connect(read_url, &config.database).await?
+        }
+        None => pool.clone(),
+    };
 
     let (ws_tx, _) = broadcast::channel(100);
     let state = AppState {
-        config: config.clone(),
+        config: Arc::new(RwLock::new(Arc::new(config.clone()))),
         pool: pool.clone(),
+        read_pool,
         http: reqwest::Client::new(),
         ws_tx,
+        ws_clients: Arc::new(AtomicUsize::new(0)),
         db_kind,
+        channel_health: Arc::new(RwLock::new(HashMap::new())),
+        maintenance_running: Arc::new(AtomicBool::new(false)),
+        send_limits: Arc::new(RwLock::new(build_send_limits(&config))),
+        ready: Arc::new(AtomicBool::new(false)),
     };
 
-    let backend_cfg = config.backend.clone();
-    tokio::spawn(outbox::start_outbox_worker(
-        pool.clone(),
-        backend_cfg,
-        db_kind,
-    ));
+    tokio::spawn(outbox::start_outbox_worker(state.clone()));
+    tokio::spawn(start_inbound_retry_worker(state.clone()));
+    tokio::spawn(start_outbound_retry_worker(state.clone()));
+    tokio::spawn(start_session_eviction_worker(state.clone()));
+
+    if let Some(hours) = config
+        .database
+        .maintenance_interval_hours
+        .filter(|h| *h > 0)
+    {
+        let state_clone = state.clone();
+        tokio::spawn(async move {
+            let period = std::time::Duration::from_secs(hours * 3600);
+            let start = tokio::time::Instant::now() + period;
+            let mut ticker = tokio::time::interval_at(start, period);
+            loop {
+                ticker.tick().await;
+                if !spawn_maintenance(&state_clone) {
+                    warn!("scheduled database maintenance skipped: already running");
+                }
+            }
+        });
+    }
 
+    let mut poller_started = false;
     if config.channels.telegram.enabled && channel_transport(&config, "telegram") == "native" {
         if let Some(token) = config.channels.telegram.bot_token.clone() {
             let (tx, mut rx) = mpsc::channel::<InboundMessage>(100);
             let interval = config.channels.telegram.poll_interval_seconds;
             let state_clone = state.clone();
+            let ready = state.ready.clone();
+            poller_started = true;
             tokio::spawn(async move {
-                telegram_channel::start_telegram_poller(token, tx, interval).await;
+                telegram_channel::start_telegram_poller(token, tx, interval, ready).await;
             });
             tokio::spawn(async move {
                 while let Some(msg) = rx.recv().await {
-                    if let Err(err) = handle_inbound(state_clone.clone(), msg).await {
+                    if let Err(err) = handle_acked_inbound(&state_clone, msg).await {
                         error!("telegram inbound error: {err:?}");
                     }
                 }
@@ -128,41 +405,214 @@ pub async fn create_app() -> anyhow::Result<(AppState, Router)> {
         }
     }
 
+    // Without a poller to wait for, the database being initialized is enough.
+    if !poller_started {
+        state.ready.store(true, Ordering::SeqCst);
+    }
+
+    let app = build_router(&state);
+    Ok((state, app))
+}
+
+pub fn build_router(state: &AppState) -> Router {
+    let config = &state.config();
+
     let authed_routes = Router::new()
         .route("/v1/messages/send", post(send_message))
         .route("/v1/messages/send-bulk", post(send_bulk))
-        .route("/v1/sessions", get(list_sessions))
+        .route("/v1/messages/send-by-identity", post(send_by_identity))
+        .route(
+            "/v1/messages/:message_id",
+            patch(edit_message).delete(delete_message),
+        )
+        .route("/v1/messages/:message_id/reactions", post(add_reaction))
+        .route("/v1/messages/:message_id/resend", post(resend_message))
+        .route("/v1/sessions", get(list_sessions).post(create_session))
+        .route("/v1/sessions/batch", post(get_sessions_batch))
         .route("/v1/sessions/:session_key", get(get_session))
+        .route("/v1/sessions/:session_key/route", put(set_session_route))
+        .route("/v1/sessions/:session_key/window", get(get_session_window))
+        .route(
+            "/v1/sessions/:session_key/system",
+            post(append_system_message),
+        )
         .route("/v1/sessions/:session_key/messages", get(list_messages))
+        .route(
+            "/v1/sessions/:session_key/messages/stream",
+            get(stream_messages),
+        )
         .route("/v1/runtime/inbound", post(runtime_inbound))
+        .route("/v1/stats", get(stats))
+        .route("/v1/config", get(get_config))
+        .route("/v1/admin/reload", post(admin_reload))
+        .route("/v1/admin/maintenance", post(admin_maintenance))
+        .route("/v1/admin/inbound-failures", get(list_inbound_failures))
+        .route(
+            "/v1/admin/inbound-failures/:id/retry",
+            post(retry_inbound_failure_endpoint),
+        )
+        .route("/v1/admin/media-failures", get(list_media_failures))
+        .route(
+            "/v1/admin/media-failures/:id/retry",
+            post(retry_media_failure_endpoint),
+        )
+        .route("/v1/channels", get(list_channels))
         .route("/v1/channels/identities", get(channel_identities))
+        .route(
+            "/v1/channels/:channel/capabilities",
+            get(channel_capabilities),
+        )
         .route("/v1/channels/whatsapp/status", get(whatsapp_channel_status))
         .route("/v1/channels/whatsapp/link", post(whatsapp_channel_link))
         .route("/v1/channels/whatsapp/logout", post(whatsapp_channel_logout))
-        .route("/v1/inbound/ack", post(inbound_ack))
+        .route("/v1/inbound", post(generic_inbound))
+        .route("/v1/inbound/ack", post(inbound_ack));
+    let authed_routes = if config.server.status_requires_auth {
+        authed_routes.route("/v1/status", get(status))
+    } else {
+        authed_routes
+    };
+    let authed_routes = authed_routes
+        .layer(DefaultBodyLimit::max(config.server.max_body_bytes))
+        .layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
+    let ws_routes = Router::new()
         .route("/v1/ws", get(ws_handler))
         .layer(middleware::from_fn_with_state(state.clone(), require_auth));
 
+    let channels = &config.channels;
     let public_routes = Router::new()
         .route("/v1/health", get(health))
-        .route("/v1/status", get(status))
-        .route(&config.channels.slack.webhook_path, post(slack_events))
+        .route("/v1/healthz", get(health))
+        .route("/v1/readyz", get(readyz));
+    let public_routes = if config.server.status_requires_auth {
+        public_routes
+    } else {
+        public_routes.route("/v1/status", get(status))
+    };
+    let public_routes = public_routes
+        .route("/v1/openapi.json", get(openapi_json))
+        .route(
+            &channels.slack.webhook_path,
+            post(slack_events).layer(DefaultBodyLimit::max(channels.slack.max_webhook_bytes)),
+        )
         .route(
-            &config.channels.telegram.webhook_path,
-            post(telegram_webhook),
+            &channels.telegram.webhook_path,
+            post(telegram_webhook)
+                .layer(DefaultBodyLimit::max(channels.telegram.max_webhook_bytes)),
         )
         .route(
-            &config.channels.whatsapp.inbound_path,
-            get(whatsapp_verify).post(whatsapp_inbound),
+            &channels.whatsapp.inbound_path,
+            get(whatsapp_verify)
+                .post(whatsapp_inbound)
+                .layer(DefaultBodyLimit::max(channels.whatsapp.max_webhook_bytes)),
         )
-        .route(&config.channels.teams.webhook_path, post(teams_webhook));
+        .route(
+            &channels.teams.webhook_path,
+            post(teams_webhook).layer(DefaultBodyLimit::max(channels.teams.max_webhook_bytes)),
+        )
+        // Inflate gzip/deflate webhook bodies before handlers read them; the
+        // webhook body limits apply to the decompressed size.
+        .route_layer(RequestDecompressionLayer::new())
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_allowed_ip,
+        ));
+
+    let mut router = Router::new().merge(authed_routes).merge(public_routes);
+    if config.server.compression {
+        router = router.layer(CompressionLayer::new());
+    }
+    let router = router.merge(ws_routes);
+    let prefix = config.server.route_prefix();
+    let router = if prefix.is_empty() {
+        router
+    } else {
+        Router::new().nest(&prefix, router)
+    };
+    router
+        .layer(middleware::from_fn(request_context))
+        .with_state(state.clone())
+}
 
-    let app = Router::new()
-        .merge(authed_routes)
-        .merge(public_routes)
-        .with_state(state.clone());
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
-    Ok((state, app))
+/// Marks a response relayed verbatim from an adapter runtime, whose error
+/// bodies belong to the provider and are not rewritten.
+#[derive(Clone, Copy)]
+struct ProviderResponse;
+
+/// Tags each request with an id (the caller's `X-Request-Id` when usable),
+/// echoes it on the response and in `ApiError` bodies, and wraps bare or
+/// plain-text error responses from the router and extractors in the same
+/// envelope.
+async fn request_context(
+    req: axum::http::Request<Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let response = error::REQUEST_ID
+        .scope(request_id.clone(), next.run(req))
+        .await;
+    let mut response = if needs_error_envelope(&response) {
+        wrap_error_response(response, &request_id).await
+    } else {
+        response
+    };
+    if let Ok(value) = request_id.parse() {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn needs_error_envelope(response: &axum::response::Response) -> bool {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error())
+        || response.extensions().get::<ProviderResponse>().is_some()
+        || response
+            .headers()
+            .contains_key(axum::http::header::CONTENT_ENCODING)
+    {
+        return false;
+    }
+    match response.headers().get(axum::http::header::CONTENT_TYPE) {
+        None => true,
+        Some(value) => value.to_str().is_ok_and(|v| v.starts_with("text/plain")),
+    }
+}
+
+async fn wrap_error_response(
+    response: axum::response::Response,
+    request_id: &str,
+) -> axum::response::Response {
+    let (mut parts, body) = response.into_parts();
+    let text = axum::body::to_bytes(body, 64 * 1024)
+        .await
+        .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+        .unwrap_or_default();
+    let message = if text.is_empty() {
+        parts
+            .status
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_string()
+    } else {
+        text
+    };
+    let value = ApiError::new(parts.status, message).to_json(Some(request_id));
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    parts.headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        axum::http::HeaderValue::from_static("application/json"),
+    );
+    axum::response::Response::from_parts(parts, Body::from(value.to_string()))
 }
 
 async fn require_auth(
@@ -171,43 +621,320 @@ async fn require_auth(
     req: axum::http::Request<axum::body::Body>,
     next: middleware::Next,
 ) -> impl IntoResponse {
-    if let Some(token) = state.config.auth.token.as_ref() {
-        let header = headers
-            .get("X-Agent-Ping-Token")
-            .and_then(|v| v.to_str().ok());
-        if header != Some(token.as_str()) {
-            return StatusCode::UNAUTHORIZED.into_response();
-        }
+    let header = headers
+        .get("X-Agent-Ping-Token")
+        .and_then(|v| v.to_str().ok());
+    if !state.config().auth.accepts(header) {
+        return ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid X-Agent-Ping-Token",
+        )
+        .into_response();
     }
     next.run(req).await
 }
 
+fn webhook_allowlist(config: &Config, path: &str) -> Vec<String> {
+    let channels = &config.channels;
+    if path == channels.slack.webhook_path {
+        channels.slack.allowed_ips.clone()
+    } else if path == channels.telegram.webhook_path {
+        channels.telegram.allowed_ips.clone()
+    } else if path == channels.whatsapp.inbound_path {
+        channels.whatsapp.allowed_ips.clone()
+    } else if path == channels.teams.webhook_path {
+        channels.teams.allowed_ips.clone()
+    } else {
+        Vec::new()
+    }
+}
+
+async fn require_allowed_ip(
+    State(state): State<AppState>,
+    req: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let allowlist = ipfilter::parse_cidrs(&webhook_allowlist(&state.config(), req.uri().path()));
+    if allowlist.is_empty() {
+        return next.run(req).await;
+    }
+    let trusted = ipfilter::parse_cidrs(&state.config().server.trusted_proxies);
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok());
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| ipfilter::client_ip(info.0.ip(), forwarded, &trusted));
+    match client {
+        Some(ip) if ipfilter::is_allowed(&allowlist, ip) => next.run(req).await,
+        client => {
+            warn!("rejected webhook {} from {client:?}", req.uri().path());
+            ApiError::new(StatusCode::FORBIDDEN, "source address not allowed").into_response()
+        }
+    }
+}
+
 async fn health() -> impl IntoResponse {
     Json(HealthResponse {
         status: "ok".to_string(),
     })
 }
 
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let started = state.ready.load(Ordering::SeqCst);
+    let database = match sqlx::query("SELECT 1").execute(&state.pool).await {
+        Ok(_) => true,
+        Err(err) => {
+            warn!("readiness database check failed: {err}");
+            false
+        }
+    };
+    let ready = started && database;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {"database": database, "started": started}
+        })),
+    )
+}
+
+const STATS_WINDOWS: &[(&str, i64)] = &[
+    ("1h", 3_600),
+    ("6h", 21_600),
+    ("24h", 86_400),
+    ("7d", 604_800),
+    ("30d", 2_592_000),
+];
+const STATS_BUCKETS: &[(&str, i64)] = &[
+    ("5m", 300),
+    ("15m", 900),
+    ("1h", 3_600),
+    ("6h", 21_600),
+    ("1d", 86_400),
+];
+const STATS_MAX_BUCKETS: i64 = 720;
+
+fn stats_duration(options: &[(&str, i64)], value: &str) -> Option<i64> {
+    options
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, seconds)| *seconds)
+}
+
+async fn stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> impl IntoResponse {
+    let window = query.window.as_deref().unwrap_or("24h");
+    let bucket = query.bucket.as_deref().unwrap_or("1h");
+    let (Some(window_secs), Some(bucket_secs)) = (
+        stats_duration(STATS_WINDOWS, window),
+        stats_duration(STATS_BUCKETS, bucket),
+    ) else {
+        let names = |options: &[(&'static str, i64)]| {
+            options.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        };
+        return ApiError::new(StatusCode::BAD_REQUEST, "unsupported window or bucket")
+            .with_detail("windows", names(STATS_WINDOWS))
+            .with_detail("buckets", names(STATS_BUCKETS))
+            .into_response();
+    };
+    let count = window_secs / bucket_secs;
+    if !(1..=STATS_MAX_BUCKETS).contains(&count) {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "window {window} with bucket {bucket} must give 1 to {STATS_MAX_BUCKETS} buckets"
+            ),
+        )
+        .into_response();
+    }
+
+    // The last bucket is the one in progress, so the series ends at "now".
+    let now = Utc::now().timestamp();
+    let first = now - now.rem_euclid(bucket_secs) - (count - 1) * bucket_secs;
+    let since = DateTime::from_timestamp(first, 0).unwrap_or_default();
+    let rows =
+        match db::message_histogram(&state.read_pool, state.db_kind, since, bucket_secs).await {
+            Ok(rows) => rows,
+            Err(err) => {
+                error!("message_histogram error: {err:?}");
+                return ApiError::internal(err).into_response();
+            }
+        };
+
+    let mut buckets: Vec<serde_json::Value> = (0..count)
+        .map(|i| {
+            let start = DateTime::from_timestamp(first + i * bucket_secs, 0).unwrap_or_default();
+            json!({"start": start, "inbound": 0, "outbound": 0, "channels": {}})
+        })
+        .collect();
+    for row in rows {
+        let index = (row.bucket_start.timestamp() - first) / bucket_secs;
+        let Some(entry) = usize::try_from(index).ok().and_then(|i| buckets.get_mut(i)) else {
+            continue;
+        };
+        let direction = row.direction.as_str();
+        if let Some(total) = entry[direction].as_i64() {
+            entry[direction] = json!(total + row.count);
+        }
+        let channel = &mut entry["channels"][row.channel.as_str()];
+        let current = channel[direction].as_i64().unwrap_or(0);
+        channel[direction] = json!(current + row.count);
+    }
+
+    Json(json!({
+        "window": window,
+        "bucket": bucket,
+        "since": since,
+        "buckets": buckets,
+    }))
+    .into_response()
+}
+
+async fn openapi_json(State(state): State<AppState>) -> impl IntoResponse {
+    Json(openapi::openapi_document(&state.config()))
+}
+
 async fn status(State(state): State<AppState>) -> impl IntoResponse {
     let sessions = sqlx::query_scalar::<_, i64>("SELECT COUNT(1) FROM sessions")
-        .fetch_one(&state.pool)
+        .fetch_one(&state.read_pool)
         .await
         .unwrap_or(0);
     let messages = sqlx::query_scalar::<_, i64>("SELECT COUNT(1) FROM messages")
-        .fetch_one(&state.pool)
+        .fetch_one(&state.read_pool)
         .await
         .unwrap_or(0);
-    Json(StatusResponse { sessions, messages })
+    let outbox = match db::outbox_stats(&state.read_pool, state.db_kind).await {
+        Ok(outbox) => outbox,
+        Err(err) => {
+            error!("outbox_stats error: {err:?}");
+            db::OutboxStats::default()
+        }
+    };
+    Json(StatusResponse {
+        sessions,
+        messages,
+        outbox_pending: outbox.pending,
+        outbox_failed: outbox.failed,
+        outbox_dead: outbox.dead,
+        oldest_pending_age_seconds: outbox
+            .oldest_pending_at
+            .map(|at| (Utc::now() - at).num_seconds().max(0)),
+    })
 }
 
 async fn ws_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> impl IntoResponse {
     let rx = state.ws_tx.subscribe();
-    let token = state.config.auth.token.clone();
-    ws.on_upgrade(move |socket| ws::handle_ws(socket, rx, token))
+    let config = state.config();
+    let auth = config.auth.clone();
+    let bucket = ws::CommandBucket::new(config.ws.command_rate, config.ws.command_burst);
+    ws.max_frame_size(config.ws.max_frame_bytes)
+        .max_message_size(config.ws.max_frame_bytes)
+        .on_upgrade(move |socket| async move {
+            let _presence = ws::PresenceGuard::join(state.ws_clients.clone(), state.ws_tx.clone());
+            ws::handle_ws(socket, state, rx, auth, bucket).await
+        })
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundAckRequest {
+    outbox_id: Option<String>,
+    #[serde(default)]
+    inbound_id: Option<String>,
+    /// `ok` (the default) or `error`.
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
 }
 
-async fn inbound_ack() -> impl IntoResponse {
-    (StatusCode::OK, Json(json!({"status": "ok"})))
+/// Lets the backend confirm it handled an outbox row: `ok` marks it delivered
+/// and `error` marks it failed so the worker retries it with backoff. When
+/// `inbound_id` is given it must match the row's. Only rows still `pending` or
+/// `sending` can be acked; anything else is a 409.
+async fn inbound_ack(
+    State(state): State<AppState>,
+    Json(req): Json<InboundAckRequest>,
+) -> impl IntoResponse {
+    let Some(outbox_id) = req
+        .outbox_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+    else {
+        return ApiError::new(StatusCode::BAD_REQUEST, "outbox_id is required").into_response();
+    };
+    let failed = match req.status.as_deref().map(str::trim) {
+        None | Some("ok") => false,
+        Some("error") => true,
+        Some(other) => {
+            return ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("unknown ack status {other:?}"),
+            )
+            .into_response();
+        }
+    };
+    let row = match db::get_outbox_by_id(&state.pool, state.db_kind, outbox_id).await {
+        Ok(row) => row.filter(|row| {
+            req.inbound_id.as_deref().is_none_or(|inbound_id| {
+                let stored = row.inbound_id.as_deref();
+                stored.or(row.payload["inbound_id"].as_str()) == Some(inbound_id)
+            })
+        }),
+        Err(err) => {
+            error!("inbound_ack error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    };
+    let Some(row) = row else {
+        return ApiError::new(StatusCode::NOT_FOUND, "unknown outbox row")
+            .with_detail("outbox_id", outbox_id)
+            .into_response();
+    };
+    let updated = if failed {
+        let reason = req.error.as_deref().unwrap_or("backend reported an error");
+        let (retry, next) = outbox::next_failure(&row);
+        let error = format!("backend ack: {reason}");
+        db::settle_outbox_failed(
+            &state.pool,
+            state.db_kind,
+            &row.id,
+            &["pending", "sending"],
+            retry,
+            next,
+            &error,
+        )
+        .await
+    } else {
+        db::settle_outbox_delivered(&state.pool, state.db_kind, &row.id, &["pending", "sending"])
+            .await
+    };
+    match updated {
+        Ok(true) => {}
+        Ok(false) => {
+            return ApiError::new(StatusCode::CONFLICT, "outbox row already settled")
+                .with_detail("outbox_id", row.id)
+                .with_detail("outbox_status", row.status)
+                .into_response();
+        }
+        Err(err) => {
+            error!("inbound_ack error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    }
+    let outbox_status = if failed { "failed" } else { "delivered" };
+    Json(json!({"status": "ok", "outbox_id": row.id, "outbox_status": outbox_status}))
+        .into_response()
 }
 
 async fn runtime_inbound(
@@ -218,11 +945,30 @@ async fn runtime_inbound(
         Ok(()) => Json(json!({"status": "accepted"})).into_response(),
         Err(err) => {
             error!("runtime_inbound error: {err:?}");
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+    }
+}
+
+/// Accepts a normalized inbound message for any channel, e.g. one listed under
+/// `channels.custom`, and processes it like a native webhook delivery.
+async fn generic_inbound(
+    State(state): State<AppState>,
+    Json(inbound): Json<InboundMessage>,
+) -> impl IntoResponse {
+    if inbound.channel.trim().is_empty() || inbound.peer_id.trim().is_empty() {
+        return ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "channel and peer_id are required",
+        )
+        .into_response();
+    }
+    let inbound_id = inbound.inbound_id.clone();
+    match handle_inbound(state.clone(), inbound).await {
+        Ok(()) => Json(json!({"status": "accepted", "inbound_id": inbound_id})).into_response(),
+        Err(err) => {
+            error!("generic_inbound error: {err:?}");
+            ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into_response()
         }
     }
 }
@@ -232,65 +978,600 @@ async fn whatsapp_channel_status(State(state): State<AppState>) -> impl IntoResp
         Ok(value) => Json(value).into_response(),
         Err(err) => {
             error!("whatsapp_channel_status error: {err:?}");
-            (
-                StatusCode::BAD_GATEWAY,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()).into_response()
         }
     }
 }
 
-async fn channel_identities(State(state): State<AppState>) -> impl IntoResponse {
-    match runtime_value(&state, "/internal/identities").await {
-        Ok(value) => Json(value).into_response(),
+async fn get_config(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.config().redacted())
+}
+
+async fn admin_reload(State(state): State<AppState>) -> impl IntoResponse {
+    let next = match try_load_config() {
+        Ok(next) => next,
         Err(err) => {
-            error!("channel_identities error: {err:?}");
-            (
-                StatusCode::BAD_GATEWAY,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            error!("admin_reload error: {err:?}");
+            return ApiError::new(StatusCode::BAD_REQUEST, format!("{err:#}")).into_response();
         }
+    };
+    let requires_restart = reload_config(&state, next);
+    info!("config reloaded; restart required for {requires_restart:?}");
+    Json(json!({"status": "reloaded", "requires_restart": requires_restart})).into_response()
+}
+
+async fn admin_maintenance(State(state): State<AppState>) -> impl IntoResponse {
+    if !spawn_maintenance(&state) {
+        return ApiError::new(StatusCode::CONFLICT, "maintenance already running").into_response();
     }
+    (StatusCode::ACCEPTED, Json(json!({"status": "accepted"}))).into_response()
 }
 
-async fn whatsapp_channel_link(
+fn spawn_maintenance(state: &AppState) -> bool {
+    if state
+        .maintenance_running
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return false;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        let started = std::time::Instant::now();
+        match db::maintain(&state.pool, state.db_kind).await {
+            Ok(()) => info!("database maintenance finished in {:?}", started.elapsed()),
+            Err(err) => error!("database maintenance error: {err:?}"),
+        }
+        state.maintenance_running.store(false, Ordering::SeqCst);
+    });
+    true
+}
+
+async fn list_inbound_failures(
     State(state): State<AppState>,
-    Json(req): Json<ChannelLinkRequest>,
+    Query(page): Query<Pagination>,
 ) -> impl IntoResponse {
-    let payload = json!({ "force": req.force.unwrap_or(false) });
-    match runtime_post_value(&state, "/internal/whatsapp/link", &payload).await {
-        Ok(value) => Json(value).into_response(),
+    let limit = page.limit.unwrap_or(100).min(500);
+    let offset = page.offset.unwrap_or(0);
+    match db::list_inbound_failures(&state.read_pool, state.db_kind, limit, offset).await {
+        Ok(failures) => Json(failures).into_response(),
         Err(err) => {
-            error!("whatsapp_channel_link error: {err:?}");
-            (
-                StatusCode::BAD_GATEWAY,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            error!("list_inbound_failures error: {err:?}");
+            ApiError::internal(err).into_response()
         }
     }
 }
 
-async fn whatsapp_channel_logout(State(state): State<AppState>) -> impl IntoResponse {
+async fn retry_inbound_failure_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let record = match db::get_inbound_failure(&state.pool, state.db_kind, &id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "inbound failure not found")
+                .into_response();
+        }
+        Err(err) => {
+            return ApiError::internal(err).into_response();
+        }
+    };
+    if record.status == "resolved" {
+        return ApiError::new(StatusCode::CONFLICT, "inbound failure already resolved")
+            .into_response();
+    }
+    match claim_inbound_retry(&state, &record.id, &["pending", "dead"]).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ApiError::new(
+                StatusCode::CONFLICT,
+                "inbound failure is already being retried",
+            )
+            .with_detail("id", record.id)
+            .into_response();
+        }
+        Err(err) => return ApiError::internal(err).into_response(),
+    }
+    match retry_inbound_failure(&state, &record).await {
+        Ok(()) => Json(json!({"status": "resolved", "id": record.id})).into_response(),
+        Err(err) => ApiError::new(StatusCode::BAD_GATEWAY, format!("{err:#}"))
+            .with_detail("id", record.id)
+            .into_response(),
+    }
+}
+
+async fn list_media_failures(
+    State(state): State<AppState>,
+    Query(page): Query<Pagination>,
+) -> impl IntoResponse {
+    let limit = page.limit.unwrap_or(100).min(500);
+    let offset = page.offset.unwrap_or(0);
+    match db::list_media_failures(&state.read_pool, state.db_kind, limit, offset).await {
+        Ok(failures) => Json(failures).into_response(),
+        Err(err) => {
+            error!("list_media_failures error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+async fn retry_media_failure_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let record = match db::get_media_failure(&state.pool, state.db_kind, &id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "media failure not found").into_response();
+        }
+        Err(err) => {
+            return ApiError::internal(err).into_response();
+        }
+    };
+    if record.status == "resolved" {
+        return ApiError::new(StatusCode::CONFLICT, "media failure already resolved")
+            .into_response();
+    }
+    let now = Utc::now();
+    let lease_until = now + chrono::Duration::seconds(MEDIA_RETRY_LEASE_SECONDS);
+    match db::claim_media_failure(&state.pool, state.db_kind, &record.id, now, lease_until).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ApiError::new(
+                StatusCode::CONFLICT,
+                "media failure is already being retried",
+            )
+            .with_detail("id", record.id)
+            .into_response();
+        }
+        Err(err) => return ApiError::internal(err).into_response(),
+    }
+    match retry_media_failure(&state, &record).await {
+        Ok(attachment) => Json(json!({
+            "status": "resolved",
+            "id": record.id,
+            "message_id": record.message_id,
+            "attachment": attachment,
+        }))
+        .into_response(),
+        Err(err) => ApiError::new(StatusCode::BAD_GATEWAY, format!("{err:#}"))
+            .with_detail("id", record.id)
+            .into_response(),
+    }
+}
+
+const MEDIA_RETRY_LEASE_SECONDS: i64 = 300;
+
+/// Re-runs the upload for a claimed media failure. On success the stored
+/// message's attachment is repointed at the rehosted URL, the change is sent
+/// to WS clients and the backend, and the failure is resolved; on error the
+/// failure is released with its retry count and last error updated.
+async fn retry_media_failure(
+    state: &AppState,
+    record: &db::MediaFailureRecord,
+) -> anyhow::Result<Attachment> {
+    let config = state.config();
+    let result = async {
+        let message = db::get_message(&state.pool, state.db_kind, &record.message_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("message {} no longer exists", record.message_id))?;
+        let upload_url = config
+            .backend
+            .media_upload_url
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("backend.media_upload_url is not configured"))?;
+        let original: Attachment = serde_json::from_value(record.attachment.clone())?;
+        let uploaded = upload_attachment(
+            state,
+            &config,
+            upload_url,
+            &record.channel,
+            &record.session_key,
+            &original,
+            None,
+        )
+        .await?;
+        anyhow::Ok((message, original, uploaded))
+    }
+    .await;
+    let (mut message, original, uploaded) = match result {
+        Ok(found) => found,
+        Err(err) => {
+            db::mark_media_failure_retry(
+                &state.pool,
+                state.db_kind,
+                &record.id,
+                record.retry_count + 1,
+                &format!("{err:#}"),
+            )
+            .await?;
+            return Err(err);
+        }
+    };
+    let mut attachments: Vec<Attachment> = message
+        .attachments
+        .clone()
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default();
+    if let Some(slot) = attachments
+        .iter_mut()
+        .find(|att| att.url == original.url && att.id == original.id)
+    {
+        *slot = uploaded.clone();
+        let value = serde_json::to_value(&attachments)?;
+        db::update_message_attachments(&state.pool, state.db_kind, &message.id, &value).await?;
+        message.attachments = Some(value);
+        let binding = bind_route(
+            &config,
+            &message.channel,
+            message.account_id.as_deref(),
+            message.peer_id.as_deref(),
+            None,
+        );
+        let payload = json!({
+            "event": "media_rehosted",
+            "session_key": message.session_key,
+            "channel": message.channel,
+            "message_id": message.provider_message_id,
+            "original_url": original.url,
+            "attachment": uploaded,
+        });
+        db::insert_outbox(
+            &state.pool,
+            state.db_kind,
+            payload,
+            Utc::now(),
+            binding.webhook_url.as_deref(),
+            None,
+        )
+        .await?;
+        let _ = state.ws_tx.send(ws::WsEvent {
+            event: "chat".to_string(),
+            payload: json!({"direction": "inbound", "edited": true, "message": message}),
+        });
+    }
+    db::mark_media_failure_resolved(&state.pool, state.db_kind, &record.id).await?;
+    Ok(uploaded)
+}
+
+const SESSION_EVICTION_POLL_SECONDS: u64 = 300;
+
+/// Applies `session.max_sessions` and `session.idle_ttl_days`, read fresh on
+/// each pass so reloads take effect without a restart.
+async fn start_session_eviction_worker(state: AppState) {
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(
+            SESSION_EVICTION_POLL_SECONDS,
+        ))
+        .await;
+        if let Err(err) = evict_sessions(&state).await {
+            error!("session eviction error: {err:?}");
+        }
+    }
+}
+
+async fn evict_sessions(state: &AppState) -> anyhow::Result<Vec<String>> {
+    let (max_sessions, idle_ttl_days) = {
+        let config = state.config();
+        (config.session.max_sessions, config.session.idle_ttl_days)
+    };
+    if max_sessions == 0 && idle_ttl_days == 0 {
+        return Ok(Vec::new());
+    }
+    let idle_before = (idle_ttl_days > 0)
+        .then(|| Utc::now() - chrono::Duration::days(idle_ttl_days.min(36_500) as i64));
+    let evicted = db::evict_sessions(&state.pool, state.db_kind, max_sessions, idle_before).await?;
+    if !evicted.is_empty() {
+        info!("evicted {} sessions", evicted.len());
+    }
+    Ok(evicted)
+}
+
+const INBOUND_RETRY_POLL_SECONDS: u64 = 5;
+const INBOUND_RETRY_BATCH: i64 = 25;
+/// How long a claimed replay may run before the worker may claim it again.
+const INBOUND_REPLAY_LEASE_SECONDS: i64 = 300;
+
+/// Claims an inbound failure for one replay so the retry worker and a manual
+/// retry never replay it twice. Returns whether the caller got the row.
+async fn claim_inbound_retry(
+    state: &AppState,
+    id: &str,
+    statuses: &[&str],
+) -> anyhow::Result<bool> {
+    let now = Utc::now();
+    let lease_until = now + chrono::Duration::seconds(INBOUND_REPLAY_LEASE_SECONDS);
+    db::claim_inbound_failure(&state.pool, state.db_kind, id, statuses, now, lease_until).await
+}
+
+async fn start_inbound_retry_worker(state: AppState) {
+    loop {
+        match db::due_inbound_failures(&state.pool, state.db_kind, Utc::now(), INBOUND_RETRY_BATCH)
+            .await
+        {
+            Ok(rows) => {
+                for row in rows {
+                    match claim_inbound_retry(&state, &row.id, &["pending"]).await {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => {
+                            error!("inbound retry claim error: {err:?}");
+                            continue;
+                        }
+                    }
+                    if let Err(err) = retry_inbound_failure(&state, &row).await {
+                        warn!("inbound retry {} failed: {err:#}", row.id);
+                    }
+                }
+            }
+            Err(err) => error!("inbound retry poll error: {err:?}"),
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(INBOUND_RETRY_POLL_SECONDS)).await;
+    }
+}
+
+/// Replays a recorded inbound failure, resolving it on success and scheduling
+/// the next attempt (or marking it dead) on error. The caller must have claimed
+/// the row.
+async fn retry_inbound_failure(
+    state: &AppState,
+    record: &db::InboundFailureRecord,
+) -> anyhow::Result<()> {
+    let result = match serde_json::from_value::<InboundMessage>(record.payload.clone()) {
+        Ok(inbound) => handle_inbound(state.clone(), inbound).await,
+        Err(err) => Err(err.into()),
+    };
+    let err = match result {
+        Ok(()) => {
+            return db::mark_inbound_failure_resolved(&state.pool, state.db_kind, &record.id).await
+        }
+        Err(err) => err,
+    };
+    let retry = record.retry_count + 1;
+    let status = if retry >= db::OUTBOX_MAX_RETRIES {
+        "dead"
+    } else {
+        "pending"
+    };
+    let next = Utc::now() + outbox::compute_backoff(retry + 1);
+    db::mark_inbound_failure_retry(
+        &state.pool,
+        state.db_kind,
+        &record.id,
+        status,
+        retry,
+        next,
+        &format!("{err:#}"),
+    )
+    .await?;
+    Err(err)
+}
+
+fn reload_config(state: &AppState, mut next: Config) -> Vec<&'static str> {
+    let running = state.config();
+    let mut restart = Vec::new();
+    keep_running(
+        "server.host",
+        &running.server.host,
+        &mut next.server.host,
+        &mut restart,
+    );
+    keep_running(
+        "server.port",
+        &running.server.port,
+        &mut next.server.port,
+        &mut restart,
+    );
+    keep_running(
+        "server.compression",
+        &running.server.compression,
+        &mut next.server.compression,
+        &mut restart,
+    );
+    keep_running(
+        "server.base_path",
+        &running.server.base_path,
+        &mut next.server.base_path,
+        &mut restart,
+    );
+    keep_running(
+        "server.max_body_bytes",
+        &running.server.max_body_bytes,
+        &mut next.server.max_body_bytes,
+        &mut restart,
+    );
+    keep_running(
+        "server.status_requires_auth",
+        &running.server.status_requires_auth,
+        &mut next.server.status_requires_auth,
+        &mut restart,
+    );
+    keep_running("logging", &running.logging, &mut next.logging, &mut restart);
+    keep_running(
+        "database",
+        &running.database,
+        &mut next.database,
+        &mut restart,
+    );
+    let (run, new) = (&running.channels, &mut next.channels);
+    keep_running(
+        "channels.slack.webhook_path",
+        &run.slack.webhook_path,
+        &mut new.slack.webhook_path,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.enabled",
+        &run.telegram.enabled,
+        &mut new.telegram.enabled,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.transport",
+        &run.telegram.transport,
+        &mut new.telegram.transport,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.bot_token",
+        &run.telegram.bot_token,
+        &mut new.telegram.bot_token,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.poll_interval_seconds",
+        &run.telegram.poll_interval_seconds,
+        &mut new.telegram.poll_interval_seconds,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.webhook_path",
+        &run.telegram.webhook_path,
+        &mut new.telegram.webhook_path,
+        &mut restart,
+    );
+    keep_running(
+        "channels.whatsapp.inbound_path",
+        &run.whatsapp.inbound_path,
+        &mut new.whatsapp.inbound_path,
+        &mut restart,
+    );
+    keep_running(
+        "channels.teams.webhook_path",
+        &run.teams.webhook_path,
+        &mut new.teams.webhook_path,
+        &mut restart,
+    );
+    keep_running(
+        "channels.slack.max_webhook_bytes",
+        &run.slack.max_webhook_bytes,
+        &mut new.slack.max_webhook_bytes,
+        &mut restart,
+    );
+    keep_running(
+        "channels.telegram.max_webhook_bytes",
+        &run.telegram.max_webhook_bytes,
+        &mut new.telegram.max_webhook_bytes,
+        &mut restart,
+    );
+    keep_running(
+        "channels.whatsapp.max_webhook_bytes",
+        &run.whatsapp.max_webhook_bytes,
+        &mut new.whatsapp.max_webhook_bytes,
+        &mut restart,
+    );
+    keep_running(
+        "channels.teams.max_webhook_bytes",
+        &run.teams.max_webhook_bytes,
+        &mut new.teams.max_webhook_bytes,
+        &mut restart,
+    );
+    // A limiter whose permits changed is replaced; sends holding a permit of
+    // the old one finish on it. Unchanged limiters keep any 429 pause.
+    if let Ok(mut limits) = state.send_limits.write() {
+        for (channel, limiter) in build_send_limits(&next) {
+            if limits.get(&channel).map(channels::SendLimiter::permits) != Some(limiter.permits()) {
+                limits.insert(channel, limiter);
+            }
+        }
+    }
+    state.replace_config(next);
+    restart
+}
+
+fn keep_running<T: Serialize + Clone>(
+    name: &'static str,
+    running: &T,
+    next: &mut T,
+    restart: &mut Vec<&'static str>,
+) {
+    if serde_json::to_value(running).ok() != serde_json::to_value(&*next).ok() {
+        *next = running.clone();
+        restart.push(name);
+    }
+}
+
+async fn list_channels(State(state): State<AppState>) -> impl IntoResponse {
+    let health = state
+        .channel_health
+        .read()
+        .map(|health| health.clone())
+        .unwrap_or_default();
+    let channels = &state.config().channels;
+    let mut out = serde_json::Map::new();
+    for (name, enabled) in [
+        ("slack", channels.slack.enabled),
+        ("telegram", channels.telegram.enabled),
+        ("whatsapp", channels.whatsapp.enabled),
+        ("teams", channels.teams.enabled),
+    ] {
+        let entry = health.get(name).cloned().unwrap_or_default();
+        let status = ChannelStatus {
+            enabled,
+            configured: channel_configured(&state.config(), name),
+            last_inbound_at: entry.last_inbound_at,
+            last_lag_seconds: entry.last_lag_seconds,
+            last_error: entry.last_error,
+        };
+        out.insert(name.to_string(), json!(status));
+    }
+    Json(json!({ "channels": out }))
+}
+
+async fn channel_capabilities(Path(channel): Path<String>) -> impl IntoResponse {
+    match channels::capabilities(&channel) {
+        Some(caps) => Json(json!({"channel": channel, "capabilities": caps})).into_response(),
+        None => ApiError::new(StatusCode::NOT_FOUND, format!("unknown channel: {channel}"))
+            .into_response(),
+    }
+}
+
+async fn channel_identities(State(state): State<AppState>) -> impl IntoResponse {
+    match runtime_value(&state, "/internal/identities").await {
+        Ok(value) => Json(value).into_response(),
+        Err(err) => {
+            error!("channel_identities error: {err:?}");
+            ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()).into_response()
+        }
+    }
+}
+
+async fn whatsapp_channel_link(
+    State(state): State<AppState>,
+    Json(req): Json<ChannelLinkRequest>,
+) -> impl IntoResponse {
+    let payload = json!({ "force": req.force.unwrap_or(false) });
+    match runtime_post_value(&state, "/internal/whatsapp/link", &payload).await {
+        Ok(value) => Json(value).into_response(),
+        Err(err) => {
+            error!("whatsapp_channel_link error: {err:?}");
+            ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()).into_response()
+        }
+    }
+}
+
+async fn whatsapp_channel_logout(State(state): State<AppState>) -> impl IntoResponse {
     match runtime_post_value(&state, "/internal/whatsapp/logout", &json!({})).await {
         Ok(value) => Json(value).into_response(),
         Err(err) => {
             error!("whatsapp_channel_logout error: {err:?}");
-            (
-                StatusCode::BAD_GATEWAY,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()).into_response()
         }
     }
 }
 
 async fn send_message(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(req): Json<SendMessageRequest>,
 ) -> impl IntoResponse {
+    let config = state.config();
+    let dry_run = req.dry_run
+        || headers
+            .get("X-Agent-Ping-Dry-Run")
+            .and_then(|value| value.to_str().ok())
+            .and_then(config::parse_bool_env)
+            .unwrap_or(false);
     let attachments = req.attachments.unwrap_or_default();
     let outbound = OutboundMessage {
         session_key: req.session_key.clone(),
@@ -300,9 +1581,22 @@ async fn send_message(
         account_id: req.account_id.clone(),
         peer_id: req.peer_id.clone(),
         reply_to: req.reply_to.clone(),
+        caption_mode: req.caption_mode,
+        metadata: req.metadata.clone(),
+        peer_kind: req.peer_kind.clone(),
+        thread_id: req.thread_id.clone(),
+        sender_name: req.sender_name.clone(),
+        sender_icon: req.sender_icon.clone(),
+        reply_broadcast: req.reply_broadcast,
     };
 
-    match handle_outbound(state.clone(), outbound).await {
+    if dry_run {
+        return match preview_outbound(&state, &config, outbound).await {
+            Ok(preview) => Json(preview).into_response(),
+            Err(err) => err.into_response(),
+        };
+    }
+    match handle_outbound(state.clone(), &config, outbound).await {
         Ok(message_id) => Json(SendMessageResponse {
             message_id,
             status: "sent".to_string(),
@@ -310,21 +1604,107 @@ async fn send_message(
         .into_response(),
         Err(err) => {
             error!("send_message error: {err:?}");
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response()
+            err.into_response()
+        }
+    }
+}
+
+/// Sends to the most recently updated session of a linked identity, over that
+/// session's last route.
+async fn send_by_identity(
+    State(state): State<AppState>,
+    Json(req): Json<SendByIdentityRequest>,
+) -> impl IntoResponse {
+    let config = state.config();
+    let is_linked =
+        |session: &db::SessionRecord| session_linked_to(&config, session, &req.canonical_id);
+    let found =
+        db::find_session_by_identity(&state.pool, state.db_kind, &req.canonical_id, is_linked)
+            .await;
+    let record = match found {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "no session found for identity")
+                .with_code("unknown_identity")
+                .with_detail("canonical_id", req.canonical_id)
+                .into_response();
+        }
+        Err(err) => {
+            error!("send_by_identity error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    };
+    let outbound = OutboundMessage {
+        session_key: record.session_key,
+        text: req.text,
+        attachments: req.attachments.unwrap_or_default(),
+        channel: None,
+        account_id: None,
+        peer_id: None,
+        reply_to: None,
+        caption_mode: false,
+        metadata: req.metadata,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
+    };
+
+    if req.dry_run {
+        return match preview_outbound(&state, &config, outbound).await {
+            Ok(preview) => Json(preview).into_response(),
+            Err(err) => err.into_response(),
+        };
+    }
+    match handle_outbound(state.clone(), &config, outbound).await {
+        Ok(message_id) => Json(SendMessageResponse {
+            message_id,
+            status: "sent".to_string(),
+        })
+        .into_response(),
+        Err(err) => {
+            error!("send_by_identity error: {err:?}");
+            err.into_response()
         }
     }
 }
 
+/// Whether the peer on `session`'s last route is linked to `canonical_id` by
+/// the identity links stored on the session, keyed the way inbound peers are.
+fn session_linked_to(config: &Config, session: &db::SessionRecord, canonical_id: &str) -> bool {
+    let Some(links) = session
+        .identity_links
+        .clone()
+        .and_then(|links| serde_json::from_value::<HashMap<String, Vec<String>>>(links).ok())
+    else {
+        return false;
+    };
+    let Some(route) = session.last_route.as_ref() else {
+        return false;
+    };
+    let field = |name: &str| route.get(name).and_then(|value| value.as_str());
+    let (Some(channel), Some(peer_id)) = (field("channel"), field("peer_id")) else {
+        return false;
+    };
+    let peer = session_peer_id(config, channel, field("account_id"), peer_id);
+    session::resolve_identity_link(&links, channel, &peer)
+        .is_some_and(|linked| linked == session::normalize_token(canonical_id))
+}
+
 async fn send_bulk(
     State(state): State<AppState>,
     Json(req): Json<BulkSendRequest>,
 ) -> impl IntoResponse {
+    let config = state.config();
     let mut results = Vec::new();
+    let mut seen: HashMap<String, serde_json::Value> = HashMap::new();
     for msg in req.messages {
+        let batch_key = msg.batch_key();
+        if let Some(result) = seen.get(&batch_key) {
+            results.push(result.clone());
+            continue;
+        }
         let attachments = msg.attachments.unwrap_or_default();
         let outbound = OutboundMessage {
             session_key: msg.session_key.clone(),
@@ -334,86 +1714,614 @@ async fn send_bulk(
             account_id: msg.account_id.clone(),
             peer_id: msg.peer_id.clone(),
             reply_to: msg.reply_to.clone(),
+            caption_mode: msg.caption_mode,
+            metadata: msg.metadata.clone(),
+            peer_kind: msg.peer_kind.clone(),
+            thread_id: msg.thread_id.clone(),
+            sender_name: msg.sender_name.clone(),
+            sender_icon: msg.sender_icon.clone(),
+            reply_broadcast: msg.reply_broadcast,
         };
-        match handle_outbound(state.clone(), outbound).await {
-            Ok(message_id) => results.push(json!({"message_id": message_id, "status": "sent"})),
-            Err(err) => results.push(json!({"error": err.to_string()})),
-        }
+        let result = if msg.dry_run {
+            preview_outbound(&state, &config, outbound)
+                .await
+                .unwrap_or_else(|err| err.to_json())
+        } else {
+            match handle_outbound(state.clone(), &config, outbound).await {
+                Ok(message_id) => json!({"message_id": message_id, "status": "sent"}),
+                Err(err) => err.to_json(),
+            }
+        };
+        seen.insert(batch_key, result.clone());
+        results.push(result);
     }
     Json(json!({"results": results}))
 }
 
-async fn list_sessions(
+async fn edit_message(
     State(state): State<AppState>,
-    Query(page): Query<Pagination>,
+    Path(message_id): Path<String>,
+    Json(req): Json<EditMessageRequest>,
 ) -> impl IntoResponse {
-    let limit = page.limit.unwrap_or(100).min(500);
-    let offset = page.offset.unwrap_or(0);
-    let sessions = db::list_sessions(&state.pool, state.db_kind, limit, offset)
-        .await
-        .unwrap_or_default();
-    Json(sessions)
+    let result = async {
+        let mut message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .map_err(SendError::Internal)?
+            .filter(|message| message.direction == "outbound")
+            .ok_or(SendError::UnknownMessage)?;
+        edit_via_channel(&state, &message, &req.text).await?;
+        db::update_message_content(&state.pool, state.db_kind, &message.id, &req.text)
+            .await
+            .map_err(SendError::Internal)?;
+        message.content = Some(req.text.clone());
+        Ok::<_, SendError>(message)
+    }
+    .await;
+
+    match result {
+        Ok(message) => {
+            let _ = state.ws_tx.send(ws::WsEvent {
+                event: "chat".to_string(),
+                payload: json!({"direction": "outbound", "edited": true, "message": message}),
+            });
+            Json(json!({"message_id": message.id, "status": "edited"})).into_response()
+        }
+        Err(err) => {
+            error!("edit_message error: {err:?}");
+            err.into_response()
+        }
+    }
 }
 
-async fn get_session(
+async fn delete_message(
     State(state): State<AppState>,
-    Path(session_key): Path<String>,
+    Path(message_id): Path<String>,
 ) -> impl IntoResponse {
-    let session = db::get_session(&state.pool, state.db_kind, &session_key)
-        .await
-        .unwrap_or(None);
-    if let Some(session) = session {
-        Json(session).into_response()
-    } else {
-        StatusCode::NOT_FOUND.into_response()
+    let result = async {
+        let mut message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .map_err(SendError::Internal)?
+            .filter(|message| message.direction == "outbound" && message.status != "deleted")
+            .ok_or(SendError::UnknownMessage)?;
+        delete_via_channel(&state, &message).await?;
+        db::set_message_status(&state.pool, state.db_kind, &message.id, "deleted")
+            .await
+            .map_err(SendError::Internal)?;
+        message.status = "deleted".to_string();
+        Ok::<_, SendError>(message)
+    }
+    .await;
+
+    match result {
+        Ok(message) => {
+            let _ = state.ws_tx.send(ws::WsEvent {
+                event: "chat".to_string(),
+                payload: json!({"direction": "outbound", "deleted": true, "message": message}),
+            });
+            Json(json!({"message_id": message.id, "status": "deleted"})).into_response()
+        }
+        Err(err) => {
+            error!("delete_message error: {err:?}");
+            err.into_response()
+        }
     }
 }
 
-async fn list_messages(
-    State(state): State<AppState>,
-    Path(session_key): Path<String>,
-    Query(page): Query<Pagination>,
-) -> impl IntoResponse {
-    let limit = page.limit.unwrap_or(200).min(500);
-    let offset = page.offset.unwrap_or(0);
-    let messages = db::list_messages(&state.pool, state.db_kind, &session_key, limit, offset)
-        .await
-        .unwrap_or_default();
-    Json(messages)
+#[derive(Debug, Deserialize)]
+struct AddReactionRequest {
+    reaction: String,
 }
 
-async fn slack_events(
+async fn add_reaction(
     State(state): State<AppState>,
-    method: Method,
-    headers: HeaderMap,
-    RawQuery(query): RawQuery,
-    body: Bytes,
-) -> axum::response::Response {
-    if channel_transport(&state.config, "slack") == "embedded" {
-        return embedded_channel_webhook(state, "slack", method, headers, query, body).await;
+    Path(message_id): Path<String>,
+    Json(req): Json<AddReactionRequest>,
+) -> impl IntoResponse {
+    let result = async {
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .map_err(SendError::Internal)?
+            .ok_or(SendError::UnknownMessage)?;
+        react_via_channel(&state, &message, &req.reaction).await?;
+        Ok::<_, SendError>(message)
     }
+    .await;
 
-    let payload = match serde_json::from_slice::<serde_json::Value>(&body) {
-        Ok(payload) => payload,
+    match result {
+        Ok(message) => Json(json!({"message_id": message.id, "status": "reacted"})).into_response(),
         Err(err) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": format!("invalid slack payload: {err}")})),
-            )
-                .into_response();
-        }
-    };
-
-    if payload.get("type").and_then(|v| v.as_str()) == Some("url_verification") {
-        if let Some(challenge) = payload.get("challenge").and_then(|v| v.as_str()) {
-            return Json(json!({"challenge": challenge})).into_response();
+            error!("add_reaction error: {err:?}");
+            err.into_response()
         }
     }
+}
 
-    if let Some(inbound) = slack_channel::parse_slack_event(&payload) {
-        if let Err(err) = handle_inbound(state.clone(), inbound).await {
+async fn list_sessions(
+    State(state): State<AppState>,
+    Query(page): Query<Pagination>,
+) -> impl IntoResponse {
+    let limit = page.limit.unwrap_or(100).min(500);
+    let offset = page.offset.unwrap_or(0);
+    match db::list_sessions(&state.read_pool, state.db_kind, limit, offset).await {
+        Ok(sessions) => Json(sessions).into_response(),
+        Err(err) => {
+            error!("list_sessions error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+/// Most session keys one `POST /v1/sessions/batch` may look up.
+const SESSION_BATCH_MAX: usize = 200;
+
+/// Looks up many sessions at once, keyed by session key. Unknown keys are left
+/// out of the map.
+async fn get_sessions_batch(
+    State(state): State<AppState>,
+    Json(req): Json<SessionBatchRequest>,
+) -> impl IntoResponse {
+    let mut keys: Vec<String> = req
+        .session_keys
+        .iter()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    if keys.len() > SESSION_BATCH_MAX {
+        return ApiError::new(StatusCode::BAD_REQUEST, "too many session_keys")
+            .with_detail("max", SESSION_BATCH_MAX)
+            .into_response();
+    }
+    match db::get_sessions_by_keys(&state.read_pool, state.db_kind, &keys).await {
+        Ok(sessions) => {
+            let sessions: BTreeMap<String, db::SessionRecord> = sessions
+                .into_iter()
+                .map(|session| (session.session_key.clone(), session))
+                .collect();
+            Json(sessions).into_response()
+        }
+        Err(err) => {
+            error!("get_sessions_batch error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+async fn create_session(
+    State(state): State<AppState>,
+    Json(req): Json<CreateSessionRequest>,
+) -> axum::response::Response {
+    let config = state.config();
+    let non_empty = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let channel = non_empty(&req.channel).map(|channel| channel.to_lowercase());
+    let peer_id = non_empty(&req.peer_id).map(|peer_id| match channel.as_deref() {
+        Some(channel) => canonical_peer_id(channel, &peer_id),
+        None => peer_id,
+    });
+    let binding = match (channel.as_deref(), peer_id.as_deref()) {
+        (Some(channel), Some(peer_id)) => Some(bind_route(
+            &config,
+            channel,
+            req.account_id.as_deref(),
+            Some(peer_id),
+            req.thread_id.as_deref(),
+        )),
+        _ => None,
+    };
+    let agent_id = non_empty(&req.agent_id)
+        .or_else(|| binding.as_ref().and_then(|b| b.agent_id.clone()))
+        .unwrap_or_else(|| match channel.as_deref() {
+            Some(channel) => fallback_agent_id(&config, channel),
+            None => config.session.agent_id.clone(),
+        });
+
+    let session_key = match (
+        non_empty(&req.session_key),
+        channel.as_deref(),
+        peer_id.as_deref(),
+    ) {
+        (Some(session_key), _, _) => session_key,
+        (None, Some(channel), Some(peer_id)) => session::build_session_key(
+            &config.session,
+            Some(&agent_id),
+            channel,
+            req.account_id.as_deref(),
+            req.peer_kind.as_deref().unwrap_or("dm"),
+            &session_peer_id(&config, channel, req.account_id.as_deref(), peer_id),
+            req.thread_id.as_deref(),
+        ),
+        _ => {
+            return ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "session_key or channel and peer_id is required",
+            )
+            .into_response()
+        }
+    };
+
+    let result = async {
+        let existing = db::get_session(&state.pool, state.db_kind, &session_key).await?;
+        if existing.is_some() && !req.upsert {
+            return Ok(None);
+        }
+        let now = Utc::now();
+        let last_route = match (channel.as_deref(), peer_id.as_deref()) {
+            (Some(channel), Some(peer_id)) => Some(json!({
+                "channel": channel,
+                "account_id": req.account_id,
+                "peer_id": peer_id,
+                "thread_id": req.thread_id,
+            })),
+            _ => existing.as_ref().and_then(|s| s.last_route.clone()),
+        };
+        let record = db::SessionRecord {
+            session_key: session_key.clone(),
+            agent_id,
+            business_profile_id: non_empty(&req.business_profile_id)
+                .or_else(|| binding.as_ref().and_then(|b| b.business_profile_id.clone())),
+            user_id: non_empty(&req.user_id)
+                .or_else(|| binding.as_ref().and_then(|b| b.user_id.clone())),
+            last_route,
+            dm_scope: config.session.dm_scope.clone(),
+            identity_links: req.identity_links.clone(),
+            created_at: existing.as_ref().map(|s| s.created_at).unwrap_or(now),
+            updated_at: now,
+            metadata: req.metadata.clone(),
+        };
+        db::upsert_session(&state.pool, state.db_kind, &record).await?;
+        let stored = db::get_session(&state.pool, state.db_kind, &session_key).await?;
+        anyhow::Ok(Some((existing.is_some(), stored.unwrap_or(record))))
+    }
+    .await;
+
+    match result {
+        Ok(Some((false, session))) => (StatusCode::CREATED, Json(session)).into_response(),
+        Ok(Some((true, session))) => Json(session).into_response(),
+        Ok(None) => ApiError::new(StatusCode::CONFLICT, "session already exists")
+            .with_detail("session_key", session_key)
+            .into_response(),
+        Err(err) => {
+            error!("create_session error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+async fn get_session(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+) -> impl IntoResponse {
+    match db::get_session(&state.read_pool, state.db_kind, &session_key).await {
+        Ok(Some(session)) => Json(session).into_response(),
+        Ok(None) => SendError::UnknownSession.into_response(),
+        Err(err) => {
+            error!("get_session error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+/// Whether the session's WhatsApp messaging window is open. Sessions routed to
+/// other channels have no window and always report open.
+async fn get_session_window(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+) -> impl IntoResponse {
+    let session = match db::get_session(&state.read_pool, state.db_kind, &session_key).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return SendError::UnknownSession.into_response(),
+        Err(err) => {
+            error!("get_session_window error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    };
+    let channel = session
+        .last_route
+        .as_ref()
+        .and_then(|route| route["channel"].as_str());
+    if channel != Some("whatsapp") {
+        return Json(json!({"open": true, "expires_at": null})).into_response();
+    }
+    match db::last_inbound_at(&state.read_pool, state.db_kind, &session_key, "whatsapp").await {
+        Ok(last_inbound_at) => {
+            let (open, expires_at) =
+                whatsapp_channel::messaging_window(last_inbound_at, Utc::now());
+            Json(json!({"open": open, "expires_at": expires_at})).into_response()
+        }
+        Err(err) => {
+            error!("get_session_window error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+/// Appends a `system` message (a join, an assignment, an internal note) to a
+/// session. It is stored and streamed like chat but never sent to a channel.
+async fn append_system_message(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+    Json(req): Json<SystemMessageRequest>,
+) -> impl IntoResponse {
+    let text = req.text.trim();
+    if text.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "text is required").into_response();
+    }
+    let session = match db::get_session(&state.pool, state.db_kind, &session_key).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return SendError::UnknownSession.into_response(),
+        Err(err) => {
+            error!("append_system_message error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    };
+    let channel = session
+        .last_route
+        .as_ref()
+        .and_then(|route| route["channel"].as_str())
+        .unwrap_or(db::SYSTEM_DIRECTION);
+    let record = db::MessageRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        session_key: session_key.clone(),
+        direction: db::SYSTEM_DIRECTION.to_string(),
+        channel: channel.to_string(),
+        account_id: None,
+        peer_id: None,
+        content: Some(text.to_string()),
+        attachments: None,
+        status: "recorded".to_string(),
+        dedupe_key: None,
+        created_at: Utc::now(),
+        provider_message_id: None,
+        metadata: req.metadata,
+    };
+    if let Err(err) = db::insert_message(&state.pool, state.db_kind, &record).await {
+        error!("append_system_message error: {err:?}");
+        return ApiError::internal(err).into_response();
+    }
+    let _ = state.ws_tx.send(ws::WsEvent {
+        event: "chat".to_string(),
+        payload: json!({"direction": db::SYSTEM_DIRECTION, "system": true, "message": record}),
+    });
+    Json(SendMessageResponse {
+        message_id: record.id,
+        status: record.status,
+    })
+    .into_response()
+}
+
+/// With `channels.whatsapp.enforce_messaging_window`, refuses WhatsApp sends
+/// to sessions whose last inbound message is older than the messaging window.
+async fn check_messaging_window(
+    state: &AppState,
+    config: &Config,
+    route: &RouteInfo,
+    session_key: &str,
+) -> Result<(), SendError> {
+    if route.channel != "whatsapp" || !config.channels.whatsapp.enforce_messaging_window {
+        return Ok(());
+    }
+    let last_inbound_at = db::last_inbound_at(&state.pool, state.db_kind, session_key, "whatsapp")
+        .await
+        .map_err(SendError::Internal)?;
+    let (open, _) = whatsapp_channel::messaging_window(last_inbound_at, Utc::now());
+    if !open {
+        return Err(SendError::WindowClosed);
+    }
+    Ok(())
+}
+
+async fn set_session_route(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+    Json(route): Json<RouteInfo>,
+) -> axum::response::Response {
+    let channel = route.channel.trim().to_lowercase();
+    if !matches!(
+        channel.as_str(),
+        "slack" | "telegram" | "whatsapp" | "teams"
+    ) && !state.config().channels.custom.contains_key(&channel)
+    {
+        return SendError::UnsupportedChannel(route.channel).into_response();
+    }
+    let peer_id = route
+        .peer_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|peer| !peer.is_empty());
+    let Some(peer_id) = peer_id else {
+        return SendError::MissingPeer(channel).into_response();
+    };
+    let last_route = json!({
+        "channel": channel,
+        "account_id": route.account_id,
+        "peer_id": peer_id,
+        "peer_kind": route.peer_kind,
+        "thread_id": route.thread_id,
+    });
+    match db::set_session_route(
+        &state.pool,
+        state.db_kind,
+        &session_key,
+        &last_route,
+        Utc::now(),
+    )
+    .await
+    {
+        Ok(true) => {}
+        Ok(false) => return SendError::UnknownSession.into_response(),
+        Err(err) => {
+            error!("set_session_route error: {err:?}");
+            return ApiError::internal(err).into_response();
+        }
+    }
+    match db::get_session(&state.pool, state.db_kind, &session_key).await {
+        Ok(Some(session)) => Json(session).into_response(),
+        Ok(None) => SendError::UnknownSession.into_response(),
+        Err(err) => {
+            error!("set_session_route error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+async fn list_messages(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+    Query(page): Query<MessageQuery>,
+) -> impl IntoResponse {
+    let limit = page.limit.unwrap_or(200).min(500);
+    let offset = page.offset.unwrap_or(0);
+    let after = match page
+        .after
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    {
+        None => None,
+        Some(raw) => match raw.parse::<i64>() {
+            Ok(millis) => DateTime::<Utc>::from_timestamp_millis(millis),
+            Err(_) => {
+                match db::get_message_created_at(&state.read_pool, state.db_kind, raw).await {
+                    Ok(Some(created_at)) => Some(created_at),
+                    Ok(None) => {
+                        return ApiError::new(
+                            StatusCode::BAD_REQUEST,
+                            "unknown message id in after",
+                        )
+                        .into_response()
+                    }
+                    Err(err) => {
+                        error!("list_messages error: {err:?}");
+                        return ApiError::internal(err).into_response();
+                    }
+                }
+            }
+        },
+    };
+    match db::list_messages(
+        &state.read_pool,
+        state.db_kind,
+        &session_key,
+        after,
+        limit,
+        offset,
+    )
+    .await
+    {
+        Ok(messages) => Json(messages).into_response(),
+        Err(err) => {
+            error!("list_messages error: {err:?}");
+            ApiError::internal(err).into_response()
+        }
+    }
+}
+
+async fn stream_messages(
+    State(state): State<AppState>,
+    Path(session_key): Path<String>,
+) -> impl IntoResponse {
+    use futures::StreamExt;
+
+    let rows = db::stream_messages(state.read_pool.clone(), state.db_kind, session_key);
+    let lines = rows.map(|row| {
+        let record = row.map_err(|err| {
+            error!("stream_messages error: {err:?}");
+            std::io::Error::other(err.to_string())
+        })?;
+        let mut line = serde_json::to_vec(&record).map_err(std::io::Error::other)?;
+        line.push(b'\n');
+        Ok::<_, std::io::Error>(Bytes::from(line))
+    });
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+}
+
+async fn slack_events(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+    body: Bytes,
+) -> axum::response::Response {
+    let config = state.config();
+    if let Some(secret) = config.channels.slack.signing_secret.as_deref() {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let verified = match (
+            header("X-Slack-Request-Timestamp"),
+            header("X-Slack-Signature"),
+        ) {
+            (Some(timestamp), Some(signature)) => slack_channel::verify_slack_signature(
+                secret,
+                timestamp,
+                &body,
+                signature,
+                Utc::now().timestamp(),
+            ),
+            _ => false,
+        };
+        if !verified {
+            warn!("rejected slack event with a missing, stale or invalid signature");
+            return ApiError::new(StatusCode::UNAUTHORIZED, "invalid slack signature")
+                .into_response();
+        }
+    }
+    if channel_transport(&config, "slack") == "embedded" {
+        return embedded_channel_webhook(state, "slack", method, headers, query, body).await;
+    }
+
+    let payload = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            return ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("invalid slack payload: {err}"),
+            )
+            .into_response();
+        }
+    };
+
+    if payload.get("type").and_then(|v| v.as_str()) == Some("url_verification") {
+        if let Some(challenge) = payload.get("challenge").and_then(|v| v.as_str()) {
+            return Json(json!({"challenge": challenge})).into_response();
+        }
+    }
+
+    let track_edits = config.channels.slack.track_edits;
+    if let Some(inbound) = slack_channel::parse_slack_event(&payload) {
+        // Transcription can outlast Slack's 3 s ack deadline, after which the
+        // event is redelivered, so audio messages are processed after the ack.
+        if transcribes_audio(&config, &inbound) {
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_acked_inbound(&state, inbound).await {
+                    error!("slack inbound error: {err:?}");
+                }
+            });
+        } else if let Err(err) = handle_acked_inbound(&state, inbound).await {
             error!("slack inbound error: {err:?}");
         }
+    } else if let Some(edit) = track_edits
+        .then(|| slack_channel::parse_slack_message_change(&payload))
+        .flatten()
+    {
+        if let Err(err) = handle_message_change(&state, edit).await {
+            error!("slack message change error: {err:?}");
+        }
+    } else if config.channels.slack.inbound_reactions {
+        if let Some(reaction) = slack_channel::parse_slack_reaction(&payload) {
+            if let Err(err) = handle_reaction(&state, reaction).await {
+                error!("slack reaction error: {err:?}");
+            }
+        }
     }
     Json(json!({"ok": true})).into_response()
 }
@@ -425,29 +2333,32 @@ async fn telegram_webhook(
     RawQuery(query): RawQuery,
     body: Bytes,
 ) -> axum::response::Response {
-    if channel_transport(&state.config, "telegram") == "embedded" {
+    let config = state.config();
+    if channel_transport(&config, "telegram") == "embedded" {
         return embedded_channel_webhook(state, "telegram", method, headers, query, body).await;
     }
 
     let payload = match serde_json::from_slice::<serde_json::Value>(&body) {
         Ok(payload) => payload,
         Err(err) => {
-            return (
+            return ApiError::new(
                 StatusCode::BAD_REQUEST,
-                Json(json!({"error": format!("invalid telegram payload: {err}")})),
+                format!("invalid telegram payload: {err}"),
             )
-                .into_response();
+            .into_response();
         }
     };
 
     if let Some(inbound) = telegram_channel::parse_telegram_update(&payload) {
         if let Err(err) = handle_inbound(state.clone(), inbound).await {
             error!("telegram inbound error: {err:?}");
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response();
+            return ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into_response();
+        }
+    } else if config.channels.telegram.inbound_reactions {
+        for reaction in telegram_channel::parse_telegram_reaction(&payload) {
+            if let Err(err) = handle_reaction(&state, reaction).await {
+                error!("telegram reaction error: {err:?}");
+            }
         }
     }
 
@@ -461,29 +2372,25 @@ async fn whatsapp_inbound(
     RawQuery(query): RawQuery,
     body: Bytes,
 ) -> axum::response::Response {
-    if channel_transport(&state.config, "whatsapp") == "embedded" {
+    if channel_transport(&state.config(), "whatsapp") == "embedded" {
         return embedded_channel_webhook(state, "whatsapp", method, headers, query, body).await;
     }
 
     let payload = match serde_json::from_slice::<whatsapp_channel::WhatsAppInboundPayload>(&body) {
         Ok(payload) => payload,
         Err(err) => {
-            return (
+            return ApiError::new(
                 StatusCode::BAD_REQUEST,
-                Json(json!({"error": format!("invalid whatsapp payload: {err}")})),
+                format!("invalid whatsapp payload: {err}"),
             )
-                .into_response();
+            .into_response();
         }
     };
 
     let inbound = whatsapp_channel::normalize_whatsapp_inbound(payload);
     if let Err(err) = handle_inbound(state.clone(), inbound).await {
         error!("whatsapp inbound error: {err:?}");
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": err.to_string()})),
-        )
-            .into_response();
+        return ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into_response();
     }
     Json(json!({"status": "accepted"})).into_response()
 }
@@ -494,16 +2401,16 @@ async fn whatsapp_verify(
     headers: HeaderMap,
     RawQuery(query): RawQuery,
 ) -> axum::response::Response {
-    if channel_transport(&state.config, "whatsapp") == "embedded" {
+    if channel_transport(&state.config(), "whatsapp") == "embedded" {
         return embedded_channel_webhook(state, "whatsapp", method, headers, query, Bytes::new())
             .await;
     }
 
-    (
+    ApiError::new(
         StatusCode::METHOD_NOT_ALLOWED,
-        Json(json!({"error": "whatsapp verification requires embedded transport"})),
+        "whatsapp verification requires embedded transport",
     )
-        .into_response()
+    .into_response()
 }
 
 async fn teams_webhook(
@@ -513,15 +2420,15 @@ async fn teams_webhook(
     RawQuery(query): RawQuery,
     body: Bytes,
 ) -> axum::response::Response {
-    if channel_transport(&state.config, "teams") == "embedded" {
+    if channel_transport(&state.config(), "teams") == "embedded" {
         return embedded_channel_webhook(state, "teams", method, headers, query, body).await;
     }
 
-    (
+    ApiError::new(
         StatusCode::NOT_IMPLEMENTED,
-        Json(json!({"error": "teams webhook requires embedded transport"})),
+        "teams webhook requires embedded transport",
     )
-        .into_response()
+    .into_response()
 }
 
 async fn embedded_channel_webhook(
@@ -532,15 +2439,16 @@ async fn embedded_channel_webhook(
     query: Option<String>,
     body: Bytes,
 ) -> axum::response::Response {
-    let Some(runtime_url) = state.config.adapters.runtime_url.as_deref() else {
-        return (
+    let config = state.config();
+    let Some(runtime_url) = config.adapters.runtime_url.as_deref() else {
+        return ApiError::new(
             StatusCode::BAD_GATEWAY,
-            Json(json!({"error": "embedded adapter runtime url not configured"})),
+            "embedded adapter runtime url not configured",
         )
-            .into_response();
+        .into_response();
     };
 
-    let path = channel_webhook_path(&state.config, channel);
+    let path = channel_webhook_path(&config, channel);
     let mut runtime_response = match adapters::runtime::ingest(
         &state.http,
         runtime_url,
@@ -556,11 +2464,7 @@ async fn embedded_channel_webhook(
         Ok(response) => response,
         Err(err) => {
             error!("{channel} embedded inbound error: {err:?}");
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response();
+            return ApiError::new(StatusCode::BAD_GATEWAY, err.to_string()).into_response();
         }
     };
 
@@ -568,11 +2472,7 @@ async fn embedded_channel_webhook(
     for inbound in messages {
         if let Err(err) = handle_inbound(state.clone(), inbound).await {
             error!("{channel} inbound processing error: {err:?}");
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": err.to_string()})),
-            )
-                .into_response();
+            return ApiError::new(StatusCode::BAD_REQUEST, err.to_string()).into_response();
         }
     }
 
@@ -585,6 +2485,7 @@ fn runtime_response_into_response(
     let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
     let mut reply = status.into_response();
     *reply.body_mut() = Body::from(response.body);
+    reply.extensions_mut().insert(ProviderResponse);
     if let Some(content_type) = response.content_type {
         if let Ok(value) = content_type.parse() {
             reply
@@ -595,36 +2496,117 @@ fn runtime_response_into_response(
     reply
 }
 
-async fn handle_inbound(state: AppState, mut inbound: InboundMessage) -> anyhow::Result<()> {
-    let binding = match resolve_backend_binding(&state, &inbound).await {
+/// Processes an event the provider has already been acked for. Failures are
+/// parked in `inbound_failures` so the retry worker can replay them later.
+async fn handle_acked_inbound(state: &AppState, inbound: InboundMessage) -> anyhow::Result<()> {
+    let payload = serde_json::to_value(&inbound)?;
+    let mut session_key = None;
+    let Err(err) = process_inbound(state.clone(), inbound, &mut session_key).await else {
+        return Ok(());
+    };
+    let next = Utc::now() + outbox::compute_backoff(1);
+    let error = format!("{err:#}");
+    if let Err(record_err) = db::insert_inbound_failure(
+        &state.pool,
+        state.db_kind,
+        payload,
+        session_key.as_deref(),
+        &error,
+        next,
+    )
+    .await
+    {
+        error!("failed to record inbound failure: {record_err:?}");
+    }
+    Err(err)
+}
+
+async fn handle_inbound(state: AppState, inbound: InboundMessage) -> anyhow::Result<()> {
+    process_inbound(state, inbound, &mut None).await
+}
+
+/// Stores and forwards one inbound message. `stored_session` is set once the
+/// message's session exists, so a failure after that point can be tied to it.
+async fn process_inbound(
+    state: AppState,
+    mut inbound: InboundMessage,
+    stored_session: &mut Option<String>,
+) -> anyhow::Result<()> {
+    let config = state.config();
+    state.record_inbound(&inbound.channel, inbound.sent_at());
+    let mut original_text = None;
+    if config.queue.sanitize_inbound {
+        if let Some(text) = inbound.text.as_deref() {
+            let clean = sanitize_content(text, config.queue.collapse_whitespace);
+            if clean != text {
+                original_text = inbound.text.replace(clean);
+            }
+        }
+    }
+    let mut original_content_bytes = None;
+    if let (Some(max), Some(text)) = (config.queue.max_content_bytes, inbound.text.as_ref()) {
+        if text.len() > max {
+            if config.queue.on_oversize == "reject" {
+                warn!(
+                    "rejected {} inbound from {}: {} bytes exceeds {max}",
+                    inbound.channel,
+                    inbound.peer_id,
+                    text.len()
+                );
+                return Ok(());
+            }
+            original_content_bytes = Some(text.len());
+            inbound.text = Some(truncate_content(text, max));
+        }
+    }
+    let mut dropped_attachments = Vec::new();
+    if let Some(max) = config.queue.max_attachments {
+        dropped_attachments = cap_attachments(&mut inbound.attachments, max, &config.queue.drop);
+        if !dropped_attachments.is_empty() {
+            warn!(
+                "dropped {} of {} inbound attachments from {}",
+                dropped_attachments.len(),
+                dropped_attachments.len() + inbound.attachments.len(),
+                inbound.peer_id
+            );
+        }
+    }
+    let binding = match resolve_backend_binding(&state, &config, &inbound).await {
         Ok(Some(binding)) => binding,
-        Ok(None) => resolve_binding(
-            &state.config.bindings,
+        Ok(None) => bind_route(
+            &config,
             &inbound.channel,
             inbound.account_id.as_deref(),
             Some(&inbound.peer_id),
+            inbound.thread_id.as_deref(),
         ),
         Err(err) => {
             error!("backend route resolve error: {err:?}");
-            resolve_binding(
-                &state.config.bindings,
+            bind_route(
+                &config,
                 &inbound.channel,
                 inbound.account_id.as_deref(),
                 Some(&inbound.peer_id),
+                inbound.thread_id.as_deref(),
             )
         }
     };
     let resolved_agent_id = binding
         .agent_id
         .clone()
-        .unwrap_or_else(|| state.config.session.agent_id.clone());
+        .unwrap_or_else(|| fallback_agent_id(&config, &inbound.channel));
     let session_key = session::build_session_key(
-        &state.config.session,
+        &config.session,
         Some(resolved_agent_id.as_str()),
         &inbound.channel,
         inbound.account_id.as_deref(),
         &inbound.peer_kind,
-        &inbound.peer_id,
+        &session_peer_id(
+            &config,
+            &inbound.channel,
+            inbound.account_id.as_deref(),
+            &inbound.peer_id,
+        ),
         inbound.thread_id.as_deref(),
     );
 
@@ -632,7 +2614,9 @@ async fn handle_inbound(state: AppState, mut inbound: InboundMessage) -> anyhow:
         "channel": inbound.channel,
         "account_id": inbound.account_id,
         "peer_id": inbound.peer_id,
+        "peer_kind": inbound.peer_kind,
         "thread_id": inbound.thread_id,
+        "message_id": inbound.message_id,
     });
 
     let now = Utc::now();
@@ -642,41 +2626,48 @@ async fn handle_inbound(state: AppState, mut inbound: InboundMessage) -> anyhow:
         business_profile_id: binding.business_profile_id,
         user_id: binding.user_id,
         last_route: Some(last_route),
-        dm_scope: state.config.session.dm_scope.clone(),
-        identity_links: if state.config.session.identity_links.is_empty() {
+        dm_scope: config.session.dm_scope.clone(),
+        identity_links: if config.session.identity_links.is_empty() {
             None
         } else {
-            Some(serde_json::to_value(&state.config.session.identity_links).unwrap_or(json!({})))
+            Some(serde_json::to_value(&config.session.identity_links).unwrap_or(json!({})))
         },
         created_at: now,
         updated_at: now,
+        metadata: None,
     };
     db::upsert_session(&state.pool, state.db_kind, &session_record).await?;
+    *stored_session = Some(session_key.clone());
 
-    if let Some(dedupe_key) = inbound
+    let dedupe_key = inbound
         .message_id
         .clone()
-        .map(|id| format!("{}:{}:{}", inbound.channel, inbound.peer_id, id))
-    {
-        if db::message_dedupe_exists(&state.pool, state.db_kind, &dedupe_key)
+        .map(|id| format!("{}:{}:{}", inbound.channel, inbound.peer_id, id));
+    if let Some(dedupe_key) = dedupe_key.as_deref() {
+        if db::message_dedupe_exists(&state.pool, state.db_kind, dedupe_key)
             .await
             .unwrap_or(false)
         {
+            emit_dedupe(&state, &session_key, dedupe_key);
             return Ok(());
         }
     }
 
+    let message_id = uuid::Uuid::new_v4().to_string();
+    let mut media_failures = Vec::new();
     if !inbound.attachments.is_empty() {
-        inbound.attachments =
-            upload_media(&state, &inbound.channel, &session_key, &inbound.attachments).await;
+        let downloaded = transcribe_audio(&state, &config, &session_key, &mut inbound).await;
+        (inbound.attachments, media_failures) = upload_media(
+            &state,
+            &config,
+            &inbound.channel,
+            &session_key,
+            &inbound.attachments,
+            &downloaded,
+        )
+        .await;
     }
 
-    let message_id = uuid::Uuid::new_v4().to_string();
-    let dedupe_key = inbound
-        .message_id
-        .clone()
-        .map(|id| format!("{}:{}:{}", inbound.channel, inbound.peer_id, id));
-
     let record = db::MessageRecord {
         id: message_id.clone(),
         session_key: session_key.clone(),
@@ -687,12 +2678,44 @@ async fn handle_inbound(state: AppState, mut inbound: InboundMessage) -> anyhow:
         content: inbound.text.clone(),
         attachments: Some(serde_json::to_value(&inbound.attachments).unwrap_or(json!([]))),
         status: "received".to_string(),
-        dedupe_key,
-        created_at: now,
+        dedupe_key: dedupe_key.clone(),
+        created_at: inbound.sent_at().unwrap_or(now),
+        provider_message_id: inbound.message_id.clone(),
+        metadata: None,
     };
-    db::insert_message(&state.pool, state.db_kind, &record).await?;
+    let bridges = inbound_bridges(&state, &config, &inbound)
+        .await
+        .unwrap_or_else(|err| {
+            error!(
+                "bridge lookup for {} inbound failed: {err:?}",
+                inbound.channel
+            );
+            Vec::new()
+        });
+    // The message and its outbox row commit together, so a failed outbox
+    // insert leaves nothing behind for a replay to dedupe against.
+    let mut tx = state.pool.begin().await?;
+    if !db::insert_message(&mut *tx, state.db_kind, &record).await? {
+        if let Some(dedupe_key) = dedupe_key.as_deref() {
+            emit_dedupe(&state, &session_key, dedupe_key);
+        }
+        return Ok(());
+    }
+    for (attachment, error) in media_failures {
+        let attachment = serde_json::to_value(&attachment).unwrap_or(json!({}));
+        db::insert_media_failure(
+            &mut *tx,
+            state.db_kind,
+            &message_id,
+            &inbound.channel,
+            &session_key,
+            attachment,
+            &error,
+        )
+        .await?;
+    }
 
-    let payload = json!({
+    let mut payload = json!({
         "inbound_id": inbound.inbound_id,
         "session_key": session_key,
         "channel": inbound.channel,
@@ -709,62 +2732,530 @@ async fn handle_inbound(state: AppState, mut inbound: InboundMessage) -> anyhow:
         "user_id": session_record.user_id,
         "agent_id": session_record.agent_id,
     });
-
-    let next_attempt =
-        Utc::now() + chrono::Duration::milliseconds(state.config.queue.debounce_ms as i64);
-    let _ = db::insert_outbox(&state.pool, state.db_kind, payload, next_attempt).await?;
-
-    let _ = state.ws_tx.send(ws::WsEvent {
+    if let Some(bytes) = original_content_bytes {
+        payload["original_content_bytes"] = json!(bytes);
+    }
+    if let Some(text) = original_text {
+        payload["original_text"] = json!(text);
+    }
+    if !dropped_attachments.is_empty() {
+        payload["dropped_attachments"] = json!(dropped_attachments.len());
+        if config.queue.drop == "summarize" {
+            payload["dropped_attachments_summary"] =
+                json!(summarize_attachments(&dropped_attachments));
+        }
+    }
+
+    let forward = config.backend.forward_filter.allows(
+        &inbound.channel,
+        &inbound.peer_kind,
+        inbound.text.as_deref(),
+    );
+    if forward {
+        if let Some(template) = &config.backend.payload_template {
+            payload = outbox::render_payload_template(template, &payload);
+        }
+        let debounce_ms = channel_debounce_ms(&config, &inbound.channel);
+        let next_attempt = Utc::now() + chrono::Duration::milliseconds(debounce_ms as i64);
+        let _ = db::insert_outbox(
+            &mut *tx,
+            state.db_kind,
+            payload,
+            next_attempt,
+            binding.webhook_url.as_deref(),
+            Some(&inbound.inbound_id),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    let _ = state.ws_tx.send(ws::WsEvent {
         event: "chat".to_string(),
         payload: json!({"direction": "inbound", "message": record}),
     });
 
+    for to in bridges {
+        let target = to.channel.clone();
+        let outbound = bridged_outbound(&inbound, &session_key, &record.id, to);
+        let state = state.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_outbound(state, &config, outbound).await {
+                warn!("bridge send to {target} failed: {err}");
+            }
+        });
+    }
+
     Ok(())
 }
 
-async fn handle_outbound(state: AppState, outbound: OutboundMessage) -> anyhow::Result<String> {
-    let session = db::get_session(&state.pool, state.db_kind, &outbound.session_key).await?;
-    let route = if let Some(channel) = outbound.channel.clone() {
-        RouteInfo {
-            channel,
-            account_id: outbound.account_id.clone(),
-            peer_id: outbound.peer_id.clone(),
-            thread_id: None,
+/// Targets of the bridges whose `from` matches `inbound`. An inbound that is
+/// the echo of an earlier bridged send is never bridged again, so two-way
+/// bridges do not loop; echoes that arrive before the send returns are the
+/// bot's own messages, which the channel parsers already drop.
+async fn inbound_bridges(
+    state: &AppState,
+    config: &Config,
+    inbound: &InboundMessage,
+) -> anyhow::Result<Vec<RouteInfo>> {
+    let targets: Vec<RouteInfo> = config
+        .bridges
+        .iter()
+        .filter(|bridge| bridge_matches(&bridge.from, inbound))
+        .map(|bridge| bridge.to.clone())
+        .collect();
+    if targets.is_empty() {
+        return Ok(targets);
+    }
+    if let Some(message_id) = inbound.message_id.as_deref() {
+        let echo = db::find_message_by_provider_id(
+            &state.pool,
+            state.db_kind,
+            &inbound.channel,
+            &inbound.peer_id,
+            message_id,
+        )
+        .await?
+        .is_some_and(|message| {
+            message.direction == "outbound"
+                && message
+                    .metadata
+                    .as_ref()
+                    .is_some_and(|meta| meta.get("bridge").is_some())
+        });
+        if echo {
+            debug!(
+                "not bridging {} message {message_id}: sent by a bridge",
+                inbound.channel
+            );
+            return Ok(Vec::new());
+        }
+    }
+    Ok(targets)
+}
+
+fn bridge_matches(from: &config::RouteMatch, inbound: &InboundMessage) -> bool {
+    let same = |want: Option<&str>, got: Option<&str>| {
+        want.map(str::trim)
+            .filter(|want| !want.is_empty())
+            .is_none_or(|want| got.is_some_and(|got| got.trim().eq_ignore_ascii_case(want)))
+    };
+    let peer_matches = match (inbound.channel.as_str(), from.peer_id.as_deref()) {
+        ("whatsapp", Some(peer)) => {
+            whatsapp_channel::normalize_phone_number(peer)
+                == whatsapp_channel::normalize_phone_number(&inbound.peer_id)
+        }
+        (_, peer) => same(peer, Some(&inbound.peer_id)),
+    };
+    same(Some(&from.channel), Some(&inbound.channel))
+        && same(from.account_id.as_deref(), inbound.account_id.as_deref())
+        && peer_matches
+        && same(from.peer_kind.as_deref(), Some(&inbound.peer_kind))
+        && same(from.thread_id.as_deref(), inbound.thread_id.as_deref())
+}
+
+/// The outbound mirroring `inbound` on a bridge target, prefixed with the
+/// sender's name. `metadata.bridge` marks it so its echo is not re-bridged.
+fn bridged_outbound(
+    inbound: &InboundMessage,
+    session_key: &str,
+    message_id: &str,
+    to: RouteInfo,
+) -> OutboundMessage {
+    let text = match (inbound.sender_name.as_deref(), inbound.text.as_deref()) {
+        (Some(sender), Some(text)) => Some(format!("{sender}: {text}")),
+        (_, text) => text.map(str::to_string),
+    };
+    OutboundMessage {
+        session_key: String::new(),
+        text,
+        attachments: inbound.attachments.clone(),
+        channel: Some(to.channel),
+        account_id: to.account_id,
+        peer_id: to.peer_id,
+        reply_to: None,
+        caption_mode: false,
+        metadata: Some(json!({"bridge": {
+            "channel": inbound.channel,
+            "peer_id": inbound.peer_id,
+            "session_key": session_key,
+            "message_id": message_id,
+        }})),
+        peer_kind: to.peer_kind,
+        thread_id: to.thread_id,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
+    }
+}
+
+fn truncate_content(text: &str, max_bytes: usize) -> String {
+    const MARKER: &str = "…";
+    let with_marker = max_bytes >= MARKER.len();
+    let mut end = if with_marker {
+        max_bytes - MARKER.len()
+    } else {
+        max_bytes
+    };
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    if with_marker {
+        format!("{}{MARKER}", &text[..end])
+    } else {
+        text[..end].to_string()
+    }
+}
+
+/// Keeps at most `max` attachments and returns the rest. The `old` drop policy
+/// drops the earliest ones; any other policy keeps the first `max`.
+fn cap_attachments(attachments: &mut Vec<Attachment>, max: usize, drop: &str) -> Vec<Attachment> {
+    if attachments.len() <= max {
+        return Vec::new();
+    }
+    if drop == "old" {
+        let excess = attachments.len() - max;
+        attachments.drain(..excess).collect()
+    } else {
+        attachments.split_off(max)
+    }
+}
+
+/// One line naming dropped attachments by filename, falling back to MIME type.
+fn summarize_attachments(attachments: &[Attachment]) -> String {
+    let names: Vec<&str> = attachments
+        .iter()
+        .map(|att| {
+            att.filename
+                .as_deref()
+                .or(att.mime_type.as_deref())
+                .unwrap_or("attachment")
+        })
+        .collect();
+    format!(
+        "{} more attachments: {}",
+        attachments.len(),
+        names.join(", ")
+    )
+}
+
+/// Invisible characters that can reorder or disguise text: zero-width spaces,
+/// the word joiner, the BOM and bidi marks, embeddings, overrides and isolates.
+/// ZWJ and ZWNJ stay, since emoji sequences and several scripts rely on them.
+fn is_disallowed_char(c: char) -> bool {
+    (c.is_control() && c != '\n' && c != '\t')
+        || matches!(
+            c,
+            '\u{061C}'
+                | '\u{200B}'
+                | '\u{200E}'
+                | '\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'
+                | '\u{2066}'..='\u{2069}'
+                | '\u{FEFF}'
+        )
+}
+
+/// Drops disallowed characters and normalizes to NFC. With
+/// `collapse_whitespace`, runs of spaces and tabs become one space, more than
+/// one blank line becomes one, and the ends are trimmed.
+fn sanitize_content(text: &str, collapse_whitespace: bool) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    let clean: String = text
+        .chars()
+        .filter(|c| !is_disallowed_char(*c))
+        .nfc()
+        .collect();
+    if !collapse_whitespace {
+        return clean;
+    }
+    let mut out = String::with_capacity(clean.len());
+    let mut blank_lines = 0;
+    for line in clean.split('\n') {
+        let line = line
+            .split([' ', '\t'])
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
         }
-    } else if let Some(session) = session {
-        if let Some(last_route) = session.last_route {
-            let channel = last_route
-                .get("channel")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default()
-                .to_string();
-            let account_id = last_route
-                .get("account_id")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let peer_id = last_route
-                .get("peer_id")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let thread_id = last_route
-                .get("thread_id")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            RouteInfo {
+        if !out.is_empty() {
+            out.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        blank_lines = 0;
+        out.push_str(&line.join(" "));
+    }
+    out
+}
+
+fn emit_dedupe(state: &AppState, session_key: &str, dedupe_key: &str) {
+    debug!("suppressed duplicate inbound {dedupe_key} for {session_key}");
+    let _ = state.ws_tx.send(ws::WsEvent {
+        event: "dedupe".to_string(),
+        payload: json!({"session_key": session_key, "dedupe_key": dedupe_key}),
+    });
+}
+
+async fn handle_message_change(state: &AppState, edit: InboundEdit) -> anyhow::Result<()> {
+    state.record_inbound(&edit.channel, None);
+    let Some(mut message) = db::find_message_by_provider_id(
+        &state.pool,
+        state.db_kind,
+        &edit.channel,
+        &edit.peer_id,
+        &edit.message_id,
+    )
+    .await?
+    else {
+        debug!(
+            "{} change for unknown message {} in {}",
+            edit.channel, edit.message_id, edit.peer_id
+        );
+        return Ok(());
+    };
+
+    let mut payload = json!({"direction": message.direction});
+    if edit.deleted {
+        db::set_message_status(&state.pool, state.db_kind, &message.id, "deleted").await?;
+        message.status = "deleted".to_string();
+        payload["deleted"] = json!(true);
+    } else {
+        let text = edit.text.unwrap_or_default();
+        db::update_message_content(&state.pool, state.db_kind, &message.id, &text).await?;
+        message.content = Some(text);
+        payload["edited"] = json!(true);
+    }
+    payload["message"] = json!(message);
+    let _ = state.ws_tx.send(ws::WsEvent {
+        event: "chat".to_string(),
+        payload,
+    });
+    Ok(())
+}
+
+async fn handle_reaction(state: &AppState, reaction: InboundReaction) -> anyhow::Result<()> {
+    state.record_inbound(&reaction.channel, None);
+    let message = db::find_message_by_provider_id(
+        &state.pool,
+        state.db_kind,
+        &reaction.channel,
+        &reaction.peer_id,
+        &reaction.message_id,
+    )
+    .await?;
+    let _ = state.ws_tx.send(ws::WsEvent {
+        event: "reaction".to_string(),
+        payload: json!({
+            "reaction": reaction,
+            "message_id": message.as_ref().map(|message| message.id.clone()),
+            "session_key": message.as_ref().map(|message| message.session_key.clone()),
+        }),
+    });
+    Ok(())
+}
+
+/// The peer id used in session keys. Slack channel ids are only unique within
+/// a workspace, so with `channels.slack.qualify_peer_ids` they are prefixed
+/// with the team id (`account_id`) when one is known.
+fn session_peer_id(
+    config: &Config,
+    channel: &str,
+    account_id: Option<&str>,
+    peer_id: &str,
+) -> String {
+    let team = account_id.map(str::trim).filter(|team| !team.is_empty());
+    match team {
+        Some(team)
+            if config.channels.slack.qualify_peer_ids
+                && session::normalize_token(channel) == "slack" =>
+        {
+            format!("{team}:{}", peer_id.trim())
+        }
+        _ => canonical_peer_id(channel, peer_id),
+    }
+}
+
+/// `peer_id` as inbound messages carry it: WhatsApp numbers in E.164, other
+/// channels' ids unchanged. Outbound peers go through this too so both
+/// directions land on the same session.
+fn canonical_peer_id(channel: &str, peer_id: &str) -> String {
+    if session::normalize_token(channel) == "whatsapp" {
+        whatsapp_channel::normalize_phone_number(peer_id)
+    } else {
+        peer_id.to_string()
+    }
+}
+
+const REPLY_QUOTE_CHARS: usize = 80;
+
+fn reply_quote_enabled(config: &Config, channel: &str) -> bool {
+    match channel {
+        "telegram" => config.channels.telegram.reply_quote,
+        "whatsapp" => config.channels.whatsapp.reply_quote,
+        _ => false,
+    }
+}
+
+/// `text` preceded by a one-line `> ` quote of `quoted`, with whitespace
+/// collapsed and cut to `REPLY_QUOTE_CHARS` characters.
+fn quote_reply(quoted: &str, text: Option<&str>) -> String {
+    let snippet = quoted.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut line: String = snippet.chars().take(REPLY_QUOTE_CHARS).collect();
+    if snippet.chars().count() > REPLY_QUOTE_CHARS {
+        line.push('…');
+    }
+    match text {
+        Some(text) => format!("> {line}\n{text}"),
+        None => format!("> {line}"),
+    }
+}
+
+/// Fills in the session key from the binding when only a channel and peer are
+/// given, resolves where the message would go, and maps a `reply_to` naming a
+/// stored message on that channel to its provider id. With `reply_quote` on
+/// for the channel, the stored message is also quoted at the top of the text.
+async fn prepare_outbound(
+    state: &AppState,
+    config: &Config,
+    outbound: &mut OutboundMessage,
+) -> Result<(Option<db::SessionRecord>, RouteInfo), SendError> {
+    if let (Some(channel), Some(peer_id)) = (outbound.channel.as_deref(), outbound.peer_id.as_mut())
+    {
+        *peer_id = canonical_peer_id(channel, peer_id);
+    }
+    if outbound.session_key.trim().is_empty() {
+        if let (Some(channel), Some(peer_id)) =
+            (outbound.channel.as_deref(), outbound.peer_id.as_deref())
+        {
+            let binding = bind_route(
+                config,
                 channel,
-                account_id,
-                peer_id,
-                thread_id,
+                outbound.account_id.as_deref(),
+                Some(peer_id),
+                outbound.thread_id.as_deref(),
+            );
+            outbound.session_key = session::build_session_key(
+                &config.session,
+                binding.agent_id.as_deref(),
+                channel,
+                outbound.account_id.as_deref(),
+                outbound.peer_kind.as_deref().unwrap_or("dm"),
+                &session_peer_id(config, channel, outbound.account_id.as_deref(), peer_id),
+                outbound.thread_id.as_deref(),
+            );
+        }
+    }
+
+    let session = db::get_session(&state.pool, state.db_kind, &outbound.session_key)
+        .await
+        .map_err(SendError::Internal)?;
+    let route = resolve_outbound_route(config, session.as_ref(), outbound)?;
+    if let Some(reply_to) = outbound.reply_to.as_deref() {
+        let stored = db::get_message(&state.pool, state.db_kind, reply_to)
+            .await
+            .map_err(SendError::Internal)?;
+        if let Some(quoted) = stored
+            .as_ref()
+            .and_then(|message| message.content.as_deref())
+            .filter(|content| !content.trim().is_empty())
+            .filter(|_| reply_quote_enabled(config, &route.channel))
+        {
+            outbound.text = Some(quote_reply(quoted, outbound.text.as_deref()));
+        }
+        if let Some(provider_id) = stored
+            .filter(|message| message.channel == route.channel)
+            .and_then(|message| message.provider_message_id)
+        {
+            outbound.reply_to = Some(provider_id);
+        }
+    }
+    Ok((session, route))
+}
+
+/// Runs the send path up to delivery and returns the resolved route and the
+/// payload that would be sent, without touching the channel or the database.
+async fn preview_outbound(
+    state: &AppState,
+    config: &Config,
+    mut outbound: OutboundMessage,
+) -> Result<serde_json::Value, SendError> {
+    let (_, route) = prepare_outbound(state, config, &mut outbound).await?;
+    if config.channels.custom.contains_key(&route.channel) {
+        return Ok(json!({
+            "status": "dry_run",
+            "session_key": outbound.session_key,
+            "route": route,
+            "payload": custom_channel::custom_send_payload(&outbound.session_key, &route, &outbound),
+        }));
+    }
+    let native = channel_transport(config, &route.channel) != "embedded";
+    if native && !channels::capabilities(&route.channel).is_some_and(|caps| caps.send) {
+        return Err(SendError::UnsupportedChannel(route.channel.clone()));
+    }
+    check_messaging_window(state, config, &route, &outbound.session_key).await?;
+    let payload = match (native, route.channel.as_str(), route.peer_id.as_deref()) {
+        (true, "slack" | "telegram" | "whatsapp", None) => {
+            return Err(SendError::MissingPeer(route.channel.clone()));
+        }
+        (true, "slack", Some(peer)) => {
+            let mut payload = slack_channel::slack_message_payload(
+                peer,
+                outbound.text.as_deref().unwrap_or_default(),
+                outbound.reply_to.as_deref().or(route.thread_id.as_deref()),
+                outbound.metadata.as_ref(),
+            );
+            slack_channel::apply_slack_sender(
+                &mut payload,
+                outbound.sender_name.as_deref(),
+                outbound.sender_icon.as_deref(),
+            );
+            slack_channel::apply_slack_reply_broadcast(&mut payload, outbound.reply_broadcast);
+            if !outbound.attachments.is_empty() {
+                payload["attachments"] = json!(outbound.attachments);
             }
-        } else {
-            return Err(anyhow::anyhow!("no route for session"));
+            payload
         }
-    } else {
-        return Err(anyhow::anyhow!("unknown session"));
+        (true, "whatsapp", Some(peer)) => {
+            let mut payload = whatsapp_channel::whatsapp_send_payload(
+                peer,
+                outbound.text.as_deref(),
+                &outbound.attachments,
+                outbound.caption_mode,
+                outbound.metadata.as_ref(),
+            );
+            if let Some(kind) = route.peer_kind.as_deref() {
+                payload["peer_kind"] = json!(kind);
+            }
+            payload
+        }
+        _ => json!(outbound),
     };
+    Ok(json!({
+        "status": "dry_run",
+        "session_key": outbound.session_key,
+        "route": route,
+        "payload": payload,
+    }))
+}
+
+async fn handle_outbound(
+    state: AppState,
+    config: &Config,
+    mut outbound: OutboundMessage,
+) -> Result<String, SendError> {
+    let (session, route) = prepare_outbound(&state, config, &mut outbound).await?;
+    if session.is_none() && route.peer_id.is_some() {
+        create_outbound_session(&state, config, &outbound.session_key, &route)
+            .await
+            .map_err(SendError::Internal)?;
+    }
+    let payload = json!({"route": route, "outbound": outbound});
 
     let message_id = uuid::Uuid::new_v4().to_string();
-    let record = db::MessageRecord {
+    let mut record = db::MessageRecord {
         id: message_id.clone(),
         session_key: outbound.session_key.clone(),
         direction: "outbound".to_string(),
@@ -776,10 +3267,47 @@ async fn handle_outbound(state: AppState, outbound: OutboundMessage) -> anyhow::
         status: "queued".to_string(),
         dedupe_key: None,
         created_at: Utc::now(),
+        provider_message_id: None,
+        metadata: outbound.metadata.clone(),
     };
-    db::insert_message(&state.pool, state.db_kind, &record).await?;
+    db::insert_message(&state.pool, state.db_kind, &record)
+        .await
+        .map_err(SendError::Internal)?;
 
-    send_via_channel(&state, &route, &outbound).await?;
+    match send_via_channel(&state, config, &route, &outbound).await {
+        Ok(provider_message_id) => {
+            state.record_channel_error(&route.channel, None);
+            mark_message_sent(&state, &message_id, provider_message_id.as_deref())
+                .await
+                .map_err(SendError::Internal)?;
+            record.provider_message_id = provider_message_id;
+            record.status = "sent".to_string();
+        }
+        Err(err) => {
+            state.record_channel_error(&route.channel, Some(err.to_string()));
+            db::set_message_status(&state.pool, state.db_kind, &message_id, "failed")
+                .await
+                .map_err(SendError::Internal)?;
+            let SendError::Other(source) = err else {
+                return Err(err);
+            };
+            if !channels::is_retryable(&source) {
+                return Err(SendError::Other(source));
+            }
+            let next = Utc::now() + outbox::compute_backoff(1);
+            db::insert_outbound_queue(
+                &state.pool,
+                state.db_kind,
+                &message_id,
+                payload,
+                Some(&format!("{source:#}")),
+                next,
+            )
+            .await
+            .map_err(SendError::Internal)?;
+            return Err(SendError::Queued { message_id, source });
+        }
+    }
     let _ = state.ws_tx.send(ws::WsEvent {
         event: "chat".to_string(),
         payload: json!({"direction": "outbound", "message": record}),
@@ -788,166 +3316,785 @@ async fn handle_outbound(state: AppState, outbound: OutboundMessage) -> anyhow::
     Ok(message_id)
 }
 
-async fn send_via_channel(
+async fn mark_message_sent(
     state: &AppState,
-    route: &RouteInfo,
-    outbound: &OutboundMessage,
+    message_id: &str,
+    provider_message_id: Option<&str>,
 ) -> anyhow::Result<()> {
-    if channel_transport(&state.config, &route.channel) == "embedded" {
-        let runtime_url = state
-            .config
-            .adapters
-            .runtime_url
-            .as_deref()
-            .ok_or_else(|| anyhow::anyhow!("embedded adapter runtime url missing"))?;
-        adapters::runtime::send(&state.http, runtime_url, &route.channel, route, outbound).await?;
-        return Ok(());
+    if let Some(provider_message_id) = provider_message_id {
+        db::set_message_provider_id(&state.pool, state.db_kind, message_id, provider_message_id)
+            .await?;
     }
+    db::set_message_status(&state.pool, state.db_kind, message_id, "sent").await
+}
 
-    match route.channel.as_str() {
-        "slack" => {
-            let token = state
-                .config
-                .channels
-                .slack
-                .bot_token
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("slack token missing"))?;
-            let peer = route
-                .peer_id
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("slack peer missing"))?;
-            slack_channel::send_slack_message(
-                &state.http,
-                token,
-                peer,
-                outbound.text.as_deref(),
-                outbound.reply_to.as_deref().or(route.thread_id.as_deref()),
-                &outbound.attachments,
-            )
-            .await?;
-        }
-        "telegram" => {
-            let token = state
-                .config
-                .channels
-                .telegram
-                .bot_token
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("telegram token missing"))?;
-            let peer = route
-                .peer_id
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("telegram peer missing"))?;
-            telegram_channel::send_telegram_message(
-                &state.http,
-                token,
-                peer,
-                outbound.text.as_deref(),
-                outbound.reply_to.as_deref(),
-                &outbound.attachments,
-            )
-            .await?;
+async fn resend_message(
+    State(state): State<AppState>,
+    Path(message_id): Path<String>,
+) -> impl IntoResponse {
+    let result = async {
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .map_err(SendError::Internal)?
+            .filter(|message| message.direction == "outbound")
+            .ok_or(SendError::UnknownMessage)?;
+        let record = db::get_outbound_queue(&state.pool, state.db_kind, &message.id)
+            .await
+            .map_err(SendError::Internal)?
+            .filter(|record| message.status == "failed" && record.status != "sent")
+            .ok_or(SendError::NotResendable)?;
+        if !claim_outbound_retry(&state, &record.message_id, &["pending", "dead"]).await? {
+            return Err(SendError::NotResendable);
         }
-        "whatsapp" => {
-            let peer = route
-                .peer_id
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("whatsapp peer missing"))?;
-            whatsapp_channel::send_whatsapp_message(
-                &state.http,
-                &state.config.channels.whatsapp.sidecar_url,
-                peer,
-                outbound.text.as_deref(),
-                &outbound.attachments,
-            )
-            .await?;
+        resend_outbound(&state, &record).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => Json(SendMessageResponse {
+            message_id,
+            status: "sent".to_string(),
+        })
+        .into_response(),
+        Err(err) => {
+            error!("resend_message error: {err:?}");
+            err.into_response()
         }
-        _ => return Err(anyhow::anyhow!("unsupported channel")),
     }
-    Ok(())
 }
 
-async fn upload_media(
+const OUTBOUND_RETRY_POLL_SECONDS: u64 = 5;
+const OUTBOUND_RETRY_BATCH: i64 = 25;
+/// How long a claimed retry may run before the worker may claim it again.
+const OUTBOUND_SEND_LEASE_SECONDS: i64 = 300;
+
+/// Claims a queued send for one attempt so the retry worker and a manual
+/// resend never deliver it twice. Returns whether the caller got the row.
+async fn claim_outbound_retry(
     state: &AppState,
-    channel: &str,
-    session_key: &str,
-    attachments: &[Attachment],
-) -> Vec<Attachment> {
-    let Some(upload_url) = state.config.backend.media_upload_url.as_ref() else {
-        return attachments.to_vec();
-    };
-    let backend_token = state.config.backend.api_token.clone();
-    let mut out = Vec::new();
+    message_id: &str,
+    statuses: &[&str],
+) -> Result<bool, SendError> {
+    let now = Utc::now();
+    let lease_until = now + chrono::Duration::seconds(OUTBOUND_SEND_LEASE_SECONDS);
+    db::claim_outbound_queue(
+        &state.pool,
+        state.db_kind,
+        message_id,
+        statuses,
+        now,
+        lease_until,
+    )
+    .await
+    .map_err(SendError::Internal)
+}
 
-    for att in attachments {
-        let mut url = att.url.clone();
-        if channel == "telegram" && url.starts_with("telegram://file/") {
-            let file_id = url.trim_start_matches("telegram://file/");
-            if let Some(token) = state.config.channels.telegram.bot_token.as_ref() {
-                if let Ok(Some(real)) =
-                    telegram_channel::resolve_telegram_file_url(&state.http, token, file_id).await
-                {
-                    url = real;
+async fn start_outbound_retry_worker(state: AppState) {
+    loop {
+        match db::due_outbound_queue(&state.pool, state.db_kind, Utc::now(), OUTBOUND_RETRY_BATCH)
+            .await
+        {
+            Ok(rows) => {
+                for row in rows {
+                    match claim_outbound_retry(&state, &row.message_id, &["pending"]).await {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => {
+                            error!("outbound retry claim error: {err:?}");
+                            continue;
+                        }
+                    }
+                    if let Err(err) = resend_outbound(&state, &row).await {
+                        warn!("outbound resend {} failed: {err}", row.message_id);
+                    }
                 }
             }
+            Err(err) => error!("outbound retry poll error: {err:?}"),
         }
+        tokio::time::sleep(std::time::Duration::from_secs(OUTBOUND_RETRY_POLL_SECONDS)).await;
+    }
+}
 
-        let mut req = state.http.get(&url);
-        if channel == "slack" {
-            if let Some(token) = state.config.channels.slack.bot_token.as_ref() {
-                req = req.bearer_auth(token);
+/// Re-attempts a failed channel send on its original route; the caller must
+/// have claimed the queue row. The message moves to `sent` on success; on error
+/// the next attempt is scheduled (reported as `SendError::Queued`) or the queue
+/// row marked dead, and the message stays `failed`.
+async fn resend_outbound(
+    state: &AppState,
+    record: &db::OutboundQueueRecord,
+) -> Result<(), SendError> {
+    let internal = |err: serde_json::Error| SendError::Internal(err.into());
+    let route: RouteInfo =
+        serde_json::from_value(record.payload["route"].clone()).map_err(internal)?;
+    let outbound: OutboundMessage =
+        serde_json::from_value(record.payload["outbound"].clone()).map_err(internal)?;
+    let config = state.config();
+    let err = match send_via_channel(state, &config, &route, &outbound).await {
+        Ok(provider_message_id) => {
+            state.record_channel_error(&route.channel, None);
+            mark_message_sent(state, &record.message_id, provider_message_id.as_deref())
+                .await
+                .map_err(SendError::Internal)?;
+            db::mark_outbound_queue_sent(&state.pool, state.db_kind, &record.message_id)
+                .await
+                .map_err(SendError::Internal)?;
+            if let Some(message) = db::get_message(&state.pool, state.db_kind, &record.message_id)
+                .await
+                .map_err(SendError::Internal)?
+            {
+                let _ = state.ws_tx.send(ws::WsEvent {
+                    event: "chat".to_string(),
+                    payload: json!({"direction": "outbound", "message": message}),
+                });
             }
+            return Ok(());
         }
+        Err(err) => err,
+    };
+    state.record_channel_error(&route.channel, Some(err.to_string()));
+    let retry = record.retry_count + 1;
+    let retryable = match &err {
+        SendError::Other(source) => channels::is_retryable(source),
+        _ => false,
+    };
+    let status = if retryable && retry < db::OUTBOX_MAX_RETRIES {
+        "pending"
+    } else {
+        "dead"
+    };
+    let next = Utc::now() + outbox::compute_backoff(retry + 1);
+    db::mark_outbound_queue_retry(
+        &state.pool,
+        state.db_kind,
+        &record.message_id,
+        status,
+        retry,
+        next,
+        &err.to_string(),
+    )
+    .await
+    .map_err(SendError::Internal)?;
+    match err {
+        SendError::Other(source) if status == "pending" => Err(SendError::Queued {
+            message_id: record.message_id.clone(),
+            source,
+        }),
+        err => Err(err),
+    }
+}
 
-        let resp = match req.send().await {
-            Ok(resp) => resp,
-            Err(_) => {
-                out.push(att.clone());
-                continue;
-            }
-        };
-        let bytes = match resp.bytes().await {
-            Ok(bytes) => bytes,
-            Err(_) => {
-                out.push(att.clone());
-                continue;
+fn resolve_outbound_route(
+    config: &Config,
+    session: Option<&db::SessionRecord>,
+    outbound: &OutboundMessage,
+) -> Result<RouteInfo, SendError> {
+    let last_route = session.and_then(|session| session.last_route.as_ref());
+    let last_str = |key: &str| {
+        last_route
+            .and_then(|route| route.get(key))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+
+    let mut route = if let Some(channel) = outbound.channel.clone() {
+        let same_conversation = last_str("channel").as_deref() == Some(channel.as_str())
+            && last_str("peer_id") == outbound.peer_id;
+        let from_session = |key: &str| {
+            if same_conversation {
+                last_str(key)
+            } else {
+                None
             }
         };
-
-        let filename = att.filename.clone().unwrap_or_else(|| "file".to_string());
-        let part = reqwest::multipart::Part::bytes(bytes.to_vec()).file_name(filename.clone());
-        let mut form = reqwest::multipart::Form::new()
-            .part("file", part)
-            .text("channel", channel.to_string())
-            .text("session_key", session_key.to_string());
-        if let Some(source_id) = &att.id {
-            form = form.text("source_id", source_id.to_string());
-        }
-
-        let mut upload_req = state.http.post(upload_url).multipart(form);
-        if let Some(token) = backend_token.as_ref() {
-            upload_req = upload_req.header("X-Agent-Ping-Token", token);
-        }
-
-        let uploaded = upload_req.send().await;
-        if let Ok(resp) = uploaded {
-            if let Ok(value) = resp.json::<serde_json::Value>().await {
-                if let Some(storage_url) = value.get("url").and_then(|v| v.as_str()) {
-                    out.push(Attachment {
-                        id: att.id.clone(),
-                        url: storage_url.to_string(),
-                        mime_type: att.mime_type.clone(),
-                        filename: Some(filename),
-                        size: att.size,
-                    });
-                    continue;
-                }
+        RouteInfo {
+            channel,
+            account_id: outbound.account_id.clone(),
+            peer_id: outbound.peer_id.clone(),
+            thread_id: outbound
+                .thread_id
+                .clone()
+                .or_else(|| from_session("thread_id")),
+            peer_kind: outbound
+                .peer_kind
+                .clone()
+                .or_else(|| from_session("peer_kind")),
+        }
+    } else if session.is_none() {
+        return Err(SendError::UnknownSession);
+    } else if last_route.is_some() {
+        RouteInfo {
+            channel: last_str("channel").unwrap_or_default(),
+            account_id: last_str("account_id"),
+            peer_id: last_str("peer_id"),
+            thread_id: outbound.thread_id.clone().or_else(|| last_str("thread_id")),
+            peer_kind: last_str("peer_kind"),
+        }
+    } else {
+        return Err(SendError::NoRoute);
+    };
+
+    if route.channel == "slack"
+        && config.channels.slack.always_thread
+        && route.thread_id.is_none()
+        && last_str("channel").as_deref() == Some("slack")
+        && last_str("peer_id") == route.peer_id
+    {
+        route.thread_id = last_str("message_id");
+    }
+    Ok(route)
+}
+
+async fn create_outbound_session(
+    state: &AppState,
+    config: &Config,
+    session_key: &str,
+    route: &RouteInfo,
+) -> anyhow::Result<()> {
+    let binding = bind_route(
+        config,
+        &route.channel,
+        route.account_id.as_deref(),
+        route.peer_id.as_deref(),
+        route.thread_id.as_deref(),
+    );
+    let now = Utc::now();
+    let record = db::SessionRecord {
+        session_key: session_key.to_string(),
+        agent_id: binding
+            .agent_id
+            .unwrap_or_else(|| fallback_agent_id(config, &route.channel)),
+        business_profile_id: binding.business_profile_id,
+        user_id: binding.user_id,
+        last_route: Some(json!({
+            "channel": route.channel,
+            "account_id": route.account_id,
+            "peer_id": route.peer_id,
+            "peer_kind": route.peer_kind,
+            "thread_id": route.thread_id,
+        })),
+        dm_scope: config.session.dm_scope.clone(),
+        identity_links: None,
+        created_at: now,
+        updated_at: now,
+        metadata: None,
+    };
+    db::upsert_session(&state.pool, state.db_kind, &record).await
+}
+
+fn build_send_limits(config: &Config) -> HashMap<String, channels::SendLimiter> {
+    let channels = &config.channels;
+    [
+        ("slack", channels.slack.max_concurrent_sends),
+        ("telegram", channels.telegram.max_concurrent_sends),
+        ("whatsapp", channels.whatsapp.max_concurrent_sends),
+        ("teams", channels.teams.max_concurrent_sends),
+    ]
+    .into_iter()
+    .map(|(name, permits)| (name.to_string(), channels::SendLimiter::new(permits)))
+    .collect()
+}
+
+async fn send_via_channel(
+    state: &AppState,
+    config: &Config,
+    route: &RouteInfo,
+    outbound: &OutboundMessage,
+) -> Result<Option<String>, SendError> {
+    check_messaging_window(state, config, route, &outbound.session_key).await?;
+    let Some(limiter) = state.send_limiter(&route.channel) else {
+        return deliver_via_channel(state, config, route, outbound).await;
+    };
+    let permit = limiter.acquire().await;
+    let result = deliver_via_channel(state, config, route, outbound).await;
+    if let Err(SendError::Other(err)) = &result {
+        if let Some(limited @ channels::ChannelError::RateLimited { retry_after, .. }) =
+            err.downcast_ref::<channels::ChannelError>()
+        {
+            warn!("{}; pausing sends", limited);
+            limiter.pause(*retry_after);
+        }
+    }
+    drop(permit);
+    result
+}
+
+async fn deliver_via_channel(
+    state: &AppState,
+    config: &Config,
+    route: &RouteInfo,
+    outbound: &OutboundMessage,
+) -> Result<Option<String>, SendError> {
+    if channel_transport(config, &route.channel) == "echo" {
+        info!(
+            "echo send on {}: {}",
+            route.channel,
+            json!({"route": route, "outbound": outbound})
+        );
+        return Ok(Some(format!("echo-{}", uuid::Uuid::new_v4())));
+    }
+    if channel_transport(config, &route.channel) == "embedded" {
+        let runtime_url = config.adapters.runtime_url.as_deref().ok_or_else(|| {
+            SendError::NotConfigured("embedded adapter runtime url missing".to_string())
+        })?;
+        let response =
+            adapters::runtime::send(&state.http, runtime_url, &route.channel, route, outbound)
+                .await?;
+        return Ok(response.message_id);
+    }
+    if let Some(custom) = config.channels.custom.get(&route.channel) {
+        let payload = custom_channel::custom_send_payload(&outbound.session_key, route, outbound);
+        let provider_message_id =
+            custom_channel::send_custom_message(&state.http, &route.channel, custom, &payload)
+                .await?;
+        return Ok(provider_message_id);
+    }
+    if !channels::capabilities(&route.channel).is_some_and(|caps| caps.send) {
+        return Err(SendError::UnsupportedChannel(route.channel.clone()));
+    }
+
+    let provider_message_id = match route.channel.as_str() {
+        "slack" => {
+            let token =
+                config.channels.slack.bot_token.as_ref().ok_or_else(|| {
+                    SendError::NotConfigured("slack bot token missing".to_string())
+                })?;
+            let peer = route
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("slack".to_string()))?;
+            slack_channel::send_slack_message(
+                &state.http,
+                token,
+                peer,
+                outbound.text.as_deref(),
+                outbound.reply_to.as_deref().or(route.thread_id.as_deref()),
+                &outbound.attachments,
+                outbound.metadata.as_ref(),
+                outbound.sender_name.as_deref(),
+                outbound.sender_icon.as_deref(),
+                outbound.reply_broadcast,
+            )
+            .await?
+        }
+        "telegram" => {
+            let token = config.channels.telegram.bot_token.as_ref().ok_or_else(|| {
+                SendError::NotConfigured("telegram bot token missing".to_string())
+            })?;
+            let peer = route
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("telegram".to_string()))?;
+            telegram_channel::send_telegram_message(
+                &state.http,
+                token,
+                peer,
+                outbound.text.as_deref(),
+                outbound.reply_to.as_deref(),
+                &outbound.attachments,
+                outbound.caption_mode,
+            )
+            .await?
+        }
+        "whatsapp" => {
+            let peer = route
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("whatsapp".to_string()))?;
+            whatsapp_channel::send_whatsapp_message(
+                &state.http,
+                &config.channels.whatsapp.sidecar_url,
+                peer,
+                outbound.text.as_deref(),
+                &outbound.attachments,
+                outbound.caption_mode,
+                outbound.metadata.as_ref(),
+                route.peer_kind.as_deref(),
+                config.channels.whatsapp.split_attachments,
+            )
+            .await?
+        }
+        other => return Err(SendError::UnsupportedChannel(other.to_string())),
+    };
+    Ok(provider_message_id)
+}
+
+async fn edit_via_channel(
+    state: &AppState,
+    message: &db::MessageRecord,
+    text: &str,
+) -> Result<(), SendError> {
+    let config = state.config();
+    let provider_message_id = message
+        .provider_message_id
+        .as_deref()
+        .ok_or(SendError::UnknownMessage)?;
+    if channel_transport(&config, &message.channel) == "embedded" {
+        return Err(SendError::EditUnsupported(message.channel.clone()));
+    }
+    if !channels::capabilities(&message.channel).is_some_and(|caps| caps.edits) {
+        return Err(SendError::EditUnsupported(message.channel.clone()));
+    }
+    if channel_transport(&config, &message.channel) == "echo" {
+        info!("echo edit on {}: {provider_message_id}", message.channel);
+        return Ok(());
+    }
+    match message.channel.as_str() {
+        "slack" => {
+            let token =
+                config.channels.slack.bot_token.as_ref().ok_or_else(|| {
+                    SendError::NotConfigured("slack bot token missing".to_string())
+                })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("slack".to_string()))?;
+            slack_channel::edit_slack_message(&state.http, token, peer, provider_message_id, text)
+                .await?;
+        }
+        "telegram" => {
+            let token = config.channels.telegram.bot_token.as_ref().ok_or_else(|| {
+                SendError::NotConfigured("telegram bot token missing".to_string())
+            })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("telegram".to_string()))?;
+            telegram_channel::edit_telegram_message(
+                &state.http,
+                token,
+                peer,
+                provider_message_id,
+                text,
+            )
+            .await?;
+        }
+        other => return Err(SendError::EditUnsupported(other.to_string())),
+    }
+    Ok(())
+}
+
+async fn delete_via_channel(
+    state: &AppState,
+    message: &db::MessageRecord,
+) -> Result<(), SendError> {
+    let config = state.config();
+    let provider_message_id = message
+        .provider_message_id
+        .as_deref()
+        .ok_or(SendError::UnknownMessage)?;
+    if channel_transport(&config, &message.channel) == "embedded" {
+        return Err(SendError::DeleteUnsupported(message.channel.clone()));
+    }
+    if !channels::capabilities(&message.channel).is_some_and(|caps| caps.deletes) {
+        return Err(SendError::DeleteUnsupported(message.channel.clone()));
+    }
+    if channel_transport(&config, &message.channel) == "echo" {
+        info!("echo delete on {}: {provider_message_id}", message.channel);
+        return Ok(());
+    }
+    match message.channel.as_str() {
+        "slack" => {
+            let token =
+                config.channels.slack.bot_token.as_ref().ok_or_else(|| {
+                    SendError::NotConfigured("slack bot token missing".to_string())
+                })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("slack".to_string()))?;
+            slack_channel::delete_slack_message(&state.http, token, peer, provider_message_id)
+                .await?;
+        }
+        "telegram" => {
+            let token = config.channels.telegram.bot_token.as_ref().ok_or_else(|| {
+                SendError::NotConfigured("telegram bot token missing".to_string())
+            })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("telegram".to_string()))?;
+            telegram_channel::delete_telegram_message(
+                &state.http,
+                token,
+                peer,
+                provider_message_id,
+            )
+            .await?;
+        }
+        other => return Err(SendError::DeleteUnsupported(other.to_string())),
+    }
+    Ok(())
+}
+
+async fn react_via_channel(
+    state: &AppState,
+    message: &db::MessageRecord,
+    reaction: &str,
+) -> Result<(), SendError> {
+    let config = state.config();
+    let provider_message_id = message
+        .provider_message_id
+        .as_deref()
+        .ok_or(SendError::UnknownMessage)?;
+    if channel_transport(&config, &message.channel) == "embedded" {
+        return Err(SendError::ReactionUnsupported(message.channel.clone()));
+    }
+    if !channels::capabilities(&message.channel).is_some_and(|caps| caps.reactions) {
+        return Err(SendError::ReactionUnsupported(message.channel.clone()));
+    }
+    if channel_transport(&config, &message.channel) == "echo" {
+        info!(
+            "echo reaction on {}: {provider_message_id}",
+            message.channel
+        );
+        return Ok(());
+    }
+    match message.channel.as_str() {
+        "slack" => {
+            let token =
+                config.channels.slack.bot_token.as_ref().ok_or_else(|| {
+                    SendError::NotConfigured("slack bot token missing".to_string())
+                })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("slack".to_string()))?;
+            slack_channel::add_slack_reaction(
+                &state.http,
+                token,
+                peer,
+                provider_message_id,
+                reaction,
+            )
+            .await?;
+        }
+        "telegram" => {
+            let token = config.channels.telegram.bot_token.as_ref().ok_or_else(|| {
+                SendError::NotConfigured("telegram bot token missing".to_string())
+            })?;
+            let peer = message
+                .peer_id
+                .as_ref()
+                .ok_or_else(|| SendError::MissingPeer("telegram".to_string()))?;
+            telegram_channel::add_telegram_reaction(
+                &state.http,
+                token,
+                peer,
+                provider_message_id,
+                reaction,
+            )
+            .await?;
+        }
+        other => return Err(SendError::ReactionUnsupported(other.to_string())),
+    }
+    Ok(())
+}
+
+/// Rehosts inbound attachments at `backend.media_upload_url`. Attachments that
+/// fail to upload keep their original URL and are returned with their error,
+/// for the caller to record in `media_failures` once the message is stored.
+/// Bodies already in `downloaded`, keyed by URL, are not fetched again.
+async fn upload_media(
+    state: &AppState,
+    config: &Config,
+    channel: &str,
+    session_key: &str,
+    attachments: &[Attachment],
+    downloaded: &HashMap<String, Bytes>,
+) -> (Vec<Attachment>, Vec<(Attachment, String)>) {
+    let Some(upload_url) = config.backend.media_upload_url.as_ref() else {
+        return (attachments.to_vec(), Vec::new());
+    };
+    let mut out = Vec::new();
+    let mut failures = Vec::new();
+
+    for att in attachments {
+        let body = downloaded.get(&att.url).cloned();
+        match upload_attachment(state, config, upload_url, channel, session_key, att, body).await {
+            Ok(uploaded) => out.push(uploaded),
+            Err(err) => {
+                warn!("media upload for {channel} attachment failed: {err:#}");
+                failures.push((att.clone(), format!("{err:#}")));
+                out.push(att.clone());
             }
         }
-        out.push(att.clone());
     }
-    out
+    (out, failures)
+}
+
+async fn upload_attachment(
+    state: &AppState,
+    config: &Config,
+    upload_url: &str,
+    channel: &str,
+    session_key: &str,
+    att: &Attachment,
+    downloaded: Option<Bytes>,
+) -> anyhow::Result<Attachment> {
+    let filename = att.filename.clone().unwrap_or_else(|| "file".to_string());
+    let part = match downloaded {
+        Some(body) => channels::bytes_part(body, filename.clone()),
+        None => {
+            let req = media_download_request(state, config, channel, att).await;
+            channels::download_part(channel, req, filename.clone()).await?
+        }
+    };
+    let mut form = reqwest::multipart::Form::new()
+        .part("file", part)
+        .text("channel", channel.to_string())
+        .text("session_key", session_key.to_string());
+    if let Some(source_id) = &att.id {
+        form = form.text("source_id", source_id.to_string());
+    }
+
+    let value: serde_json::Value =
+        outbox::backend_request(state.http.post(upload_url).multipart(form), &config.backend)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+    let storage_url = value
+        .get("url")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("media upload response has no url"))?;
+    Ok(Attachment {
+        id: att.id.clone(),
+        url: storage_url.to_string(),
+        mime_type: att.mime_type.clone(),
+        filename: Some(filename),
+        size: att.size,
+    })
+}
+
+/// Builds the request that fetches an inbound attachment from its channel,
+/// resolving Telegram file ids and authenticating Slack downloads.
+async fn media_download_request(
+    state: &AppState,
+    config: &Config,
+    channel: &str,
+    att: &Attachment,
+) -> reqwest::RequestBuilder {
+    let mut url = att.url.clone();
+    if channel == "telegram" && url.starts_with("telegram://file/") {
+        let file_id = url.trim_start_matches("telegram://file/");
+        if let Some(token) = config.channels.telegram.bot_token.as_ref() {
+            if let Ok(Some(real)) =
+                telegram_channel::resolve_telegram_file_url(&state.http, token, file_id).await
+            {
+                url = real;
+            }
+        }
+    }
+
+    let mut req = state.http.get(&url);
+    if channel == "slack" {
+        if let Some(token) = config.channels.slack.bot_token.as_ref() {
+            req = req.bearer_auth(token);
+        }
+    }
+    req
+}
+
+/// How long a transcription request may take before the audio is passed on
+/// untranscribed.
+const TRANSCRIPTION_TIMEOUT_SECONDS: u64 = 20;
+
+/// Posts each audio attachment to `backend.transcription_url` and appends the
+/// returned text to the message. A failed transcription leaves the message as
+/// it was, with the audio still attached. Returns the downloaded audio keyed by
+/// URL, so the media upload doesn't fetch it again.
+async fn transcribe_audio(
+    state: &AppState,
+    config: &Config,
+    session_key: &str,
+    inbound: &mut InboundMessage,
+) -> HashMap<String, Bytes> {
+    let mut downloaded = HashMap::new();
+    let Some(transcription_url) = config.backend.transcription_url.as_ref() else {
+        return downloaded;
+    };
+    if !transcribes_audio(config, inbound) {
+        return downloaded;
+    }
+    for att in inbound.attachments.iter().filter(|att| is_audio(att)) {
+        let filename = att.filename.clone().unwrap_or_else(|| "audio".to_string());
+        let result = async {
+            let req = media_download_request(state, config, &inbound.channel, att).await;
+            let audio = channels::download_bytes(&inbound.channel, req).await?;
+            downloaded.insert(att.url.clone(), audio.clone());
+            let form = reqwest::multipart::Form::new()
+                .part("file", channels::bytes_part(audio, filename))
+                .text("channel", inbound.channel.clone())
+                .text("session_key", session_key.to_string())
+                .text("mime_type", att.mime_type.clone().unwrap_or_default());
+            let request = state
+                .http
+                .post(transcription_url)
+                .timeout(std::time::Duration::from_secs(
+                    TRANSCRIPTION_TIMEOUT_SECONDS,
+                ))
+                .multipart(form);
+            let value: serde_json::Value = outbox::backend_request(request, &config.backend)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            anyhow::Ok(
+                value
+                    .get("text")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+            )
+        }
+        .await;
+        match result {
+            Ok(Some(text)) if !text.trim().is_empty() => {
+                let text = text.trim();
+                inbound.text = Some(match inbound.text.as_deref() {
+                    Some(existing) if !existing.trim().is_empty() => {
+                        format!("{existing}\n\n{text}")
+                    }
+                    _ => text.to_string(),
+                });
+            }
+            Ok(_) => {}
+            Err(err) => warn!(
+                "transcription of {} attachment failed: {err:#}",
+                inbound.channel
+            ),
+        }
+    }
+    downloaded
+}
+
+/// Whether `inbound` has audio that `transcribe_audio` would send off.
+fn transcribes_audio(config: &Config, inbound: &InboundMessage) -> bool {
+    config.backend.transcription_url.is_some()
+        && channel_transcribes_audio(config, &inbound.channel)
+        && inbound.attachments.iter().any(is_audio)
+}
+
+fn is_audio(att: &Attachment) -> bool {
+    att.mime_type
+        .as_deref()
+        .is_some_and(|mime| mime.starts_with("audio/"))
+}
+
+fn channel_configured(config: &Config, channel: &str) -> bool {
+    let present = |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+    match channel_transport(config, channel) {
+        "embedded" => return present(&config.adapters.runtime_url),
+        "echo" => return true,
+        _ => {}
+    }
+    match channel {
+        "slack" => present(&config.channels.slack.bot_token),
+        "telegram" => present(&config.channels.telegram.bot_token),
+        "whatsapp" => !config.channels.whatsapp.sidecar_url.trim().is_empty(),
+        other => config.channels.custom.contains_key(other),
+    }
 }
 
 fn channel_transport<'a>(config: &'a Config, channel: &str) -> &'a str {
@@ -960,9 +4107,48 @@ fn channel_transport<'a>(config: &'a Config, channel: &str) -> &'a str {
     }
 }
 
+fn fallback_agent_id(config: &Config, channel: &str) -> String {
+    let channels = &config.channels;
+    let channel_default = match channel {
+        "slack" => channels.slack.default_agent.as_deref(),
+        "telegram" => channels.telegram.default_agent.as_deref(),
+        "whatsapp" => channels.whatsapp.default_agent.as_deref(),
+        "teams" => channels.teams.default_agent.as_deref(),
+        _ => None,
+    };
+    channel_default
+        .map(str::trim)
+        .filter(|agent_id| !agent_id.is_empty())
+        .unwrap_or(&config.session.agent_id)
+        .to_string()
+}
+
+fn channel_transcribes_audio(config: &Config, channel: &str) -> bool {
+    let channels = &config.channels;
+    match channel {
+        "slack" => channels.slack.transcribe_audio,
+        "telegram" => channels.telegram.transcribe_audio,
+        "whatsapp" => channels.whatsapp.transcribe_audio,
+        "teams" => channels.teams.transcribe_audio,
+        _ => false,
+    }
+}
+
+fn channel_debounce_ms(config: &Config, channel: &str) -> u64 {
+    let channels = &config.channels;
+    let channel_debounce = match channel {
+        "slack" => channels.slack.debounce_ms,
+        "telegram" => channels.telegram.debounce_ms,
+        "whatsapp" => channels.whatsapp.debounce_ms,
+        "teams" => channels.teams.debounce_ms,
+        _ => None,
+    };
+    channel_debounce.unwrap_or(config.queue.debounce_ms)
+}
+
 async fn runtime_value(state: &AppState, path: &str) -> anyhow::Result<serde_json::Value> {
-    let runtime_url = state
-        .config
+    let config = state.config();
+    let runtime_url = config
         .adapters
         .runtime_url
         .as_deref()
@@ -975,8 +4161,8 @@ async fn runtime_post_value(
     path: &str,
     payload: &serde_json::Value,
 ) -> anyhow::Result<serde_json::Value> {
-    let runtime_url = state
-        .config
+    let config = state.config();
+    let runtime_url = config
         .adapters
         .runtime_url
         .as_deref()
@@ -1021,22 +4207,21 @@ fn backend_route_resolve_url(config: &config::BackendConfig) -> Option<String> {
 
 async fn resolve_backend_binding(
     state: &AppState,
+    config: &Config,
     inbound: &InboundMessage,
 ) -> anyhow::Result<Option<BindingMatch>> {
-    let Some(url) = backend_route_resolve_url(&state.config.backend) else {
+    let Some(url) = backend_route_resolve_url(&config.backend) else {
         return Ok(None);
     };
 
-    let mut request = state.http.post(url).json(&json!({
+    let request = state.http.post(url).json(&json!({
         "channel": inbound.channel,
         "account_id": inbound.account_id,
         "peer_id": inbound.peer_id,
         "thread_id": inbound.thread_id,
         "text": inbound.text,
     }));
-    if let Some(token) = state.config.backend.api_token.as_ref() {
-        request = request.header("X-Agent-Ping-Token", token);
-    }
+    let request = outbox::backend_request(request, &config.backend);
 
     let response = request.send().await?;
     if !response.status().is_success() {
@@ -1071,6 +4256,7 @@ async fn resolve_backend_binding(
         business_profile_id,
         user_id,
         agent_id,
+        webhook_url: None,
     }))
 }
 
@@ -1079,17 +4265,47 @@ struct BindingMatch {
     business_profile_id: Option<String>,
     user_id: Option<String>,
     agent_id: Option<String>,
+    webhook_url: Option<String>,
+}
+
+/// Resolves the binding for a route, first mapping the peer to its canonical
+/// identity through `session.identity_links`.
+fn bind_route(
+    config: &Config,
+    channel: &str,
+    account_id: Option<&str>,
+    peer_id: Option<&str>,
+    thread_id: Option<&str>,
+) -> BindingMatch {
+    let canonical_id = peer_id.and_then(|peer| {
+        session::resolve_identity_link(&config.session.identity_links, channel, peer)
+    });
+    resolve_binding(
+        &config.bindings,
+        channel,
+        account_id,
+        peer_id,
+        thread_id,
+        canonical_id.as_deref(),
+    )
 }
 
+/// Layers every matching binding from least to most specific, so each field
+/// comes from the most specific match that sets it.
 fn resolve_binding(
     bindings: &[config::Binding],
     channel: &str,
     account_id: Option<&str>,
     peer_id: Option<&str>,
+    thread_id: Option<&str>,
+    canonical_id: Option<&str>,
 ) -> BindingMatch {
-    let mut best: Option<(i32, &config::Binding)> = None;
-    for binding in bindings {
-        if binding.channel != channel {
+    let mut matches: Vec<(i32, bool, std::cmp::Reverse<usize>, &config::Binding)> = Vec::new();
+    for (index, binding) in bindings.iter().enumerate() {
+        if !binding.channel.is_empty() && binding.channel != channel {
+            continue;
+        }
+        if binding.channel.is_empty() && binding.canonical_id.is_none() {
             continue;
         }
         if let Some(bind_account) = binding.account_id.as_deref() {
@@ -1098,7 +4314,24 @@ fn resolve_binding(
             }
         }
         if let Some(bind_peer) = binding.peer_id.as_deref() {
-            if peer_id != Some(bind_peer) {
+            let matches = match (channel, peer_id) {
+                ("whatsapp", Some(peer)) => {
+                    whatsapp_channel::normalize_phone_number(peer)
+                        == whatsapp_channel::normalize_phone_number(bind_peer)
+                }
+                _ => peer_id == Some(bind_peer),
+            };
+            if !matches {
+                continue;
+            }
+        }
+        if let Some(bind_thread) = binding.thread_id.as_deref() {
+            if thread_id != Some(bind_thread) {
+                continue;
+            }
+        }
+        if let Some(bind_canonical) = binding.canonical_id.as_deref() {
+            if canonical_id != Some(session::normalize_token(bind_canonical).as_str()) {
                 continue;
             }
         }
@@ -1110,30 +4343,54 @@ fn resolve_binding(
         if binding.peer_id.is_some() {
             score += 2;
         }
-        if best.as_ref().map(|(s, _)| score > *s).unwrap_or(true) {
-            best = Some((score, binding));
+        if binding.thread_id.is_some() {
+            score += 5;
         }
+        if binding.canonical_id.is_some() {
+            score += 2;
+        }
+        // A binding pinned to one channel outranks one that spans every channel.
+        if binding.channel.is_empty() {
+            score -= 1;
+        }
+        // Equal specificity prefers a binding that names an agent; otherwise the
+        // earliest binding in config order wins. Winners are applied last.
+        matches.push((
+            score,
+            binding.agent_id.is_some(),
+            std::cmp::Reverse(index),
+            binding,
+        ));
     }
+    matches.sort_by_key(|(score, has_agent, index, _)| (*score, *has_agent, *index));
 
-    if let Some((_, binding)) = best {
-        return BindingMatch {
-            business_profile_id: binding.business_profile_id.clone(),
-            user_id: binding.user_id.clone(),
-            agent_id: binding.agent_id.clone(),
-        };
-    }
-
-    BindingMatch {
+    let mut result = BindingMatch {
         business_profile_id: None,
         user_id: None,
         agent_id: None,
-    }
-}
+        webhook_url: None,
+    };
+    for (_, _, _, binding) in matches {
+        if binding.business_profile_id.is_some() {
+            result.business_profile_id = binding.business_profile_id.clone();
+        }
+        if binding.user_id.is_some() {
+            result.user_id = binding.user_id.clone();
+        }
+        if binding.agent_id.is_some() {
+            result.agent_id = binding.agent_id.clone();
+        }
+        if binding.webhook_url.is_some() {
+            result.webhook_url = binding.webhook_url.clone();
+        }
+    }
+    result
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Binding;
+    use crate::config::{Binding, DatabaseConfig};
 
     #[test]
     fn test_resolve_binding_no_match() {
@@ -1144,8 +4401,9 @@ mod tests {
             business_profile_id: None,
             user_id: None,
             agent_id: None,
+            ..Binding::default()
         }];
-        let result = resolve_binding(&bindings, "telegram", None, Some("U2"));
+        let result = resolve_binding(&bindings, "telegram", None, Some("U2"), None, None);
         assert!(result.agent_id.is_none());
     }
 
@@ -1158,8 +4416,9 @@ mod tests {
             business_profile_id: Some("bp_123".to_string()),
             user_id: None,
             agent_id: Some("agent_1".to_string()),
+            ..Binding::default()
         }];
-        let result = resolve_binding(&bindings, "slack", None, None);
+        let result = resolve_binding(&bindings, "slack", None, None, None, None);
         assert_eq!(result.business_profile_id, Some("bp_123".to_string()));
         assert_eq!(result.agent_id, Some("agent_1".to_string()));
     }
@@ -1173,8 +4432,9 @@ mod tests {
             business_profile_id: None,
             user_id: Some("user_1".to_string()),
             agent_id: None,
+            ..Binding::default()
         }];
-        let result = resolve_binding(&bindings, "slack", Some("ACC123"), None);
+        let result = resolve_binding(&bindings, "slack", Some("ACC123"), None, None, None);
         assert_eq!(result.user_id, Some("user_1".to_string()));
     }
 
@@ -1187,11 +4447,33 @@ mod tests {
             business_profile_id: Some("bp_456".to_string()),
             user_id: None,
             agent_id: None,
+            ..Binding::default()
         }];
-        let result = resolve_binding(&bindings, "whatsapp", None, Some("+1234567890"));
+        let result = resolve_binding(&bindings, "whatsapp", None, Some("+1234567890"), None, None);
         assert_eq!(result.business_profile_id, Some("bp_456".to_string()));
     }
 
+    #[test]
+    fn test_resolve_binding_whatsapp_peer_ignores_formatting() {
+        let bindings = vec![Binding {
+            channel: "whatsapp".to_string(),
+            peer_id: Some("+1 234 567 890".to_string()),
+            agent_id: Some("support".to_string()),
+            ..Binding::default()
+        }];
+        let result = resolve_binding(&bindings, "whatsapp", None, Some("1234567890"), None, None);
+        assert_eq!(result.agent_id.as_deref(), Some("support"));
+
+        let bindings = vec![Binding {
+            channel: "telegram".to_string(),
+            peer_id: Some("+1 234".to_string()),
+            agent_id: Some("support".to_string()),
+            ..Binding::default()
+        }];
+        let result = resolve_binding(&bindings, "telegram", None, Some("1234"), None, None);
+        assert!(result.agent_id.is_none());
+    }
+
     #[test]
     fn test_resolve_binding_best_score() {
         let bindings = vec![
@@ -1202,6 +4484,7 @@ mod tests {
                 business_profile_id: None,
                 user_id: None,
                 agent_id: Some("agent_generic".to_string()),
+                ..Binding::default()
             },
             Binding {
                 channel: "slack".to_string(),
@@ -1210,12 +4493,184 @@ mod tests {
                 business_profile_id: None,
                 user_id: None,
                 agent_id: Some("agent_specific".to_string()),
+                ..Binding::default()
             },
         ];
-        let result = resolve_binding(&bindings, "slack", Some("ACC1"), Some("U1"));
+        let result = resolve_binding(&bindings, "slack", Some("ACC1"), Some("U1"), None, None);
         assert_eq!(result.agent_id, Some("agent_specific".to_string()));
     }
 
+    #[test]
+    fn test_resolve_binding_tie_prefers_agent_id() {
+        let bindings = vec![
+            Binding {
+                channel: "slack".to_string(),
+                peer_id: Some("C1".to_string()),
+                business_profile_id: Some("bp_first".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                peer_id: Some("C1".to_string()),
+                business_profile_id: Some("bp_second".to_string()),
+                agent_id: Some("agent_second".to_string()),
+                ..Binding::default()
+            },
+        ];
+        let result = resolve_binding(&bindings, "slack", None, Some("C1"), None, None);
+        assert_eq!(result.agent_id, Some("agent_second".to_string()));
+        assert_eq!(result.business_profile_id, Some("bp_second".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_binding_tie_keeps_first_declared() {
+        let bindings = vec![
+            Binding {
+                channel: "slack".to_string(),
+                account_id: Some("T1".to_string()),
+                agent_id: Some("agent_first".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                account_id: Some("T1".to_string()),
+                agent_id: Some("agent_second".to_string()),
+                ..Binding::default()
+            },
+        ];
+        let result = resolve_binding(&bindings, "slack", Some("T1"), Some("C9"), None, None);
+        assert_eq!(result.agent_id, Some("agent_first".to_string()));
+
+        let reversed: Vec<Binding> = bindings.into_iter().rev().collect();
+        let result = resolve_binding(&reversed, "slack", Some("T1"), Some("C9"), None, None);
+        assert_eq!(result.agent_id, Some("agent_second".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_binding_layers_overlapping_matches() {
+        let bindings = vec![
+            Binding {
+                channel: "slack".to_string(),
+                peer_id: Some("C1".to_string()),
+                agent_id: Some("agent_peer".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                business_profile_id: Some("bp_workspace".to_string()),
+                user_id: Some("user_default".to_string()),
+                agent_id: Some("agent_default".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                account_id: Some("T1".to_string()),
+                user_id: Some("user_team".to_string()),
+                ..Binding::default()
+            },
+        ];
+        let result = resolve_binding(&bindings, "slack", Some("T1"), Some("C1"), None, None);
+        assert_eq!(result.business_profile_id.as_deref(), Some("bp_workspace"));
+        assert_eq!(result.user_id.as_deref(), Some("user_team"));
+        assert_eq!(result.agent_id.as_deref(), Some("agent_peer"));
+
+        let result = resolve_binding(&bindings, "slack", None, Some("C2"), None, None);
+        assert_eq!(result.business_profile_id.as_deref(), Some("bp_workspace"));
+        assert_eq!(result.user_id.as_deref(), Some("user_default"));
+        assert_eq!(result.agent_id.as_deref(), Some("agent_default"));
+    }
+
+    #[test]
+    fn test_resolve_binding_thread_beats_peer() {
+        let bindings = vec![
+            Binding {
+                channel: "slack".to_string(),
+                account_id: Some("T1".to_string()),
+                peer_id: Some("C1".to_string()),
+                agent_id: Some("agent_channel".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                peer_id: Some("C1".to_string()),
+                thread_id: Some("1700000000.000100".to_string()),
+                agent_id: Some("agent_thread".to_string()),
+                ..Binding::default()
+            },
+        ];
+        let in_thread = resolve_binding(
+            &bindings,
+            "slack",
+            Some("T1"),
+            Some("C1"),
+            Some("1700000000.000100"),
+            None,
+        );
+        assert_eq!(in_thread.agent_id, Some("agent_thread".to_string()));
+
+        let other_thread = resolve_binding(
+            &bindings,
+            "slack",
+            Some("T1"),
+            Some("C1"),
+            Some("1700000000.000200"),
+            None,
+        );
+        assert_eq!(other_thread.agent_id, Some("agent_channel".to_string()));
+
+        let no_thread = resolve_binding(&bindings, "slack", Some("T1"), Some("C1"), None, None);
+        assert_eq!(no_thread.agent_id, Some("agent_channel".to_string()));
+    }
+
+    #[test]
+    fn test_bind_route_matches_canonical_identity_across_channels() {
+        let mut config = Config::default();
+        config.session.identity_links.insert(
+            "Acme-Owner".to_string(),
+            vec![
+                "slack:U02ACME".to_string(),
+                "whatsapp:+44 7700 900123".to_string(),
+            ],
+        );
+        config.bindings = vec![
+            Binding {
+                canonical_id: Some("acme-owner".to_string()),
+                business_profile_id: Some("bp_acme".to_string()),
+                user_id: Some("user_owner".to_string()),
+                agent_id: Some("owner_agent".to_string()),
+                ..Binding::default()
+            },
+            Binding {
+                channel: "slack".to_string(),
+                agent_id: Some("slack_default".to_string()),
+                ..Binding::default()
+            },
+        ];
+        assert!(config.validate().is_ok());
+
+        let slack = bind_route(&config, "slack", Some("T1"), Some("U02ACME"), None);
+        let whatsapp = bind_route(&config, "whatsapp", None, Some("+447700900123"), None);
+        for result in [slack, whatsapp] {
+            assert_eq!(result.agent_id.as_deref(), Some("owner_agent"));
+            assert_eq!(result.user_id.as_deref(), Some("user_owner"));
+            assert_eq!(result.business_profile_id.as_deref(), Some("bp_acme"));
+        }
+
+        let other = bind_route(&config, "slack", Some("T1"), Some("U09OTHER"), None);
+        assert_eq!(other.agent_id.as_deref(), Some("slack_default"));
+        let unlinked = bind_route(&config, "telegram", None, Some("123"), None);
+        assert_eq!(unlinked.agent_id, None);
+
+        config.bindings.push(Binding {
+            channel: "slack".to_string(),
+            peer_id: Some("U02ACME".to_string()),
+            agent_id: Some("slack_peer".to_string()),
+            ..Binding::default()
+        });
+        let pinned = bind_route(&config, "slack", Some("T1"), Some("U02ACME"), None);
+        assert_eq!(pinned.agent_id.as_deref(), Some("slack_peer"));
+    }
+
     #[test]
     fn test_send_message_request_default() {
         let req = SendMessageRequest {
@@ -1226,6 +4681,15 @@ mod tests {
             account_id: None,
             peer_id: None,
             reply_to: None,
+            idempotency_key: None,
+            caption_mode: false,
+            metadata: None,
+            dry_run: false,
+            thread_id: None,
+            peer_kind: None,
+            sender_name: None,
+            sender_icon: None,
+            reply_broadcast: false,
         };
         assert!(req.text.is_none());
         assert!(req.attachments.is_none());
@@ -1255,6 +4719,7 @@ mod tests {
             account_id: None,
             peer_id: None,
             thread_id: None,
+            peer_kind: None,
         };
         assert!(route.account_id.is_none());
         assert!(route.peer_id.is_none());
@@ -1289,13 +4754,20 @@ mod tests {
             account_id: None,
             peer_id: Some("12345".to_string()),
             reply_to: None,
+            caption_mode: false,
+            metadata: None,
+            thread_id: None,
+            peer_kind: None,
+            sender_name: None,
+            sender_icon: None,
+            reply_broadcast: false,
         };
         assert!(msg.reply_to.is_none());
     }
 
     #[test]
     fn test_attachment_multiple() {
-        let attachments = vec![
+        let attachments = [
             Attachment {
                 id: Some("a1".to_string()),
                 url: "https://example.com/1.jpg".to_string(),
@@ -1333,10 +4805,18 @@ mod tests {
         let empty = StatusResponse {
             sessions: 0,
             messages: 0,
+            outbox_pending: 0,
+            outbox_failed: 0,
+            outbox_dead: 0,
+            oldest_pending_age_seconds: None,
         };
         let populated = StatusResponse {
             sessions: 1000,
             messages: 5000,
+            outbox_pending: 3,
+            outbox_failed: 1,
+            outbox_dead: 0,
+            oldest_pending_age_seconds: Some(42),
         };
         assert_eq!(empty.sessions, 0);
         assert_eq!(populated.sessions, 1000);
@@ -1358,6 +4838,15 @@ mod tests {
             account_id: Some("C123".to_string()),
             peer_id: Some("U456".to_string()),
             reply_to: None,
+            idempotency_key: None,
+            caption_mode: false,
+            metadata: None,
+            dry_run: false,
+            thread_id: None,
+            peer_kind: None,
+            sender_name: None,
+            sender_icon: None,
+            reply_broadcast: false,
         };
         assert!(req.attachments.is_some());
         assert_eq!(req.attachments.as_ref().unwrap().len(), 1);
@@ -1374,6 +4863,15 @@ mod tests {
                 account_id: Some("C123".to_string()),
                 peer_id: Some("U456".to_string()),
                 reply_to: None,
+                idempotency_key: None,
+                caption_mode: false,
+                metadata: None,
+                dry_run: false,
+                thread_id: None,
+                peer_kind: None,
+                sender_name: None,
+                sender_icon: None,
+                reply_broadcast: false,
             },
             SendMessageRequest {
                 session_key: "sess_2".to_string(),
@@ -1383,6 +4881,15 @@ mod tests {
                 account_id: None,
                 peer_id: Some("123456789".to_string()),
                 reply_to: None,
+                idempotency_key: None,
+                caption_mode: false,
+                metadata: None,
+                dry_run: false,
+                thread_id: None,
+                peer_kind: None,
+                sender_name: None,
+                sender_icon: None,
+                reply_broadcast: false,
             },
         ];
         let req = BulkSendRequest {
@@ -1408,6 +4915,7 @@ mod tests {
             account_id: Some("C123".to_string()),
             peer_id: Some("U456".to_string()),
             thread_id: Some("TS789".to_string()),
+            peer_kind: None,
         };
         assert_eq!(route.channel, "slack");
         assert!(route.account_id.is_some());
@@ -1445,6 +4953,13 @@ mod tests {
             account_id: None,
             peer_id: None,
             reply_to: None,
+            caption_mode: false,
+            metadata: None,
+            thread_id: None,
+            peer_kind: None,
+            sender_name: None,
+            sender_icon: None,
+            reply_broadcast: false,
         };
         assert!(msg.text.is_none());
         assert!(msg.channel.is_none());
@@ -1477,7 +4992,4576 @@ mod tests {
     #[test]
     fn test_app_state_clone() {
         let config = Config::default();
-        assert!(config.server.host.len() > 0);
+        assert!(!config.server.host.is_empty());
         assert!(config.server.port > 0);
     }
+
+    async fn test_state(config: Config) -> AppState {
+        AppState::for_tests(config).await
+    }
+
+    async fn authed_status(state: AppState, token: Option<&str>) -> StatusCode {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/v1/sessions", get(list_sessions))
+            .layer(middleware::from_fn_with_state(state.clone(), require_auth))
+            .with_state(state);
+        let mut req = axum::http::Request::builder().uri("/v1/sessions");
+        if let Some(token) = token {
+            req = req.header("X-Agent-Ping-Token", token);
+        }
+        let res = app
+            .oneshot(req.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn test_require_auth_accepts_rotated_tokens() {
+        let mut config = Config::default();
+        config.auth.tokens = vec!["old-token".to_string(), "new-token".to_string()];
+        let state = test_state(config).await;
+
+        assert_eq!(
+            authed_status(state.clone(), Some("old-token")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            authed_status(state.clone(), Some("new-token")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            authed_status(state.clone(), Some("unknown")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(authed_status(state, None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_list_channels_reflects_config_and_health() {
+        use tower::ServiceExt;
+
+        let mut config = Config::default();
+        config.channels.slack.enabled = true;
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        config.channels.telegram.enabled = false;
+        let state = test_state(config).await;
+        state.record_inbound("slack", None);
+        state.record_channel_error("slack", Some("rate limited".to_string()));
+
+        let app = Router::new()
+            .route("/v1/channels", get(list_channels))
+            .with_state(state);
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/v1/channels")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let slack = &value["channels"]["slack"];
+        assert_eq!(slack["enabled"], true);
+        assert_eq!(slack["configured"], true);
+        assert!(slack["last_inbound_at"].is_string());
+        assert_eq!(slack["last_error"], "rate limited");
+
+        let telegram = &value["channels"]["telegram"];
+        assert_eq!(telegram["enabled"], false);
+        assert_eq!(telegram["configured"], false);
+        assert!(telegram.get("last_inbound_at").is_none());
+        assert!(telegram.get("last_error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_lag_is_recorded_from_provider_timestamp() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+
+        let mut inbound = threaded_inbound(None);
+        inbound.timestamp = Some((Utc::now() - chrono::Duration::seconds(90)).to_rfc3339());
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let (status, body) = get_json(app.clone(), "/v1/channels").await;
+        assert_eq!(status, StatusCode::OK);
+        let lag = body["channels"]["slack"]["last_lag_seconds"]
+            .as_f64()
+            .unwrap();
+        assert!((90.0..120.0).contains(&lag), "{lag}");
+
+        let mut untimed = threaded_inbound(None);
+        untimed.message_id = Some("1700000000.000300".to_string());
+        handle_inbound(state.clone(), untimed).await.unwrap();
+        let (_, body) = get_json(app, "/v1/channels").await;
+        assert_eq!(
+            body["channels"]["slack"]["last_lag_seconds"].as_f64(),
+            Some(lag)
+        );
+        assert!(body["channels"]["telegram"]
+            .get("last_lag_seconds")
+            .is_none());
+
+        let now = Utc::now();
+        assert_eq!(
+            ingest_lag_seconds(now, now + chrono::Duration::seconds(5)),
+            0.0
+        );
+        assert_eq!(
+            ingest_lag_seconds(now, now - chrono::Duration::milliseconds(1500)),
+            1.5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_by_identity_uses_latest_linked_session_route() {
+        let mut config = Config::default();
+        config.session.dm_scope = "per-channel-peer".to_string();
+        config.session.identity_links.insert(
+            "Acme-Owner".to_string(),
+            vec!["slack:U02ACME".to_string(), "telegram:42".to_string()],
+        );
+        config.channels.telegram.bot_token = Some("token".to_string());
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let dm = |channel: &str, peer_id: &str, inbound_id: &str| InboundMessage {
+            inbound_id: inbound_id.to_string(),
+            channel: channel.to_string(),
+            peer_id: peer_id.to_string(),
+            peer_kind: "dm".to_string(),
+            thread_id: None,
+            message_id: Some(inbound_id.to_string()),
+            account_id: None,
+            ..threaded_inbound(None)
+        };
+        handle_inbound(state.clone(), dm("slack", "U02ACME", "in-1"))
+            .await
+            .unwrap();
+        let slack_session = only_session(&state).await.session_key;
+        handle_inbound(state.clone(), dm("telegram", "42", "in-2"))
+            .await
+            .unwrap();
+        handle_inbound(state.clone(), dm("slack", "U09OTHER", "in-3"))
+            .await
+            .unwrap();
+        // Later inbound messages make later sessions more recently updated.
+        let sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        let base = Utc::now().timestamp();
+        for session in sessions {
+            let offset = match session.last_route.unwrap()["peer_id"].as_str().unwrap() {
+                "U02ACME" => 1,
+                "42" => 2,
+                _ => 3,
+            };
+            sqlx::query("UPDATE sessions SET updated_at = ? WHERE session_key = ?")
+                .bind(base + offset)
+                .bind(&session.session_key)
+                .execute(&state.pool)
+                .await
+                .unwrap();
+        }
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send-by-identity",
+            json!({"canonical_id": "acme-owner", "text": "ping", "dry_run": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(body["session_key"], slack_session.as_str());
+        assert_eq!(body["route"]["channel"], "telegram");
+        assert_eq!(body["route"]["peer_id"], "42");
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send-by-identity",
+            json!({"canonical_id": "nobody", "text": "ping"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "unknown_identity");
+        assert_eq!(body["error"]["canonical_id"], "nobody");
+    }
+
+    #[tokio::test]
+    async fn test_send_by_identity_matches_team_qualified_link() {
+        let mut config = Config::default();
+        config.session.dm_scope = "per-channel-peer".to_string();
+        config.channels.slack.qualify_peer_ids = true;
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        config
+            .session
+            .identity_links
+            .insert("owner".to_string(), vec!["slack:T1:D456".to_string()]);
+        let state = test_state(config).await;
+        let dm = |team: &str, inbound_id: &str| InboundMessage {
+            inbound_id: inbound_id.to_string(),
+            peer_id: "D456".to_string(),
+            peer_kind: "dm".to_string(),
+            thread_id: None,
+            account_id: Some(team.to_string()),
+            message_id: Some(inbound_id.to_string()),
+            ..threaded_inbound(None)
+        };
+        handle_inbound(state.clone(), dm("T1", "in-1"))
+            .await
+            .unwrap();
+        handle_inbound(state.clone(), dm("T2", "in-2"))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE sessions SET updated_at = updated_at + 60 WHERE session_key LIKE ?")
+            .bind("%:t2:d456")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let (status, body) = post_json(
+            build_router(&state),
+            "/v1/messages/send-by-identity",
+            json!({"canonical_id": "owner", "text": "ping", "dry_run": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["route"]["account_id"], "T1");
+        assert_eq!(body["route"]["peer_id"], "D456");
+    }
+
+    async fn post_json(
+        app: Router,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .body(axum::body::Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = res.status();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(json!(null)),
+        )
+    }
+
+    #[test]
+    fn test_send_error_status_and_code() {
+        let cases = [
+            (
+                SendError::UnknownSession,
+                StatusCode::NOT_FOUND,
+                "unknown_session",
+            ),
+            (
+                SendError::NoRoute,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "no_route",
+            ),
+            (
+                SendError::UnsupportedChannel("irc".to_string()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unsupported_channel",
+            ),
+            (
+                SendError::MissingPeer("slack".to_string()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "missing_peer",
+            ),
+            (
+                SendError::Other(anyhow::anyhow!("boom")),
+                StatusCode::BAD_REQUEST,
+                "send_failed",
+            ),
+            (
+                SendError::NotConfigured("slack bot token missing".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "not_configured",
+            ),
+            (
+                SendError::Internal(anyhow::anyhow!("pool closed")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+            ),
+        ];
+        for (err, status, code) in cases {
+            assert_eq!(err.status(), status);
+            assert_eq!(err.code(), code);
+            assert_eq!(err.to_json()["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_message_error_codes() {
+        let state = test_state(Config::default()).await;
+        let now = Utc::now();
+        db::upsert_session(
+            &state.pool,
+            state.db_kind,
+            &db::SessionRecord {
+                session_key: "agent:main:routeless".to_string(),
+                agent_id: "main".to_string(),
+                business_profile_id: None,
+                user_id: None,
+                last_route: None,
+                dm_scope: "main".to_string(),
+                identity_links: None,
+                created_at: now,
+                updated_at: now,
+                metadata: None,
+            },
+        )
+        .await
+        .unwrap();
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .route("/v1/messages/send-bulk", post(send_bulk))
+            .with_state(state);
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "agent:main:missing", "text": "hi"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "unknown_session");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "agent:main:routeless", "text": "hi"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "no_route");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "agent:main:routeless", "text": "hi", "channel": "irc"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "unsupported_channel");
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send-bulk",
+            json!({"messages": [
+                {"session_key": "agent:main:missing", "text": "hi"},
+                {"session_key": "agent:main:routeless", "text": "hi"}
+            ]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["code"], "unknown_session");
+        assert_eq!(body["results"][1]["code"], "no_route");
+    }
+
+    #[tokio::test]
+    async fn test_send_message_database_failure_is_internal_error() {
+        let state = test_state(Config::default()).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+        state.pool.close().await;
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({"session_key": "agent:main:any", "text": "hi"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["error"]["code"], "internal_error");
+    }
+
+    #[tokio::test]
+    async fn test_send_without_bot_token_is_not_configured() {
+        let state = test_state(Config::default()).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({"session_key": "", "text": "hi", "channel": "slack", "peer_id": "C1"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"]["code"], "not_configured");
+        let due = db::due_outbound_queue(&state.pool, state.db_kind, Utc::now(), 10)
+            .await
+            .unwrap();
+        assert!(due.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stats_endpoint_returns_bucketed_series() {
+        let state = test_state(Config::default()).await;
+        let now = Utc::now().timestamp();
+        let current = now - now % 3600;
+        for (id, at, channel, direction) in [
+            ("m1", current, "slack", "inbound"),
+            ("m2", current, "slack", "outbound"),
+            ("m3", current - 3600 + 5, "telegram", "inbound"),
+            ("m4", current - 5 * 3600, "slack", "inbound"),
+            ("m5", current - 48 * 3600, "slack", "inbound"),
+        ] {
+            db::insert_message(
+                &state.pool,
+                state.db_kind,
+                &db::MessageRecord {
+                    id: id.to_string(),
+                    session_key: "agent:main:main".to_string(),
+                    direction: direction.to_string(),
+                    channel: channel.to_string(),
+                    account_id: None,
+                    peer_id: None,
+                    content: None,
+                    attachments: None,
+                    status: "received".to_string(),
+                    dedupe_key: None,
+                    provider_message_id: None,
+                    created_at: DateTime::from_timestamp(at, 0).unwrap(),
+                    metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let app = build_router(&state);
+
+        let (status, body) = get_json(app.clone(), "/v1/stats?window=24h&bucket=1h").await;
+        assert_eq!(status, StatusCode::OK);
+        let buckets = body["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 24);
+        let last = &buckets[23];
+        assert_eq!(
+            last["start"],
+            json!(DateTime::from_timestamp(current, 0).unwrap())
+        );
+        assert_eq!(last["inbound"], 1);
+        assert_eq!(last["outbound"], 1);
+        assert_eq!(last["channels"]["slack"]["outbound"], 1);
+        assert_eq!(buckets[22]["channels"]["telegram"]["inbound"], 1);
+        assert_eq!(buckets[18]["inbound"], 1);
+        let total: i64 = buckets
+            .iter()
+            .map(|b| b["inbound"].as_i64().unwrap() + b["outbound"].as_i64().unwrap())
+            .sum();
+        assert_eq!(total, 4);
+
+        let (status, body) = get_json(app.clone(), "/v1/stats").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["window"], "24h");
+        let (status, _) = get_json(app.clone(), "/v1/stats?window=2h").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get_json(app, "/v1/stats?window=30d&bucket=5m").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_first_contact_send_uses_peer_kind_and_thread() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .and(body_partial_json(
+                json!({"to": "120363@g.us", "peer_kind": "group"}),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"message_id": "wamid.9"})),
+            )
+            .expect(1)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "text": "hello team",
+                "channel": "slack",
+                "peer_id": "C9",
+                "peer_kind": "group",
+                "thread_id": "1700000000.000100",
+                "dry_run": true
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["session_key"],
+            "agent:main:slack:group:c9:thread:1700000000.000100"
+        );
+        assert_eq!(body["route"]["peer_kind"], "group");
+        assert_eq!(body["route"]["thread_id"], "1700000000.000100");
+        assert_eq!(body["payload"]["thread_ts"], "1700000000.000100");
+        assert!(body["payload"].get("username").is_none());
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "text": "invoice ready",
+                "channel": "slack",
+                "peer_id": "C9",
+                "sender_name": "Billing Bot",
+                "sender_icon": ":moneybag:",
+                "dry_run": true
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"]["username"], "Billing Bot");
+        assert_eq!(body["payload"]["icon_emoji"], ":moneybag:");
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "text": "hello group",
+                "channel": "whatsapp",
+                "peer_id": "120363@g.us",
+                "peer_kind": "group"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let session = only_session(&state).await;
+        assert_eq!(session.session_key, "agent:main:whatsapp:group:120363@g.us");
+        let route = session.last_route.unwrap();
+        assert_eq!(route["peer_kind"], "group");
+        assert_eq!(route["peer_id"], "120363@g.us");
+    }
+
+    #[tokio::test]
+    async fn test_slack_reply_broadcast_only_for_threaded_sends() {
+        let mut config = Config::default();
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let mut send = json!({
+            "session_key": "",
+            "text": "deploy finished",
+            "channel": "slack",
+            "peer_id": "C9",
+            "peer_kind": "channel",
+            "thread_id": "1700000000.000100",
+            "reply_broadcast": true,
+            "dry_run": true
+        });
+        let (status, body) = post_json(app.clone(), "/v1/messages/send", send.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["payload"]["thread_ts"], "1700000000.000100");
+        assert_eq!(body["payload"]["reply_broadcast"], true);
+
+        send["thread_id"] = serde_json::Value::Null;
+        let (status, body) = post_json(app, "/v1/messages/send", send).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert!(body["payload"].get("reply_broadcast").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_custom_channel_inbound_and_outbound_forwarding() {
+        use wiremock::matchers::{body_partial_json, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let gateway = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/sms/send"))
+            .and(header("x-api-key", "k1"))
+            .and(body_partial_json(json!({
+                "channel": "sms",
+                "peer_id": "+15550001",
+                "text": "got it",
+                "session_key": "agent:main:sms:dm:+15550001"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"message_id": "sms-42"})))
+            .expect(1)
+            .mount(&gateway)
+            .await;
+        let mut config = Config::default();
+        config.session.dm_scope = "per-channel-peer".to_string();
+        config.channels.custom.insert(
+            "sms".to_string(),
+            crate::config::CustomChannelConfig {
+                forward_url: format!("{}/sms/send", gateway.uri()),
+                headers: std::collections::HashMap::from([(
+                    "x-api-key".to_string(),
+                    "k1".to_string(),
+                )]),
+            },
+        );
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/inbound",
+            json!({
+                "inbound_id": "sms-in-1",
+                "channel": "sms",
+                "account_id": null,
+                "peer_id": "+15550001",
+                "peer_kind": "dm",
+                "thread_id": null,
+                "message_id": "gw-1",
+                "sender_name": "Ada",
+                "text": "hello",
+                "attachments": [],
+                "timestamp": null
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["status"], "accepted");
+        let session = only_session(&state).await;
+        assert_eq!(session.session_key, "agent:main:sms:dm:+15550001");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "agent:main:sms:dm:+15550001", "text": "got it"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let message_id = body["message_id"].as_str().unwrap();
+        let message = db::get_message(&state.pool, state.db_kind, message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.status, "sent");
+        assert_eq!(message.provider_message_id.as_deref(), Some("sms-42"));
+
+        let (status, _) = post_json(
+            app,
+            "/v1/inbound",
+            json!({
+                "inbound_id": "sms-in-2",
+                "channel": " ",
+                "account_id": null,
+                "peer_id": "+15550001",
+                "peer_kind": "dm",
+                "thread_id": null,
+                "message_id": null,
+                "sender_name": null,
+                "text": "hello",
+                "attachments": [],
+                "timestamp": null
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_reply_to_internal_message_id_uses_provider_id() {
+        let mut config = Config::default();
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        let state = test_state(config).await;
+        for (id, channel, provider_id) in [
+            ("msg-parent", "slack", "1700000000.000100"),
+            ("msg-telegram", "telegram", "55"),
+        ] {
+            db::insert_message(
+                &state.pool,
+                state.db_kind,
+                &db::MessageRecord {
+                    id: id.to_string(),
+                    session_key: "agent:main:slack:channel:c1".to_string(),
+                    direction: "inbound".to_string(),
+                    channel: channel.to_string(),
+                    account_id: None,
+                    peer_id: Some("C1".to_string()),
+                    content: Some("hi".to_string()),
+                    attachments: None,
+                    status: "received".to_string(),
+                    dedupe_key: None,
+                    provider_message_id: Some(provider_id.to_string()),
+                    created_at: Utc::now(),
+                    metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state);
+
+        for (reply_to, thread_ts) in [
+            ("msg-parent", "1700000000.000100"),
+            ("1700000000.000999", "1700000000.000999"),
+            ("msg-telegram", "msg-telegram"),
+        ] {
+            let (status, body) = post_json(
+                app.clone(),
+                "/v1/messages/send",
+                json!({
+                    "session_key": "agent:main:slack:channel:c1",
+                    "text": "reply",
+                    "channel": "slack",
+                    "peer_id": "C1",
+                    "reply_to": reply_to,
+                    "dry_run": true
+                }),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["payload"]["thread_ts"], thread_ts, "{reply_to}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reply_quote_prepends_replied_to_snippet() {
+        let mut config = Config::default();
+        config.channels.whatsapp.reply_quote = true;
+        let state = test_state(config).await;
+        let long = format!("Is the   invoice\nready? {}", "x".repeat(100));
+        db::insert_message(
+            &state.pool,
+            state.db_kind,
+            &db::MessageRecord {
+                id: "msg-question".to_string(),
+                session_key: "agent:main:whatsapp:dm:+447700900123".to_string(),
+                direction: "inbound".to_string(),
+                channel: "whatsapp".to_string(),
+                account_id: None,
+                peer_id: Some("+447700900123".to_string()),
+                content: Some(long),
+                attachments: None,
+                status: "received".to_string(),
+                dedupe_key: None,
+                provider_message_id: Some("wamid.1".to_string()),
+                created_at: Utc::now(),
+                metadata: None,
+            },
+        )
+        .await
+        .unwrap();
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state);
+
+        let send = |channel: &str| {
+            json!({
+                "session_key": "",
+                "text": "Yes, sent it",
+                "channel": channel,
+                "peer_id": "+447700900123",
+                "reply_to": "msg-question",
+                "dry_run": true
+            })
+        };
+        let (status, body) = post_json(app.clone(), "/v1/messages/send", send("whatsapp")).await;
+        assert_eq!(status, StatusCode::OK);
+        let text = body["payload"]["text"].as_str().unwrap();
+        let (quote, reply) = text.split_once('\n').unwrap();
+        assert!(quote.starts_with("> Is the invoice ready? xxx"), "{quote}");
+        assert!(quote.ends_with('…'));
+        assert_eq!(quote.chars().count(), 2 + REPLY_QUOTE_CHARS + 1);
+        assert_eq!(reply, "Yes, sent it");
+
+        let (_, body) = post_json(app, "/v1/messages/send", send("telegram")).await;
+        assert_eq!(body["payload"]["text"], "Yes, sent it");
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_send_resolves_route_without_sending() {
+        use tower::ServiceExt;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "text": "hello",
+                "channel": "whatsapp",
+                "peer_id": "+447700900123",
+                "dry_run": true
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "dry_run");
+        assert_eq!(body["session_key"], "agent:main:main");
+        assert_eq!(body["route"]["channel"], "whatsapp");
+        assert_eq!(body["route"]["peer_id"], "+447700900123");
+        assert_eq!(body["payload"]["to"], "+447700900123");
+        assert_eq!(body["payload"]["text"], "hello");
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri("/v1/messages/send")
+                    .header("content-type", "application/json")
+                    .header("X-Agent-Ping-Dry-Run", "true")
+                    .body(axum::body::Body::from(
+                        json!({
+                            "session_key": "agent:main:slack:channel:c1",
+                            "text": "hi",
+                            "channel": "slack",
+                            "peer_id": "C1"
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["status"], "dry_run");
+        assert_eq!(body["route"]["channel"], "slack");
+        assert_eq!(body["payload"]["channel"], "C1");
+        assert_eq!(body["payload"]["text"], "hi");
+
+        for table in ["messages", "sessions"] {
+            let count = sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(1) FROM {table}"))
+                .fetch_one(&state.pool)
+                .await
+                .unwrap();
+            assert_eq!(count, 0, "{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_first_explicit_send_creates_session() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.bindings = vec![Binding {
+            channel: "whatsapp".to_string(),
+            business_profile_id: Some("bp_acme".to_string()),
+            agent_id: Some("sales".to_string()),
+            ..Binding::default()
+        }];
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({
+                "session_key": "agent:sales:whatsapp:dm:447700900123",
+                "text": "hello",
+                "channel": "whatsapp",
+                "peer_id": "447700900123"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let session = db::get_session(
+            &state.pool,
+            state.db_kind,
+            "agent:sales:whatsapp:dm:447700900123",
+        )
+        .await
+        .unwrap()
+        .expect("session created");
+        assert_eq!(session.agent_id, "sales");
+        assert_eq!(session.business_profile_id.as_deref(), Some("bp_acme"));
+        let last_route = session.last_route.unwrap();
+        assert_eq!(last_route["channel"], "whatsapp");
+        assert_eq!(last_route["peer_id"], "+447700900123");
+
+        let (status, _) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({"session_key": "agent:sales:whatsapp:dm:447700900123", "text": "again"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri(uri)
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = res.status();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(json!(null)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_share_envelope() {
+        use tower::ServiceExt;
+
+        let mut config = Config::default();
+        config.auth.tokens = vec!["secret".to_string()];
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        let call = |method: &str, uri: &str, token: bool, body: &str| {
+            let mut req = axum::http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json");
+            if token {
+                req = req.header("X-Agent-Ping-Token", "secret");
+            }
+            let req = req.body(axum::body::Body::from(body.to_string())).unwrap();
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let request_id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+                let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(body["error"]["request_id"], request_id.as_str(), "{body}");
+                assert!(body["error"]["message"].is_string(), "{body}");
+                (
+                    status,
+                    body["error"]["code"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                )
+            }
+        };
+
+        let (status, code) = call("GET", "/v1/sessions", false, "").await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::UNAUTHORIZED, "unauthorized")
+        );
+        let (status, code) = call("GET", "/v1/sessions/agent:main:none", true, "").await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::NOT_FOUND, "unknown_session")
+        );
+        let (status, code) = call("GET", "/v1/no-such-route", true, "").await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::NOT_FOUND, "not_found")
+        );
+        let send = r#"{"session_key": "agent:main:missing", "text": "hi"}"#;
+        let (status, code) = call("POST", "/v1/messages/send", true, send).await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::NOT_FOUND, "unknown_session")
+        );
+        let (status, code) = call("POST", "/v1/messages/send", true, "{not json").await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::BAD_REQUEST, "bad_request")
+        );
+        let (status, code) = call("POST", "/v1/messages/send", true, r#"{"text": 1}"#).await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::UNPROCESSABLE_ENTITY, "unprocessable_entity")
+        );
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/v1/sessions")
+                    .header("x-request-id", "req-123")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["x-request-id"], "req-123");
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["request_id"], "req-123");
+    }
+
+    #[tokio::test]
+    async fn test_list_handlers_return_500_on_db_error() {
+        let state = test_state(Config::default()).await;
+        let app = Router::new()
+            .route("/v1/sessions", get(list_sessions))
+            .route("/v1/sessions/:session_key", get(get_session))
+            .route("/v1/sessions/:session_key/messages", get(list_messages))
+            .with_state(state.clone());
+
+        let (status, body) = get_json(app.clone(), "/v1/sessions").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!([]));
+        let (status, _) = get_json(app.clone(), "/v1/sessions/agent:main:none").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        state.pool.close().await;
+        for uri in [
+            "/v1/sessions",
+            "/v1/sessions/agent:main:none",
+            "/v1/sessions/agent:main:none/messages",
+        ] {
+            let (status, body) = get_json(app.clone(), uri).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{uri}");
+            assert!(body["error"]["message"].is_string(), "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_messages_after_watermark() {
+        let state = test_state(Config::default()).await;
+        let older = Utc::now() - chrono::Duration::seconds(60);
+        let newer = Utc::now();
+        for (id, created_at) in [("m-old", older), ("m-new-1", newer), ("m-new-2", newer)] {
+            db::insert_message(
+                &state.pool,
+                state.db_kind,
+                &db::MessageRecord {
+                    id: id.to_string(),
+                    session_key: "agent:main:poll".to_string(),
+                    direction: "inbound".to_string(),
+                    channel: "slack".to_string(),
+                    account_id: None,
+                    peer_id: Some("U1".to_string()),
+                    content: Some(id.to_string()),
+                    attachments: None,
+                    status: "received".to_string(),
+                    dedupe_key: None,
+                    created_at,
+                    provider_message_id: None,
+                    metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let app = Router::new()
+            .route("/v1/sessions/:session_key/messages", get(list_messages))
+            .with_state(state);
+
+        let uri = format!(
+            "/v1/sessions/agent:main:poll/messages?after={}",
+            older.timestamp_millis()
+        );
+        let (status, body) = get_json(app.clone(), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let ids: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&"m-old"));
+
+        let (status, body) = get_json(
+            app.clone(),
+            "/v1/sessions/agent:main:poll/messages?after=m-old",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (status, body) = get_json(app.clone(), "/v1/sessions/agent:main:poll/messages").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 3);
+
+        let (status, _) =
+            get_json(app, "/v1/sessions/agent:main:poll/messages?after=missing").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_stream_messages_writes_json_lines() {
+        use tower::ServiceExt;
+
+        let state = test_state(Config::default()).await;
+        let now = Utc::now();
+        for (id, offset) in [("m1", 2), ("m2", 1), ("m3", 0)] {
+            db::insert_message(
+                &state.pool,
+                state.db_kind,
+                &db::MessageRecord {
+                    id: id.to_string(),
+                    session_key: "agent:main:export".to_string(),
+                    direction: "inbound".to_string(),
+                    channel: "telegram".to_string(),
+                    account_id: None,
+                    peer_id: Some("42".to_string()),
+                    content: Some(format!("line {id}\nwith newline")),
+                    attachments: None,
+                    status: "received".to_string(),
+                    dedupe_key: None,
+                    created_at: now - chrono::Duration::seconds(offset),
+                    provider_message_id: None,
+                    metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let app = Router::new()
+            .route(
+                "/v1/sessions/:session_key/messages/stream",
+                get(stream_messages),
+            )
+            .with_state(state);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/v1/sessions/agent:main:export/messages/stream")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        let ids: Vec<String> = text
+            .lines()
+            .map(|line| {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                value["id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(ids, vec!["m1", "m2", "m3"]);
+    }
+
+    fn threaded_inbound(thread_id: Option<&str>) -> InboundMessage {
+        InboundMessage {
+            inbound_id: "in-1".to_string(),
+            channel: "slack".to_string(),
+            account_id: Some("T1".to_string()),
+            peer_id: "C1".to_string(),
+            peer_kind: "channel".to_string(),
+            thread_id: thread_id.map(|v| v.to_string()),
+            message_id: Some("1700000000.000200".to_string()),
+            sender_name: Some("U1".to_string()),
+            text: Some("hi".to_string()),
+            attachments: Vec::new(),
+            timestamp: None,
+        }
+    }
+
+    fn reply(session_key: &str, channel: Option<&str>) -> OutboundMessage {
+        OutboundMessage {
+            session_key: session_key.to_string(),
+            text: Some("reply".to_string()),
+            attachments: Vec::new(),
+            channel: channel.map(|v| v.to_string()),
+            account_id: None,
+            peer_id: channel.map(|_| "C1".to_string()),
+            reply_to: None,
+            caption_mode: false,
+            metadata: None,
+            thread_id: None,
+            peer_kind: None,
+            sender_name: None,
+            sender_icon: None,
+            reply_broadcast: false,
+        }
+    }
+
+    async fn only_session(state: &AppState) -> db::SessionRecord {
+        let mut sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 1);
+        sessions.remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_slack_qualified_peer_ids_keep_workspaces_apart() {
+        let from_team = |team: &str, inbound_id: &str| InboundMessage {
+            inbound_id: inbound_id.to_string(),
+            account_id: Some(team.to_string()),
+            message_id: Some(inbound_id.to_string()),
+            ..threaded_inbound(None)
+        };
+
+        let state = test_state(Config::default()).await;
+        handle_inbound(state.clone(), from_team("T1", "in-1"))
+            .await
+            .unwrap();
+        handle_inbound(state.clone(), from_team("T2", "in-2"))
+            .await
+            .unwrap();
+        only_session(&state).await;
+
+        let mut config = Config::default();
+        config.channels.slack.qualify_peer_ids = true;
+        config.channels.slack.bot_token = Some("xoxb-test".to_string());
+        let state = test_state(config).await;
+        handle_inbound(state.clone(), from_team("T1", "in-1"))
+            .await
+            .unwrap();
+        handle_inbound(state.clone(), from_team("T2", "in-2"))
+            .await
+            .unwrap();
+        let mut sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        sessions.sort_by(|a, b| a.session_key.cmp(&b.session_key));
+        let keys: Vec<&str> = sessions.iter().map(|s| s.session_key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "agent:main:slack:channel:t1:c1",
+                "agent:main:slack:channel:t2:c1"
+            ]
+        );
+        for (session, team) in sessions.iter().zip(["T1", "T2"]) {
+            let route = session.last_route.as_ref().unwrap();
+            assert_eq!(route["account_id"], team);
+            assert_eq!(route["peer_id"], "C1");
+        }
+
+        let (status, body) = post_json(
+            build_router(&state),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "channel": "slack",
+                "account_id": "T2",
+                "peer_id": "C1",
+                "peer_kind": "channel",
+                "text": "hi",
+                "dry_run": true
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["session_key"], "agent:main:slack:channel:t2:c1");
+        assert_eq!(body["payload"]["channel"], "C1");
+    }
+
+    #[tokio::test]
+    async fn test_outbound_defaults_into_inbound_thread() {
+        let state = test_state(Config::default()).await;
+        handle_inbound(state.clone(), threaded_inbound(Some("1700000000.000100")))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+        let last_route = session.last_route.as_ref().unwrap();
+        assert_eq!(last_route["thread_id"], "1700000000.000100");
+        assert_eq!(last_route["message_id"], "1700000000.000200");
+
+        let implicit = resolve_outbound_route(
+            &state.config(),
+            Some(&session),
+            &reply(&session.session_key, None),
+        )
+        .unwrap();
+        assert_eq!(implicit.thread_id.as_deref(), Some("1700000000.000100"));
+
+        let explicit = resolve_outbound_route(
+            &state.config(),
+            Some(&session),
+            &reply(&session.session_key, Some("slack")),
+        )
+        .unwrap();
+        assert_eq!(explicit.thread_id.as_deref(), Some("1700000000.000100"));
+    }
+
+    #[tokio::test]
+    async fn test_outbound_always_thread_replies_under_last_message() {
+        let mut config = Config::default();
+        config.channels.slack.always_thread = true;
+        let state = test_state(config).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+
+        let route = resolve_outbound_route(
+            &state.config(),
+            Some(&session),
+            &reply(&session.session_key, None),
+        )
+        .unwrap();
+        assert_eq!(route.thread_id.as_deref(), Some("1700000000.000200"));
+
+        let mut plain = (*state.config()).clone();
+        plain.channels.slack.always_thread = false;
+        let route =
+            resolve_outbound_route(&plain, Some(&session), &reply(&session.session_key, None))
+                .unwrap();
+        assert!(route.thread_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_edit_message_error_codes() {
+        let state = test_state(Config::default()).await;
+        for (id, channel, provider_message_id) in [
+            ("out-wa", "whatsapp", Some("wamid.1")),
+            ("out-tg-unsent", "telegram", None),
+        ] {
+            db::insert_message(
+                &state.pool,
+                state.db_kind,
+                &db::MessageRecord {
+                    id: id.to_string(),
+                    session_key: "agent:main:edit".to_string(),
+                    direction: "outbound".to_string(),
+                    channel: channel.to_string(),
+                    account_id: None,
+                    peer_id: Some("42".to_string()),
+                    content: Some("draft".to_string()),
+                    attachments: None,
+                    status: "queued".to_string(),
+                    dedupe_key: None,
+                    provider_message_id: provider_message_id.map(|v| v.to_string()),
+                    created_at: Utc::now(),
+                    metadata: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let app = Router::new()
+            .route("/v1/messages/:message_id", patch(edit_message))
+            .with_state(state);
+
+        for (id, status, code) in [
+            ("missing", StatusCode::NOT_FOUND, "unknown_message"),
+            ("out-tg-unsent", StatusCode::NOT_FOUND, "unknown_message"),
+            (
+                "out-wa",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "edit_unsupported",
+            ),
+        ] {
+            let res = tower::ServiceExt::oneshot(
+                app.clone(),
+                axum::http::Request::builder()
+                    .method("PATCH")
+                    .uri(format!("/v1/messages/{id}"))
+                    .header("content-type", "application/json")
+                    .body(axum::body::Body::from(r#"{"text":"final"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(res.status(), status, "{id}");
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(value["error"]["code"], code, "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_message_error_codes() {
+        let state = test_state(Config::default()).await;
+        db::insert_message(
+            &state.pool,
+            state.db_kind,
+            &db::MessageRecord {
+                id: "out-wa".to_string(),
+                session_key: "agent:main:delete".to_string(),
+                direction: "outbound".to_string(),
+                channel: "whatsapp".to_string(),
+                account_id: None,
+                peer_id: Some("447700900123".to_string()),
+                content: Some("oops".to_string()),
+                attachments: None,
+                status: "queued".to_string(),
+                dedupe_key: None,
+                provider_message_id: Some("wamid.1".to_string()),
+                created_at: Utc::now(),
+                metadata: None,
+            },
+        )
+        .await
+        .unwrap();
+        let app = Router::new()
+            .route(
+                "/v1/messages/:message_id",
+                axum::routing::delete(delete_message),
+            )
+            .with_state(state.clone());
+
+        for (id, status, code) in [
+            ("missing", StatusCode::NOT_FOUND, "unknown_message"),
+            (
+                "out-wa",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "delete_unsupported",
+            ),
+        ] {
+            let res = tower::ServiceExt::oneshot(
+                app.clone(),
+                axum::http::Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/v1/messages/{id}"))
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(res.status(), status, "{id}");
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(value["error"]["code"], code, "{id}");
+        }
+        let message = db::get_message(&state.pool, state.db_kind, "out-wa")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.status, "queued");
+    }
+
+    #[tokio::test]
+    async fn test_slack_events_require_signature_when_secret_set() {
+        use hmac::{Hmac, Mac};
+        use tower::ServiceExt;
+
+        let body = json!({"type": "url_verification", "challenge": "c-1"}).to_string();
+        let sign = |secret: &str, timestamp: i64| {
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(format!("v0:{timestamp}:{body}").as_bytes());
+            format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+        };
+        let now = Utc::now().timestamp();
+        let send = |secret: Option<&str>, headers: Option<(i64, String)>| {
+            let mut config = Config::default();
+            config.channels.slack.signing_secret = secret.map(str::to_string);
+            let body = body.clone();
+            async move {
+                let state = test_state(config).await;
+                let app = Router::new()
+                    .route("/v1/channels/slack/events", post(slack_events))
+                    .with_state(state);
+                let mut request = axum::http::Request::builder()
+                    .method("POST")
+                    .uri("/v1/channels/slack/events")
+                    .header("content-type", "application/json");
+                if let Some((timestamp, signature)) = headers {
+                    request = request
+                        .header("X-Slack-Request-Timestamp", timestamp.to_string())
+                        .header("X-Slack-Signature", signature);
+                }
+                let res = app
+                    .oneshot(request.body(axum::body::Body::from(body)).unwrap())
+                    .await
+                    .unwrap();
+                res.status()
+            }
+        };
+
+        assert_eq!(send(None, None).await, StatusCode::OK);
+        assert_eq!(send(Some("s3cret"), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            send(Some("s3cret"), Some((now, sign("s3cret", now)))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(Some("s3cret"), Some((now, sign("wrong", now)))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let stale = now - 600;
+        assert_eq!(
+            send(Some("s3cret"), Some((stale, sign("s3cret", stale)))).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slack_reaction_events_are_opt_in() {
+        use tower::ServiceExt;
+
+        let payload = json!({
+            "type": "event_callback",
+            "team_id": "T1",
+            "event": {
+                "type": "reaction_added",
+                "user": "U1",
+                "reaction": "eyes",
+                "item": {"type": "message", "channel": "C1", "ts": "1700000000.000100"}
+            }
+        })
+        .to_string();
+
+        for enabled in [false, true] {
+            let mut config = Config::default();
+            config.channels.slack.inbound_reactions = enabled;
+            let state = test_state(config).await;
+            let mut rx = state.ws_tx.subscribe();
+            let app = Router::new()
+                .route("/v1/channels/slack/events", post(slack_events))
+                .with_state(state);
+            let res = app
+                .oneshot(
+                    axum::http::Request::builder()
+                        .method("POST")
+                        .uri("/v1/channels/slack/events")
+                        .header("content-type", "application/json")
+                        .body(axum::body::Body::from(payload.clone()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            match rx.try_recv() {
+                Ok(event) => {
+                    assert!(enabled);
+                    assert_eq!(event.event, "reaction");
+                    assert_eq!(event.payload["reaction"]["reaction"], "eyes");
+                    assert_eq!(event.payload["reaction"]["added"], true);
+                    assert!(event.payload["message_id"].is_null());
+                }
+                Err(_) => assert!(!enabled),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slack_message_changes_are_opt_in() {
+        let changed = json!({
+            "type": "event_callback",
+            "team_id": "T1",
+            "event": {
+                "type": "message",
+                "subtype": "message_changed",
+                "channel": "C1",
+                "message": {"text": "edited", "ts": "1700000000.000200"}
+            }
+        });
+        let deleted = json!({
+            "type": "event_callback",
+            "event": {
+                "type": "message",
+                "subtype": "message_deleted",
+                "channel": "C1",
+                "deleted_ts": "1700000000.000200"
+            }
+        });
+
+        for enabled in [false, true] {
+            let mut config = Config::default();
+            config.channels.slack.track_edits = enabled;
+            let state = test_state(config).await;
+            handle_inbound(state.clone(), threaded_inbound(None))
+                .await
+                .unwrap();
+            let stored = db::find_message_by_provider_id(
+                &state.pool,
+                state.db_kind,
+                "slack",
+                "C1",
+                "1700000000.000200",
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let mut rx = state.ws_tx.subscribe();
+            let app = Router::new()
+                .route("/v1/channels/slack/events", post(slack_events))
+                .with_state(state.clone());
+
+            let (status, _) =
+                post_json(app.clone(), "/v1/channels/slack/events", changed.clone()).await;
+            assert_eq!(status, StatusCode::OK);
+            let message = db::get_message(&state.pool, state.db_kind, &stored.id)
+                .await
+                .unwrap()
+                .unwrap();
+            if !enabled {
+                assert_eq!(message.content.as_deref(), Some("hi"));
+                assert!(rx.try_recv().is_err());
+                continue;
+            }
+            assert_eq!(message.content.as_deref(), Some("edited"));
+            let event = rx.try_recv().unwrap();
+            assert_eq!(event.event, "chat");
+            assert_eq!(event.payload["edited"], true);
+            assert_eq!(event.payload["direction"], "inbound");
+            assert_eq!(event.payload["message"]["id"], stored.id.as_str());
+            assert_eq!(event.payload["message"]["content"], "edited");
+
+            let (status, _) = post_json(app, "/v1/channels/slack/events", deleted.clone()).await;
+            assert_eq!(status, StatusCode::OK);
+            let message = db::get_message(&state.pool, state.db_kind, &stored.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message.status, "deleted");
+            let event = rx.try_recv().unwrap();
+            assert_eq!(event.payload["deleted"], true);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_reaction_error_codes() {
+        let state = test_state(Config::default()).await;
+        db::insert_message(
+            &state.pool,
+            state.db_kind,
+            &db::MessageRecord {
+                id: "in-wa".to_string(),
+                session_key: "agent:main:react".to_string(),
+                direction: "inbound".to_string(),
+                channel: "whatsapp".to_string(),
+                account_id: None,
+                peer_id: Some("447700900123".to_string()),
+                content: Some("hi".to_string()),
+                attachments: None,
+                status: "received".to_string(),
+                dedupe_key: None,
+                provider_message_id: Some("wamid.1".to_string()),
+                created_at: Utc::now(),
+                metadata: None,
+            },
+        )
+        .await
+        .unwrap();
+        let app = Router::new()
+            .route("/v1/messages/:message_id/reactions", post(add_reaction))
+            .with_state(state);
+
+        for (id, status, code) in [
+            ("missing", StatusCode::NOT_FOUND, "unknown_message"),
+            (
+                "in-wa",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "reaction_unsupported",
+            ),
+        ] {
+            let (got, value) = post_json(
+                app.clone(),
+                &format!("/v1/messages/{id}/reactions"),
+                json!({"reaction": "👍"}),
+            )
+            .await;
+            assert_eq!(got, status, "{id}");
+            assert_eq!(value["error"]["code"], code, "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_inbound_refreshes_session_without_new_message() {
+        let state = test_state(Config::default()).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+        sqlx::query("UPDATE sessions SET updated_at = ?")
+            .bind(Utc::now().timestamp() - 3600)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        let mut rx = state.ws_tx.subscribe();
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+
+        let refreshed = only_session(&state).await;
+        assert!(refreshed.updated_at > Utc::now() - chrono::Duration::minutes(1));
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages.len(), 1);
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.event, "dedupe");
+        assert_eq!(event.payload["session_key"], session.session_key);
+        assert_eq!(event.payload["dedupe_key"], "slack:C1:1700000000.000200");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_client_roundtrip() {
+        use crate::client::{BulkSendResult, Client, ClientError};
+
+        let mut config = Config::default();
+        config.auth.tokens = vec!["secret".to_string()];
+        let state = test_state(config).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let app = build_router(&state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = Client::new(base_url.clone(), Some("secret".to_string()));
+        let mut subscription = client.subscribe(&["chat"]).await.unwrap();
+        let ack = subscription.next_event().await.unwrap().unwrap();
+        assert_eq!(ack.event, "presence");
+
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let event = subscription.next_event().await.unwrap().unwrap();
+        assert_eq!(event.event, "chat");
+        assert_eq!(event.payload["direction"], "inbound");
+
+        let sessions = client.list_sessions(Some(10), None).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        let session_key = sessions[0].session_key.clone();
+        let session = client.get_session(&session_key).await.unwrap().unwrap();
+        assert_eq!(session.agent_id, "main");
+        assert!(client
+            .get_session("agent:main:missing")
+            .await
+            .unwrap()
+            .is_none());
+        let messages = client
+            .list_messages(&session_key, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content.as_deref(), Some("hi"));
+
+        let unknown = SendMessageRequest {
+            session_key: "agent:main:missing".to_string(),
+            text: Some("hello".to_string()),
+            ..SendMessageRequest::default()
+        };
+        match client.send_message(&unknown).await {
+            Err(ClientError::Api { status, code, .. }) => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(code.as_deref(), Some("unknown_session"));
+            }
+            other => panic!("unexpected send result: {other:?}"),
+        }
+        let results = client.send_bulk(&[unknown]).await.unwrap();
+        assert!(matches!(
+            &results[0],
+            BulkSendResult::Failed { code, .. } if code == "unknown_session"
+        ));
+
+        let anonymous = Client::new(base_url, None);
+        match anonymous.list_sessions(None, None).await {
+            Err(ClientError::Api { status, .. }) => assert_eq!(status, StatusCode::UNAUTHORIZED),
+            other => panic!("unexpected list result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_lists_routes() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+        let (status, doc) = get_json(app, "/v1/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(doc["paths"]["/v1/messages/send"]["post"].is_object());
+        assert!(doc["paths"]["/v1/channels/slack/events"]["post"].is_object());
+        assert_eq!(
+            doc["components"]["securitySchemes"]["token"]["name"],
+            "X-Agent-Ping-Token"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_pool_serves_list_endpoints() {
+        sqlx::any::install_default_drivers();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        std::fs::File::create(&path).unwrap();
+        let url = format!("sqlite://{}", path.to_string_lossy());
+        let mut state = test_state(Config::default()).await;
+        state.pool = db::connect(&url, &DatabaseConfig::default()).await.unwrap();
+        db::init_db(&state.pool, DbKind::Sqlite).await.unwrap();
+        state.read_pool = db::connect(&url, &DatabaseConfig::default()).await.unwrap();
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session_key = only_session(&state).await.session_key;
+
+        let app = build_router(&state);
+        let (status, sessions) = get_json(app.clone(), "/v1/sessions").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        let (status, _) = get_json(app.clone(), &format!("/v1/sessions/{session_key}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, messages) =
+            get_json(app.clone(), &format!("/v1/sessions/{session_key}/messages")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(messages.as_array().unwrap().len(), 1);
+        let (status, counts) = get_json(app, "/v1/status").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(counts["messages"], 1);
+
+        state.read_pool.close().await;
+        let (status, _) = get_json(build_router(&state), "/v1/sessions").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_upload_media_streams_download_to_backend() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let file: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        Mock::given(method("GET"))
+            .and(path("/files/clip.bin"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(file.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/media/upload"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"url": "https://storage/clip"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut config = Config::default();
+        config.backend.media_upload_url = Some(format!("{}/media/upload", server.uri()));
+        let state = test_state(config).await;
+
+        let (uploaded, failures) = upload_media(
+            &state,
+            &state.config(),
+            "whatsapp",
+            "agent:main:media",
+            &[Attachment {
+                id: Some("src-1".to_string()),
+                url: format!("{}/files/clip.bin", server.uri()),
+                mime_type: Some("application/octet-stream".to_string()),
+                filename: Some("clip.bin".to_string()),
+                size: Some(file.len() as i64),
+            }],
+            &HashMap::new(),
+        )
+        .await;
+
+        assert_eq!(uploaded.len(), 1);
+        assert_eq!(uploaded[0].url, "https://storage/clip");
+        assert!(failures.is_empty());
+        let requests = server.received_requests().await.unwrap();
+        let upload = requests
+            .iter()
+            .find(|req| req.url.path() == "/media/upload")
+            .unwrap();
+        let content_length: usize = upload
+            .headers
+            .get("content-length")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(content_length, upload.body.len());
+        assert!(upload
+            .body
+            .windows(file.len())
+            .any(|window| window == file.as_slice()));
+    }
+
+    #[tokio::test]
+    async fn test_upload_media_keeps_attachment_when_download_fails() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/gone.bin"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("not found"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/media/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"url": "x"})))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let mut config = Config::default();
+        config.backend.media_upload_url = Some(format!("{}/media/upload", server.uri()));
+        let state = test_state(config).await;
+
+        let attachment = Attachment {
+            id: Some("src-1".to_string()),
+            url: format!("{}/files/gone.bin", server.uri()),
+            mime_type: Some("application/octet-stream".to_string()),
+            filename: Some("gone.bin".to_string()),
+            size: None,
+        };
+        let (uploaded, failures) = upload_media(
+            &state,
+            &state.config(),
+            "whatsapp",
+            "agent:main:media",
+            std::slice::from_ref(&attachment),
+            &HashMap::new(),
+        )
+        .await;
+
+        assert_eq!(uploaded.len(), 1);
+        assert_eq!(uploaded[0].url, attachment.url);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0.url, attachment.url);
+        assert!(failures[0].1.contains("404"), "{}", failures[0].1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_allowlist_uses_trusted_forwarded_for() {
+        use tower::ServiceExt;
+
+        let mut config = Config::default();
+        config.channels.telegram.allowed_ips = vec!["203.0.113.0/24".to_string()];
+        config.server.trusted_proxies = vec!["10.0.0.0/8".to_string()];
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let cases = [
+            ("203.0.113.7:5000", None, StatusCode::OK),
+            ("198.51.100.1:5000", None, StatusCode::FORBIDDEN),
+            ("10.0.0.2:5000", Some("203.0.113.7"), StatusCode::OK),
+            ("10.0.0.2:5000", Some("198.51.100.1"), StatusCode::FORBIDDEN),
+            (
+                "198.51.100.1:5000",
+                Some("203.0.113.7"),
+                StatusCode::FORBIDDEN,
+            ),
+        ];
+        for (peer, forwarded, expected) in cases {
+            let mut builder = axum::http::Request::builder()
+                .method("POST")
+                .uri("/v1/channels/telegram/webhook")
+                .header("content-type", "application/json");
+            if let Some(forwarded) = forwarded {
+                builder = builder.header("X-Forwarded-For", forwarded);
+            }
+            let mut req = builder.body(axum::body::Body::from("{}")).unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), expected, "{peer} {forwarded:?}");
+        }
+
+        let (status, _) = post_json(app.clone(), "/v1/channels/telegram/webhook", json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = get_json(app, "/v1/health").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_memory_mode_serves_without_touching_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let sqlite_path = dir.path().join("state.sqlite");
+        let mut config = Config::default();
+        config.database.mode = "memory".to_string();
+        config.database.sqlite_path = sqlite_path.to_string_lossy().to_string();
+        let (state, app) = create_app_with_config(config).await.unwrap();
+
+        let (status, _) = get_json(app.clone(), "/v1/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let update = json!({
+            "update_id": 1,
+            "message": {"message_id": 7, "chat": {"id": 42, "type": "private"}, "text": "hi"}
+        });
+        let (status, _) = post_json(app, "/v1/channels/telegram/webhook", update).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(!sqlite_path.exists());
+
+        let mut config = Config::default();
+        config.database.mode = "memory".to_string();
+        config.database.read_url = Some("sqlite://replica.sqlite".to_string());
+        assert!(config.validate().is_err());
+        assert!(create_app_with_config(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_custom_telegram_webhook_path_routes() {
+        let mut config = Config::default();
+        config.channels.telegram.webhook_path = "/hooks/telegram/bot-a".to_string();
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        let update = json!({
+            "update_id": 1,
+            "message": {"message_id": 7, "chat": {"id": 42, "type": "private"}, "text": "hi"}
+        });
+
+        let (status, body) = post_json(app.clone(), "/hooks/telegram/bot-a", update.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "accepted");
+        let session = only_session(&state).await;
+        assert_eq!(session.last_route.unwrap()["channel"], "telegram");
+
+        let (status, _) = post_json(app, "/v1/channels/telegram/webhook", update).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_channel_debounce_overrides_queue_default() {
+        let mut config = Config::default();
+        config.queue.debounce_ms = 1_000;
+        config.channels.telegram.debounce_ms = Some(60_000);
+        let state = test_state(config).await;
+
+        let before = Utc::now();
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let mut telegram = threaded_inbound(None);
+        telegram.inbound_id = "in-tg".to_string();
+        telegram.channel = "telegram".to_string();
+        telegram.account_id = None;
+        telegram.peer_id = "-1001".to_string();
+        telegram.peer_kind = "group".to_string();
+        telegram.message_id = Some("42".to_string());
+        handle_inbound(state.clone(), telegram).await.unwrap();
+
+        let later = before + chrono::Duration::hours(1);
+        let rows = db::claim_outbox_batch(&state.pool, state.db_kind, later, 10)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        for row in rows {
+            let delay = (row.next_attempt_at - before).num_seconds();
+            match row.payload["channel"].as_str() {
+                Some("slack") => assert!(delay <= 2, "slack delay {delay}"),
+                Some("telegram") => assert!((59..=61).contains(&delay), "telegram delay {delay}"),
+                other => panic!("unexpected channel {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audio_attachment_transcription_is_appended() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/voice.ogg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"OggS".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/transcribe"))
+            .and(header("X-Agent-Ping-Token", "internal"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"text": " see you at 5 "})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/broken"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let voice = Attachment {
+            id: Some("v1".to_string()),
+            url: format!("{}/voice.ogg", server.uri()),
+            mime_type: Some("audio/ogg".to_string()),
+            filename: Some("voice.ogg".to_string()),
+            size: Some(4),
+        };
+        let photo = Attachment {
+            mime_type: Some("image/jpeg".to_string()),
+            ..voice.clone()
+        };
+        for (url, enabled, expected) in [
+            ("/transcribe", true, "hi\n\nsee you at 5"),
+            ("/transcribe", false, "hi"),
+            ("/broken", true, "hi"),
+        ] {
+            let mut config = Config::default();
+            config.backend.transcription_url = Some(format!("{}{url}", server.uri()));
+            config.backend.api_token = Some("internal".to_string());
+            config.channels.slack.transcribe_audio = enabled;
+            let state = test_state(config).await;
+            let mut inbound = threaded_inbound(None);
+            inbound.attachments = vec![voice.clone(), photo.clone()];
+            handle_inbound(state.clone(), inbound).await.unwrap();
+
+            let session = only_session(&state).await;
+            let messages = db::list_messages(
+                &state.pool,
+                state.db_kind,
+                &session.session_key,
+                None,
+                10,
+                0,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                messages[0].content.as_deref(),
+                Some(expected),
+                "{url} {enabled}"
+            );
+            assert_eq!(
+                messages[0].attachments.as_ref().unwrap()[0]["url"],
+                voice.url
+            );
+            let rows = db::claim_outbox_batch(
+                &state.pool,
+                state.db_kind,
+                Utc::now() + chrono::Duration::hours(1),
+                10,
+            )
+            .await
+            .unwrap();
+            assert_eq!(rows[0].payload["text"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slack_audio_is_transcribed_after_the_ack_and_downloaded_once() {
+        use tower::ServiceExt;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/voice.ogg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"OggS".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/transcribe"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"text": "see you at 5"}))
+                    .set_delay(std::time::Duration::from_secs(2)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/media/upload"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"url": "https://cdn.example/voice.ogg"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.backend.transcription_url = Some(format!("{}/transcribe", server.uri()));
+        config.backend.media_upload_url = Some(format!("{}/media/upload", server.uri()));
+        config.channels.slack.transcribe_audio = true;
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/channels/slack/events", post(slack_events))
+            .with_state(state.clone());
+        let payload = json!({
+            "type": "event_callback",
+            "team_id": "T1",
+            "event": {
+                "type": "message",
+                "channel": "D1",
+                "user": "U1",
+                "text": "hi",
+                "ts": "1700000000.000100",
+                "files": [{
+                    "id": "F1",
+                    "url_private_download": format!("{}/voice.ogg", server.uri()),
+                    "mimetype": "audio/ogg",
+                    "name": "voice.ogg"
+                }]
+            }
+        });
+
+        let started = std::time::Instant::now();
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri("/v1/channels/slack/events")
+                    .header("content-type", "application/json")
+                    .body(axum::body::Body::from(payload.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let message = loop {
+            let sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+                .await
+                .unwrap();
+            if let Some(session) = sessions.first() {
+                let messages = db::list_messages(
+                    &state.pool,
+                    state.db_kind,
+                    &session.session_key,
+                    None,
+                    10,
+                    0,
+                )
+                .await
+                .unwrap();
+                if let Some(message) = messages.into_iter().next() {
+                    break message;
+                }
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "message was never stored"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert_eq!(message.content.as_deref(), Some("hi\n\nsee you at 5"));
+        assert_eq!(
+            message.attachments.as_ref().unwrap()[0]["url"],
+            "https://cdn.example/voice.ogg"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_filter_skips_outbox_but_keeps_message() {
+        let mut config = Config::default();
+        config.backend.forward_filter.peer_kinds = vec!["dm".to_string(), "channel".to_string()];
+        let state = test_state(config).await;
+        let mut rx = state.ws_tx.subscribe();
+
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let mut group = threaded_inbound(None);
+        group.inbound_id = "in-group".to_string();
+        group.peer_id = "G1".to_string();
+        group.peer_kind = "group".to_string();
+        group.message_id = Some("1700000000.000300".to_string());
+        handle_inbound(state.clone(), group).await.unwrap();
+
+        let later = Utc::now() + chrono::Duration::hours(1);
+        let rows = db::claim_outbox_batch(&state.pool, state.db_kind, later, 10)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].payload["peer_id"], "C1");
+
+        let stored = db::find_message_by_provider_id(
+            &state.pool,
+            state.db_kind,
+            "slack",
+            "G1",
+            "1700000000.000300",
+        )
+        .await
+        .unwrap();
+        assert!(stored.is_some());
+        let peers: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter(|event| event.event == "chat")
+            .map(|event| event.payload["message"]["peer_id"].clone())
+            .collect();
+        assert_eq!(peers, vec![json!("C1"), json!("G1")]);
+    }
+
+    #[tokio::test]
+    async fn test_binding_webhook_url_routes_outbox_row() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/tenant-a"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let tenant_url = format!("{}/tenant-a", server.uri());
+        let mut config = Config::default();
+        config.queue.debounce_ms = 0;
+        config.backend.webhook_url = Some(format!("{}/global", server.uri()));
+        config.bindings = vec![Binding {
+            channel: "slack".to_string(),
+            account_id: Some("T1".to_string()),
+            webhook_url: Some(tenant_url.clone()),
+            ..Binding::default()
+        }];
+        assert!(config.validate().is_ok());
+        let state = test_state(config).await;
+
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let (url,): (Option<String>,) = sqlx::query_as("SELECT webhook_url FROM inbound_outbox")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(url.as_deref(), Some(tenant_url.as_str()));
+
+        let worker = tokio::spawn(outbox::start_outbox_worker(state.clone()));
+        for _ in 0..50 {
+            if !server.received_requests().await.unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        worker.abort();
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url.path(), "/tenant-a");
+    }
+
+    #[tokio::test]
+    async fn test_outbox_worker_picks_up_webhook_added_by_reload() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let mut config = Config::default();
+        config.queue.debounce_ms = 0;
+        let state = test_state(config.clone()).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+
+        let worker = tokio::spawn(outbox::start_outbox_worker(state.clone()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        config.backend.webhook_url = Some(format!("{}/hook", server.uri()));
+        assert!(reload_config(&state, config).is_empty());
+        for _ in 0..100 {
+            if !server.received_requests().await.unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        worker.abort();
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_inbound_ack_marks_outbox_rows() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+        let delivered = db::insert_outbox(
+            &state.pool,
+            state.db_kind,
+            json!({"inbound_id": "in-1"}),
+            Utc::now(),
+            None,
+            Some("in-1"),
+        )
+        .await
+        .unwrap();
+        let failed = db::insert_outbox(
+            &state.pool,
+            state.db_kind,
+            json!({"msg": "templated payload without the inbound id"}),
+            Utc::now(),
+            None,
+            Some("in-2"),
+        )
+        .await
+        .unwrap();
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"inbound_id": "in-1", "outbox_id": delivered.id}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["outbox_status"], "delivered");
+        let row = db::get_outbox_by_id(&state.pool, state.db_kind, &delivered.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, "delivered");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({
+                "inbound_id": "in-2",
+                "outbox_id": failed.id,
+                "status": "error",
+                "error": "agent crashed"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["outbox_status"], "failed");
+        let row = db::get_outbox_by_id(&state.pool, state.db_kind, &failed.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, "failed");
+        assert_eq!(row.retry_count, 1);
+        assert!(row.last_error.unwrap().contains("agent crashed"));
+
+        // Settled rows cannot be flipped: a late error must not resend a
+        // delivered row, and a late ok must not drop a retrying one.
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"outbox_id": delivered.id, "status": "error"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["outbox_status"], "delivered");
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"outbox_id": failed.id}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let row = db::get_outbox_by_id(&state.pool, state.db_kind, &delivered.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, "delivered");
+
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"inbound_id": "in-1", "outbox_id": failed.id}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"outbox_id": "missing"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/inbound/ack",
+            json!({"inbound_id": "in-1"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_payload_template_shapes_outbox_payload() {
+        let mut config = Config::default();
+        config.backend.payload_template = Some(json!({
+            "msg": "{{text}}",
+            "meta": {"from": "{{channel}}:{{ peer_id }}", "thread": "t={{thread_id}}"},
+            "files": "{{attachments}}",
+            "source": "agent-ping",
+            "version": 2
+        }));
+        let state = test_state(config).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+
+        let later = Utc::now() + chrono::Duration::hours(1);
+        let rows = db::claim_outbox_batch(&state.pool, state.db_kind, later, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].payload,
+            json!({
+                "msg": "hi",
+                "meta": {"from": "slack:C1", "thread": "t="},
+                "files": [],
+                "source": "agent-ping",
+                "version": 2
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inbound_failure_is_recorded_and_retried() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+        sqlx::query("DROP TABLE messages")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        assert!(handle_acked_inbound(&state, threaded_inbound(None))
+            .await
+            .is_err());
+        let (status, body) = get_json(app.clone(), "/v1/admin/inbound-failures").await;
+        assert_eq!(status, StatusCode::OK);
+        let failures = body.as_array().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["status"], "pending");
+        assert_eq!(failures[0]["retry_count"], 0);
+        assert_eq!(failures[0]["payload"]["inbound_id"], "in-1");
+        assert!(failures[0]["last_error"]
+            .as_str()
+            .unwrap()
+            .contains("messages"));
+        let id = failures[0]["id"].as_str().unwrap().to_string();
+
+        let uri = format!("/v1/admin/inbound-failures/{id}/retry");
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["error"]["id"], id.as_str());
+        let record = db::get_inbound_failure(&state.pool, state.db_kind, &id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.status, "pending");
+        assert_eq!(record.retry_count, 1);
+        assert!(record.next_attempt_at > Utc::now());
+
+        db::init_db(&state.pool, state.db_kind).await.unwrap();
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "resolved");
+        let (_, messages) = get_json(
+            app.clone(),
+            "/v1/sessions/agent:main:slack:channel:c1/messages",
+        )
+        .await;
+        assert_eq!(messages.as_array().map(|m| m.len()), Some(1));
+
+        let (status, _) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, body) = get_json(app.clone(), "/v1/admin/inbound-failures").await;
+        assert!(body.as_array().unwrap().is_empty());
+        let (status, _) =
+            post_json(app, "/v1/admin/inbound-failures/missing/retry", json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_failed_outbox_insert_rolls_back_inbound_message() {
+        let mut config = Config::default();
+        config.backend.webhook_url = Some("http://127.0.0.1:1/inbound".to_string());
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        sqlx::query("DROP TABLE inbound_outbox")
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        assert!(handle_acked_inbound(&state, threaded_inbound(None))
+            .await
+            .is_err());
+        let (_, messages) = get_json(
+            app.clone(),
+            "/v1/sessions/agent:main:slack:channel:c1/messages",
+        )
+        .await;
+        assert_eq!(messages.as_array().map(|m| m.len()), Some(0));
+        let (_, failures) = get_json(app.clone(), "/v1/admin/inbound-failures").await;
+        let id = failures[0]["id"].as_str().unwrap().to_string();
+        let uri = format!("/v1/admin/inbound-failures/{id}/retry");
+
+        // While the worker holds the row, a manual retry must not replay it too.
+        let now = Utc::now();
+        assert!(db::claim_inbound_failure(
+            &state.pool,
+            state.db_kind,
+            &id,
+            &["pending"],
+            now,
+            now + chrono::Duration::seconds(60),
+        )
+        .await
+        .unwrap());
+        let (status, _) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        sqlx::query("UPDATE inbound_failures SET status='pending' WHERE id = ?")
+            .bind(&id)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+
+        db::init_db(&state.pool, state.db_kind).await.unwrap();
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "resolved");
+        let (_, messages) =
+            get_json(app, "/v1/sessions/agent:main:slack:channel:c1/messages").await;
+        assert_eq!(messages.as_array().map(|m| m.len()), Some(1));
+        let outbox: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM inbound_outbox")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        assert_eq!(outbox, 1);
+    }
+
+    #[tokio::test]
+    async fn test_media_upload_failure_is_recorded_and_retried() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/photo.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"jpeg".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/media/upload"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let mut config = Config::default();
+        config.backend.media_upload_url = Some(format!("{}/media/upload", server.uri()));
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let mut inbound = threaded_inbound(None);
+        inbound.attachments = vec![Attachment {
+            id: Some("F1".to_string()),
+            url: format!("{}/files/photo.jpg", server.uri()),
+            mime_type: Some("image/jpeg".to_string()),
+            filename: Some("photo.jpg".to_string()),
+            size: Some(4),
+        }];
+        handle_inbound(state.clone(), inbound).await.unwrap();
+
+        let (status, body) = get_json(app.clone(), "/v1/admin/media-failures").await;
+        assert_eq!(status, StatusCode::OK);
+        let failures = body.as_array().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["status"], "pending");
+        assert_eq!(failures[0]["channel"], "slack");
+        assert_eq!(failures[0]["attachment"]["id"], "F1");
+        assert!(failures[0]["last_error"].as_str().unwrap().contains("503"));
+        let id = failures[0]["id"].as_str().unwrap().to_string();
+        let message_id = failures[0]["message_id"].as_str().unwrap().to_string();
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(message.attachments.unwrap()[0]["url"]
+            .as_str()
+            .unwrap()
+            .ends_with("/files/photo.jpg"));
+
+        let uri = format!("/v1/admin/media-failures/{id}/retry");
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["error"]["id"], id.as_str());
+        let record = db::get_media_failure(&state.pool, state.db_kind, &id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.status, "pending");
+        assert_eq!(record.retry_count, 1);
+
+        let now = Utc::now();
+        let lease = now + chrono::Duration::seconds(60);
+        assert!(
+            db::claim_media_failure(&state.pool, state.db_kind, &id, now, lease)
+                .await
+                .unwrap()
+        );
+        let (status, _) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        db::mark_media_failure_retry(&state.pool, state.db_kind, &id, 1, "released")
+            .await
+            .unwrap();
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/files/photo.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"jpeg".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/media/upload"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"url": "https://storage/photo"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut events = state.ws_tx.subscribe();
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "resolved");
+        assert_eq!(body["attachment"]["url"], "https://storage/photo");
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            message.attachments.unwrap()[0]["url"],
+            "https://storage/photo"
+        );
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.payload["edited"], true);
+        assert_eq!(event.payload["message"]["id"], message_id.as_str());
+        let payloads: Vec<String> = sqlx::query_scalar("SELECT payload FROM inbound_outbox")
+            .fetch_all(&state.pool)
+            .await
+            .unwrap();
+        let rehosted = payloads
+            .iter()
+            .map(|payload| serde_json::from_str::<serde_json::Value>(payload).unwrap())
+            .find(|payload| payload["event"] == "media_rehosted")
+            .unwrap();
+        assert_eq!(rehosted["attachment"]["url"], "https://storage/photo");
+        assert!(rehosted["original_url"]
+            .as_str()
+            .unwrap()
+            .ends_with("/files/photo.jpg"));
+
+        let (status, _) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, body) = get_json(app.clone(), "/v1/admin/media-failures").await;
+        assert!(body.as_array().unwrap().is_empty());
+        let (status, _) = post_json(app, "/v1/admin/media-failures/missing/retry", json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_maintenance_runs_in_background() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+
+        state.maintenance_running.store(true, Ordering::SeqCst);
+        let (status, body) = post_json(app.clone(), "/v1/admin/maintenance", json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["message"], "maintenance already running");
+        state.maintenance_running.store(false, Ordering::SeqCst);
+
+        let (status, body) = post_json(app, "/v1/admin/maintenance", json!({})).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "accepted");
+        for _ in 0..100 {
+            if !state.maintenance_running.load(Ordering::SeqCst) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("maintenance did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_body_limits_are_route_aware() {
+        let mut config = Config::default();
+        config.server.max_body_bytes = 8 * 1024;
+        config.channels.telegram.max_webhook_bytes = 1024;
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        let padding = "x".repeat(2048);
+
+        let (status, _) = post_json(app.clone(), "/v1/channels/telegram/webhook", json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/channels/telegram/webhook",
+            json!({"pad": padding}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send-bulk",
+            json!({"messages": [{"session_key": "missing", "text": padding}]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let (status, _) = post_json(
+            app,
+            "/v1/messages/send-bulk",
+            json!({"messages": [{"session_key": "missing", "text": "x".repeat(16 * 1024)}]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_send_bulk_collapses_duplicates_within_batch() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send-bulk", post(send_bulk))
+            .with_state(state.clone());
+        let item = |text: &str, key: Option<&str>| {
+            json!({
+                "session_key": "",
+                "text": text,
+                "channel": "whatsapp",
+                "peer_id": "447700900123",
+                "idempotency_key": key
+            })
+        };
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send-bulk",
+            json!({"messages": [
+                item("first", Some("retry-1")),
+                item("first (retried)", Some("retry-1")),
+                item("  hello ", None),
+                item("hello", None),
+                item("different", None),
+            ]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r["status"] == "sent"));
+        assert_eq!(results[0]["message_id"], results[1]["message_id"]);
+        assert_eq!(results[2]["message_id"], results[3]["message_id"]);
+        assert_ne!(results[0]["message_id"], results[2]["message_id"]);
+        assert_ne!(results[2]["message_id"], results[4]["message_id"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_is_queued_and_resent() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(
+                ResponseTemplate::new(503).set_body_json(json!({"error": "sidecar offline"})),
+            )
+            .up_to_n_times(1)
+            .mount(&sidecar)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"message_id": "wamid.1"})),
+            )
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .route("/v1/messages/:message_id/resend", post(resend_message))
+            .with_state(state.clone());
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "", "text": "hello", "channel": "whatsapp", "peer_id": "+447700900123"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "queued");
+        assert_eq!(body["code"], "queued");
+        let message_id = body["message_id"].as_str().unwrap().to_string();
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.status, "failed");
+        let queued = db::get_outbound_queue(&state.pool, state.db_kind, &message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(queued.status, "pending");
+        assert_eq!(queued.payload["route"]["channel"], "whatsapp");
+        assert!(queued.last_error.unwrap().contains("sidecar offline"));
+
+        // While the retry worker holds the row, a manual resend must not race it.
+        let now = Utc::now();
+        assert!(db::claim_outbound_queue(
+            &state.pool,
+            state.db_kind,
+            &message_id,
+            &["pending"],
+            now,
+            now + chrono::Duration::seconds(60),
+        )
+        .await
+        .unwrap());
+        let uri = format!("/v1/messages/{message_id}/resend");
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["code"], "not_resendable");
+        assert_eq!(sidecar.received_requests().await.unwrap().len(), 1);
+        db::mark_outbound_queue_retry(
+            &state.pool,
+            state.db_kind,
+            &message_id,
+            "pending",
+            0,
+            now,
+            "released",
+        )
+        .await
+        .unwrap();
+
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "sent");
+        let message = db::get_message(&state.pool, state.db_kind, &message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.status, "sent");
+        assert_eq!(message.provider_message_id.as_deref(), Some("wamid.1"));
+        assert_eq!(sidecar.received_requests().await.unwrap().len(), 2);
+
+        let (status, body) = post_json(app.clone(), &uri, json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["code"], "not_resendable");
+        let (status, _) = post_json(app, "/v1/messages/missing/resend", json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_send_failure_is_not_queued() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "error": "not_on_whatsapp",
+                "debug": {"session": "internal"}
+            })))
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let app = Router::new()
+            .route("/v1/messages/send", post(send_message))
+            .with_state(state.clone());
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({"session_key": "", "text": "hello", "channel": "whatsapp", "peer_id": "+447700900123"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "invalid_recipient");
+        assert_eq!(
+            body["error"]["message"],
+            "whatsapp recipient is invalid (not_on_whatsapp)"
+        );
+        assert!(body["error"].get("message_id").is_none());
+        let due = db::due_outbound_queue(
+            &state.pool,
+            state.db_kind,
+            Utc::now() + chrono::Duration::days(1),
+            10,
+        )
+        .await
+        .unwrap();
+        assert!(due.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_stores_provider_timestamp() {
+        let state = test_state(Config::default()).await;
+        let mut inbound = threaded_inbound(None);
+        inbound.timestamp = Some("1700000000.000200".to_string());
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let mut late = threaded_inbound(None);
+        late.message_id = Some("1600000000.000100".to_string());
+        late.timestamp = None;
+        handle_inbound(state.clone(), late).await.unwrap();
+
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages.len(), 2);
+        let sent_at = |provider_id: &str| {
+            messages
+                .iter()
+                .find(|m| m.provider_message_id.as_deref() == Some(provider_id))
+                .unwrap()
+                .created_at
+        };
+        assert_eq!(sent_at("1700000000.000200").timestamp(), 1_700_000_000);
+        assert!(sent_at("1600000000.000100") > Utc::now() - chrono::Duration::minutes(1));
+    }
+    #[tokio::test]
+    async fn test_reload_config_applies_new_bindings() {
+        let state = test_state(Config::default()).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        assert!(only_session(&state)
+            .await
+            .session_key
+            .starts_with("agent:main:"));
+
+        let mut next = (*state.config()).clone();
+        next.bindings = vec![Binding {
+            channel: "slack".to_string(),
+            peer_id: Some("C1".to_string()),
+            agent_id: Some("finance".to_string()),
+            ..Binding::default()
+        }];
+        next.server.port = 9999;
+        next.channels.slack.max_concurrent_sends = 1;
+        let telegram = state.send_limiter("telegram").unwrap();
+        let restart = reload_config(&state, next);
+        assert_eq!(restart, vec!["server.port"]);
+        assert_eq!(state.config().server.port, 8091);
+        assert_eq!(state.config().bindings.len(), 1);
+        assert_eq!(state.config().channels.slack.max_concurrent_sends, 1);
+        assert_eq!(state.send_limiter("slack").unwrap().available(), 1);
+        let _held = telegram.acquire().await;
+        assert_eq!(
+            state.send_limiter("telegram").unwrap().available(),
+            telegram.available()
+        );
+
+        let mut inbound = threaded_inbound(None);
+        inbound.inbound_id = "in-2".to_string();
+        inbound.message_id = Some("1700000000.000300".to_string());
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        assert!(sessions
+            .iter()
+            .any(|session| session.session_key.starts_with("agent:finance:")));
+    }
+    #[tokio::test]
+    async fn test_large_list_response_is_gzip_encoded() {
+        use std::io::Read;
+        use tower::ServiceExt;
+
+        let state = test_state(Config::default()).await;
+        for i in 0..40 {
+            let mut inbound = threaded_inbound(None);
+            inbound.inbound_id = format!("in-{i}");
+            inbound.message_id = Some(format!("1700000000.{i:06}"));
+            inbound.text = Some(format!("message {i} {}", "lorem ipsum ".repeat(20)));
+            handle_inbound(state.clone(), inbound).await.unwrap();
+        }
+        let session_key = only_session(&state).await.session_key;
+        let uri = format!("/v1/sessions/{session_key}/messages?limit=100");
+        let app = build_router(&state);
+
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri(&uri)
+                    .header("accept-encoding", "gzip")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+        let compressed = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert!(compressed.len() < decoded.len());
+
+        let (status, plain) = get_json(app, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let decoded: serde_json::Value = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(decoded, plain);
+        assert_eq!(decoded.as_array().map(Vec::len), Some(40));
+    }
+    #[tokio::test]
+    async fn test_gzip_webhook_body_is_decompressed() {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use std::io::Write;
+        use tower::ServiceExt;
+
+        let mut config = Config::default();
+        config.channels.slack.max_webhook_bytes = 1024;
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        let post_encoded = |encoding: &'static str, body: Vec<u8>| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .method("POST")
+                            .uri("/v1/channels/slack/events")
+                            .header("content-type", "application/json")
+                            .header("content-encoding", encoding)
+                            .body(axum::body::Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = res.status();
+                let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap_or_default(),
+                )
+            }
+        };
+
+        let challenge = br#"{"type":"url_verification","challenge":"gz-abc"}"#;
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(challenge).unwrap();
+        let (status, body) = post_encoded("gzip", gz.finish().unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["challenge"], "gz-abc");
+
+        let mut deflate = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(challenge).unwrap();
+        let (status, body) = post_encoded("deflate", deflate.finish().unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["challenge"], "gz-abc");
+
+        let padded = format!(
+            r#"{{"type":"url_verification","challenge":"{}"}}"#,
+            "a".repeat(4096)
+        );
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gz.write_all(padded.as_bytes()).unwrap();
+        let compressed = gz.finish().unwrap();
+        assert!(compressed.len() < 1024);
+        let (status, _) = post_encoded("gzip", compressed).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_status_requires_auth_when_configured() {
+        use tower::ServiceExt;
+
+        let status_with = |app: Router, token: Option<&'static str>| async move {
+            let mut req = axum::http::Request::builder().uri("/v1/status");
+            if let Some(token) = token {
+                req = req.header("X-Agent-Ping-Token", token);
+            }
+            app.oneshot(req.body(axum::body::Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        };
+        let mut config = Config::default();
+        config.auth.tokens = vec!["secret".to_string()];
+        let open = build_router(&test_state(config.clone()).await);
+        assert_eq!(status_with(open, None).await, StatusCode::OK);
+
+        config.server.status_requires_auth = true;
+        let state = test_state(config).await;
+        let app = build_router(&state);
+        assert_eq!(
+            status_with(app.clone(), None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_with(app.clone(), Some("wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status_with(app, Some("secret")).await, StatusCode::OK);
+        let doc = openapi::openapi_document(&state.config());
+        assert!(doc["paths"]["/v1/status"]["get"].get("security").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_base_path_prefixes_routes() {
+        let mut config = Config::default();
+        config.server.base_path = "/ap/".to_string();
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let (status, _) = get_json(app.clone(), "/ap/v1/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = get_json(app.clone(), "/v1/health").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(
+            app.clone(),
+            "/ap/v1/channels/slack/events",
+            json!({"type": "url_verification", "challenge": "abc"}),
+        )
+        .await;
+        assert_ne!(status, StatusCode::NOT_FOUND);
+        let (_, doc) = get_json(app, "/ap/v1/openapi.json").await;
+        assert_eq!(doc["servers"][0]["url"], "/ap");
+    }
+    #[tokio::test]
+    async fn test_send_caption_mode_sends_single_whatsapp_media_message() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .and(body_partial_json(json!({
+                "attachments": [{"url": "https://cdn.example.com/a.jpg", "caption": "see this"}]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"message_id": "wa-1"})))
+            .expect(1)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+
+        let (status, body) = post_json(
+            build_router(&state),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "channel": "whatsapp",
+                "peer_id": "447700900123",
+                "text": "see this",
+                "attachments": [{"url": "https://cdn.example.com/a.jpg", "mime_type": "image/jpeg"}],
+                "caption_mode": true
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let requests = sidecar.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let sent: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert!(sent.get("text").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_whatsapp_messaging_window_gates_sends() {
+        let mut config = Config::default();
+        config.channels.whatsapp.enforce_messaging_window = true;
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let inbound = InboundMessage {
+            channel: "whatsapp".to_string(),
+            peer_id: "+447700900123".to_string(),
+            peer_kind: "dm".to_string(),
+            account_id: None,
+            ..threaded_inbound(None)
+        };
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let session_key = only_session(&state).await.session_key;
+        let window = format!("/v1/sessions/{session_key}/window");
+        let send = json!({"session_key": session_key, "text": "hello", "dry_run": true});
+
+        let (status, body) = get_json(app.clone(), &window).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["open"], true);
+        assert!(body["expires_at"].is_string());
+        let (status, _) = post_json(app.clone(), "/v1/messages/send", send.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let stale = (Utc::now() - chrono::Duration::hours(25)).timestamp();
+        sqlx::query("UPDATE messages SET created_at = ? WHERE direction = 'inbound'")
+            .bind(stale)
+            .execute(&state.pool)
+            .await
+            .unwrap();
+        let (_, body) = get_json(app.clone(), &window).await;
+        assert_eq!(body["open"], false);
+        let (status, body) = post_json(app.clone(), "/v1/messages/send", send).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "window_closed");
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "channel": "whatsapp",
+                "peer_id": "447700900999",
+                "text": "hi"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("template"));
+    }
+
+    #[tokio::test]
+    async fn test_whatsapp_outbound_peer_shares_inbound_session() {
+        let mut config = Config::default();
+        config.channels.whatsapp.transport = "echo".to_string();
+        config.session.dm_scope = "per-channel-peer".to_string();
+        let state = test_state(config).await;
+        let app = build_router(&state);
+
+        let inbound = InboundMessage {
+            channel: "whatsapp".to_string(),
+            peer_id: "+447700900123".to_string(),
+            peer_kind: "dm".to_string(),
+            account_id: None,
+            ..threaded_inbound(None)
+        };
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let session_key = only_session(&state).await.session_key;
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/messages/send",
+            json!({"session_key": "", "channel": "whatsapp", "peer_id": "44 7700 900123", "text": "hi"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let stored = db::get_message(
+            &state.pool,
+            state.db_kind,
+            body["message_id"].as_str().unwrap(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(stored.session_key, session_key);
+        assert_eq!(stored.peer_id.as_deref(), Some("+447700900123"));
+
+        let (status, body) = post_json(
+            app,
+            "/v1/sessions",
+            json!({"channel": "whatsapp", "peer_id": "447700900123"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT, "{body}");
+        assert_eq!(body["error"]["session_key"], session_key.as_str());
+        assert_eq!(only_session(&state).await.session_key, session_key);
+    }
+
+    #[tokio::test]
+    async fn test_echo_transport_records_send_without_http() {
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.transport = "echo".to_string();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.channels.slack.transport = "echo".to_string();
+        assert!(config.validate().is_ok());
+        let state = test_state(config).await;
+        let mut rx = state.ws_tx.subscribe();
+        let app = build_router(&state);
+
+        for (channel, peer_id) in [("whatsapp", "447700900123"), ("slack", "C1")] {
+            let (status, body) = post_json(
+                app.clone(),
+                "/v1/messages/send",
+                json!({"session_key": "", "channel": channel, "peer_id": peer_id, "text": "hi"}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{body}");
+            let message_id = body["message_id"].as_str().unwrap();
+            let stored = db::get_message(&state.pool, state.db_kind, message_id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored.status, "sent");
+            assert!(stored.provider_message_id.unwrap().starts_with("echo-"));
+            let event = rx.try_recv().unwrap();
+            assert_eq!(event.event, "chat");
+            assert_eq!(event.payload["message"]["id"], message_id);
+        }
+
+        let (_, body) = get_json(app, "/v1/channels").await;
+        assert_eq!(body["channels"]["slack"]["configured"], true);
+    }
+
+    #[tokio::test]
+    async fn test_send_metadata_roundtrips_to_row_and_event() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let metadata = json!({"ticket_id": "T-42", "campaign": "spring"});
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .and(body_partial_json(json!({"metadata": metadata})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"message_id": "wa-1"})))
+            .expect(1)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let mut rx = state.ws_tx.subscribe();
+
+        let (status, body) = post_json(
+            build_router(&state),
+            "/v1/messages/send",
+            json!({
+                "session_key": "",
+                "channel": "whatsapp",
+                "peer_id": "447700900123",
+                "text": "hello",
+                "metadata": metadata
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let message_id = body["message_id"].as_str().unwrap();
+
+        let stored = db::get_message(&state.pool, state.db_kind, message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.metadata, Some(metadata.clone()));
+
+        let event = loop {
+            let event = rx.recv().await.unwrap();
+            if event.event == "chat" {
+                break event;
+            }
+        };
+        assert_eq!(event.payload["direction"], "outbound");
+        assert_eq!(event.payload["message"]["metadata"], metadata);
+    }
+
+    #[tokio::test]
+    async fn test_send_concurrency_limit_serializes_channel_calls() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"message_id": "wa-1"}))
+                    .set_delay(std::time::Duration::from_millis(200)),
+            )
+            .expect(2)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.channels.whatsapp.max_concurrent_sends = 1;
+        let state = test_state(config).await;
+        let config = state.config();
+
+        let send = |peer: &str| {
+            let mut outbound = reply("", Some("whatsapp"));
+            outbound.peer_id = Some(peer.to_string());
+            handle_outbound(state.clone(), &config, outbound)
+        };
+        let started = std::time::Instant::now();
+        let (first, second) = tokio::join!(send("447700900001"), send("447700900002"));
+        first.unwrap();
+        second.unwrap();
+        assert!(
+            started.elapsed() >= std::time::Duration::from_millis(400),
+            "sends overlapped: {:?}",
+            started.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_send_pauses_channel() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.channels.whatsapp.max_concurrent_sends = 2;
+        let state = test_state(config).await;
+
+        let mut outbound = reply("", Some("whatsapp"));
+        outbound.peer_id = Some("447700900001".to_string());
+        let err = handle_outbound(state.clone(), &state.config(), outbound)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("rate limited"), "{err}");
+
+        let limiter = state.send_limiter("whatsapp").unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(limiter.available(), 0);
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        assert_eq!(limiter.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_channel_default_agent_between_binding_and_global() {
+        let mut config = Config::default();
+        config.session.agent_id = "global".to_string();
+        config.channels.slack.default_agent = Some("support".to_string());
+        config.bindings = vec![Binding {
+            channel: "slack".to_string(),
+            peer_id: Some("C9".to_string()),
+            agent_id: Some("finance".to_string()),
+            ..Binding::default()
+        }];
+        let state = test_state(config).await;
+
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+        assert_eq!(session.agent_id, "support");
+        assert!(session.session_key.starts_with("agent:support:"));
+
+        let mut bound = threaded_inbound(None);
+        bound.inbound_id = "in-2".to_string();
+        bound.peer_id = "C9".to_string();
+        handle_inbound(state.clone(), bound).await.unwrap();
+        let mut telegram = threaded_inbound(None);
+        telegram.inbound_id = "in-3".to_string();
+        telegram.channel = "telegram".to_string();
+        telegram.peer_id = "42".to_string();
+        handle_inbound(state.clone(), telegram).await.unwrap();
+
+        let sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        let agent_for = |peer: &str| {
+            sessions
+                .iter()
+                .find(|session| session.session_key.ends_with(&format!(":{peer}")))
+                .map(|session| session.agent_id.clone())
+        };
+        assert_eq!(agent_for("c9").as_deref(), Some("finance"));
+        assert_eq!(agent_for("42").as_deref(), Some("global"));
+    }
+    #[tokio::test]
+    async fn test_ws_presence_broadcasts_client_count() {
+        let state = test_state(Config::default()).await;
+        let mut rx = state.ws_tx.subscribe();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/v1/ws", listener.local_addr().unwrap());
+        let app = build_router(&state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        async fn next_presence(rx: &mut broadcast::Receiver<ws::WsEvent>) -> serde_json::Value {
+            loop {
+                let event = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+                    .await
+                    .expect("presence event")
+                    .unwrap();
+                if event.event == "presence" {
+                    return event.payload;
+                }
+            }
+        }
+
+        let (first, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        assert_eq!(
+            next_presence(&mut rx).await,
+            json!({"status": "connected", "clients": 1})
+        );
+        let (second, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        assert_eq!(next_presence(&mut rx).await["clients"], 2);
+
+        drop(second);
+        assert_eq!(
+            next_presence(&mut rx).await,
+            json!({"status": "disconnected", "clients": 1})
+        );
+        drop(first);
+        assert_eq!(next_presence(&mut rx).await["clients"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_system_message_is_listed_but_never_dispatched() {
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let mut rx = state.ws_tx.subscribe();
+        let app = build_router(&state);
+
+        let (status, session) = post_json(
+            app.clone(),
+            "/v1/sessions",
+            json!({"channel": "whatsapp", "peer_id": "447700900123"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{session}");
+        let key = session["session_key"].as_str().unwrap().to_string();
+
+        let (status, body) = post_json(
+            app.clone(),
+            &format!("/v1/sessions/{key}/system"),
+            json!({"text": "conversation assigned to Ana", "metadata": {"event": "assigned"}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["status"], "recorded");
+        let message_id = body["message_id"].as_str().unwrap().to_string();
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.event, "chat");
+        assert_eq!(event.payload["system"], true);
+        assert_eq!(event.payload["message"]["direction"], "system");
+
+        let (_, messages) = get_json(app.clone(), &format!("/v1/sessions/{key}/messages")).await;
+        let messages = messages.as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["direction"], "system");
+        assert_eq!(messages[0]["channel"], "whatsapp");
+        assert_eq!(messages[0]["content"], "conversation assigned to Ana");
+        assert_eq!(messages[0]["metadata"]["event"], "assigned");
+
+        for path in ["resend", "reactions"] {
+            let (status, _) = post_json(
+                app.clone(),
+                &format!("/v1/messages/{message_id}/{path}"),
+                json!({"reaction": "thumbsup"}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+        }
+        let (status, _) = post_json(
+            app.clone(),
+            "/v1/sessions/unknown/system",
+            json!({"text": "hi"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(
+            app,
+            &format!("/v1/sessions/{key}/system"),
+            json!({"text": " "}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ws_send_command_requires_auth_and_sends() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let mut config = Config::default();
+        config.auth.tokens = vec!["secret".to_string()];
+        config.channels.slack.transport = "echo".to_string();
+        let state = test_state(config).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/v1/ws", listener.local_addr().unwrap());
+        let app = build_router(&state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("X-Agent-Ping-Token", "secret".parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        async fn next_send(
+            socket: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> serde_json::Value {
+            loop {
+                let msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+                    .await
+                    .expect("socket message")
+                    .expect("socket open")
+                    .unwrap();
+                if let WsMessage::Text(text) = msg {
+                    let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    if event["event"] == "send" {
+                        return event["payload"].clone();
+                    }
+                }
+            }
+        }
+        let send = json!({
+            "type": "send", "session_key": "", "channel": "slack", "peer_id": "C1", "text": "hi",
+        });
+
+        socket
+            .send(WsMessage::Text(send.to_string()))
+            .await
+            .unwrap();
+        let denied = next_send(&mut socket).await;
+        assert_eq!(denied["status"], "failed");
+        assert_eq!(denied["error"]["code"], "unauthorized");
+
+        let connect = json!({"type": "connect", "token": "secret"});
+        socket
+            .send(WsMessage::Text(connect.to_string()))
+            .await
+            .unwrap();
+        socket
+            .send(WsMessage::Text(send.to_string()))
+            .await
+            .unwrap();
+        let sent = next_send(&mut socket).await;
+        assert_eq!(sent["status"], "sent", "{sent}");
+        let message_id = sent["message_id"].as_str().unwrap();
+        let stored = db::get_message(&state.pool, state.db_kind, message_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, "sent");
+        assert_eq!(stored.content.as_deref(), Some("hi"));
+    }
+
+    #[tokio::test]
+    async fn test_ws_slow_send_does_not_block_the_socket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"message_id": "w1"}))
+                    .set_delay(std::time::Duration::from_secs(2)),
+            )
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/v1/ws", listener.local_addr().unwrap());
+        let app = build_router(&state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let send = json!({
+            "type": "send", "session_key": "", "channel": "whatsapp",
+            "peer_id": "+447700900123", "text": "hi",
+        });
+        socket
+            .send(WsMessage::Text(send.to_string()))
+            .await
+            .unwrap();
+        socket
+            .send(WsMessage::Text(json!({"type": "ping"}).to_string()))
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while events.len() < 2 {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+                .await
+                .expect("socket message")
+                .expect("socket open")
+                .unwrap();
+            if let WsMessage::Text(text) = msg {
+                let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+                if event["event"] == "health" || event["event"] == "send" {
+                    events.push(event);
+                }
+            }
+        }
+        assert_eq!(events[0]["event"], "health");
+        assert_eq!(events[1]["event"], "send");
+        assert_eq!(events[1]["payload"]["status"], "sent", "{}", events[1]);
+    }
+
+    #[tokio::test]
+    async fn test_ws_command_flood_closes_socket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let mut config = Config::default();
+        config.ws.command_rate = 1;
+        config.ws.command_burst = 3;
+        let state = test_state(config).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/v1/ws", listener.local_addr().unwrap());
+        let app = build_router(&state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        for _ in 0..10 {
+            if socket
+                .send(WsMessage::Text(r#"{"type":"ping"}"#.to_string()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+
+        let mut health = 0;
+        let close = loop {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+                .await
+                .expect("socket message")
+                .expect("socket open")
+                .unwrap();
+            match msg {
+                WsMessage::Text(text) if text.contains("\"health\"") => health += 1,
+                WsMessage::Close(frame) => break frame,
+                _ => {}
+            }
+        };
+        assert_eq!(health, 3);
+        let close = close.expect("close frame");
+        assert_eq!(close.code, CloseCode::Policy);
+        assert_eq!(close.reason, "command rate limit exceeded");
+    }
+    #[test]
+    fn test_truncate_content_respects_char_boundaries() {
+        assert_eq!(truncate_content("hello world", 8), "hello…");
+        assert_eq!(truncate_content("héllo", 5), "h…");
+        assert_eq!(truncate_content("hello", 2), "he");
+        assert!(truncate_content(&"é".repeat(50), 20).len() <= 20);
+    }
+
+    #[test]
+    fn test_sanitize_content_strips_invisible_characters() {
+        assert_eq!(sanitize_content("pay\u{200B}pal\u{FEFF}", false), "paypal");
+        assert_eq!(
+            sanitize_content("invoice\u{202E}fdp.exe", false),
+            "invoicefdp.exe"
+        );
+        assert_eq!(
+            sanitize_content("a\u{2066}b\u{2069}\u{200F}c\u{0007}", false),
+            "abc"
+        );
+        assert_eq!(sanitize_content("cafe\u{0301}", false), "caf\u{e9}");
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(sanitize_content(family, false), family);
+        assert_eq!(sanitize_content("a  b\n\n\n\nc", false), "a  b\n\n\n\nc");
+        assert_eq!(sanitize_content("  a \t b\n\n\n\nc  ", true), "a b\n\nc");
+    }
+
+    #[tokio::test]
+    async fn test_bridge_mirrors_inbound_without_looping() {
+        async fn next_outbound(rx: &mut broadcast::Receiver<ws::WsEvent>) -> db::MessageRecord {
+            loop {
+                let event = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+                    .await
+                    .expect("outbound event")
+                    .unwrap();
+                if event.event == "chat" && event.payload["direction"] == "outbound" {
+                    return serde_json::from_value(event.payload["message"].clone()).unwrap();
+                }
+            }
+        }
+
+        let mut config = Config::default();
+        config.channels.slack.transport = "echo".to_string();
+        config.channels.telegram.transport = "echo".to_string();
+        let route = |channel: &str, peer: &str| RouteInfo {
+            channel: channel.to_string(),
+            account_id: None,
+            peer_id: Some(peer.to_string()),
+            thread_id: None,
+            peer_kind: Some(if channel == "slack" { "channel" } else { "dm" }.to_string()),
+        };
+        config.bridges = vec![
+            config::Bridge {
+                from: config::RouteMatch {
+                    channel: "telegram".to_string(),
+                    peer_id: Some("42".to_string()),
+                    ..Default::default()
+                },
+                to: route("slack", "C1"),
+            },
+            config::Bridge {
+                from: config::RouteMatch {
+                    channel: "slack".to_string(),
+                    peer_id: Some("C1".to_string()),
+                    ..Default::default()
+                },
+                to: route("telegram", "42"),
+            },
+        ];
+        assert!(config.validate().is_ok());
+        let state = test_state(config).await;
+        let mut rx = state.ws_tx.subscribe();
+
+        let inbound = InboundMessage {
+            inbound_id: "in-tg".to_string(),
+            channel: "telegram".to_string(),
+            account_id: None,
+            peer_id: "42".to_string(),
+            peer_kind: "dm".to_string(),
+            thread_id: None,
+            message_id: Some("7".to_string()),
+            sender_name: Some("Ana".to_string()),
+            text: Some("where is my order?".to_string()),
+            attachments: Vec::new(),
+            timestamp: None,
+        };
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let bridged = next_outbound(&mut rx).await;
+        assert_eq!(bridged.channel, "slack");
+        assert_eq!(bridged.peer_id.as_deref(), Some("C1"));
+        assert_eq!(bridged.content.as_deref(), Some("Ana: where is my order?"));
+        assert_eq!(
+            bridged.metadata.as_ref().unwrap()["bridge"]["peer_id"],
+            "42"
+        );
+        let stored = db::get_message(&state.pool, state.db_kind, &bridged.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let echo_id = stored.provider_message_id.unwrap();
+
+        let mut echo = threaded_inbound(None);
+        echo.account_id = None;
+        echo.message_id = Some(echo_id);
+        echo.text = bridged.content.clone();
+        handle_inbound(state.clone(), echo).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        while let Ok(event) = rx.try_recv() {
+            assert_ne!(
+                event.payload["direction"], "outbound",
+                "echo was re-bridged"
+            );
+        }
+
+        let mut reply = threaded_inbound(None);
+        reply.message_id = Some("1700000000.000300".to_string());
+        reply.sender_name = Some("Ops".to_string());
+        reply.text = Some("shipped today".to_string());
+        handle_inbound(state.clone(), reply).await.unwrap();
+        let bridged = next_outbound(&mut rx).await;
+        assert_eq!(bridged.channel, "telegram");
+        assert_eq!(bridged.peer_id.as_deref(), Some("42"));
+        assert_eq!(bridged.content.as_deref(), Some("Ops: shipped today"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_bridge_send_is_queued_for_retry() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .respond_with(
+                ResponseTemplate::new(503).set_body_json(json!({"error": "sidecar offline"})),
+            )
+            .expect(1)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        config.bridges = vec![config::Bridge {
+            from: config::RouteMatch {
+                channel: "telegram".to_string(),
+                peer_id: Some("42".to_string()),
+                ..Default::default()
+            },
+            to: RouteInfo {
+                channel: "whatsapp".to_string(),
+                account_id: None,
+                peer_id: Some("+447700900123".to_string()),
+                thread_id: None,
+                peer_kind: Some("dm".to_string()),
+            },
+        }];
+        let state = test_state(config).await;
+
+        let inbound = InboundMessage {
+            inbound_id: "in-tg".to_string(),
+            channel: "telegram".to_string(),
+            account_id: None,
+            peer_id: "42".to_string(),
+            peer_kind: "dm".to_string(),
+            thread_id: None,
+            message_id: Some("7".to_string()),
+            sender_name: Some("Ana".to_string()),
+            text: Some("where is my order?".to_string()),
+            attachments: Vec::new(),
+            timestamp: None,
+        };
+        handle_inbound(state.clone(), inbound).await.unwrap();
+
+        let later = Utc::now() + chrono::Duration::hours(1);
+        let mut queued = None;
+        for _ in 0..50 {
+            let rows = db::due_outbound_queue(&state.pool, state.db_kind, later, 10)
+                .await
+                .unwrap();
+            if let Some(row) = rows.into_iter().next() {
+                let message = db::get_message(&state.pool, state.db_kind, &row.message_id)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if message.status == "failed" {
+                    queued = Some((row, message));
+                    break;
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let (queued, message) = queued.expect("bridged send queued for retry");
+        assert_eq!(queued.status, "pending");
+        assert_eq!(queued.payload["route"]["channel"], "whatsapp");
+        assert!(queued.last_error.unwrap().contains("sidecar offline"));
+        assert_eq!(message.content.as_deref(), Some("Ana: where is my order?"));
+    }
+
+    #[tokio::test]
+    async fn test_max_attachments_caps_inbound_and_counts_dropped() {
+        let attachment = |name: &str| Attachment {
+            id: None,
+            url: format!("https://files.example.com/{name}"),
+            mime_type: Some("image/jpeg".to_string()),
+            filename: Some(name.to_string()),
+            size: None,
+        };
+        for (drop, kept, summary) in [
+            (
+                "summarize",
+                ["a.jpg", "b.jpg"],
+                Some("2 more attachments: c.jpg, d.jpg"),
+            ),
+            ("old", ["c.jpg", "d.jpg"], None),
+        ] {
+            let mut config = Config::default();
+            config.queue.max_attachments = Some(2);
+            config.queue.drop = drop.to_string();
+            let state = test_state(config).await;
+            let mut inbound = threaded_inbound(None);
+            inbound.attachments = ["a.jpg", "b.jpg", "c.jpg", "d.jpg"]
+                .map(attachment)
+                .to_vec();
+            handle_inbound(state.clone(), inbound).await.unwrap();
+
+            let session = only_session(&state).await;
+            let messages = db::list_messages(
+                &state.pool,
+                state.db_kind,
+                &session.session_key,
+                None,
+                10,
+                0,
+            )
+            .await
+            .unwrap();
+            let stored: Vec<Attachment> =
+                serde_json::from_value(messages[0].attachments.clone().unwrap()).unwrap();
+            let names: Vec<_> = stored
+                .iter()
+                .filter_map(|att| att.filename.clone())
+                .collect();
+            assert_eq!(names, kept, "{drop}");
+
+            let outbox = db::claim_outbox_batch(
+                &state.pool,
+                state.db_kind,
+                Utc::now() + chrono::Duration::hours(1),
+                10,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                outbox[0].payload["attachments"].as_array().unwrap().len(),
+                2
+            );
+            assert_eq!(outbox[0].payload["dropped_attachments"], 2);
+            assert_eq!(
+                outbox[0].payload["dropped_attachments_summary"].as_str(),
+                summary
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sanitize_inbound_keeps_original_in_payload() {
+        let mut config = Config::default();
+        config.queue.sanitize_inbound = true;
+        let state = test_state(config).await;
+        let raw = "hello\u{200B} wor\u{202E}ld";
+        let mut inbound = threaded_inbound(None);
+        inbound.text = Some(raw.to_string());
+        handle_inbound(state.clone(), inbound).await.unwrap();
+
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages[0].content.as_deref(), Some("hello world"));
+        let outbox = db::claim_outbox_batch(
+            &state.pool,
+            state.db_kind,
+            Utc::now() + chrono::Duration::hours(1),
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outbox[0].payload["text"], "hello world");
+        assert_eq!(outbox[0].payload["original_text"], raw);
+
+        let state = test_state(Config::default()).await;
+        let mut inbound = threaded_inbound(None);
+        inbound.text = Some(raw.to_string());
+        handle_inbound(state.clone(), inbound).await.unwrap();
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages[0].content.as_deref(), Some(raw));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_inbound_truncate_policy() {
+        let mut config = Config::default();
+        config.queue.max_content_bytes = Some(16);
+        let state = test_state(config).await;
+        let mut inbound = threaded_inbound(None);
+        inbound.text = Some("a".repeat(100));
+        handle_inbound(state.clone(), inbound).await.unwrap();
+
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        let content = messages[0].content.as_deref().unwrap();
+        assert_eq!(content, format!("{}…", "a".repeat(13)));
+        assert!(content.len() <= 16);
+
+        let outbox = db::claim_outbox_batch(
+            &state.pool,
+            state.db_kind,
+            Utc::now() + chrono::Duration::hours(1),
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outbox[0].payload["text"], content);
+        assert_eq!(outbox[0].payload["original_content_bytes"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_inbound_reject_policy() {
+        let mut config = Config::default();
+        config.queue.max_content_bytes = Some(16);
+        config.queue.on_oversize = "reject".to_string();
+        let state = test_state(config).await;
+        let mut inbound = threaded_inbound(None);
+        inbound.text = Some("a".repeat(100));
+        handle_inbound(state.clone(), inbound).await.unwrap();
+
+        let sessions = db::list_sessions(&state.pool, state.db_kind, 10, 0)
+            .await
+            .unwrap();
+        assert!(sessions.is_empty());
+        let outbox = db::claim_outbox_batch(
+            &state.pool,
+            state.db_kind,
+            Utc::now() + chrono::Duration::hours(1),
+            10,
+        )
+        .await
+        .unwrap();
+        assert!(outbox.is_empty());
+
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+        let messages = db::list_messages(
+            &state.pool,
+            state.db_kind,
+            &session.session_key,
+            None,
+            10,
+            0,
+        )
+        .await
+        .unwrap();
+        assert_eq!(messages[0].content.as_deref(), Some("hi"));
+    }
+    #[tokio::test]
+    async fn test_session_route_override_redirects_implicit_sends() {
+        use tower::ServiceExt;
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let sidecar = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/send"))
+            .and(body_partial_json(
+                json!({"to": "447700900123", "text": "moved"}),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&sidecar)
+            .await;
+        let mut config = Config::default();
+        config.channels.whatsapp.sidecar_url = sidecar.uri();
+        let state = test_state(config).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session_key = only_session(&state).await.session_key;
+        let app = build_router(&state);
+
+        let put_route = |uri: String, body: serde_json::Value| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .method("PUT")
+                            .uri(uri)
+                            .header("content-type", "application/json")
+                            .body(axum::body::Body::from(body.to_string()))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = res.status();
+                let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+
+        let uri = format!("/v1/sessions/{session_key}/route");
+        let (status, body) =
+            put_route(uri.clone(), json!({"channel": "fax", "peer_id": "1"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "unsupported_channel");
+        let (status, body) = put_route(uri.clone(), json!({"channel": "whatsapp"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "missing_peer");
+        let (status, body) = put_route(
+            "/v1/sessions/agent:nobody/route".to_string(),
+            json!({"channel": "whatsapp", "peer_id": "1"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "unknown_session");
+
+        let (status, body) = put_route(
+            uri,
+            json!({"channel": "whatsapp", "peer_id": "447700900123"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["last_route"]["channel"], "whatsapp");
+        assert_eq!(body["last_route"]["peer_id"], "447700900123");
+
+        let (status, body) = post_json(
+            app,
+            "/v1/messages/send",
+            json!({"session_key": session_key, "text": "moved"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+    #[tokio::test]
+    async fn test_sessions_batch_returns_existing_keys_only() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+        for key in ["agent:ops:a", "agent:ops:b"] {
+            let (status, body) =
+                post_json(app.clone(), "/v1/sessions", json!({"session_key": key})).await;
+            assert_eq!(status, StatusCode::CREATED, "{body}");
+        }
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/sessions/batch",
+            json!({"session_keys": ["agent:ops:a", "agent:ops:missing", "agent:ops:b"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let sessions = body.as_object().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions["agent:ops:a"]["session_key"], "agent:ops:a");
+        assert_eq!(sessions["agent:ops:b"]["session_key"], "agent:ops:b");
+
+        let keys: Vec<String> = (0..=SESSION_BATCH_MAX).map(|i| format!("k{i}")).collect();
+        let (status, body) =
+            post_json(app, "/v1/sessions/batch", json!({"session_keys": keys})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["max"], SESSION_BATCH_MAX);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_endpoint() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/sessions",
+            json!({
+                "session_key": "agent:ops:custom",
+                "agent_id": "ops",
+                "user_id": "u-1",
+                "metadata": {"crm_id": "c-9"}
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        assert_eq!(body["session_key"], "agent:ops:custom");
+        assert_eq!(body["agent_id"], "ops");
+        assert_eq!(body["metadata"]["crm_id"], "c-9");
+        assert!(body["last_route"].is_null());
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/sessions",
+            json!({"session_key": "agent:ops:custom", "agent_id": "other"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["session_key"], "agent:ops:custom");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/sessions",
+            json!({"session_key": "agent:ops:custom", "agent_id": "other", "upsert": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["agent_id"], "other");
+        assert_eq!(body["metadata"]["crm_id"], "c-9");
+
+        let (status, body) = post_json(
+            app.clone(),
+            "/v1/sessions",
+            json!({"channel": "Telegram", "peer_id": "42", "agent_id": "sales"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        let expected = session::build_session_key(
+            &state.config().session,
+            Some("sales"),
+            "telegram",
+            None,
+            "dm",
+            "42",
+            None,
+        );
+        assert_eq!(body["session_key"], expected.as_str());
+        assert_eq!(body["last_route"]["channel"], "telegram");
+        assert_eq!(body["last_route"]["peer_id"], "42");
+
+        let (status, _) = post_json(app, "/v1/sessions", json!({"channel": "slack"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_session_and_message_json_include_timestamps() {
+        let state = test_state(Config::default()).await;
+        handle_inbound(state.clone(), threaded_inbound(None))
+            .await
+            .unwrap();
+        let session = only_session(&state).await;
+        let app = build_router(&state);
+
+        let (status, body) = get_json(
+            app.clone(),
+            &format!("/v1/sessions/{}", session.session_key),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let created_at = body["created_at"].as_str().unwrap();
+        assert!(
+            DateTime::parse_from_rfc3339(created_at).is_ok(),
+            "{created_at}"
+        );
+        assert!(body["updated_at"].is_string());
+
+        let (status, body) = get_json(
+            app,
+            &format!("/v1/sessions/{}/messages", session.session_key),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let created_at = body[0]["created_at"].as_str().unwrap();
+        assert!(
+            DateTime::parse_from_rfc3339(created_at).is_ok(),
+            "{created_at}"
+        );
+
+        let parsed: db::SessionRecord = serde_json::from_value(json!({
+            "session_key": "agent:main:main",
+            "agent_id": "main",
+            "dm_scope": "main"
+        }))
+        .unwrap();
+        assert_eq!(parsed.session_key, "agent:main:main");
+    }
+    #[tokio::test]
+    async fn test_channel_capabilities_endpoint() {
+        let state = test_state(Config::default()).await;
+        let app = build_router(&state);
+
+        let (status, body) = get_json(app.clone(), "/v1/channels/slack/capabilities").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["channel"], "slack");
+        assert_eq!(body["capabilities"]["threads"], true);
+        assert_eq!(body["capabilities"]["typing"], false);
+
+        let (status, body) = get_json(app.clone(), "/v1/channels/whatsapp/capabilities").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["capabilities"]["reactions"], false);
+
+        let (status, _) = get_json(app, "/v1/channels/irc/capabilities").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
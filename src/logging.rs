@@ -0,0 +1,44 @@
+use tracing::Subscriber;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "pretty" => Some(Self::Pretty),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+pub fn build_subscriber(format: LogFormat, level: &str) -> Box<dyn Subscriber + Send + Sync> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    match format {
+        LogFormat::Pretty => Box::new(
+            tracing_subscriber::fmt()
+                .compact()
+                .with_env_filter(filter)
+                .finish(),
+        ),
+        LogFormat::Json => Box::new(
+            tracing_subscriber::fmt()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_env_filter(filter)
+                .finish(),
+        ),
+    }
+}
+
+pub fn init_tracing(format: LogFormat, level: &str) -> anyhow::Result<()> {
+    build_subscriber(format, level).try_init()?;
+    Ok(())
+}
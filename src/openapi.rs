@@ -0,0 +1,1113 @@
+use crate::config::Config;
+use serde_json::{json, Value};
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{name}")})
+}
+
+fn json_body(name: &str) -> Value {
+    json!({
+        "required": true,
+        "content": {"application/json": {"schema": schema_ref(name)}}
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": schema}}
+    })
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, schema_ref("Error"))
+}
+
+fn path_param(name: &str) -> Value {
+    json!({"name": name, "in": "path", "required": true, "schema": {"type": "string"}})
+}
+
+fn query_param(name: &str, schema: Value, description: &str) -> Value {
+    json!({"name": name, "in": "query", "required": false, "schema": schema, "description": description})
+}
+
+fn pagination_params() -> Vec<Value> {
+    vec![
+        query_param(
+            "limit",
+            json!({"type": "integer"}),
+            "Maximum number of items",
+        ),
+        query_param(
+            "offset",
+            json!({"type": "integer"}),
+            "Number of items to skip",
+        ),
+    ]
+}
+
+fn public(operation: Value) -> Value {
+    let mut operation = operation;
+    operation["security"] = json!([]);
+    operation
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({"type": [kind, "null"]})
+}
+
+fn schemas() -> Value {
+    // Built in two halves so neither `json!` call hits the macro recursion limit.
+    let mut schemas = json!({
+        "Error": {
+            "type": "object",
+            "required": ["error"],
+            "properties": {
+                "error": {
+                    "type": "object",
+                    "required": ["code", "message", "request_id"],
+                    "properties": {
+                        "code": {"type": "string"},
+                        "message": {"type": "string"},
+                        "request_id": {"type": "string", "description": "Also returned in the X-Request-Id header"}
+                    }
+                }
+            }
+        },
+        "BulkSendError": {
+            "type": "object",
+            "required": ["error", "code"],
+            "properties": {
+                "error": {"type": "string"},
+                "code": {"type": "string"},
+                "message_id": {"type": "string", "description": "Set when a failed send was queued for retry"},
+                "status": {"type": "string", "enum": ["queued"], "description": "Set when a failed send was queued for retry"}
+            }
+        },
+        "QueuedSend": {
+            "type": "object",
+            "required": ["message_id", "status", "code", "error"],
+            "properties": {
+                "message_id": {"type": "string"},
+                "status": {"type": "string", "enum": ["queued"]},
+                "code": {"type": "string", "enum": ["queued"]},
+                "error": {"type": "string", "description": "Why the first attempt failed"}
+            }
+        },
+        "Attachment": {
+            "type": "object",
+            "required": ["url"],
+            "properties": {
+                "id": nullable("string"),
+                "url": {"type": "string"},
+                "mime_type": nullable("string"),
+                "filename": nullable("string"),
+                "size": nullable("integer")
+            }
+        },
+        "InboundMessage": {
+            "type": "object",
+            "required": ["inbound_id", "channel", "peer_id", "peer_kind", "attachments"],
+            "properties": {
+                "inbound_id": {"type": "string"},
+                "channel": {"type": "string"},
+                "account_id": nullable("string"),
+                "peer_id": {"type": "string"},
+                "peer_kind": {"type": "string"},
+                "thread_id": nullable("string"),
+                "message_id": nullable("string"),
+                "sender_name": nullable("string"),
+                "text": nullable("string"),
+                "attachments": {"type": "array", "items": schema_ref("Attachment")},
+                "timestamp": nullable("string")
+            }
+        },
+        "ReadinessResponse": {
+            "type": "object",
+            "required": ["status", "checks"],
+            "properties": {
+                "status": {"type": "string", "enum": ["ready", "not_ready"]},
+                "checks": {
+                    "type": "object",
+                    "properties": {
+                        "database": {"type": "boolean"},
+                        "started": {"type": "boolean"}
+                    }
+                }
+            }
+        },
+        "SendMessageRequest": {
+            "type": "object",
+            "required": ["session_key"],
+            "properties": {
+                "session_key": {"type": "string"},
+                "text": nullable("string"),
+                "attachments": {"type": ["array", "null"], "items": schema_ref("Attachment")},
+                "channel": nullable("string"),
+                "account_id": nullable("string"),
+                "peer_id": nullable("string"),
+                "peer_kind": {
+                    "type": ["string", "null"],
+                    "description": "dm, group, channel or thread; defaults to dm for new sessions"
+                },
+                "thread_id": nullable("string"),
+                "reply_to": {
+                    "type": ["string", "null"],
+                    "description": "agent-ping message id or provider message id to reply to"
+                },
+                "idempotency_key": nullable("string"),
+                "caption_mode": {"type": "boolean"},
+                "metadata": {"type": ["object", "null"]},
+                "sender_name": {
+                    "type": ["string", "null"],
+                    "description": "Slack only: bot display name for this message"
+                },
+                "sender_icon": {
+                    "type": ["string", "null"],
+                    "description": "Slack only: emoji name or http(s) image URL for the bot icon"
+                },
+                "reply_broadcast": {
+                    "type": "boolean",
+                    "description": "Slack only: also post a threaded reply to the channel"
+                },
+                "dry_run": {"type": "boolean"}
+            }
+        },
+        "SendByIdentityRequest": {
+            "type": "object",
+            "required": ["canonical_id"],
+            "properties": {
+                "canonical_id": {
+                    "type": "string",
+                    "description": "Key of `session.identity_links`"
+                },
+                "text": nullable("string"),
+                "attachments": {"type": ["array", "null"], "items": schema_ref("Attachment")},
+                "metadata": {"type": ["object", "null"]},
+                "dry_run": {"type": "boolean"}
+            }
+        },
+        "DryRunResponse": {
+            "type": "object",
+            "required": ["status", "session_key", "route", "payload"],
+            "properties": {
+                "status": {"type": "string", "enum": ["dry_run"]},
+                "session_key": {"type": "string"},
+                "route": {"type": "object"},
+                "payload": {"type": "object", "description": "Request body the channel would receive"}
+            }
+        },
+        "SendMessageResponse": {
+            "type": "object",
+            "required": ["message_id", "status"],
+            "properties": {
+                "message_id": {"type": "string"},
+                "status": {"type": "string"}
+            }
+        },
+        "BulkSendRequest": {
+            "type": "object",
+            "required": ["messages"],
+            "properties": {
+                "messages": {"type": "array", "items": schema_ref("SendMessageRequest")}
+            }
+        },
+        "BulkSendResponse": {
+            "type": "object",
+            "required": ["results"],
+            "properties": {
+                "results": {
+                    "type": "array",
+                    "items": {"oneOf": [
+                        schema_ref("SendMessageResponse"),
+                        schema_ref("DryRunResponse"),
+                        schema_ref("BulkSendError")
+                    ]}
+                }
+            }
+        },
+        "EditMessageRequest": {
+            "type": "object",
+            "required": ["text"],
+            "properties": {"text": {"type": "string"}}
+        },
+        "AddReactionRequest": {
+            "type": "object",
+            "required": ["reaction"],
+            "properties": {"reaction": {"type": "string"}}
+        },
+        "MessageStatus": {
+            "type": "object",
+            "required": ["message_id", "status"],
+            "properties": {
+                "message_id": {"type": "string"},
+                "status": {"type": "string"}
+            }
+        },
+        "HealthResponse": {
+            "type": "object",
+            "required": ["status"],
+            "properties": {"status": {"type": "string"}}
+        },
+        "StatusResponse": {
+            "type": "object",
+            "required": ["sessions", "messages", "outbox_pending", "outbox_failed", "outbox_dead"],
+            "properties": {
+                "sessions": {"type": "integer"},
+                "messages": {"type": "integer"},
+                "outbox_pending": {"type": "integer"},
+                "outbox_failed": {"type": "integer"},
+                "outbox_dead": {"type": "integer"},
+                "oldest_pending_age_seconds": nullable("integer")
+            }
+        }
+    });
+    let records = json!({
+        "StatsResponse": {
+            "type": "object",
+            "required": ["window", "bucket", "since", "buckets"],
+            "properties": {
+                "window": {"type": "string"},
+                "bucket": {"type": "string"},
+                "since": {"type": "string", "format": "date-time"},
+                "buckets": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["start", "inbound", "outbound", "channels"],
+                        "properties": {
+                            "start": {"type": "string", "format": "date-time"},
+                            "inbound": {"type": "integer"},
+                            "outbound": {"type": "integer"},
+                            "channels": {
+                                "type": "object",
+                                "additionalProperties": {
+                                    "type": "object",
+                                    "additionalProperties": {"type": "integer"}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        "ChannelStatus": {
+            "type": "object",
+            "required": ["enabled", "configured"],
+            "properties": {
+                "enabled": {"type": "boolean"},
+                "configured": {"type": "boolean"},
+                "last_inbound_at": {"type": "string", "format": "date-time"},
+                "last_lag_seconds": {
+                    "type": "number",
+                    "description": "Seconds from the provider timestamp of the last inbound message to its ingestion"
+                },
+                "last_error": {"type": "string"}
+            }
+        },
+        "SessionRecord": {
+            "type": "object",
+            "required": ["session_key", "agent_id", "dm_scope"],
+            "properties": {
+                "session_key": {"type": "string"},
+                "agent_id": {"type": "string"},
+                "business_profile_id": nullable("string"),
+                "user_id": nullable("string"),
+                "last_route": {"type": ["object", "null"]},
+                "dm_scope": {"type": "string"},
+                "identity_links": {"type": ["object", "null"]},
+                "created_at": {"type": "string", "format": "date-time"},
+                "updated_at": {"type": "string", "format": "date-time"},
+                "metadata": {"type": ["object", "null"]}
+            }
+        },
+        "InboundFailureRecord": {
+            "type": "object",
+            "required": ["id", "payload", "status", "retry_count", "next_attempt_at", "created_at"],
+            "properties": {
+                "id": {"type": "string"},
+                "payload": {"type": "object", "description": "Normalized inbound message"},
+                "session_key": nullable("string"),
+                "status": {"type": "string", "enum": ["pending", "retrying", "dead", "resolved"]},
+                "retry_count": {"type": "integer"},
+                "next_attempt_at": {"type": "string", "format": "date-time"},
+                "last_error": nullable("string"),
+                "created_at": {"type": "string", "format": "date-time"}
+            }
+        },
+        "MediaFailureRecord": {
+            "type": "object",
+            "required": ["id", "message_id", "channel", "session_key", "attachment", "status", "retry_count", "created_at"],
+            "properties": {
+                "id": {"type": "string"},
+                "message_id": {"type": "string"},
+                "channel": {"type": "string"},
+                "session_key": {"type": "string"},
+                "attachment": schema_ref("Attachment"),
+                "status": {"type": "string", "enum": ["pending", "retrying", "resolved"]},
+                "retry_count": {"type": "integer"},
+                "last_error": nullable("string"),
+                "created_at": {"type": "string", "format": "date-time"}
+            }
+        },
+        "CreateSessionRequest": {
+            "type": "object",
+            "properties": {
+                "session_key": nullable("string"),
+                "agent_id": nullable("string"),
+                "business_profile_id": nullable("string"),
+                "user_id": nullable("string"),
+                "identity_links": {"type": ["object", "null"]},
+                "metadata": {"type": ["object", "null"]},
+                "channel": nullable("string"),
+                "account_id": nullable("string"),
+                "peer_id": nullable("string"),
+                "peer_kind": nullable("string"),
+                "thread_id": nullable("string"),
+                "upsert": {"type": "boolean"}
+            }
+        },
+        "MessageRecord": {
+            "type": "object",
+            "required": ["id", "session_key", "direction", "channel", "status"],
+            "properties": {
+                "id": {"type": "string"},
+                "session_key": {"type": "string"},
+                "direction": {"type": "string", "enum": ["inbound", "outbound", "system"]},
+                "channel": {"type": "string"},
+                "account_id": nullable("string"),
+                "peer_id": nullable("string"),
+                "content": nullable("string"),
+                "attachments": {"type": ["array", "null"], "items": schema_ref("Attachment")},
+                "status": {"type": "string"},
+                "dedupe_key": nullable("string"),
+                "provider_message_id": nullable("string"),
+                "created_at": {"type": "string", "format": "date-time"},
+                "metadata": {"type": ["object", "null"]}
+            }
+        }
+    });
+    if let (Some(schemas), Value::Object(records)) = (schemas.as_object_mut(), records) {
+        schemas.extend(records);
+    }
+    schemas
+}
+
+pub fn openapi_document(config: &Config) -> Value {
+    let mut paths = serde_json::Map::new();
+    let mut add = |path: &str, method: &str, operation: Value| {
+        let entry = paths.entry(path.to_string()).or_insert_with(|| json!({}));
+        entry[method] = operation;
+    };
+
+    add(
+        "/v1/health",
+        "get",
+        public(json!({
+            "summary": "Liveness check",
+            "responses": {"200": json_response("Daemon is up", schema_ref("HealthResponse"))}
+        })),
+    );
+    add(
+        "/v1/healthz",
+        "get",
+        public(json!({
+            "summary": "Liveness check (same as /v1/health)",
+            "responses": {"200": json_response("Daemon is up", schema_ref("HealthResponse"))}
+        })),
+    );
+    add(
+        "/v1/readyz",
+        "get",
+        public(json!({
+            "summary": "Readiness check: database reachable and channel pollers started",
+            "responses": {
+                "200": json_response("Ready", schema_ref("ReadinessResponse")),
+                "503": json_response("Not ready", schema_ref("ReadinessResponse"))
+            }
+        })),
+    );
+    let status = json!({
+        "summary": "Session and message counts",
+        "responses": {"200": json_response("Counts", schema_ref("StatusResponse"))}
+    });
+    add(
+        "/v1/status",
+        "get",
+        if config.server.status_requires_auth {
+            status
+        } else {
+            public(status)
+        },
+    );
+    add(
+        "/v1/openapi.json",
+        "get",
+        public(json!({
+            "summary": "This document",
+            "responses": {"200": {"description": "OpenAPI document"}}
+        })),
+    );
+    for (path, summary) in [
+        (
+            config.channels.slack.webhook_path.as_str(),
+            "Slack Events API webhook",
+        ),
+        (
+            config.channels.telegram.webhook_path.as_str(),
+            "Telegram webhook",
+        ),
+        (
+            config.channels.whatsapp.inbound_path.as_str(),
+            "WhatsApp sidecar inbound",
+        ),
+        (config.channels.teams.webhook_path.as_str(), "Teams webhook"),
+    ] {
+        add(
+            path,
+            "post",
+            public(json!({
+                "summary": summary,
+                "requestBody": {"required": true, "content": {"application/json": {"schema": {"type": "object"}}}},
+                "responses": {"200": {"description": "Accepted"}, "400": error_response("Invalid payload")}
+            })),
+        );
+    }
+    add(
+        &config.channels.whatsapp.inbound_path,
+        "get",
+        public(json!({
+            "summary": "WhatsApp webhook verification",
+            "responses": {"200": {"description": "Verification challenge"}}
+        })),
+    );
+
+    add(
+        "/v1/messages/send",
+        "post",
+        json!({
+            "summary": "Send a message into a session",
+            "parameters": [{
+                "name": "X-Agent-Ping-Dry-Run",
+                "in": "header",
+                "required": false,
+                "schema": {"type": "string"},
+                "description": "Same as `dry_run: true`"
+            }],
+            "requestBody": json_body("SendMessageRequest"),
+            "responses": {
+                "200": json_response("Sent, or the would-be delivery for a dry run", json!({
+                    "oneOf": [schema_ref("SendMessageResponse"), schema_ref("DryRunResponse")]
+                })),
+                "202": json_response("Channel send failed; the message is queued for retry", schema_ref("QueuedSend")),
+                "400": error_response("Channel send failed for good"),
+                "404": error_response("Unknown session"),
+                "422": error_response("No route, unsupported channel or missing peer")
+            }
+        }),
+    );
+    add(
+        "/v1/messages/send-bulk",
+        "post",
+        json!({
+            "summary": "Send several messages; failures are reported per message",
+            "requestBody": json_body("BulkSendRequest"),
+            "responses": {"200": json_response("Per-message results", schema_ref("BulkSendResponse"))}
+        }),
+    );
+    add(
+        "/v1/messages/send-by-identity",
+        "post",
+        json!({
+            "summary": "Send to the latest session of a linked identity over its last route",
+            "requestBody": json_body("SendByIdentityRequest"),
+            "responses": {
+                "200": json_response("Sent, or the would-be delivery for a dry run", json!({
+                    "oneOf": [schema_ref("SendMessageResponse"), schema_ref("DryRunResponse")]
+                })),
+                "400": error_response("Channel send failed; the message is queued for retry"),
+                "404": error_response("No session found for the identity"),
+                "422": error_response("No route or unsupported channel")
+            }
+        }),
+    );
+    add(
+        "/v1/messages/{message_id}",
+        "patch",
+        json!({
+            "summary": "Edit a sent Slack or Telegram message",
+            "parameters": [path_param("message_id")],
+            "requestBody": json_body("EditMessageRequest"),
+            "responses": {
+                "200": json_response("Edited", schema_ref("MessageStatus")),
+                "404": error_response("Unknown message"),
+                "422": error_response("Editing unsupported on the channel")
+            }
+        }),
+    );
+    add(
+        "/v1/messages/{message_id}",
+        "delete",
+        json!({
+            "summary": "Delete a sent Slack or Telegram message",
+            "parameters": [path_param("message_id")],
+            "responses": {
+                "200": json_response("Deleted", schema_ref("MessageStatus")),
+                "404": error_response("Unknown message"),
+                "422": error_response("Deleting unsupported on the channel")
+            }
+        }),
+    );
+    add(
+        "/v1/messages/{message_id}/reactions",
+        "post",
+        json!({
+            "summary": "React to a stored Slack or Telegram message",
+            "parameters": [path_param("message_id")],
+            "requestBody": json_body("AddReactionRequest"),
+            "responses": {
+                "200": json_response("Reacted", schema_ref("MessageStatus")),
+                "404": error_response("Unknown message"),
+                "422": error_response("Reactions unsupported on the channel")
+            }
+        }),
+    );
+    add(
+        "/v1/messages/{message_id}/resend",
+        "post",
+        json!({
+            "summary": "Retry a failed outbound send now",
+            "parameters": [path_param("message_id")],
+            "responses": {
+                "200": json_response("Sent", schema_ref("SendMessageResponse")),
+                "202": json_response("Channel send failed again and is queued for the next retry", schema_ref("QueuedSend")),
+                "400": error_response("Channel send failed for good"),
+                "404": error_response("Unknown message"),
+                "409": error_response("Message has no failed send to retry, or the retry worker is sending it")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions",
+        "get",
+        json!({
+            "summary": "List sessions, most recently updated first",
+            "parameters": pagination_params(),
+            "responses": {
+                "200": json_response("Sessions", json!({"type": "array", "items": schema_ref("SessionRecord")})),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions",
+        "post",
+        json!({
+            "summary": "Create a session before any message",
+            "requestBody": json_body("CreateSessionRequest"),
+            "responses": {
+                "201": json_response("Created session", schema_ref("SessionRecord")),
+                "200": json_response("Existing session updated (upsert)", schema_ref("SessionRecord")),
+                "409": error_response("Session already exists"),
+                "422": error_response("Neither session_key nor channel and peer_id given"),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/batch",
+        "post",
+        json!({
+            "summary": "Look up many sessions at once",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {
+                "type": "object",
+                "required": ["session_keys"],
+                "properties": {
+                    "session_keys": {"type": "array", "items": {"type": "string"}, "maxItems": 200}
+                }
+            }}}},
+            "responses": {
+                "200": json_response("Sessions by key; unknown keys are omitted", json!({
+                    "type": "object",
+                    "additionalProperties": schema_ref("SessionRecord")
+                })),
+                "400": error_response("Too many session_keys"),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/{session_key}",
+        "get",
+        json!({
+            "summary": "Get one session",
+            "parameters": [path_param("session_key")],
+            "responses": {
+                "200": json_response("Session", schema_ref("SessionRecord")),
+                "404": {"description": "Unknown session"},
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/{session_key}/route",
+        "put",
+        json!({
+            "summary": "Override the route used for sends without an explicit channel",
+            "parameters": [path_param("session_key")],
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {
+                "type": "object",
+                "required": ["channel", "peer_id"],
+                "properties": {
+                    "channel": {"type": "string", "enum": ["slack", "telegram", "whatsapp", "teams"]},
+                    "account_id": {"type": ["string", "null"]},
+                    "peer_id": {"type": "string"},
+                    "thread_id": {"type": ["string", "null"]}
+                }
+            }}}},
+            "responses": {
+                "200": json_response("Session", schema_ref("SessionRecord")),
+                "404": error_response("Unknown session"),
+                "422": error_response("Unsupported channel or missing peer")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/{session_key}/window",
+        "get",
+        json!({
+            "summary": "Whether the WhatsApp 24h messaging window is open",
+            "parameters": [path_param("session_key")],
+            "responses": {
+                "200": json_response("Messaging window", json!({
+                    "type": "object",
+                    "required": ["open", "expires_at"],
+                    "properties": {
+                        "open": {"type": "boolean"},
+                        "expires_at": {"type": ["string", "null"], "format": "date-time"}
+                    }
+                })),
+                "404": error_response("Unknown session")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/{session_key}/system",
+        "post",
+        json!({
+            "summary": "Append a system message that is never sent to a channel",
+            "parameters": [path_param("session_key")],
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {
+                "type": "object",
+                "required": ["text"],
+                "properties": {
+                    "text": {"type": "string"},
+                    "metadata": {"type": ["object", "null"]}
+                }
+            }}}},
+            "responses": {
+                "200": json_response("Recorded", schema_ref("SendMessageResponse")),
+                "400": error_response("Missing text"),
+                "404": error_response("Unknown session")
+            }
+        }),
+    );
+    let mut message_params = vec![path_param("session_key")];
+    message_params.extend(pagination_params());
+    message_params.push(query_param(
+        "after",
+        json!({"type": "string"}),
+        "Unix millis or message id; only newer messages are returned, oldest first",
+    ));
+    add(
+        "/v1/sessions/{session_key}/messages",
+        "get",
+        json!({
+            "summary": "List messages in a session",
+            "parameters": message_params,
+            "responses": {
+                "200": json_response("Messages", json!({"type": "array", "items": schema_ref("MessageRecord")})),
+                "400": error_response("Unknown after message id"),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/sessions/{session_key}/messages/stream",
+        "get",
+        json!({
+            "summary": "Stream the full message history as newline-delimited JSON",
+            "parameters": [path_param("session_key")],
+            "responses": {"200": {
+                "description": "One MessageRecord per line",
+                "content": {"application/x-ndjson": {"schema": schema_ref("MessageRecord")}}
+            }}
+        }),
+    );
+    add(
+        "/v1/runtime/inbound",
+        "post",
+        json!({
+            "summary": "Inject an inbound message from an embedded adapter runtime",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {"type": "object"}}}},
+            "responses": {"200": {"description": "Accepted"}, "400": error_response("Rejected")}
+        }),
+    );
+    add(
+        "/v1/stats",
+        "get",
+        json!({
+            "summary": "Message counts per time bucket, split by direction and channel",
+            "parameters": [
+                query_param(
+                    "window",
+                    json!({"type": "string", "enum": ["1h", "6h", "24h", "7d", "30d"], "default": "24h"}),
+                    "How far back the series goes",
+                ),
+                query_param(
+                    "bucket",
+                    json!({"type": "string", "enum": ["5m", "15m", "1h", "6h", "1d"], "default": "1h"}),
+                    "Bucket width; at most 720 buckets per window",
+                )
+            ],
+            "responses": {
+                "200": json_response("Series", schema_ref("StatsResponse")),
+                "400": error_response("Unsupported window or bucket")
+            }
+        }),
+    );
+    add(
+        "/v1/config",
+        "get",
+        json!({
+            "summary": "Effective config with secrets redacted",
+            "responses": {"200": json_response("Config", json!({"type": "object"}))}
+        }),
+    );
+    add(
+        "/v1/admin/reload",
+        "post",
+        json!({
+            "summary": "Reload configuration without restarting",
+            "responses": {
+                "200": json_response("Reloaded", json!({
+                    "type": "object",
+                    "required": ["status", "requires_restart"],
+                    "properties": {
+                        "status": {"type": "string"},
+                        "requires_restart": {"type": "array", "items": {"type": "string"}}
+                    }
+                })),
+                "400": error_response("Config could not be loaded or is invalid")
+            }
+        }),
+    );
+    add(
+        "/v1/admin/maintenance",
+        "post",
+        json!({
+            "summary": "Vacuum and analyze the database in the background",
+            "responses": {
+                "202": json_response("Maintenance started", json!({
+                    "type": "object",
+                    "required": ["status"],
+                    "properties": {"status": {"type": "string"}}
+                })),
+                "409": error_response("Maintenance is already running")
+            }
+        }),
+    );
+    add(
+        "/v1/admin/inbound-failures",
+        "get",
+        json!({
+            "summary": "Inbound events that failed processing and are pending or dead",
+            "parameters": pagination_params(),
+            "responses": {
+                "200": json_response("Failures", json!({"type": "array", "items": schema_ref("InboundFailureRecord")})),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/admin/inbound-failures/{id}/retry",
+        "post",
+        json!({
+            "summary": "Replay a failed inbound event now",
+            "parameters": [path_param("id")],
+            "responses": {
+                "200": json_response("Resolved", json!({
+                    "type": "object",
+                    "required": ["status", "id"],
+                    "properties": {"status": {"type": "string"}, "id": {"type": "string"}}
+                })),
+                "404": error_response("Unknown failure id"),
+                "409": error_response("Already resolved, or being replayed by the retry worker"),
+                "502": error_response("Processing failed again; the next attempt is rescheduled")
+            }
+        }),
+    );
+    add(
+        "/v1/admin/media-failures",
+        "get",
+        json!({
+            "summary": "Inbound attachments that could not be rehosted at backend.media_upload_url",
+            "parameters": pagination_params(),
+            "responses": {
+                "200": json_response("Failures", json!({"type": "array", "items": schema_ref("MediaFailureRecord")})),
+                "500": error_response("Database error")
+            }
+        }),
+    );
+    add(
+        "/v1/admin/media-failures/{id}/retry",
+        "post",
+        json!({
+            "summary": "Re-run the upload of a failed attachment",
+            "parameters": [path_param("id")],
+            "responses": {
+                "200": json_response("Resolved", json!({
+                    "type": "object",
+                    "required": ["status", "id", "message_id", "attachment"],
+                    "properties": {
+                        "status": {"type": "string"},
+                        "id": {"type": "string"},
+                        "message_id": {"type": "string"},
+                        "attachment": schema_ref("Attachment")
+                    }
+                })),
+                "404": error_response("Unknown failure id"),
+                "409": error_response("Already resolved, or another retry of it is running"),
+                "502": error_response("The upload failed again")
+            }
+        }),
+    );
+    add(
+        "/v1/channels",
+        "get",
+        json!({
+            "summary": "Per-channel health",
+            "responses": {"200": json_response(
+                "Channel status by name",
+                json!({"type": "object", "additionalProperties": schema_ref("ChannelStatus")})
+            )}
+        }),
+    );
+    add(
+        "/v1/channels/{channel}/capabilities",
+        "get",
+        json!({
+            "summary": "Features the gateway supports on a channel",
+            "parameters": [path_param("channel")],
+            "responses": {
+                "200": json_response("Capabilities", json!({
+                    "type": "object",
+                    "properties": {
+                        "channel": {"type": "string"},
+                        "capabilities": {
+                            "type": "object",
+                            "additionalProperties": {"type": "boolean"}
+                        }
+                    }
+                })),
+                "404": error_response("Unknown channel")
+            }
+        }),
+    );
+    add(
+        "/v1/channels/identities",
+        "get",
+        json!({
+            "summary": "Bot identities reported by the channels",
+            "responses": {"200": json_response("Identities", json!({"type": "object"}))}
+        }),
+    );
+    for (path, method, summary) in [
+        (
+            "/v1/channels/whatsapp/status",
+            "get",
+            "WhatsApp sidecar status",
+        ),
+        (
+            "/v1/channels/whatsapp/link",
+            "post",
+            "Start WhatsApp device linking",
+        ),
+        (
+            "/v1/channels/whatsapp/logout",
+            "post",
+            "Log the WhatsApp sidecar out",
+        ),
+    ] {
+        add(
+            path,
+            method,
+            json!({
+                "summary": summary,
+                "responses": {"200": json_response("Sidecar response", json!({"type": "object"}))}
+            }),
+        );
+    }
+    add(
+        "/v1/inbound",
+        "post",
+        json!({
+            "summary": "Accept an inbound message for any channel, e.g. one under channels.custom",
+            "requestBody": json_body("InboundMessage"),
+            "responses": {
+                "200": json_response("Accepted", json!({
+                    "type": "object",
+                    "required": ["status", "inbound_id"],
+                    "properties": {"status": {"type": "string"}, "inbound_id": {"type": "string"}}
+                })),
+                "400": error_response("Rejected"),
+                "422": error_response("Missing channel or peer_id")
+            }
+        }),
+    );
+    add(
+        "/v1/inbound/ack",
+        "post",
+        json!({
+            "summary": "Acknowledge an inbound delivery",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {
+                "type": "object",
+                "required": ["outbox_id"],
+                "properties": {
+                    "outbox_id": {"type": "string"},
+                    "inbound_id": {"type": "string"},
+                    "status": {"type": "string", "enum": ["ok", "error"]},
+                    "error": {"type": "string"}
+                }
+            }}}},
+            "responses": {
+                "200": json_response("Acknowledged", json!({
+                    "type": "object",
+                    "properties": {
+                        "status": {"type": "string"},
+                        "outbox_id": {"type": "string"},
+                        "outbox_status": {"type": "string", "enum": ["delivered", "failed"]}
+                    }
+                })),
+                "400": error_response("Missing outbox_id or unknown status"),
+                "404": error_response("Unknown outbox row"),
+                "409": error_response("Outbox row already settled")
+            }
+        }),
+    );
+    add(
+        "/v1/ws",
+        "get",
+        json!({
+            "summary": "WebSocket control plane",
+            "responses": {"101": {"description": "Switching protocols"}}
+        }),
+    );
+
+    let mut doc = json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "agent-ping",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "security": [{"token": []}],
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "token": {"type": "apiKey", "in": "header", "name": "X-Agent-Ping-Token"}
+            },
+            "schemas": schemas()
+        }
+    });
+    let prefix = config.server.route_prefix();
+    if !prefix.is_empty() {
+        doc["servers"] = json!([{"url": prefix}]);
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MessageRecord;
+    use crate::{SendByIdentityRequest, SendMessageRequest, SendMessageResponse, StatusResponse};
+    use std::collections::BTreeSet;
+
+    fn schema_keys(doc: &Value, name: &str) -> BTreeSet<String> {
+        doc["components"]["schemas"][name]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn value_keys(value: impl serde::Serialize) -> BTreeSet<String> {
+        serde_json::to_value(value)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_openapi_schemas_match_serde_types() {
+        let doc = openapi_document(&Config::default());
+        assert_eq!(
+            schema_keys(&doc, "SendMessageRequest"),
+            value_keys(SendMessageRequest::default())
+        );
+        assert_eq!(
+            schema_keys(&doc, "SendByIdentityRequest"),
+            value_keys(SendByIdentityRequest::default())
+        );
+        assert_eq!(
+            schema_keys(&doc, "SendMessageResponse"),
+            value_keys(SendMessageResponse {
+                message_id: String::new(),
+                status: String::new(),
+            })
+        );
+        assert_eq!(
+            schema_keys(&doc, "StatusResponse"),
+            value_keys(StatusResponse {
+                sessions: 0,
+                messages: 0,
+                outbox_pending: 0,
+                outbox_failed: 0,
+                outbox_dead: 0,
+                oldest_pending_age_seconds: None,
+            })
+        );
+        let message: MessageRecord = serde_json::from_value(json!({
+            "id": "m1",
+            "session_key": "s1",
+            "direction": "inbound",
+            "channel": "slack",
+            "account_id": null,
+            "peer_id": null,
+            "content": null,
+            "attachments": null,
+            "status": "received",
+            "dedupe_key": null,
+            "provider_message_id": null
+        }))
+        .unwrap();
+        assert_eq!(schema_keys(&doc, "MessageRecord"), value_keys(message));
+    }
+
+    #[test]
+    fn test_openapi_refs_resolve() {
+        let doc = openapi_document(&Config::default());
+        let text = doc.to_string();
+        for chunk in text.split("\"#/components/schemas/").skip(1) {
+            let name = chunk.split('"').next().unwrap();
+            assert!(
+                doc["components"]["schemas"].get(name).is_some(),
+                "missing schema {name}"
+            );
+        }
+    }
+}
@@ -1,15 +1,22 @@
 use crate::config::BackendConfig;
 use crate::db::{
-    claim_outbox_batch, mark_outbox_delivered, mark_outbox_failed, DbKind, OutboxRecord,
+    claim_outbox_batch, prune_outbox, reclaim_stale_sending, settle_outbox_delivered,
+    settle_outbox_failed, DbKind, OutboxRecord, OUTBOX_MAX_RETRIES,
 };
-use chrono::{Duration, Utc};
-use reqwest::Client;
+use crate::AppState;
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
 use sqlx::AnyPool;
 use tokio::time::sleep;
+use tracing::{error, info, warn};
 
 const OUTBOX_POLL_SECONDS: u64 = 2;
 const OUTBOX_BATCH: i64 = 25;
-const OUTBOX_MAX_RETRIES: i32 = 10;
+const OUTBOX_SENDING_STALE_SECONDS: i64 = 300;
+const BATCH_FILL_POLL_MS: u64 = 50;
+const OUTBOX_PRUNE_INTERVAL_SECONDS: u64 = 3600;
 
 pub fn compute_backoff(retry_count: i32) -> Duration {
     let exponent = (retry_count.max(1) - 1).min(8) as u32;
@@ -17,47 +24,278 @@ pub fn compute_backoff(retry_count: i32) -> Duration {
     Duration::seconds((base * 5).min(300))
 }
 
-pub async fn start_outbox_worker(pool: AnyPool, backend: BackendConfig, db_kind: DbKind) {
-    if backend.webhook_url.is_none() {
-        return;
+pub fn backend_request(mut req: RequestBuilder, backend: &BackendConfig) -> RequestBuilder {
+    if let Some(token) = backend.api_token.as_ref() {
+        req = req.header("X-Agent-Ping-Token", token);
+    }
+    for (name, value) in &backend.extra_headers {
+        req = req.header(name.as_str(), value.as_str());
+    }
+    req
+}
+
+/// Inbound payload fields that `backend.payload_template` may reference.
+pub const PAYLOAD_TEMPLATE_FIELDS: &[&str] = &[
+    "inbound_id",
+    "session_key",
+    "channel",
+    "account_id",
+    "peer_id",
+    "peer_kind",
+    "thread_id",
+    "message_id",
+    "sender_name",
+    "text",
+    "attachments",
+    "timestamp",
+    "business_profile_id",
+    "user_id",
+    "agent_id",
+    "original_content_bytes",
+    "original_text",
+    "dropped_attachments",
+    "dropped_attachments_summary",
+];
+
+/// Names inside the `{{placeholders}}` of `text`, trimmed.
+pub fn template_placeholders(text: &str) -> anyhow::Result<Vec<&str>> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("unclosed placeholder in {text:?}"))?;
+        names.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+/// Renders `template` with fields of the default inbound `payload`. A string
+/// that is a single placeholder takes the field's JSON value as is; placeholders
+/// inside longer strings are replaced by the field's text, `null` as empty.
+pub fn render_payload_template(template: &Value, payload: &Value) -> Value {
+    match template {
+        Value::String(text) => {
+            let whole = text.trim();
+            if let Ok([name]) = template_placeholders(text).as_deref() {
+                if whole.starts_with("{{") && whole.ends_with("}}") {
+                    return payload.get(*name).cloned().unwrap_or(Value::Null);
+                }
+            }
+            let mut out = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start + 2..].find("}}") else {
+                    break;
+                };
+                out.push_str(&rest[..start]);
+                let name = rest[start + 2..start + 2 + end].trim();
+                match payload.get(name) {
+                    Some(Value::String(value)) => out.push_str(value),
+                    Some(Value::Null) | None => {}
+                    Some(value) => out.push_str(&value.to_string()),
+                }
+                rest = &rest[start + 2 + end + 2..];
+            }
+            out.push_str(rest);
+            Value::String(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_payload_template(item, payload))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), render_payload_template(value, payload)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Delivers outbox rows until the process exits. Backend settings are read
+/// from the running config on every tick, so a reload that adds a webhook or
+/// changes batching applies without a restart; while no webhook is configured
+/// at all, rows stay pending.
+pub async fn start_outbox_worker(state: AppState) {
+    let (pool, db_kind) = (state.pool.clone(), state.db_kind);
+    let stale_before = Utc::now() - Duration::seconds(OUTBOX_SENDING_STALE_SECONDS);
+    match reclaim_stale_sending(&pool, db_kind, stale_before).await {
+        Ok(0) => {}
+        Ok(count) => warn!("reclaimed {count} stale outbox rows stuck in sending"),
+        Err(err) => error!("outbox reclaim error: {err:?}"),
     }
 
     let client = Client::new();
+    let mut last_prune: Option<tokio::time::Instant> = None;
     loop {
-        let now = Utc::now();
-        if let Ok(batch) = claim_outbox_batch(&pool, db_kind, now, OUTBOX_BATCH).await {
-            for row in batch {
-                if let Err(err) = dispatch_row(&client, &backend, &pool, db_kind, &row).await {
-                    let retry = row.retry_count + 1;
-                    if retry >= OUTBOX_MAX_RETRIES {
-                        let _ = mark_outbox_failed(
-                            &pool,
-                            db_kind,
-                            &row.id,
-                            retry,
-                            now + Duration::seconds(3600),
-                            &err.to_string(),
-                        )
-                        .await;
-                        continue;
+        let config = state.config();
+        let backend = &config.backend;
+        let has_webhook = backend.webhook_url.is_some()
+            || config
+                .bindings
+                .iter()
+                .any(|binding| binding.webhook_url.is_some());
+        if !has_webhook {
+            sleep(std::time::Duration::from_secs(OUTBOX_POLL_SECONDS)).await;
+            continue;
+        }
+        let prune_due = last_prune.is_none_or(|at| {
+            at.elapsed() >= std::time::Duration::from_secs(OUTBOX_PRUNE_INTERVAL_SECONDS)
+        });
+        if backend.outbox_retention_hours > 0 && prune_due {
+            last_prune = Some(tokio::time::Instant::now());
+            prune_finished_rows(&pool, db_kind, backend.outbox_retention_hours).await;
+        }
+        if backend.batch_size > 1 {
+            let batch = claim_for_batch(&pool, db_kind, backend).await;
+            for group in group_by_webhook(batch) {
+                let results = dispatch_batch(&client, backend, &pool, db_kind, &group).await;
+                for (row, result) in group.iter().zip(results) {
+                    if let Err(err) = result {
+                        mark_row_failed(&pool, db_kind, row, &err).await;
                     }
-                    let next = Utc::now() + compute_backoff(retry);
-                    let _ = mark_outbox_failed(
-                        &pool,
-                        db_kind,
-                        &row.id,
-                        retry,
-                        next,
-                        &err.to_string(),
-                    )
-                    .await;
                 }
             }
+        } else if let Ok(batch) = claim_outbox_batch(&pool, db_kind, Utc::now(), OUTBOX_BATCH).await
+        {
+            dispatch_rows(&client, backend, &pool, db_kind, batch).await;
         }
         sleep(std::time::Duration::from_secs(OUTBOX_POLL_SECONDS)).await;
     }
 }
 
+/// Drops delivered and dead rows past `backend.outbox_retention_hours` so
+/// the table the worker claims from stays small.
+async fn prune_finished_rows(pool: &AnyPool, db_kind: DbKind, retention_hours: u64) {
+    let cutoff = Utc::now() - Duration::hours(retention_hours.min(876_000) as i64);
+    match prune_outbox(pool, db_kind, cutoff, &["delivered", "dead"]).await {
+        Ok(0) => {}
+        Ok(count) => info!("pruned {count} finished outbox rows"),
+        Err(err) => error!("outbox prune error: {err:?}"),
+    }
+}
+
+/// The retry count and next attempt for `row` after one more failure.
+pub(crate) fn next_failure(row: &OutboxRecord) -> (i32, DateTime<Utc>) {
+    let retry = row.retry_count + 1;
+    let next = if retry >= OUTBOX_MAX_RETRIES {
+        Utc::now() + Duration::seconds(3600)
+    } else {
+        Utc::now() + compute_backoff(retry)
+    };
+    (retry, next)
+}
+
+/// Records a failed attempt on a row this worker claimed. A row an ack already
+/// settled while the attempt was in flight keeps the ack's result.
+async fn mark_row_failed(pool: &AnyPool, db_kind: DbKind, row: &OutboxRecord, err: &anyhow::Error) {
+    let (retry, next) = next_failure(row);
+    let error = err.to_string();
+    match settle_outbox_failed(pool, db_kind, &row.id, &["sending"], retry, next, &error).await {
+        Ok(true) => {}
+        Ok(false) => info!(
+            "outbox row {} was settled by an ack during dispatch",
+            row.id
+        ),
+        Err(db_err) => error!("outbox mark failed error: {db_err:?}"),
+    }
+}
+
+/// Marks a row this worker claimed delivered, unless an ack settled it first.
+async fn mark_row_delivered(
+    pool: &AnyPool,
+    db_kind: DbKind,
+    row: &OutboxRecord,
+) -> anyhow::Result<()> {
+    if !settle_outbox_delivered(pool, db_kind, &row.id, &["sending"]).await? {
+        info!(
+            "outbox row {} was settled by an ack during dispatch",
+            row.id
+        );
+    }
+    Ok(())
+}
+
+/// Claims up to `batch_size` due rows, topping the batch up until it is full
+/// or `batch_max_wait_ms` has passed since the first row was claimed.
+async fn claim_for_batch(
+    pool: &AnyPool,
+    db_kind: DbKind,
+    backend: &BackendConfig,
+) -> Vec<OutboxRecord> {
+    let size = backend.batch_size as i64;
+    let mut rows = claim_outbox_batch(pool, db_kind, Utc::now(), size)
+        .await
+        .unwrap_or_default();
+    let deadline =
+        tokio::time::Instant::now() + std::time::Duration::from_millis(backend.batch_max_wait_ms);
+    while !rows.is_empty() && (rows.len() as i64) < size {
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            break;
+        }
+        sleep((deadline - now).min(std::time::Duration::from_millis(BATCH_FILL_POLL_MS))).await;
+        let remaining = size - rows.len() as i64;
+        if let Ok(more) = claim_outbox_batch(pool, db_kind, Utc::now(), remaining).await {
+            rows.extend(more);
+        }
+    }
+    rows
+}
+
+/// The webhook a row is posted to: its binding's, else `backend.webhook_url`.
+fn row_webhook_url<'a>(
+    row: &'a OutboxRecord,
+    backend: &'a BackendConfig,
+) -> anyhow::Result<&'a str> {
+    row.webhook_url
+        .as_deref()
+        .or(backend.webhook_url.as_deref())
+        .ok_or_else(|| anyhow::anyhow!("no backend webhook_url for outbox row"))
+}
+
+/// Splits claimed rows into batches that share a webhook URL, keeping the
+/// claim order within each batch.
+fn group_by_webhook(rows: Vec<OutboxRecord>) -> Vec<Vec<OutboxRecord>> {
+    let mut groups: Vec<Vec<OutboxRecord>> = Vec::new();
+    for row in rows {
+        match groups
+            .iter_mut()
+            .find(|group| group[0].webhook_url == row.webhook_url)
+        {
+            Some(group) => group.push(row),
+            None => groups.push(vec![row]),
+        }
+    }
+    groups
+}
+
+/// Posts each row on its own, keeping up to `backend.concurrency` requests in
+/// flight. Every row is delivered or rescheduled independently of the others.
+async fn dispatch_rows(
+    client: &Client,
+    backend: &BackendConfig,
+    pool: &AnyPool,
+    db_kind: DbKind,
+    rows: Vec<OutboxRecord>,
+) {
+    futures::stream::iter(rows)
+        .map(|row| async move {
+            if let Err(err) = dispatch_row(client, backend, pool, db_kind, &row).await {
+                mark_row_failed(pool, db_kind, &row, &err).await;
+            }
+        })
+        .buffer_unordered(backend.concurrency.max(1))
+        .collect::<()>()
+        .await;
+}
+
 async fn dispatch_row(
     client: &Client,
     backend: &BackendConfig,
@@ -65,16 +303,13 @@ async fn dispatch_row(
     db_kind: DbKind,
     row: &OutboxRecord,
 ) -> anyhow::Result<()> {
-    let url = backend.webhook_url.as_ref().expect("webhook_url exists");
-    let mut req = client.post(url).json(&row.payload);
-    if let Some(token) = backend.api_token.as_ref() {
-        req = req.header("X-Agent-Ping-Token", token);
-    }
+    let url = row_webhook_url(row, backend)?;
+    let req = backend_request(client.post(url).json(&row.payload), backend)
+        .header("X-Agent-Ping-Outbox-Id", &row.id);
 
     let resp = req.send().await?;
     if resp.status().is_success() {
-        mark_outbox_delivered(pool, db_kind, &row.id).await?;
-        return Ok(());
+        return mark_row_delivered(pool, db_kind, row).await;
     }
 
     let status = resp.status();
@@ -82,10 +317,82 @@ async fn dispatch_row(
     Err(anyhow::anyhow!("backend webhook failed: {} {}", status, body))
 }
 
+/// Posts every row's payload as one JSON array to the first row's webhook (see
+/// `group_by_webhook`). Object payloads carry the row's `outbox_id` so the
+/// backend can ack items on their own. A 2xx response delivers the whole batch unless its body
+/// carries `results`, one `{"ok": bool, "error"?}` per payload in order, in
+/// which case only the `ok` rows are delivered.
+async fn dispatch_batch(
+    client: &Client,
+    backend: &BackendConfig,
+    pool: &AnyPool,
+    db_kind: DbKind,
+    rows: &[OutboxRecord],
+) -> Vec<anyhow::Result<()>> {
+    let failed = |message: String| {
+        rows.iter()
+            .map(|_| Err(anyhow::anyhow!("{message}")))
+            .collect()
+    };
+    let url = match rows.first().map(|row| row_webhook_url(row, backend)) {
+        Some(Ok(url)) => url,
+        Some(Err(err)) => return failed(err.to_string()),
+        None => return Vec::new(),
+    };
+    let payloads: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let mut payload = row.payload.clone();
+            if let Some(fields) = payload.as_object_mut() {
+                fields.insert("outbox_id".to_string(), Value::String(row.id.clone()));
+            }
+            payload
+        })
+        .collect();
+    let req = backend_request(client.post(url).json(&payloads), backend);
+
+    let resp = match req.send().await {
+        Ok(resp) => resp,
+        Err(err) => return failed(err.to_string()),
+    };
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return failed(format!("backend webhook failed: {} {}", status, body));
+    }
+
+    let results = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("results").and_then(|v| v.as_array()).cloned());
+    let mut out = Vec::with_capacity(rows.len());
+    for (index, row) in rows.iter().enumerate() {
+        let item = results.as_ref().map(|results| results.get(index));
+        let outcome = match item {
+            None => Ok(()),
+            Some(Some(item)) if item.get("ok").and_then(|v| v.as_bool()) == Some(true) => Ok(()),
+            Some(Some(item)) => Err(anyhow::anyhow!(
+                "backend rejected batch item: {}",
+                item.get("error")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("no error given")
+            )),
+            Some(None) => Err(anyhow::anyhow!("backend returned no result for batch item")),
+        };
+        let outcome = match outcome {
+            Ok(()) => mark_row_delivered(pool, db_kind, row).await,
+            Err(err) => Err(err),
+        };
+        out.push(outcome);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::Duration;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn test_compute_backoff_zero() {
@@ -134,4 +441,351 @@ mod tests {
         let backoff = compute_backoff(-1);
         assert_eq!(backoff, Duration::seconds(5));
     }
+
+    async fn test_pool() -> AnyPool {
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::init_db(&pool, DbKind::Sqlite).await.unwrap();
+        pool
+    }
+
+    async fn outbox_statuses(pool: &AnyPool) -> Vec<String> {
+        sqlx::query_scalar::<_, String>("SELECT status FROM inbound_outbox")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn batch_fixture(server: &MockServer) -> (AnyPool, BackendConfig, Vec<OutboxRecord>) {
+        let pool = test_pool().await;
+        for id in ["in-1", "in-2", "in-3"] {
+            crate::db::insert_outbox(
+                &pool,
+                DbKind::Sqlite,
+                serde_json::json!({"inbound_id": id}),
+                Utc::now(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let backend = BackendConfig {
+            webhook_url: Some(format!("{}/inbound", server.uri())),
+            batch_size: 3,
+            batch_max_wait_ms: 10,
+            ..BackendConfig::default()
+        };
+        let mut rows = claim_for_batch(&pool, DbKind::Sqlite, &backend).await;
+        rows.sort_by_key(|row| row.payload["inbound_id"].as_str().map(str::to_string));
+        (pool, backend, rows)
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_batch_posts_rows_as_one_array() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/inbound"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let (pool, backend, rows) = batch_fixture(&server).await;
+        assert_eq!(rows.len(), 3);
+
+        let results = dispatch_batch(&Client::new(), &backend, &pool, DbKind::Sqlite, &rows).await;
+        assert!(results.iter().all(|result| result.is_ok()));
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let ids: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|payload| payload["inbound_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids.len(), 3);
+        for id in ["in-1", "in-2", "in-3"] {
+            assert!(ids.contains(&id));
+        }
+        for row in &rows {
+            assert!(body
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|payload| payload["outbox_id"] == row.id.as_str()));
+        }
+        assert_eq!(outbox_statuses(&pool).await, vec!["delivered"; 3]);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_batch_applies_per_item_results() {
+        let server = MockServer::start().await;
+        let (pool, backend, rows) = batch_fixture(&server).await;
+        let results: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|row| match row.payload["inbound_id"].as_str() {
+                Some("in-2") => serde_json::json!({"ok": false, "error": "unknown tenant"}),
+                _ => serde_json::json!({"ok": true}),
+            })
+            .collect();
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"results": results})),
+            )
+            .mount(&server)
+            .await;
+
+        let outcomes = dispatch_batch(&Client::new(), &backend, &pool, DbKind::Sqlite, &rows).await;
+        for (row, outcome) in rows.iter().zip(&outcomes) {
+            match row.payload["inbound_id"].as_str() {
+                Some("in-2") => {
+                    let err = outcome.as_ref().unwrap_err().to_string();
+                    assert!(err.contains("unknown tenant"), "{err}");
+                }
+                _ => assert!(outcome.is_ok()),
+            }
+        }
+        let mut statuses = outbox_statuses(&pool).await;
+        statuses.sort();
+        assert_eq!(statuses, vec!["delivered", "delivered", "sending"]);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_rows_runs_requests_concurrently() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/inbound"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({"inbound_id": "in-bad"}),
+            ))
+            .respond_with(
+                ResponseTemplate::new(500).set_delay(std::time::Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/inbound"))
+            .respond_with(
+                ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+
+        let pool = test_pool().await;
+        for id in ["in-1", "in-2", "in-3", "in-4", "in-bad"] {
+            crate::db::insert_outbox(
+                &pool,
+                DbKind::Sqlite,
+                serde_json::json!({"inbound_id": id}),
+                Utc::now(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let backend = BackendConfig {
+            webhook_url: Some(format!("{}/inbound", server.uri())),
+            concurrency: 5,
+            ..BackendConfig::default()
+        };
+        let rows = claim_outbox_batch(&pool, DbKind::Sqlite, Utc::now(), 10)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 5);
+
+        let started = std::time::Instant::now();
+        dispatch_rows(&Client::new(), &backend, &pool, DbKind::Sqlite, rows).await;
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed < std::time::Duration::from_millis(1200),
+            "{elapsed:?}"
+        );
+
+        let failed: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT payload, retry_count FROM inbound_outbox WHERE status <> 'delivered'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].0.contains("in-bad"));
+        assert_eq!(failed[0].1, 1);
+        let mut statuses = outbox_statuses(&pool).await;
+        statuses.sort();
+        assert_eq!(statuses.iter().filter(|s| *s == "delivered").count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_row_sends_extra_headers() {
+        use wiremock::matchers::header;
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/inbound"))
+            .and(header("X-Agent-Ping-Token", "backend-token"))
+            .and(header("X-Api-Key", "gateway-key"))
+            .and(header("X-Tenant-Id", "acme"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let pool = test_pool().await;
+        let row = crate::db::insert_outbox(
+            &pool,
+            DbKind::Sqlite,
+            serde_json::json!({"inbound_id": "in-1"}),
+            Utc::now(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let backend = BackendConfig {
+            webhook_url: Some(format!("{}/inbound", server.uri())),
+            api_token: Some("backend-token".to_string()),
+            extra_headers: [("X-Api-Key", "gateway-key"), ("X-Tenant-Id", "acme")]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..BackendConfig::default()
+        };
+        dispatch_row(&Client::new(), &backend, &pool, DbKind::Sqlite, &row)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ack_during_dispatch_is_not_overwritten() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ok"))
+            .respond_with(
+                ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/down"))
+            .respond_with(
+                ResponseTemplate::new(503).set_delay(std::time::Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+        let pool = test_pool().await;
+        for (id, route) in [("in-1", "ok"), ("in-2", "down")] {
+            crate::db::insert_outbox(
+                &pool,
+                DbKind::Sqlite,
+                serde_json::json!({"inbound_id": id}),
+                Utc::now(),
+                Some(&format!("{}/{route}", server.uri())),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let rows = claim_outbox_batch(&pool, DbKind::Sqlite, Utc::now(), 10)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        let backend = BackendConfig::default();
+
+        let dispatch = {
+            let pool = pool.clone();
+            let backend = backend.clone();
+            let rows = rows.clone();
+            tokio::spawn(async move {
+                dispatch_rows(&Client::new(), &backend, &pool, DbKind::Sqlite, rows).await;
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let acks = rows.iter().map(|row| {
+            let pool = pool.clone();
+            let row = row.clone();
+            async move {
+                if row.payload["inbound_id"] == "in-1" {
+                    let (retry, next) = next_failure(&row);
+                    settle_outbox_failed(
+                        &pool,
+                        DbKind::Sqlite,
+                        &row.id,
+                        &["pending", "sending"],
+                        retry,
+                        next,
+                        "backend ack: rejected",
+                    )
+                    .await
+                } else {
+                    settle_outbox_delivered(&pool, DbKind::Sqlite, &row.id, &["pending", "sending"])
+                        .await
+                }
+            }
+        });
+        for acked in futures::future::join_all(acks).await {
+            assert!(acked.unwrap());
+        }
+        dispatch.await.unwrap();
+
+        let status = |inbound_id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, String>(
+                    "SELECT status FROM inbound_outbox WHERE payload LIKE '%' || ? || '%'",
+                )
+                .bind(inbound_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+            }
+        };
+        assert_eq!(status("in-1").await, "failed");
+        assert_eq!(status("in-2").await, "delivered");
+    }
+
+    #[tokio::test]
+    async fn test_rows_post_to_their_own_webhook_url() {
+        let server = MockServer::start().await;
+        for route in ["/inbound", "/tenant-a"] {
+            Mock::given(method("POST"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let pool = test_pool().await;
+        let tenant_url = format!("{}/tenant-a", server.uri());
+        for (id, url) in [("in-1", None), ("in-2", Some(tenant_url.as_str()))] {
+            crate::db::insert_outbox(
+                &pool,
+                DbKind::Sqlite,
+                serde_json::json!({"inbound_id": id}),
+                Utc::now(),
+                url,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let backend = BackendConfig {
+            webhook_url: Some(format!("{}/inbound", server.uri())),
+            ..BackendConfig::default()
+        };
+        let rows = claim_outbox_batch(&pool, DbKind::Sqlite, Utc::now(), 10)
+            .await
+            .unwrap();
+        let groups = group_by_webhook(rows.clone());
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|group| group.len() == 1));
+
+        dispatch_rows(&Client::new(), &backend, &pool, DbKind::Sqlite, rows).await;
+        assert_eq!(outbox_statuses(&pool).await, vec!["delivered"; 2]);
+    }
 }
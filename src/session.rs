@@ -1,3 +1,4 @@
+use crate::channels::whatsapp::normalize_phone_number;
 use crate::config::SessionConfig;
 
 pub fn normalize_token(value: &str) -> String {
@@ -9,6 +10,16 @@ pub fn resolve_identity_link(
     channel: &str,
     peer_id: &str,
 ) -> Option<String> {
+    match_identity_link(links, channel, peer_id).map(normalize_token)
+}
+
+/// Finds the canonical key whose linked ids include `peer_id`, returning it as
+/// configured. Candidates are compared trimmed and lowercased.
+pub fn match_identity_link<'a>(
+    links: &'a std::collections::HashMap<String, Vec<String>>,
+    channel: &str,
+    peer_id: &str,
+) -> Option<&'a str> {
     let peer_norm = normalize_token(peer_id);
     if peer_norm.is_empty() {
         return None;
@@ -21,15 +32,42 @@ pub fn resolve_identity_link(
     };
     for (canonical, values) in links {
         for value in values {
-            let v = normalize_token(value);
+            let mut v = normalize_token(value);
+            if channel_norm == "whatsapp" {
+                if let Some(phone) = v.strip_prefix("whatsapp:") {
+                    v = format!("whatsapp:{}", normalize_phone_number(phone));
+                } else if is_phone_like(&v) {
+                    v = normalize_phone_number(&v);
+                }
+            }
             if v == peer_norm || v == scoped {
-                return Some(normalize_token(canonical));
+                return Some(canonical.as_str());
             }
         }
     }
     None
 }
 
+/// Whether an unscoped link value is a phone number, written with digits and
+/// optional `+`, spaces, dashes, dots or parentheses.
+fn is_phone_like(value: &str) -> bool {
+    value.chars().any(|c| c.is_ascii_digit())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '.' | '(' | ')'))
+}
+
+/// The canonical identity used in place of `peer_id` in a DM session key.
+/// Canonical keys are lowercased unless `session.preserve_canonical_case` is set.
+fn linked_peer(cfg: &SessionConfig, channel: &str, peer_id: &str) -> Option<String> {
+    let canonical = match_identity_link(&cfg.identity_links, channel, peer_id)?;
+    Some(if cfg.preserve_canonical_case {
+        canonical.trim().to_string()
+    } else {
+        normalize_token(canonical)
+    })
+}
+
 pub fn build_session_key(
     cfg: &SessionConfig,
     agent_id_override: Option<&str>,
@@ -45,34 +83,73 @@ pub fn build_session_key(
         .unwrap_or_else(|| cfg.agent_id.trim().to_lowercase());
     let main_key = cfg.main_key.trim().to_lowercase();
     let channel = normalize_token(channel);
-    let peer_id = normalize_token(peer_id);
+    let peer_id = if cfg.case_sensitive {
+        peer_id.trim().to_string()
+    } else {
+        normalize_token(peer_id)
+    };
     let account_id = account_id
         .map(normalize_token)
         .unwrap_or_else(|| "default".to_string());
     let dm_scope = cfg.dm_scope.as_str();
 
-    if peer_kind == "dm" {
+    if let Some(template) = cfg
+        .key_template
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    {
         let mut key_peer = peer_id.clone();
-        if dm_scope != "main" && !cfg.identity_links.is_empty() {
-            if let Some(canonical) = resolve_identity_link(&cfg.identity_links, &channel, &peer_id)
-            {
+        if peer_kind == "dm" && !cfg.identity_links.is_empty() {
+            if let Some(canonical) = linked_peer(cfg, &channel, &peer_id) {
                 key_peer = canonical;
             }
         }
-        return match dm_scope {
-            "per-peer" => format!("agent:{}:dm:{}", agent_id, key_peer),
-            "per-channel-peer" => format!("agent:{}:{}:dm:{}", agent_id, channel, key_peer),
-            "per-account-channel-peer" => {
-                format!(
-                    "agent:{}:{}:{}:dm:{}",
-                    agent_id, channel, account_id, key_peer
-                )
-            }
-            _ => format!("agent:{}:{}", agent_id, main_key),
-        };
+        let thread = thread_id.map(normalize_token).unwrap_or_default();
+        return render_key_template(
+            template,
+            &[
+                ("agent", agent_id.as_str()),
+                ("channel", channel.as_str()),
+                ("account", account_id.as_str()),
+                ("peer", key_peer.as_str()),
+                ("thread", thread.as_str()),
+                ("peer_kind", normalize_token(peer_kind).as_str()),
+            ],
+        );
     }
 
-    let mut base = format!("agent:{}:{}:{}:{}", agent_id, channel, peer_kind, peer_id);
+    let scope = cfg
+        .scope_by_kind
+        .get(peer_kind)
+        .map(String::as_str)
+        .or((peer_kind == "dm").then_some(dm_scope));
+    let mut base = match scope {
+        Some("main") => return format!("agent:{}:{}", agent_id, main_key),
+        Some(scope) => {
+            let mut key_peer = peer_id.clone();
+            if peer_kind == "dm" && !cfg.identity_links.is_empty() {
+                if let Some(canonical) = linked_peer(cfg, &channel, &peer_id) {
+                    key_peer = canonical;
+                }
+            }
+            match scope {
+                "per-peer" => format!("agent:{}:{}:{}", agent_id, peer_kind, key_peer),
+                "per-channel-peer" => {
+                    format!("agent:{}:{}:{}:{}", agent_id, channel, peer_kind, key_peer)
+                }
+                "per-account-channel-peer" => format!(
+                    "agent:{}:{}:{}:{}:{}",
+                    agent_id, channel, account_id, peer_kind, key_peer
+                ),
+                _ => return format!("agent:{}:{}", agent_id, main_key),
+            }
+        }
+        None => format!("agent:{}:{}:{}:{}", agent_id, channel, peer_kind, peer_id),
+    };
+    if peer_kind == "dm" {
+        return base;
+    }
     if let Some(thread) = thread_id {
         let thread = normalize_token(thread);
         if !thread.is_empty() {
@@ -82,6 +159,30 @@ pub fn build_session_key(
     base
 }
 
+/// Replaces each `{name}` in `template` with its value in one pass over the
+/// template, so placeholders inside substituted values stay literal. Unknown
+/// names are kept as written.
+pub fn render_key_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &after[..end];
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,6 +230,7 @@ mod tests {
             dm_scope: "per-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "slack", None, "dm", "U123", None);
         assert_eq!(key, "agent:myagent:dm:u123");
@@ -141,6 +243,7 @@ mod tests {
             dm_scope: "per-channel-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "telegram", None, "dm", "tg456", None);
         assert_eq!(key, "agent:myagent:telegram:dm:tg456");
@@ -153,6 +256,7 @@ mod tests {
             dm_scope: "per-account-channel-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "whatsapp", Some("biz123"), "dm", "wa789", None);
         assert_eq!(key, "agent:myagent:whatsapp:biz123:dm:wa789");
@@ -165,6 +269,7 @@ mod tests {
             dm_scope: "main".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "slack", None, "dm", "U123", None);
         assert_eq!(key, "agent:myagent:main");
@@ -177,6 +282,7 @@ mod tests {
             dm_scope: "per-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "slack", Some("C123"), "thread", "U456", Some("ts789"));
         assert_eq!(key, "agent:myagent:slack:thread:u456:thread:ts789");
@@ -189,6 +295,7 @@ mod tests {
             dm_scope: "per-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "slack", None, "thread", "U456", Some("   "));
         assert_eq!(key, "agent:myagent:slack:thread:u456");
@@ -201,6 +308,7 @@ mod tests {
             dm_scope: "per-peer".to_string(),
             main_key: "Main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, None, "  Slack  ", Some("  C123  "), "dm", "  U456  ", None);
         assert_eq!(key, "agent:myagent:dm:u456");
@@ -213,8 +321,23 @@ mod tests {
             dm_scope: "per-peer".to_string(),
             main_key: "main".to_string(),
             identity_links: HashMap::new(),
+            ..SessionConfig::default()
         };
         let key = build_session_key(&cfg, Some("finance_main"), "slack", None, "dm", "U123", None);
         assert_eq!(key, "agent:finance_main:dm:u123");
     }
+
+    #[test]
+    fn test_build_session_key_template_uses_identity_link() {
+        let mut links = HashMap::new();
+        links.insert("Owner".to_string(), vec!["telegram:tg1".to_string()]);
+        let cfg = SessionConfig {
+            agent_id: "myagent".to_string(),
+            identity_links: links,
+            key_template: Some("{agent}:{peer}".to_string()),
+            ..SessionConfig::default()
+        };
+        let key = build_session_key(&cfg, None, "telegram", None, "dm", "TG1", None);
+        assert_eq!(key, "myagent:owner");
+    }
 }
@@ -1,3 +1,4 @@
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +25,50 @@ pub struct InboundMessage {
     pub timestamp: Option<String>,
 }
 
+impl InboundMessage {
+    pub fn sent_at(&self) -> Option<DateTime<Utc>> {
+        parse_provider_timestamp(self.timestamp.as_deref()?)
+    }
+}
+
+pub fn parse_provider_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    let number = value
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n > 0.0)?;
+    let millis = if number >= 1e11 {
+        number
+    } else {
+        number * 1000.0
+    };
+    Utc.timestamp_millis_opt(millis as i64).single()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundReaction {
+    pub channel: String,
+    pub account_id: Option<String>,
+    pub peer_id: String,
+    pub message_id: String,
+    pub user_id: Option<String>,
+    pub reaction: String,
+    pub added: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundEdit {
+    pub channel: String,
+    pub account_id: Option<String>,
+    pub peer_id: String,
+    pub message_id: String,
+    pub text: Option<String>,
+    pub deleted: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutboundMessage {
     pub session_key: String,
@@ -33,6 +78,27 @@ pub struct OutboundMessage {
     pub account_id: Option<String>,
     pub peer_id: Option<String>,
     pub reply_to: Option<String>,
+    #[serde(default)]
+    pub caption_mode: bool,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    /// Kind of a first-contact peer (`dm` when unset).
+    #[serde(default)]
+    pub peer_kind: Option<String>,
+    /// Thread to post into, overriding the session's last thread.
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Display name for this message (Slack `username`); ignored elsewhere.
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    /// Emoji (`:robot_face:`) or image URL for this message's avatar (Slack
+    /// `icon_emoji`/`icon_url`); ignored elsewhere.
+    #[serde(default)]
+    pub sender_icon: Option<String>,
+    /// Also post a threaded Slack reply to the channel; ignored elsewhere and
+    /// for sends outside a thread.
+    #[serde(default)]
+    pub reply_broadcast: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,4 +107,6 @@ pub struct RouteInfo {
     pub account_id: Option<String>,
     pub peer_id: Option<String>,
     pub thread_id: Option<String>,
+    #[serde(default)]
+    pub peer_kind: Option<String>,
 }
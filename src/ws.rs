@@ -1,7 +1,14 @@
-use axum::extract::ws::{Message, WebSocket};
+use crate::config::AuthConfig;
+use crate::error::{ApiError, SendError};
+use crate::types::{Attachment, OutboundMessage};
+use crate::AppState;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, mpsc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WsEvent {
@@ -18,15 +25,143 @@ pub enum WsCommand {
     Subscribe { events: Option<Vec<String>> },
     #[serde(rename = "ping")]
     Ping,
+    /// Sends through the same pipeline as `POST /v1/messages/send`.
+    #[serde(rename = "send")]
+    Send {
+        session_key: String,
+        text: Option<String>,
+        #[serde(default)]
+        attachments: Option<Vec<Attachment>>,
+        #[serde(default)]
+        channel: Option<String>,
+        #[serde(default)]
+        peer_id: Option<String>,
+    },
+}
+
+/// Token bucket for client commands: `burst` tokens, refilled at `rate` per
+/// second. A rate of 0 never runs dry.
+#[derive(Debug, Clone)]
+pub struct CommandBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl CommandBucket {
+    pub fn new(rate: u32, burst: u32) -> CommandBucket {
+        CommandBucket {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    pub fn try_take_at(&mut self, now: Instant) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+pub struct PresenceGuard {
+    clients: Arc<AtomicUsize>,
+    tx: broadcast::Sender<WsEvent>,
+}
+
+impl PresenceGuard {
+    pub fn join(clients: Arc<AtomicUsize>, tx: broadcast::Sender<WsEvent>) -> PresenceGuard {
+        let count = clients.fetch_add(1, Ordering::SeqCst) + 1;
+        broadcast_presence(&tx, "connected", count);
+        PresenceGuard { clients, tx }
+    }
+}
+
+impl Drop for PresenceGuard {
+    fn drop(&mut self) {
+        let count = self
+            .clients
+            .fetch_sub(1, Ordering::SeqCst)
+            .saturating_sub(1);
+        broadcast_presence(&self.tx, "disconnected", count);
+    }
+}
+
+fn broadcast_presence(tx: &broadcast::Sender<WsEvent>, status: &str, clients: usize) {
+    let _ = tx.send(WsEvent {
+        event: "presence".to_string(),
+        payload: serde_json::json!({"status": status, "clients": clients}),
+    });
+}
+
+/// Runs a `Send` command, answering with a `send` event that carries the
+/// message id or the same error envelope the HTTP API returns.
+async fn send_command(
+    state: &AppState,
+    session_key: String,
+    text: Option<String>,
+    attachments: Option<Vec<Attachment>>,
+    channel: Option<String>,
+    peer_id: Option<String>,
+) -> WsEvent {
+    let outbound = OutboundMessage {
+        session_key,
+        text,
+        attachments: attachments.unwrap_or_default(),
+        channel,
+        account_id: None,
+        peer_id,
+        reply_to: None,
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
+    };
+    let config = state.config();
+    let payload = match crate::handle_outbound(state.clone(), &config, outbound).await {
+        Ok(message_id) => serde_json::json!({"status": "sent", "message_id": message_id}),
+        Err(err @ SendError::Queued { .. }) => err.to_json(),
+        Err(err) => {
+            let mut body = ApiError::from(err).to_json(None);
+            body["status"] = serde_json::json!("failed");
+            body
+        }
+    };
+    WsEvent {
+        event: "send".to_string(),
+        payload,
+    }
 }
 
 pub async fn handle_ws(
     mut socket: WebSocket,
+    state: AppState,
     mut rx: broadcast::Receiver<WsEvent>,
-    auth_token: Option<String>,
+    auth: AuthConfig,
+    mut bucket: CommandBucket,
 ) {
-    let mut authorized = auth_token.is_none();
+    let mut authorized = !auth.is_enabled();
     let mut subscriptions: Option<HashSet<String>> = None;
+    // Sends run in their own tasks so a slow platform call never holds up
+    // event delivery; their results come back through this channel.
+    let (reply_tx, mut reply_rx) = mpsc::unbounded_channel::<WsEvent>();
 
     loop {
         tokio::select! {
@@ -34,18 +169,26 @@ pub async fn handle_ws(
                 if msg.is_none() {
                     break;
                 }
-                if let Some(Ok(Message::Close(_))) = msg {
+                if let Some(Ok(Message::Close(_)) | Err(_)) = msg {
                     break;
                 }
+                if let Some(Ok(Message::Text(_) | Message::Binary(_))) = msg {
+                    if !bucket.try_take() {
+                        let frame = CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "command rate limit exceeded".into(),
+                        };
+                        let _ = socket.send(Message::Close(Some(frame))).await;
+                        break;
+                    }
+                }
                 if let Some(Ok(Message::Text(text))) = msg {
                     if let Ok(cmd) = serde_json::from_str::<WsCommand>(&text) {
                         match cmd {
                             WsCommand::Connect { token } => {
-                                if let Some(expected) = auth_token.as_ref() {
-                                    if token.as_deref() != Some(expected.as_str()) {
-                                        let _ = socket.send(Message::Close(None)).await;
-                                        break;
-                                    }
+                                if !auth.accepts(token.as_deref()) {
+                                    let _ = socket.send(Message::Close(None)).await;
+                                    break;
                                 }
                                 authorized = true;
                                 let ack = WsEvent {
@@ -64,10 +207,47 @@ pub async fn handle_ws(
                                 };
                                 let _ = socket.send(Message::Text(serde_json::to_string(&health).unwrap_or_default())).await;
                             }
+                            WsCommand::Send { session_key, text, attachments, channel, peer_id } => {
+                                if authorized {
+                                    let state = state.clone();
+                                    let reply_tx = reply_tx.clone();
+                                    tokio::spawn(async move {
+                                        let result = send_command(
+                                            &state,
+                                            session_key,
+                                            text,
+                                            attachments,
+                                            channel,
+                                            peer_id,
+                                        )
+                                        .await;
+                                        let _ = reply_tx.send(result);
+                                    });
+                                } else {
+                                    let result = WsEvent {
+                                        event: "send".to_string(),
+                                        payload: serde_json::json!({
+                                            "status": "failed",
+                                            "error": {
+                                                "code": "unauthorized",
+                                                "message": "connect with a valid token before sending",
+                                            },
+                                        }),
+                                    };
+                                    let text = serde_json::to_string(&result).unwrap_or_default();
+                                    let _ = socket.send(Message::Text(text)).await;
+                                }
+                            }
                         }
                     }
                 }
             }
+            Some(result) = reply_rx.recv() => {
+                let text = serde_json::to_string(&result).unwrap_or_default();
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
             evt = rx.recv() => {
                 if let Ok(evt) = evt {
                     if !authorized {
@@ -93,6 +273,46 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_command_bucket_refills_at_rate() {
+        let start = Instant::now();
+        let mut bucket = CommandBucket::new(2, 3);
+        bucket.last = start;
+        assert!((0..3).all(|_| bucket.try_take_at(start)));
+        assert!(!bucket.try_take_at(start));
+        let later = start + std::time::Duration::from_millis(500);
+        assert!(bucket.try_take_at(later));
+        assert!(!bucket.try_take_at(later));
+        let much_later = later + std::time::Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.try_take_at(much_later)));
+        assert!(!bucket.try_take_at(much_later));
+
+        let mut unlimited = CommandBucket::new(0, 0);
+        assert!((0..1000).all(|_| unlimited.try_take_at(start)));
+    }
+
+    #[test]
+    fn test_ws_command_send_deserialize() {
+        let json = r#"{"type":"send","session_key":"s1","text":"hi","peer_id":"C1"}"#;
+        let cmd: WsCommand = serde_json::from_str(json).unwrap();
+        match cmd {
+            WsCommand::Send {
+                session_key,
+                text,
+                attachments,
+                channel,
+                peer_id,
+            } => {
+                assert_eq!(session_key, "s1");
+                assert_eq!(text.as_deref(), Some("hi"));
+                assert!(attachments.is_none());
+                assert!(channel.is_none());
+                assert_eq!(peer_id.as_deref(), Some("C1"));
+            }
+            _ => panic!("Expected Send command"),
+        }
+    }
+
     #[test]
     fn test_ws_event_serialize() {
         let event = WsEvent {
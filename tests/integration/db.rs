@@ -251,7 +251,7 @@ async fn test_insert_outbox() {
     });
     let next_attempt = Utc::now();
 
-    let record = db::insert_outbox(&pool, kind, payload.clone(), next_attempt).await.unwrap();
+    let record = db::insert_outbox(&pool, kind, payload.clone(), next_attempt, None, None).await.unwrap();
     assert!(!record.id.is_empty());
     assert_eq!(record.status, "pending");
     assert_eq!(record.retry_count, 0);
@@ -268,7 +268,7 @@ async fn test_claim_outbox_batch() {
 
     for i in 0..3 {
         let payload = json!({"index": i});
-        let _ = db::insert_outbox(&pool, kind, payload, past).await.unwrap();
+        let _ = db::insert_outbox(&pool, kind, payload, past, None, None).await.unwrap();
     }
 
     let claimed = db::claim_outbox_batch(&pool, kind, now, 2).await.unwrap();
@@ -289,7 +289,7 @@ async fn test_mark_outbox_delivered() {
     let (pool, kind) = create_test_pool(db_path.to_str().unwrap()).await;
 
     let payload = json!({"test": true});
-    let record = db::insert_outbox(&pool, kind, payload, Utc::now()).await.unwrap();
+    let record = db::insert_outbox(&pool, kind, payload, Utc::now(), None, None).await.unwrap();
 
     db::mark_outbox_delivered(&pool, kind, &record.id).await.unwrap();
 
@@ -304,7 +304,7 @@ async fn test_mark_outbox_failed() {
     let (pool, kind) = create_test_pool(db_path.to_str().unwrap()).await;
 
     let payload = json!({"test": true});
-    let record = db::insert_outbox(&pool, kind, payload, Utc::now()).await.unwrap();
+    let record = db::insert_outbox(&pool, kind, payload, Utc::now(), None, None).await.unwrap();
 
     let next_attempt = Utc::now() + chrono::Duration::hours(1);
     db::mark_outbox_failed(&pool, kind, &record.id, 1, next_attempt, "Connection refused")
@@ -330,13 +330,13 @@ async fn test_outbox_claim_with_mixed_status() {
     let now = Utc::now();
     let past = now - chrono::Duration::hours(1);
 
-    let pending = db::insert_outbox(&pool, kind, json!({"status": "pending"}), past)
+    let pending = db::insert_outbox(&pool, kind, json!({"status": "pending"}), past, None, None)
         .await
         .unwrap();
-    let _ = db::insert_outbox(&pool, kind, json!({"status": "delivered"}), past)
+    let _ = db::insert_outbox(&pool, kind, json!({"status": "delivered"}), past, None, None)
         .await
         .unwrap();
-    let failed = db::insert_outbox(&pool, kind, json!({"status": "failed"}), past)
+    let failed = db::insert_outbox(&pool, kind, json!({"status": "failed"}), past, None, None)
         .await
         .unwrap();
 
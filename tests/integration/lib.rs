@@ -1,6 +1,6 @@
 use agent_ping::config::{
-    BackendConfig, Binding, ChannelsConfig, Config, QueueConfig, ServerConfig, SessionConfig,
-    TelegramConfig, WhatsAppConfig,
+    BackendConfig, Binding, ChannelsConfig, Config, ForwardFilter, QueueConfig, ServerConfig,
+    SessionConfig, TelegramConfig, WhatsAppConfig,
 };
 use agent_ping::db::DbKind;
 use agent_ping::types::{Attachment, InboundMessage, OutboundMessage, RouteInfo};
@@ -50,6 +50,13 @@ fn test_outbound_message_structure() {
         account_id: Some("ACC123".to_string()),
         peer_id: Some("U456".to_string()),
         reply_to: Some("MSG789".to_string()),
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     assert_eq!(msg.session_key, "agent:test:default");
@@ -162,6 +169,11 @@ fn test_config_server_listen() {
         server: ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 3000,
+            trusted_proxies: Vec::new(),
+            compression: true,
+            base_path: String::new(),
+            max_body_bytes: 2 * 1024 * 1024,
+            status_requires_auth: false,
         },
         ..Config::default()
     };
@@ -284,6 +296,13 @@ fn test_outbound_message_basic() {
         account_id: Some("C123".to_string()),
         peer_id: Some("U456".to_string()),
         reply_to: None,
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     assert_eq!(outbound.session_key, "agent:test:default");
@@ -301,6 +320,13 @@ fn test_outbound_message_with_reply() {
         account_id: Some("C123".to_string()),
         peer_id: Some("U456".to_string()),
         reply_to: Some("original_msg_id".to_string()),
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     assert_eq!(outbound.reply_to, Some("original_msg_id".to_string()));
@@ -316,6 +342,13 @@ fn test_outbound_message_telegram() {
         account_id: None,
         peer_id: Some("123456789".to_string()),
         reply_to: None,
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     assert_eq!(outbound.channel, Some("telegram".to_string()));
@@ -329,6 +362,7 @@ fn test_route_info_slack() {
         account_id: Some("C123".to_string()),
         peer_id: Some("U456".to_string()),
         thread_id: Some("TS789".to_string()),
+        peer_kind: None,
     };
 
     assert_eq!(route.channel, "slack");
@@ -343,6 +377,7 @@ fn test_route_info_telegram() {
         account_id: None,
         peer_id: Some("123456789".to_string()),
         thread_id: None,
+        peer_kind: None,
     };
 
     assert_eq!(route.channel, "telegram");
@@ -357,6 +392,7 @@ fn test_route_info_whatsapp() {
         account_id: Some("business_123".to_string()),
         peer_id: Some("+1234567890".to_string()),
         thread_id: None,
+        peer_kind: None,
     };
 
     assert_eq!(route.channel, "whatsapp");
@@ -402,6 +438,7 @@ fn test_config_with_bindings() {
         business_profile_id: Some("bp_123".to_string()),
         user_id: None,
         agent_id: None,
+        ..Binding::default()
     }];
 
     let config = Config {
@@ -427,6 +464,7 @@ fn test_config_multiple_bindings() {
             business_profile_id: None,
             user_id: None,
             agent_id: Some("agent_1".to_string()),
+            ..Binding::default()
         },
         Binding {
             channel: "telegram".to_string(),
@@ -435,6 +473,7 @@ fn test_config_multiple_bindings() {
             business_profile_id: None,
             user_id: None,
             agent_id: None,
+            ..Binding::default()
         },
     ];
 
@@ -458,6 +497,14 @@ fn test_config_telegram_enabled() {
                 transport: "native".to_string(),
                 webhook_path: "/v1/channels/telegram/webhook".to_string(),
                 poll_interval_seconds: 5,
+                inbound_reactions: false,
+                allowed_ips: Vec::new(),
+                default_agent: None,
+                max_webhook_bytes: 256 * 1024,
+                max_concurrent_sends: 4,
+                debounce_ms: None,
+                transcribe_audio: false,
+                reply_quote: false,
             },
             ..ChannelsConfig::default()
         },
@@ -481,6 +528,15 @@ fn test_config_whatsapp_enabled() {
                 sidecar_url: "http://whatsapp:4040".to_string(),
                 transport: "native".to_string(),
                 inbound_path: "/v1/whatsapp".to_string(),
+                allowed_ips: Vec::new(),
+                default_agent: None,
+                max_webhook_bytes: 256 * 1024,
+                max_concurrent_sends: 4,
+                debounce_ms: None,
+                transcribe_audio: false,
+                split_attachments: false,
+                enforce_messaging_window: false,
+                reply_quote: false,
             },
             ..ChannelsConfig::default()
         },
@@ -499,6 +555,14 @@ fn test_config_backend_settings() {
             media_upload_url: Some("https://backend.example.com/upload".to_string()),
             route_resolve_url: None,
             api_token: Some("secret_token".to_string()),
+            extra_headers: HashMap::new(),
+            batch_size: 1,
+            batch_max_wait_ms: 0,
+            forward_filter: ForwardFilter::default(),
+            transcription_url: None,
+            concurrency: 1,
+            outbox_retention_hours: 0,
+            payload_template: None,
         },
         ..Config::default()
     };
@@ -547,6 +611,11 @@ fn test_config_queue_settings() {
             debounce_ms: 50,
             cap: 10,
             drop: "error".to_string(),
+            max_content_bytes: None,
+            on_oversize: "truncate".to_string(),
+            max_attachments: None,
+            sanitize_inbound: false,
+            collapse_whitespace: false,
         },
         ..Config::default()
     };
@@ -566,6 +635,13 @@ fn test_empty_outbound_message() {
         account_id: None,
         peer_id: None,
         reply_to: None,
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     assert!(outbound.text.is_none());
@@ -0,0 +1,182 @@
+use agent_ping::channels::{
+    capabilities, download_part, is_retryable, parse_retry_after, transport_error, ChannelError,
+};
+use agent_ping::error::SendError;
+use std::time::Duration;
+
+#[test]
+fn test_capabilities_known_channels() {
+    let slack = capabilities("slack").unwrap();
+    assert!(slack.send && slack.threads && slack.edits && slack.deletes && slack.reactions);
+
+    let telegram = capabilities("telegram").unwrap();
+    assert!(telegram.send && telegram.edits && telegram.deletes && telegram.reactions);
+    assert!(!telegram.threads);
+
+    let whatsapp = capabilities("whatsapp").unwrap();
+    assert!(whatsapp.send);
+    assert!(!whatsapp.edits && !whatsapp.deletes && !whatsapp.reactions);
+
+    let teams = capabilities("teams").unwrap();
+    assert!(!teams.send);
+}
+
+#[test]
+fn test_capabilities_unknown_channel() {
+    assert!(capabilities("irc").is_none());
+    assert!(capabilities("Slack").is_none());
+}
+
+#[test]
+fn test_capabilities_serialize_flat() {
+    let value = serde_json::to_value(capabilities("whatsapp").unwrap()).unwrap();
+    assert_eq!(value["send"], true);
+    assert_eq!(value["typing"], false);
+    assert_eq!(value["templates"], false);
+}
+
+#[test]
+fn test_parse_retry_after() {
+    assert_eq!(parse_retry_after(Some("7"), None), Duration::from_secs(7));
+    let telegram = serde_json::json!({"ok": false, "parameters": {"retry_after": 3}});
+    assert_eq!(
+        parse_retry_after(None, Some(&telegram)),
+        Duration::from_secs(3)
+    );
+    assert_eq!(
+        parse_retry_after(Some("5"), Some(&telegram)),
+        Duration::from_secs(5)
+    );
+    assert_eq!(
+        parse_retry_after(Some("Wed, 21 Oct 2015 07:28:00 GMT"), None),
+        Duration::from_secs(1)
+    );
+}
+
+#[test]
+fn test_is_retryable() {
+    let rejected = ChannelError::Rejected {
+        channel: "slack".to_string(),
+        code: "msg_too_long".to_string(),
+    };
+    assert!(!is_retryable(&rejected.into()));
+    let limited = ChannelError::RateLimited {
+        channel: "slack".to_string(),
+        retry_after: Duration::from_secs(3),
+    };
+    assert!(is_retryable(&limited.into()));
+    assert!(!is_retryable(&anyhow::anyhow!("slack token missing")));
+}
+
+#[tokio::test]
+async fn test_transport_error_drops_token_from_url() {
+    let token = "123456:SECRET-bot-token";
+    let err = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:1/bot{token}/sendMessage"))
+        .send()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains(token));
+
+    let err = transport_error("telegram", err);
+    assert!(is_retryable(&err));
+    assert!(matches!(
+        err.downcast_ref::<ChannelError>(),
+        Some(ChannelError::Transient { .. })
+    ));
+    let send = SendError::Other(err);
+    assert!(!send.to_string().contains(token));
+    assert!(!send.to_json().to_string().contains(token));
+}
+
+#[tokio::test]
+async fn test_download_part_streams_body_without_buffering() {
+    use axum::body::{Body, Bytes};
+    use futures_util::stream::{self, StreamExt};
+
+    let (finish_tx, finish_rx) = tokio::sync::oneshot::channel::<()>();
+    let finish_rx = std::sync::Arc::new(std::sync::Mutex::new(Some(finish_rx)));
+    let app = axum::Router::new().route(
+        "/file",
+        axum::routing::get(move || {
+            let finish_rx = finish_rx.lock().unwrap().take().unwrap();
+            async move {
+                let head = stream::once(async { Ok::<_, std::io::Error>(Bytes::from("head")) });
+                let tail = stream::once(async move {
+                    let _ = finish_rx.await;
+                    Ok::<_, std::io::Error>(Bytes::from("tail"))
+                });
+                Body::from_stream(head.chain(tail))
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    // The tail chunk is held back until the part exists, so this only
+    // returns if the body is streamed rather than read up front.
+    let request = reqwest::Client::new().get(format!("http://{addr}/file"));
+    let part = tokio::time::timeout(
+        Duration::from_secs(5),
+        download_part("slack", request, "clip.bin".to_string()),
+    )
+    .await
+    .expect("download_part waited for the whole body")
+    .unwrap();
+    finish_tx.send(()).unwrap();
+    drop(part);
+}
+
+#[tokio::test]
+async fn test_download_part_rejects_error_status() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/files/missing.bin"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("not found"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/files/missing.bin?token=secret", server.uri());
+    let err = download_part(
+        "slack",
+        reqwest::Client::new().get(&url),
+        "missing.bin".to_string(),
+    )
+    .await
+    .unwrap_err();
+    assert!(!is_retryable(&err));
+    let message = format!("{err:#}");
+    assert!(message.contains("404"), "{message}");
+    assert!(!message.contains("secret"), "{message}");
+}
+
+#[tokio::test]
+async fn test_download_part_server_error_is_transient() {
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+
+    let err = download_part(
+        "telegram",
+        reqwest::Client::new().get(server.uri()),
+        "f".to_string(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChannelError>(),
+        Some(ChannelError::Transient { .. })
+    ));
+}
@@ -1,4 +1,26 @@
-use agent_ping::db::{db_kind_from_url, rewrite_sql, DbKind};
+use agent_ping::config::DatabaseConfig;
+use agent_ping::db::{
+    claim_outbox_batch, connect, connection_setup_sql, db_kind_from_url, evict_sessions,
+    get_inbound_failure, get_message, get_outbound_queue, get_session, init_db,
+    insert_inbound_failure, insert_message, insert_outbound_queue, insert_outbox, list_messages,
+    maintain, maintenance_sql, message_histogram, pool_options, prune_outbox,
+    reclaim_stale_sending, rewrite_sql, set_message_provider_id, set_session_route, upsert_session,
+    DbKind, MessageRecord, SessionRecord,
+};
+use chrono::{Duration, TimeZone, Utc};
+use sqlx::any::AnyPoolOptions;
+use sqlx::{AnyPool, Row};
+
+async fn memory_pool() -> AnyPool {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    init_db(&pool, DbKind::Sqlite).await.unwrap();
+    pool
+}
 
 #[test]
 fn test_db_kind_from_url_sqlite() {
@@ -56,3 +78,648 @@ fn test_rewrite_sql_postgres_no_placeholders() {
     let rewritten = rewrite_sql(sql, DbKind::Postgres);
     assert_eq!(rewritten.as_ref(), sql);
 }
+
+#[tokio::test]
+async fn test_reclaim_stale_sending_makes_row_claimable() {
+    let pool = memory_pool().await;
+    let now = Utc::now();
+    let crashed_at = now - Duration::seconds(3600);
+    let row = insert_outbox(
+        &pool,
+        DbKind::Sqlite,
+        serde_json::json!({"n": 1}),
+        crashed_at,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let claimed = claim_outbox_batch(&pool, DbKind::Sqlite, crashed_at, 10)
+        .await
+        .unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert!(claim_outbox_batch(&pool, DbKind::Sqlite, now, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let reclaimed = reclaim_stale_sending(&pool, DbKind::Sqlite, now - Duration::seconds(300))
+        .await
+        .unwrap();
+    assert_eq!(reclaimed, 1);
+
+    let claimed = claim_outbox_batch(&pool, DbKind::Sqlite, now, 10)
+        .await
+        .unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].id, row.id);
+}
+
+#[tokio::test]
+async fn test_prune_outbox_drops_old_finished_rows() {
+    let pool = memory_pool().await;
+    let now = Utc::now();
+    let old = now - Duration::hours(48);
+    let mut ids = Vec::new();
+    for (status, retry_count, created_at) in [
+        ("delivered", 0, old),
+        ("pending", 0, old),
+        ("failed", 3, old),
+        ("failed", 10, old),
+        ("delivered", 0, now),
+    ] {
+        let row = insert_outbox(
+            &pool,
+            DbKind::Sqlite,
+            serde_json::json!({"n": 1}),
+            now,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let sql =
+            "UPDATE inbound_outbox SET status = ?, retry_count = ?, created_at = ? WHERE id = ?";
+        sqlx::query(sql)
+            .bind(status)
+            .bind(retry_count)
+            .bind(created_at.timestamp())
+            .bind(&row.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        ids.push(row.id);
+    }
+
+    let cutoff = now - Duration::hours(24);
+    assert_eq!(
+        prune_outbox(&pool, DbKind::Sqlite, cutoff, &[])
+            .await
+            .unwrap(),
+        0
+    );
+    let pruned = prune_outbox(&pool, DbKind::Sqlite, cutoff, &["delivered", "dead"])
+        .await
+        .unwrap();
+    assert_eq!(pruned, 2);
+
+    let rows = sqlx::query("SELECT id FROM inbound_outbox ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let mut remaining: Vec<String> = rows.iter().map(|row| row.get("id")).collect();
+    let mut expected = vec![ids[1].clone(), ids[2].clone(), ids[4].clone()];
+    remaining.sort();
+    expected.sort();
+    assert_eq!(remaining, expected);
+}
+
+#[tokio::test]
+async fn test_reclaim_stale_sending_skips_recent_claims() {
+    let pool = memory_pool().await;
+    let now = Utc::now();
+    insert_outbox(
+        &pool,
+        DbKind::Sqlite,
+        serde_json::json!({"n": 1}),
+        now,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        claim_outbox_batch(&pool, DbKind::Sqlite, now, 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let reclaimed = reclaim_stale_sending(&pool, DbKind::Sqlite, now - Duration::seconds(300))
+        .await
+        .unwrap();
+    assert_eq!(reclaimed, 0);
+}
+
+fn inbound_record(id: &str, dedupe_key: Option<&str>) -> MessageRecord {
+    MessageRecord {
+        id: id.to_string(),
+        session_key: "agent:main:slack:dm:u1".to_string(),
+        direction: "inbound".to_string(),
+        channel: "slack".to_string(),
+        account_id: None,
+        peer_id: Some("u1".to_string()),
+        content: Some("hi".to_string()),
+        attachments: None,
+        status: "received".to_string(),
+        dedupe_key: dedupe_key.map(|v| v.to_string()),
+        provider_message_id: None,
+        created_at: Utc::now(),
+        metadata: None,
+    }
+}
+
+#[tokio::test]
+async fn test_insert_message_concurrent_dedupe_keeps_one_row() {
+    let pool = memory_pool().await;
+    let first = inbound_record("m1", Some("slack:u1:ts1"));
+    let second = inbound_record("m2", Some("slack:u1:ts1"));
+
+    let (a, b) = tokio::join!(
+        insert_message(&pool, DbKind::Sqlite, &first),
+        insert_message(&pool, DbKind::Sqlite, &second)
+    );
+    let inserted = [a.unwrap(), b.unwrap()];
+    assert_eq!(inserted.iter().filter(|v| **v).count(), 1);
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM messages WHERE dedupe_key = ?")
+        .bind("slack:u1:ts1")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_init_db_makes_legacy_dedupe_index_unique_once() {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::query(
+        "CREATE TABLE messages (id TEXT PRIMARY KEY, session_key TEXT NOT NULL, direction TEXT NOT NULL,
+         channel TEXT NOT NULL, account_id TEXT, peer_id TEXT, content TEXT, attachments TEXT,
+         status TEXT NOT NULL, dedupe_key TEXT, provider_message_id TEXT, created_at INTEGER NOT NULL)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("CREATE INDEX idx_messages_dedupe ON messages(dedupe_key)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    for (id, created_at) in [("m1", 10), ("m2", 0), ("m3", 0)] {
+        sqlx::query(
+            "INSERT INTO messages (id, session_key, direction, channel, status, dedupe_key, created_at)
+             VALUES (?, 's', 'inbound', 'slack', 'received', 'slack:u1:ts1', ?)",
+        )
+        .bind(id)
+        .bind(created_at)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    init_db(&pool, DbKind::Sqlite).await.unwrap();
+    let keys: Vec<(String, Option<String>)> =
+        sqlx::query_as("SELECT id, dedupe_key FROM messages ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        keys,
+        vec![
+            ("m1".to_string(), None),
+            ("m2".to_string(), Some("slack:u1:ts1".to_string())),
+            ("m3".to_string(), None)
+        ]
+    );
+    assert!(!insert_message(
+        &pool,
+        DbKind::Sqlite,
+        &inbound_record("m4", Some("slack:u1:ts1"))
+    )
+    .await
+    .unwrap());
+
+    init_db(&pool, DbKind::Sqlite).await.unwrap();
+    let kept: i64 =
+        sqlx::query_scalar("SELECT COUNT(1) FROM messages WHERE dedupe_key IS NOT NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(kept, 1);
+}
+
+#[tokio::test]
+async fn test_insert_message_without_dedupe_key_not_deduped() {
+    let pool = memory_pool().await;
+    assert!(
+        insert_message(&pool, DbKind::Sqlite, &inbound_record("m1", None))
+            .await
+            .unwrap()
+    );
+    assert!(
+        insert_message(&pool, DbKind::Sqlite, &inbound_record("m2", None))
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_list_messages_after_returns_only_newer_ascending() {
+    let pool = memory_pool().await;
+    let t1 = Utc::now() - Duration::seconds(120);
+    let t2 = Utc::now() - Duration::seconds(60);
+    let t3 = Utc::now();
+    for (id, created_at) in [("m3", t3), ("m1", t1), ("m2", t2)] {
+        let mut record = inbound_record(id, None);
+        record.created_at = created_at;
+        insert_message(&pool, DbKind::Sqlite, &record)
+            .await
+            .unwrap();
+    }
+
+    let newer = list_messages(
+        &pool,
+        DbKind::Sqlite,
+        "agent:main:slack:dm:u1",
+        Some(t1),
+        50,
+        0,
+    )
+    .await
+    .unwrap();
+    let ids: Vec<&str> = newer.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["m2", "m3"]);
+
+    let all = list_messages(&pool, DbKind::Sqlite, "agent:main:slack:dm:u1", None, 50, 0)
+        .await
+        .unwrap();
+    let ids: Vec<&str> = all.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["m3", "m2", "m1"]);
+}
+
+#[tokio::test]
+async fn test_set_message_provider_id_roundtrip() {
+    let pool = memory_pool().await;
+    insert_message(&pool, DbKind::Sqlite, &inbound_record("m1", None))
+        .await
+        .unwrap();
+    set_message_provider_id(&pool, DbKind::Sqlite, "m1", "1700000000.000100")
+        .await
+        .unwrap();
+
+    let message = get_message(&pool, DbKind::Sqlite, "m1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        message.provider_message_id.as_deref(),
+        Some("1700000000.000100")
+    );
+    assert!(get_message(&pool, DbKind::Sqlite, "missing")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_init_db_adds_provider_message_id_to_existing_table() {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::query(
+        r#"CREATE TABLE messages (
+            id TEXT PRIMARY KEY,
+            session_key TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            channel TEXT NOT NULL,
+            account_id TEXT,
+            peer_id TEXT,
+            content TEXT,
+            attachments TEXT,
+            status TEXT NOT NULL,
+            dedupe_key TEXT,
+            created_at INTEGER NOT NULL
+        )"#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    init_db(&pool, DbKind::Sqlite).await.unwrap();
+    init_db(&pool, DbKind::Sqlite).await.unwrap();
+    insert_message(&pool, DbKind::Sqlite, &inbound_record("m1", None))
+        .await
+        .unwrap();
+    assert!(get_message(&pool, DbKind::Sqlite, "m1")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn test_connection_setup_sql() {
+    assert_eq!(connection_setup_sql(DbKind::Postgres, None), None);
+    assert_eq!(
+        connection_setup_sql(DbKind::Postgres, Some(250)).as_deref(),
+        Some("SET statement_timeout = 250")
+    );
+    assert_eq!(
+        connection_setup_sql(DbKind::Sqlite, Some(250)).as_deref(),
+        Some("PRAGMA busy_timeout = 250")
+    );
+}
+
+#[tokio::test]
+async fn test_connect_applies_sqlite_busy_timeout() {
+    sqlx::any::install_default_drivers();
+    let config = DatabaseConfig {
+        statement_timeout_ms: Some(1234),
+        ..DatabaseConfig::default()
+    };
+    let pool = connect("sqlite::memory:", &config).await.unwrap();
+    let row = sqlx::query("PRAGMA busy_timeout")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let timeout: i64 = row.try_get(0).unwrap();
+    assert_eq!(timeout, 1234);
+}
+
+#[test]
+fn test_pool_options_follow_database_config() {
+    let options = pool_options(&DatabaseConfig::default());
+    assert_eq!(
+        options.get_idle_timeout(),
+        Some(std::time::Duration::from_secs(300))
+    );
+    assert_eq!(
+        options.get_max_lifetime(),
+        Some(std::time::Duration::from_secs(1800))
+    );
+    assert!(options.get_test_before_acquire());
+
+    let config = DatabaseConfig {
+        idle_timeout_secs: 45,
+        max_lifetime_secs: 0,
+        test_before_acquire: false,
+        ..DatabaseConfig::default()
+    };
+    let options = pool_options(&config);
+    assert_eq!(
+        options.get_idle_timeout(),
+        Some(std::time::Duration::from_secs(45))
+    );
+    assert_eq!(options.get_max_lifetime(), None);
+    assert!(!options.get_test_before_acquire());
+}
+
+#[tokio::test]
+async fn test_connect_postgres_statement_timeout_aborts_slow_query() {
+    let Ok(url) = std::env::var("AGENT_PING_TEST_POSTGRES_URL") else {
+        return;
+    };
+    sqlx::any::install_default_drivers();
+    let config = DatabaseConfig {
+        statement_timeout_ms: Some(200),
+        ..DatabaseConfig::default()
+    };
+    let pool = connect(&url, &config).await.unwrap();
+    let started = std::time::Instant::now();
+    let result = sqlx::query("SELECT pg_sleep(5)").execute(&pool).await;
+    assert!(result.is_err());
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_postgres_sessions_filter_by_jsonb_route() {
+    let Ok(url) = std::env::var("AGENT_PING_TEST_POSTGRES_URL") else {
+        return;
+    };
+    sqlx::any::install_default_drivers();
+    let pool = connect(&url, &DatabaseConfig::default()).await.unwrap();
+    init_db(&pool, DbKind::Postgres).await.unwrap();
+    let prefix = format!("jsonb-{}", Utc::now().timestamp_nanos_opt().unwrap());
+    for (peer, channel) in [("a", "slack"), ("b", "telegram")] {
+        let record = SessionRecord {
+            session_key: format!("{prefix}:{peer}"),
+            agent_id: "main".to_string(),
+            business_profile_id: None,
+            user_id: None,
+            last_route: Some(serde_json::json!({"channel": channel, "peer_id": peer})),
+            dm_scope: "per-peer".to_string(),
+            identity_links: Some(serde_json::json!({"canonical": [format!("{channel}:{peer}")]})),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            metadata: None,
+        };
+        upsert_session(&pool, DbKind::Postgres, &record)
+            .await
+            .unwrap();
+    }
+
+    let routed_to = |channel: &'static str| {
+        let pool = pool.clone();
+        let pattern = format!("{prefix}:%");
+        async move {
+            sqlx::query_scalar::<_, String>(
+                "SELECT session_key FROM sessions
+                 WHERE last_route->>'channel' = $1 AND session_key LIKE $2 ORDER BY session_key",
+            )
+            .bind(channel)
+            .bind(pattern)
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    assert_eq!(routed_to("slack").await, vec![format!("{prefix}:a")]);
+
+    let key = format!("{prefix}:b");
+    let route = serde_json::json!({"channel": "slack", "peer_id": "b2"});
+    assert!(
+        set_session_route(&pool, DbKind::Postgres, &key, &route, Utc::now())
+            .await
+            .unwrap()
+    );
+    assert_eq!(routed_to("slack").await.len(), 2);
+    assert!(routed_to("telegram").await.is_empty());
+
+    let session = get_session(&pool, DbKind::Postgres, &key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.last_route, Some(route));
+    assert_eq!(
+        session.identity_links.unwrap()["canonical"][0],
+        "telegram:b"
+    );
+}
+
+#[test]
+fn test_maintenance_sql() {
+    assert_eq!(maintenance_sql(DbKind::Postgres), ["VACUUM (ANALYZE)"]);
+    assert!(maintenance_sql(DbKind::Sqlite).contains(&"VACUUM"));
+    assert!(maintenance_sql(DbKind::Sqlite).contains(&"ANALYZE"));
+}
+
+#[tokio::test]
+async fn test_evict_sessions_over_cap_removes_oldest_updated() {
+    let pool = memory_pool().await;
+    let base = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    for (peer, minutes) in [("u1", 30), ("u2", 10), ("u3", 20)] {
+        let session_key = format!("agent:main:slack:dm:{peer}");
+        let record = SessionRecord {
+            session_key: session_key.clone(),
+            agent_id: "main".to_string(),
+            business_profile_id: None,
+            user_id: None,
+            last_route: None,
+            dm_scope: "per-peer".to_string(),
+            identity_links: None,
+            created_at: base,
+            updated_at: base + Duration::minutes(minutes),
+            metadata: None,
+        };
+        upsert_session(&pool, DbKind::Sqlite, &record)
+            .await
+            .unwrap();
+        let mut message = inbound_record(&format!("m-{peer}"), None);
+        message.session_key = session_key;
+        insert_message(&pool, DbKind::Sqlite, &message)
+            .await
+            .unwrap();
+    }
+    let payload = serde_json::json!({"n": 1});
+    insert_outbound_queue(&pool, DbKind::Sqlite, "m-u2", payload.clone(), None, base)
+        .await
+        .unwrap();
+    let failure = insert_inbound_failure(
+        &pool,
+        DbKind::Sqlite,
+        payload,
+        Some("agent:main:slack:dm:u2"),
+        "boom",
+        base,
+    )
+    .await
+    .unwrap();
+
+    let evicted = evict_sessions(&pool, DbKind::Sqlite, 0, None)
+        .await
+        .unwrap();
+    assert!(evicted.is_empty());
+    let evicted = evict_sessions(&pool, DbKind::Sqlite, 3, None)
+        .await
+        .unwrap();
+    assert!(evicted.is_empty());
+
+    let evicted = evict_sessions(&pool, DbKind::Sqlite, 2, None)
+        .await
+        .unwrap();
+    assert_eq!(evicted, vec!["agent:main:slack:dm:u2".to_string()]);
+    assert!(get_session(&pool, DbKind::Sqlite, "agent:main:slack:dm:u2")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(get_message(&pool, DbKind::Sqlite, "m-u2")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(get_message(&pool, DbKind::Sqlite, "m-u1")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(get_outbound_queue(&pool, DbKind::Sqlite, "m-u2")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(get_inbound_failure(&pool, DbKind::Sqlite, &failure.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    let idle_before = base + Duration::minutes(25);
+    let evicted = evict_sessions(&pool, DbKind::Sqlite, 0, Some(idle_before))
+        .await
+        .unwrap();
+    assert_eq!(evicted, vec!["agent:main:slack:dm:u3".to_string()]);
+    assert!(get_session(&pool, DbKind::Sqlite, "agent:main:slack:dm:u1")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn test_maintain_populated_sqlite() {
+    let pool = memory_pool().await;
+    for n in 0..50 {
+        insert_message(
+            &pool,
+            DbKind::Sqlite,
+            &inbound_record(&format!("m{n}"), None),
+        )
+        .await
+        .unwrap();
+    }
+    sqlx::query("DELETE FROM messages WHERE id LIKE 'm1%'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    maintain(&pool, DbKind::Sqlite).await.unwrap();
+
+    let count: i64 = sqlx::query("SELECT COUNT(*) AS n FROM messages")
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .try_get("n")
+        .unwrap();
+    assert_eq!(count, 39);
+}
+
+#[tokio::test]
+async fn test_message_histogram_groups_by_bucket_channel_and_direction() {
+    let pool = memory_pool().await;
+    let base = Utc
+        .timestamp_opt(1_700_000_000 - 1_700_000_000 % 3600, 0)
+        .unwrap();
+    let seeds = [
+        ("m1", 10, "slack", "inbound"),
+        ("m2", 20, "slack", "inbound"),
+        ("m3", 30, "slack", "outbound"),
+        ("m4", 3_610, "telegram", "inbound"),
+        ("m5", -10, "slack", "inbound"),
+    ];
+    for (id, offset, channel, direction) in seeds {
+        let mut record = inbound_record(id, None);
+        record.created_at = base + Duration::seconds(offset);
+        record.channel = channel.to_string();
+        record.direction = direction.to_string();
+        insert_message(&pool, DbKind::Sqlite, &record)
+            .await
+            .unwrap();
+    }
+
+    let rows = message_histogram(&pool, DbKind::Sqlite, base, 3600)
+        .await
+        .unwrap();
+    let series: Vec<(i64, &str, &str, i64)> = rows
+        .iter()
+        .map(|row| {
+            (
+                row.bucket_start.timestamp() - base.timestamp(),
+                row.channel.as_str(),
+                row.direction.as_str(),
+                row.count,
+            )
+        })
+        .collect();
+    assert_eq!(
+        series,
+        vec![
+            (0, "slack", "inbound", 2),
+            (0, "slack", "outbound", 1),
+            (3600, "telegram", "inbound", 1),
+        ]
+    );
+}
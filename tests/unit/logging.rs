@@ -0,0 +1,26 @@
+use agent_ping::logging::{build_subscriber, LogFormat};
+
+#[test]
+fn test_log_format_parse() {
+    assert_eq!(LogFormat::parse("json"), Some(LogFormat::Json));
+    assert_eq!(LogFormat::parse(" Pretty "), Some(LogFormat::Pretty));
+    assert_eq!(LogFormat::parse("xml"), None);
+}
+
+#[test]
+fn test_build_subscriber_for_both_formats() {
+    for format in [LogFormat::Pretty, LogFormat::Json] {
+        let subscriber = build_subscriber(format, "debug");
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "req-1");
+            let _guard = span.enter();
+            tracing::info!(session_key = "agent:main:main", "handled");
+        });
+    }
+}
+
+#[test]
+fn test_build_subscriber_tolerates_bad_level() {
+    let subscriber = build_subscriber(LogFormat::Json, "not a level!!");
+    tracing::subscriber::with_default(subscriber, || tracing::warn!("still logs"));
+}
@@ -1,5 +1,5 @@
 use agent_ping::config::SessionConfig;
-use agent_ping::session::{build_session_key, resolve_identity_link};
+use agent_ping::session::{build_session_key, render_key_template, resolve_identity_link};
 use std::collections::HashMap;
 
 #[test]
@@ -9,6 +9,7 @@ fn test_dm_main_scope() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
     assert_eq!(key, "agent:myagent:default");
@@ -21,6 +22,7 @@ fn test_dm_per_peer_scope() {
         dm_scope: "per-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
     assert_eq!(key, "agent:myagent:dm:user123");
@@ -33,6 +35,7 @@ fn test_dm_per_channel_peer_scope() {
         dm_scope: "per-channel-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
     assert_eq!(key, "agent:myagent:slack:dm:user123");
@@ -45,6 +48,7 @@ fn test_dm_per_account_channel_peer_scope() {
         dm_scope: "per-account-channel-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", Some("account1"), "dm", "user123", None);
     assert_eq!(key, "agent:myagent:slack:account1:dm:user123");
@@ -57,6 +61,7 @@ fn test_channel_with_thread() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "channel", "C123", Some("thread1"));
     assert_eq!(key, "agent:myagent:slack:channel:c123:thread:thread1");
@@ -69,6 +74,7 @@ fn test_channel_without_thread() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "telegram", None, "channel", "C456", None);
     assert_eq!(key, "agent:myagent:telegram:channel:c456");
@@ -81,6 +87,7 @@ fn test_group_peer_kind() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "telegram", None, "group", "-123456", None);
     assert_eq!(key, "agent:myagent:telegram:group:-123456");
@@ -93,6 +100,7 @@ fn test_empty_thread_id_is_ignored() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "channel", "C123", Some(""));
     assert_eq!(key, "agent:myagent:slack:channel:c123");
@@ -105,6 +113,7 @@ fn test_case_normalization() {
         dm_scope: "Main".to_string(),
         main_key: "Default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "Slack", None, "dm", "User123", None);
     assert_eq!(key, "agent:myagent:default");
@@ -117,6 +126,7 @@ fn test_whitespace_trimming() {
         dm_scope: "  main  ".to_string(),
         main_key: "  default  ".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "  slack  ", None, "dm", "  user123  ", None);
     assert_eq!(key, "agent:myagent:default");
@@ -132,6 +142,7 @@ fn test_identity_links_direct_match() {
         dm_scope: "per-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: links,
+        ..SessionConfig::default()
     };
 
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
@@ -148,6 +159,7 @@ fn test_identity_links_scoped_match() {
         dm_scope: "per-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: links,
+        ..SessionConfig::default()
     };
 
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
@@ -167,6 +179,7 @@ fn test_identity_links_with_non_empty_dm_scope() {
         dm_scope: "per-channel-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: links,
+        ..SessionConfig::default()
     };
 
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
@@ -183,6 +196,7 @@ fn test_no_identity_link_match() {
         dm_scope: "per-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: links,
+        ..SessionConfig::default()
     };
 
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
@@ -206,6 +220,7 @@ fn test_multiple_identity_link_values() {
         dm_scope: "per-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: links,
+        ..SessionConfig::default()
     };
 
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
@@ -263,6 +278,7 @@ fn test_default_account_id_when_none() {
         dm_scope: "per-account-channel-peer".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
     assert_eq!(key, "agent:myagent:slack:default:dm:user123");
@@ -275,6 +291,7 @@ fn test_whatsapp_channel() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "whatsapp", None, "dm", "+1234567890", None);
     assert_eq!(key, "agent:myagent:default");
@@ -287,7 +304,212 @@ fn test_special_characters_in_peer_id() {
         dm_scope: "main".to_string(),
         main_key: "default".to_string(),
         identity_links: HashMap::new(),
+        ..SessionConfig::default()
     };
     let key = build_session_key(&cfg, None, "slack", None, "dm", "U123_abc-xyz", None);
     assert_eq!(key, "agent:myagent:default");
 }
+
+#[test]
+fn test_key_template_custom_layout() {
+    let cfg = SessionConfig {
+        agent_id: "MyAgent".to_string(),
+        dm_scope: "per-peer".to_string(),
+        main_key: "default".to_string(),
+        identity_links: HashMap::new(),
+        key_template: Some("{agent}/{channel}/{account}/{peer_kind}/{peer}".to_string()),
+        scope_by_kind: HashMap::new(),
+        case_sensitive: false,
+        preserve_canonical_case: false,
+        max_sessions: 0,
+        idle_ttl_days: 0,
+    };
+    let key = build_session_key(&cfg, None, "Slack", Some("T123"), "channel", "C456", None);
+    assert_eq!(key, "myagent/slack/t123/channel/c456");
+}
+
+#[test]
+fn test_key_template_thread_and_defaults() {
+    let cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        key_template: Some("{agent}|{account}|{peer}|{thread}".to_string()),
+        ..SessionConfig::default()
+    };
+    let key = build_session_key(&cfg, None, "slack", None, "channel", "C1", Some("TS9"));
+    assert_eq!(key, "myagent|default|c1|ts9");
+}
+
+#[test]
+fn test_render_key_template_does_not_expand_values() {
+    let key = render_key_template(
+        "{agent}:{peer}:{unknown}:{channel",
+        &[
+            ("agent", "a"),
+            ("peer", "{agent}{channel}"),
+            ("channel", "slack"),
+        ],
+    );
+    assert_eq!(key, "a:{agent}{channel}:{unknown}:{channel");
+
+    let cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        key_template: Some("{peer}|{thread}".to_string()),
+        ..SessionConfig::default()
+    };
+    let key = build_session_key(
+        &cfg,
+        None,
+        "slack",
+        None,
+        "channel",
+        "{thread}",
+        Some("TS9"),
+    );
+    assert_eq!(key, "{thread}|ts9");
+}
+
+#[test]
+fn test_key_template_unset_keeps_default_scheme() {
+    let cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        dm_scope: "per-channel-peer".to_string(),
+        main_key: "default".to_string(),
+        identity_links: HashMap::new(),
+        ..SessionConfig::default()
+    };
+    let key = build_session_key(&cfg, None, "slack", None, "dm", "user123", None);
+    assert_eq!(key, "agent:myagent:slack:dm:user123");
+
+    let blank = SessionConfig {
+        key_template: Some("   ".to_string()),
+        ..cfg
+    };
+    let key = build_session_key(&blank, None, "slack", None, "dm", "user123", None);
+    assert_eq!(key, "agent:myagent:slack:dm:user123");
+}
+
+#[test]
+fn test_identity_link_matches_normalized_whatsapp_number() {
+    let mut links = HashMap::new();
+    links.insert(
+        "acme-owner".to_string(),
+        vec!["whatsapp:447700900123".to_string()],
+    );
+    assert_eq!(
+        resolve_identity_link(&links, "whatsapp", "+447700900123"),
+        Some("acme-owner".to_string())
+    );
+}
+
+#[test]
+fn test_scope_by_kind_scopes_group_per_peer_and_keeps_dm_main() {
+    let cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        dm_scope: "main".to_string(),
+        main_key: "default".to_string(),
+        scope_by_kind: HashMap::from([
+            ("group".to_string(), "per-peer".to_string()),
+            ("channel".to_string(), "main".to_string()),
+        ]),
+        ..SessionConfig::default()
+    };
+    let dm = build_session_key(&cfg, None, "telegram", None, "dm", "42", None);
+    assert_eq!(dm, "agent:myagent:default");
+    let group = build_session_key(&cfg, None, "telegram", None, "group", "-1001", None);
+    assert_eq!(group, "agent:myagent:group:-1001");
+    let threaded = build_session_key(&cfg, None, "telegram", None, "group", "-1001", Some("7"));
+    assert_eq!(threaded, "agent:myagent:group:-1001:thread:7");
+    let channel = build_session_key(
+        &cfg,
+        None,
+        "slack",
+        Some("T1"),
+        "channel",
+        "C1",
+        Some("1.2"),
+    );
+    assert_eq!(channel, "agent:myagent:default");
+    let supergroup = build_session_key(&cfg, None, "telegram", None, "supergroup", "-1002", None);
+    assert_eq!(supergroup, "agent:myagent:telegram:supergroup:-1002");
+}
+
+#[test]
+fn test_scope_by_kind_dm_entry_overrides_dm_scope() {
+    let cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        dm_scope: "main".to_string(),
+        scope_by_kind: HashMap::from([
+            ("dm".to_string(), "per-account-channel-peer".to_string()),
+            ("group".to_string(), "per-channel-peer".to_string()),
+        ]),
+        ..SessionConfig::default()
+    };
+    let dm = build_session_key(&cfg, None, "slack", Some("T1"), "dm", "U1", None);
+    assert_eq!(dm, "agent:myagent:slack:t1:dm:u1");
+    let group = build_session_key(&cfg, None, "telegram", None, "group", "-1001", None);
+    assert_eq!(group, "agent:myagent:telegram:group:-1001");
+}
+
+#[test]
+fn test_case_sensitive_keeps_peer_case() {
+    let mut cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        dm_scope: "per-channel-peer".to_string(),
+        ..SessionConfig::default()
+    };
+    let upper = build_session_key(&cfg, None, "whatsapp", None, "dm", "AbC", None);
+    let lower = build_session_key(&cfg, None, "whatsapp", None, "dm", "abc", None);
+    assert_eq!(upper, lower);
+    assert_eq!(upper, "agent:myagent:whatsapp:dm:abc");
+
+    cfg.case_sensitive = true;
+    let upper = build_session_key(&cfg, None, "WhatsApp", None, "dm", "  AbC  ", None);
+    let lower = build_session_key(&cfg, None, "whatsapp", None, "dm", "abc", None);
+    assert_ne!(upper, lower);
+    assert_eq!(upper, "agent:myagent:whatsapp:dm:AbC");
+    let group = build_session_key(&cfg, None, "teams", None, "group", "19:Room", None);
+    assert_eq!(group, "agent:myagent:teams:group:19:Room");
+}
+
+#[test]
+fn test_preserve_canonical_case_keeps_canonical_id() {
+    let mut cfg = SessionConfig {
+        agent_id: "myagent".to_string(),
+        dm_scope: "per-peer".to_string(),
+        identity_links: HashMap::from([(
+            " Usr_9fA2-Bc ".to_string(),
+            vec!["Slack:U02ACME".to_string(), "telegram:123".to_string()],
+        )]),
+        ..SessionConfig::default()
+    };
+    let key = build_session_key(&cfg, None, "slack", None, "dm", "u02acme", None);
+    assert_eq!(key, "agent:myagent:dm:usr_9fa2-bc");
+
+    cfg.preserve_canonical_case = true;
+    let slack = build_session_key(&cfg, None, "slack", None, "dm", "u02acme", None);
+    assert_eq!(slack, "agent:myagent:dm:Usr_9fA2-Bc");
+    let telegram = build_session_key(&cfg, None, "Telegram", None, "dm", " 123 ", None);
+    assert_eq!(telegram, slack);
+    assert_eq!(
+        resolve_identity_link(&cfg.identity_links, "slack", "U02ACME"),
+        Some("usr_9fa2-bc".to_string())
+    );
+}
+
+#[test]
+fn test_unscoped_identity_link_matches_normalized_whatsapp_number() {
+    let mut links = HashMap::new();
+    links.insert(
+        "acme-owner".to_string(),
+        vec!["44 7700 900123".to_string(), "U123".to_string()],
+    );
+    assert_eq!(
+        resolve_identity_link(&links, "whatsapp", "+447700900123"),
+        Some("acme-owner".to_string())
+    );
+    assert_eq!(resolve_identity_link(&links, "whatsapp", "+123"), None);
+    assert_eq!(
+        resolve_identity_link(&links, "slack", "u123"),
+        Some("acme-owner".to_string())
+    );
+}
@@ -1,5 +1,11 @@
-use agent_ping::channels::slack::parse_slack_event;
+use agent_ping::channels::slack::{
+    apply_slack_reply_broadcast, apply_slack_sender, parse_slack_event, parse_slack_message_change,
+    parse_slack_reaction, slack_delete_payload, slack_error, slack_message_payload,
+    slack_reaction_payload, slack_update_payload, verify_slack_signature,
+};
+use agent_ping::channels::ChannelError;
 use serde_json::json;
+use std::time::Duration;
 
 #[test]
 fn test_parse_dm_event() {
@@ -59,6 +65,26 @@ fn test_ignore_bot_messages() {
     assert!(event.is_none());
 }
 
+#[test]
+fn test_ignore_own_bot_messages() {
+    let payload = |user: &str| {
+        json!({
+            "type": "event_callback",
+            "authorizations": [{"user_id": "UBOT", "is_bot": true}],
+            "event": {
+                "type": "message",
+                "channel": "C1234",
+                "user": user,
+                "bot_id": "B1",
+                "text": "Ana: hello",
+                "ts": "1234567890.123456"
+            }
+        })
+    };
+    assert!(parse_slack_event(&payload("UBOT")).is_none());
+    assert!(parse_slack_event(&payload("U12345")).is_some());
+}
+
 #[test]
 fn test_parse_message_with_files() {
     let payload = json!({
@@ -209,3 +235,294 @@ fn test_parse_non_message_event() {
     let event = parse_slack_event(&payload);
     assert!(event.is_none());
 }
+
+#[test]
+fn test_slack_update_payload() {
+    assert_eq!(
+        slack_update_payload("C123", "1700000000.000100", "edited"),
+        json!({"channel": "C123", "ts": "1700000000.000100", "text": "edited"})
+    );
+}
+
+#[test]
+fn test_slack_delete_payload() {
+    assert_eq!(
+        slack_delete_payload("C123", "1700000000.000100"),
+        json!({"channel": "C123", "ts": "1700000000.000100"})
+    );
+}
+
+#[test]
+fn test_parse_slack_reaction_added_and_removed() {
+    let mut payload = json!({
+        "type": "event_callback",
+        "team_id": "T1",
+        "event": {
+            "type": "reaction_added",
+            "user": "U12345",
+            "reaction": "thumbsup",
+            "item": {"type": "message", "channel": "C1234", "ts": "1234567890.123456"}
+        }
+    });
+    let reaction = parse_slack_reaction(&payload).unwrap();
+    assert_eq!(reaction.channel, "slack");
+    assert_eq!(reaction.account_id.as_deref(), Some("T1"));
+    assert_eq!(reaction.peer_id, "C1234");
+    assert_eq!(reaction.message_id, "1234567890.123456");
+    assert_eq!(reaction.user_id.as_deref(), Some("U12345"));
+    assert_eq!(reaction.reaction, "thumbsup");
+    assert!(reaction.added);
+
+    payload["event"]["type"] = json!("reaction_removed");
+    assert!(!parse_slack_reaction(&payload).unwrap().added);
+
+    payload["event"]["item"]["type"] = json!("file");
+    assert!(parse_slack_reaction(&payload).is_none());
+}
+
+#[test]
+fn test_parse_slack_message_changed_and_deleted() {
+    let changed = json!({
+        "type": "event_callback",
+        "team_id": "T1",
+        "event": {
+            "type": "message",
+            "subtype": "message_changed",
+            "channel": "C1234",
+            "ts": "1700000050.000200",
+            "message": {"type": "message", "user": "U1", "text": "fixed typo", "ts": "1700000000.000100"},
+            "previous_message": {"type": "message", "user": "U1", "text": "fixd typo", "ts": "1700000000.000100"}
+        }
+    });
+    assert!(parse_slack_event(&changed).is_none());
+    let edit = parse_slack_message_change(&changed).unwrap();
+    assert_eq!(edit.channel, "slack");
+    assert_eq!(edit.account_id.as_deref(), Some("T1"));
+    assert_eq!(edit.peer_id, "C1234");
+    assert_eq!(edit.message_id, "1700000000.000100");
+    assert_eq!(edit.text.as_deref(), Some("fixed typo"));
+    assert!(!edit.deleted);
+
+    let deleted = json!({
+        "type": "event_callback",
+        "event": {
+            "type": "message",
+            "subtype": "message_deleted",
+            "channel": "C1234",
+            "ts": "1700000060.000300",
+            "deleted_ts": "1700000000.000100"
+        }
+    });
+    let edit = parse_slack_message_change(&deleted).unwrap();
+    assert_eq!(edit.message_id, "1700000000.000100");
+    assert!(edit.text.is_none());
+    assert!(edit.deleted);
+
+    let joined = json!({
+        "type": "event_callback",
+        "event": {"type": "message", "subtype": "channel_join", "channel": "C1", "ts": "1.2"}
+    });
+    assert!(parse_slack_message_change(&joined).is_none());
+    let plain = json!({
+        "type": "event_callback",
+        "event": {"type": "message", "channel": "C1", "text": "hi", "ts": "1.2"}
+    });
+    assert!(parse_slack_message_change(&plain).is_none());
+}
+
+#[test]
+fn test_parse_slack_reaction_ignores_messages() {
+    let payload = json!({
+        "type": "event_callback",
+        "event": {"type": "message", "channel": "C1234", "text": "hi", "ts": "1.2"}
+    });
+    assert!(parse_slack_reaction(&payload).is_none());
+}
+
+#[test]
+fn test_slack_reaction_payload_strips_colons() {
+    assert_eq!(
+        slack_reaction_payload("C123", "1700000000.000100", ":eyes:"),
+        json!({"channel": "C123", "timestamp": "1700000000.000100", "name": "eyes"})
+    );
+}
+
+#[test]
+fn test_slack_message_payload_wraps_metadata() {
+    let payload = slack_message_payload("C1234", "hello", Some("111.222"), None);
+    assert_eq!(payload["thread_ts"], "111.222");
+    assert!(payload.get("metadata").is_none());
+
+    let metadata = json!({"ticket_id": "T-42"});
+    let payload = slack_message_payload("C1234", "hello", None, Some(&metadata));
+    assert_eq!(payload["metadata"]["event_type"], "agent_ping_message");
+    assert_eq!(payload["metadata"]["event_payload"], metadata);
+    assert!(payload.get("thread_ts").is_none());
+
+    let payload = slack_message_payload("C1234", "hello", None, Some(&json!("scalar")));
+    assert!(payload.get("metadata").is_none());
+}
+
+#[test]
+fn test_apply_slack_reply_broadcast_only_in_threads() {
+    let mut payload = slack_message_payload("C1234", "hello", Some("1700000000.000100"), None);
+    apply_slack_reply_broadcast(&mut payload, true);
+    assert_eq!(payload["reply_broadcast"], true);
+
+    let mut payload = slack_message_payload("C1234", "hello", Some("1700000000.000100"), None);
+    apply_slack_reply_broadcast(&mut payload, false);
+    assert!(payload.get("reply_broadcast").is_none());
+
+    let mut payload = slack_message_payload("C1234", "hello", None, None);
+    apply_slack_reply_broadcast(&mut payload, true);
+    assert!(payload.get("reply_broadcast").is_none());
+}
+
+#[test]
+fn test_apply_slack_sender() {
+    let mut payload = slack_message_payload("C1234", "hello", None, None);
+    apply_slack_sender(&mut payload, Some("Billing Bot"), Some("moneybag"));
+    assert_eq!(payload["username"], "Billing Bot");
+    assert_eq!(payload["icon_emoji"], ":moneybag:");
+
+    let mut payload = slack_message_payload("C1234", "hello", None, None);
+    apply_slack_sender(&mut payload, None, Some("https://cdn.example.com/bot.png"));
+    assert!(payload.get("username").is_none());
+    assert_eq!(payload["icon_url"], "https://cdn.example.com/bot.png");
+
+    let mut payload = slack_message_payload("C1234", "hello", None, None);
+    apply_slack_sender(&mut payload, Some("  "), Some(":robot_face:"));
+    assert!(payload.get("username").is_none());
+    assert_eq!(payload["icon_emoji"], ":robot_face:");
+}
+
+#[test]
+fn test_slack_error_classification() {
+    let error = |code: &str| {
+        slack_error(&json!({"ok": false, "error": code, "warning": "superfluous_charset"}))
+    };
+    assert_eq!(
+        error("invalid_auth"),
+        ChannelError::Auth {
+            channel: "slack".to_string(),
+            code: "invalid_auth".to_string()
+        }
+    );
+    assert_eq!(
+        error("ratelimited"),
+        ChannelError::RateLimited {
+            channel: "slack".to_string(),
+            retry_after: Duration::from_secs(1)
+        }
+    );
+    assert!(matches!(
+        error("channel_not_found"),
+        ChannelError::InvalidRecipient { .. }
+    ));
+    assert!(matches!(
+        error("not_in_channel"),
+        ChannelError::InvalidRecipient { .. }
+    ));
+    assert!(matches!(
+        error("internal_error"),
+        ChannelError::Transient { .. }
+    ));
+    assert!(matches!(
+        error("msg_too_long"),
+        ChannelError::Rejected { .. }
+    ));
+    assert!(error("service_unavailable").is_retryable());
+    assert!(!error("token_revoked").is_retryable());
+    assert_eq!(
+        error("channel_not_found").to_string(),
+        "slack recipient is invalid (channel_not_found)"
+    );
+    assert!(matches!(
+        slack_error(&json!({"ok": false})),
+        ChannelError::Rejected { code, .. } if code == "unknown_error"
+    ));
+}
+
+#[test]
+fn test_verify_slack_signature_known_triple() {
+    // Example request from Slack's "Verifying requests from Slack" guide.
+    let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+    let timestamp = "1531420618";
+    let body = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&\
+                channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner&\
+                command=%2Fwebhook-collect&text=&response_url=https%3A%2F%2Fhooks.slack.com%2F\
+                commands%2FT1DC2JH3J%2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN&\
+                trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+    let signature = "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+    let now = 1531420618 + 60;
+
+    assert!(verify_slack_signature(
+        secret,
+        timestamp,
+        body.as_bytes(),
+        signature,
+        now
+    ));
+    assert!(!verify_slack_signature(
+        "other-secret",
+        timestamp,
+        body.as_bytes(),
+        signature,
+        now
+    ));
+    assert!(!verify_slack_signature(
+        secret,
+        timestamp,
+        b"tampered",
+        signature,
+        now
+    ));
+    assert!(!verify_slack_signature(
+        secret,
+        "1531420619",
+        body.as_bytes(),
+        signature,
+        now
+    ));
+    assert!(!verify_slack_signature(
+        secret,
+        timestamp,
+        body.as_bytes(),
+        &signature[3..],
+        now
+    ));
+    assert!(!verify_slack_signature(
+        secret,
+        timestamp,
+        body.as_bytes(),
+        "v0=zz",
+        now
+    ));
+    assert!(!verify_slack_signature(
+        secret,
+        "soon",
+        body.as_bytes(),
+        signature,
+        now
+    ));
+
+    let stale = 1531420618 + 301;
+    assert!(!verify_slack_signature(
+        secret,
+        timestamp,
+        body.as_bytes(),
+        signature,
+        stale
+    ));
+
+    // Extreme timestamps must be rejected rather than overflow the age check.
+    for extreme in ["-9223372036854775808", "9223372036854775807"] {
+        assert!(!verify_slack_signature(
+            secret,
+            extreme,
+            body.as_bytes(),
+            signature,
+            now
+        ));
+    }
+}
@@ -1,4 +1,9 @@
-use agent_ping::channels::telegram::parse_telegram_update;
+use agent_ping::channels::telegram::{
+    parse_telegram_reaction, parse_telegram_update, telegram_delete_request, telegram_edit_request,
+    telegram_error, telegram_reaction_request, telegram_send_steps,
+};
+use agent_ping::channels::ChannelError;
+use agent_ping::types::Attachment;
 use serde_json::json;
 
 #[test]
@@ -58,6 +63,22 @@ fn test_parse_telegram_group_message() {
     let inbound = update.unwrap();
     assert_eq!(inbound.channel, "telegram");
     assert_eq!(inbound.peer_id, "-1001234567890");
+    assert_eq!(inbound.peer_kind, "supergroup");
+}
+
+#[test]
+fn test_parse_telegram_basic_group_message() {
+    let payload = json!({
+        "update_id": 123456790,
+        "message": {
+            "message_id": 2,
+            "from": {"id": 123456789_i64, "is_bot": false, "first_name": "Test"},
+            "chat": {"id": -4001234567_i64, "type": "group", "title": "Small Group"},
+            "date": 1609459200,
+            "text": "Hello from a basic group"
+        }
+    });
+    let inbound = parse_telegram_update(&payload).unwrap();
     assert_eq!(inbound.peer_kind, "group");
 }
 
@@ -300,3 +321,171 @@ fn test_parse_telegram_voice() {
     let inbound = update.unwrap();
     assert!(inbound.attachments.is_empty());
 }
+
+#[test]
+fn test_telegram_edit_request_uses_stored_message_id() {
+    let (url, payload) = telegram_edit_request("123:abc", "987654", "42", "updated").unwrap();
+    assert_eq!(url, "https://api.telegram.org/bot123:abc/editMessageText");
+    assert_eq!(
+        payload,
+        json!({"chat_id": "987654", "message_id": 42, "text": "updated"})
+    );
+}
+
+#[test]
+fn test_telegram_edit_request_rejects_non_numeric_id() {
+    assert!(telegram_edit_request("123:abc", "987654", "not-a-number", "updated").is_err());
+}
+
+#[test]
+fn test_telegram_delete_request_uses_stored_message_id() {
+    let (url, payload) = telegram_delete_request("123:abc", "987654", "42").unwrap();
+    assert_eq!(url, "https://api.telegram.org/bot123:abc/deleteMessage");
+    assert_eq!(payload, json!({"chat_id": "987654", "message_id": 42}));
+}
+
+#[test]
+fn test_parse_telegram_reaction_diffs_old_and_new() {
+    let update = json!({
+        "update_id": 10,
+        "message_reaction": {
+            "chat": {"id": 987654, "type": "private"},
+            "message_id": 42,
+            "user": {"id": 555},
+            "date": 1700000000,
+            "old_reaction": [{"type": "emoji", "emoji": "👍"}],
+            "new_reaction": [{"type": "emoji", "emoji": "🔥"}]
+        }
+    });
+    let reactions = parse_telegram_reaction(&update);
+    assert_eq!(reactions.len(), 2);
+    assert_eq!(reactions[0].reaction, "👍");
+    assert!(!reactions[0].added);
+    assert_eq!(reactions[1].reaction, "🔥");
+    assert!(reactions[1].added);
+    assert_eq!(reactions[1].peer_id, "987654");
+    assert_eq!(reactions[1].message_id, "42");
+    assert_eq!(reactions[1].user_id.as_deref(), Some("555"));
+}
+
+#[test]
+fn test_parse_telegram_reaction_ignores_messages() {
+    let update = json!({
+        "update_id": 11,
+        "message": {"message_id": 1, "chat": {"id": 1, "type": "private"}, "text": "hi"}
+    });
+    assert!(parse_telegram_reaction(&update).is_empty());
+}
+
+#[test]
+fn test_telegram_reaction_request() {
+    let (url, payload) = telegram_reaction_request("123:abc", "987654", "42", "👍").unwrap();
+    assert_eq!(
+        url,
+        "https://api.telegram.org/bot123:abc/setMessageReaction"
+    );
+    assert_eq!(
+        payload,
+        json!({
+            "chat_id": "987654",
+            "message_id": 42,
+            "reaction": [{"type": "emoji", "emoji": "👍"}]
+        })
+    );
+}
+
+fn photo_attachment() -> Attachment {
+    Attachment {
+        id: None,
+        url: "https://cdn.example.com/photo.jpg".to_string(),
+        mime_type: Some("image/jpeg".to_string()),
+        filename: Some("photo.jpg".to_string()),
+        size: None,
+    }
+}
+
+#[test]
+fn test_telegram_send_steps_caption_mode_single_media_message() {
+    let attachments = vec![photo_attachment(), photo_attachment()];
+    let steps = telegram_send_steps("42", Some("look at this"), Some("7"), &attachments, true);
+    assert_eq!(steps.len(), 2);
+    assert!(steps.iter().all(|step| step.method == "sendDocument"));
+    assert_eq!(
+        steps[0].payload,
+        json!({"chat_id": "42", "caption": "look at this", "reply_to_message_id": "7"})
+    );
+    assert!(steps[1].payload.get("caption").is_none());
+}
+
+#[test]
+fn test_telegram_send_steps_without_caption_mode_sends_text_separately() {
+    let attachments = vec![photo_attachment()];
+    let steps = telegram_send_steps("42", Some("look at this"), None, &attachments, false);
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0].method, "sendMessage");
+    assert_eq!(
+        steps[0].payload,
+        json!({"chat_id": "42", "text": "look at this"})
+    );
+    assert!(steps[1].payload.get("caption").is_none());
+}
+
+#[test]
+fn test_telegram_send_steps_caption_mode_falls_back_for_long_text() {
+    let attachments = vec![photo_attachment()];
+    let long = "x".repeat(1025);
+    let steps = telegram_send_steps("42", Some(&long), None, &attachments, true);
+    assert_eq!(steps[0].method, "sendMessage");
+    assert!(steps[1].payload.get("caption").is_none());
+
+    let steps = telegram_send_steps("42", Some("hi"), None, &[], true);
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].method, "sendMessage");
+}
+
+#[test]
+fn test_telegram_error_classification() {
+    let error = |code: i64, description: &str| {
+        telegram_error(&json!({"ok": false, "error_code": code, "description": description}))
+    };
+    assert!(matches!(
+        error(401, "Unauthorized"),
+        ChannelError::Auth { .. }
+    ));
+    assert!(matches!(error(404, "Not Found"), ChannelError::Auth { .. }));
+    assert!(matches!(
+        error(403, "Forbidden: bot was blocked by the user"),
+        ChannelError::InvalidRecipient { .. }
+    ));
+    assert!(matches!(
+        error(400, "Bad Request: chat not found"),
+        ChannelError::InvalidRecipient { .. }
+    ));
+    assert!(matches!(
+        error(400, "Bad Request: message text is empty"),
+        ChannelError::Rejected { .. }
+    ));
+    assert!(matches!(
+        error(502, "Bad Gateway"),
+        ChannelError::Transient { .. }
+    ));
+
+    let limited = telegram_error(&json!({
+        "ok": false,
+        "error_code": 429,
+        "description": "Too Many Requests: retry after 12",
+        "parameters": {"retry_after": 12}
+    }));
+    assert_eq!(
+        limited,
+        ChannelError::RateLimited {
+            channel: "telegram".to_string(),
+            retry_after: std::time::Duration::from_secs(12)
+        }
+    );
+    assert!(limited.is_retryable());
+    assert_eq!(
+        error(403, "Forbidden: bot was blocked by the user").to_string(),
+        "telegram recipient is invalid (Forbidden: bot was blocked by the user)"
+    );
+}
@@ -1,5 +1,6 @@
-use agent_ping::types::{Attachment, InboundMessage, OutboundMessage, RouteInfo};
-use serde_json;
+use agent_ping::types::{
+    parse_provider_timestamp, Attachment, InboundMessage, OutboundMessage, RouteInfo,
+};
 
 #[test]
 fn test_attachment_serde() {
@@ -86,6 +87,13 @@ fn test_outbound_message_serde() {
         account_id: Some("acc_123".to_string()),
         peer_id: Some("U456".to_string()),
         reply_to: Some("msg_789".to_string()),
+        caption_mode: false,
+        metadata: None,
+        peer_kind: None,
+        thread_id: None,
+        sender_name: None,
+        sender_icon: None,
+        reply_broadcast: false,
     };
 
     let json = serde_json::to_string(&msg).unwrap();
@@ -101,6 +109,7 @@ fn test_route_info_serde() {
         account_id: Some("acc_123".to_string()),
         peer_id: Some("U456".to_string()),
         thread_id: Some("thread_789".to_string()),
+        peer_kind: None,
     };
 
     let json = serde_json::to_string(&route).unwrap();
@@ -137,3 +146,31 @@ fn test_inbound_message_with_attachments() {
     assert_eq!(parsed.attachments.len(), 1);
     assert_eq!(parsed.attachments[0].url, att.url);
 }
+
+#[test]
+fn test_parse_provider_timestamp_formats() {
+    let expected = 1_700_000_000_000;
+    for value in [
+        "1700000000",
+        "1700000000.000",
+        " 1700000000 ",
+        "1700000000000",
+        "2023-11-14T22:13:20Z",
+        "2023-11-14T23:13:20+01:00",
+    ] {
+        assert_eq!(
+            parse_provider_timestamp(value).unwrap().timestamp_millis(),
+            expected,
+            "{value}"
+        );
+    }
+    assert_eq!(
+        parse_provider_timestamp("1700000000.250")
+            .unwrap()
+            .timestamp_millis(),
+        expected + 250
+    );
+    for value in ["", "soon", "-5", "0", "NaN"] {
+        assert!(parse_provider_timestamp(value).is_none(), "{value}");
+    }
+}
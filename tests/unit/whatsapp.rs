@@ -1,4 +1,11 @@
-use agent_ping::channels::whatsapp::{normalize_whatsapp_inbound, WhatsAppInboundPayload};
+use agent_ping::channels::whatsapp::{
+    messaging_window, normalize_phone_number, normalize_whatsapp_inbound, send_whatsapp_message,
+    whatsapp_error, whatsapp_send_payload, whatsapp_send_payloads, WhatsAppInboundPayload,
+    MESSAGING_WINDOW_HOURS,
+};
+use agent_ping::channels::{is_retryable, ChannelError};
+use agent_ping::config::SessionConfig;
+use agent_ping::session::build_session_key;
 use agent_ping::types::Attachment;
 
 #[test]
@@ -10,10 +17,11 @@ fn test_normalize_whatsapp_inbound_basic() {
         thread_id: None,
         attachments: None,
         sender_name: Some("Test User".to_string()),
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert_eq!(inbound.channel, "whatsapp");
-    assert_eq!(inbound.peer_id, "1234567890");
+    assert_eq!(inbound.peer_id, "+1234567890");
     assert_eq!(inbound.peer_kind, "dm");
     assert_eq!(inbound.text, Some("Hello WhatsApp".to_string()));
 }
@@ -33,10 +41,11 @@ fn test_normalize_whatsapp_with_attachments() {
             size: None,
         }]),
         sender_name: Some("Test User".to_string()),
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert_eq!(inbound.channel, "whatsapp");
-    assert_eq!(inbound.peer_id, "1234567890");
+    assert_eq!(inbound.peer_id, "+1234567890");
     assert_eq!(inbound.attachments.len(), 1);
     assert_eq!(inbound.attachments[0].id, Some("image123".to_string()));
 }
@@ -50,6 +59,7 @@ fn test_normalize_whatsapp_empty_text() {
         thread_id: None,
         attachments: None,
         sender_name: None,
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert_eq!(inbound.channel, "whatsapp");
@@ -65,6 +75,7 @@ fn test_normalize_whatsapp_with_thread() {
         thread_id: Some("msg123".to_string()),
         attachments: None,
         sender_name: None,
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert_eq!(inbound.channel, "whatsapp");
@@ -80,6 +91,7 @@ fn test_normalize_whatsapp_message_id_generation() {
         thread_id: None,
         attachments: None,
         sender_name: None,
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert!(!inbound.inbound_id.is_empty());
@@ -94,7 +106,267 @@ fn test_normalize_whatsapp_no_attachments() {
         thread_id: None,
         attachments: None,
         sender_name: None,
+        timestamp: None,
     };
     let inbound = normalize_whatsapp_inbound(payload);
     assert!(inbound.attachments.is_empty());
 }
+
+#[test]
+fn test_normalize_whatsapp_numeric_timestamp() {
+    let payload: WhatsAppInboundPayload = serde_json::from_value(serde_json::json!({
+        "peer_id": "447700900123",
+        "text": "hi",
+        "message_id": "wamid.1",
+        "timestamp": 1700000000
+    }))
+    .unwrap();
+    let inbound = normalize_whatsapp_inbound(payload);
+    assert_eq!(inbound.timestamp.as_deref(), Some("1700000000"));
+    assert_eq!(inbound.sent_at().unwrap().timestamp(), 1_700_000_000);
+}
+
+#[test]
+fn test_whatsapp_send_payload_caption_mode() {
+    let attachments = vec![Attachment {
+        id: None,
+        url: "https://cdn.example.com/photo.jpg".to_string(),
+        mime_type: Some("image/jpeg".to_string()),
+        filename: None,
+        size: None,
+    }];
+    let payload =
+        whatsapp_send_payload("447700900123", Some("caption me"), &attachments, true, None);
+    assert!(payload["text"].is_null());
+    assert_eq!(payload["attachments"][0]["caption"], "caption me");
+
+    let payload = whatsapp_send_payload(
+        "447700900123",
+        Some("caption me"),
+        &attachments,
+        false,
+        None,
+    );
+    assert_eq!(payload["text"], "caption me");
+    assert!(payload["attachments"][0].get("caption").is_none());
+}
+
+#[test]
+fn test_whatsapp_attachments_only_send_omits_text() {
+    let attachment = |name: &str| Attachment {
+        id: None,
+        url: format!("https://cdn.example.com/{name}"),
+        mime_type: Some("image/jpeg".to_string()),
+        filename: None,
+        size: None,
+    };
+    let attachments = vec![attachment("a.jpg"), attachment("b.jpg")];
+
+    let payload = whatsapp_send_payload("447700900123", None, &attachments, false, None);
+    assert!(payload.get("text").is_none());
+    assert_eq!(payload["attachments"].as_array().unwrap().len(), 2);
+
+    let payloads = whatsapp_send_payloads("447700900123", None, &attachments, false, None, true);
+    assert_eq!(payloads.len(), 2);
+    for (payload, name) in payloads.iter().zip(["a.jpg", "b.jpg"]) {
+        assert!(payload.get("text").is_none());
+        let media = payload["attachments"].as_array().unwrap();
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0]["url"], format!("https://cdn.example.com/{name}"));
+    }
+
+    let payloads = whatsapp_send_payloads(
+        "447700900123",
+        Some("see these"),
+        &attachments,
+        false,
+        None,
+        true,
+    );
+    assert_eq!(payloads.len(), 3);
+    assert_eq!(payloads[0]["text"], "see these");
+    assert!(payloads[0]["attachments"].as_array().unwrap().is_empty());
+
+    let payloads = whatsapp_send_payloads(
+        "447700900123",
+        Some("see these"),
+        &attachments,
+        true,
+        None,
+        true,
+    );
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0]["attachments"][0]["caption"], "see these");
+    assert!(payloads[1]["attachments"][0].get("caption").is_none());
+    assert!(payloads.iter().all(|payload| payload.get("text").is_none()));
+
+    let payloads = whatsapp_send_payloads("447700900123", None, &attachments, false, None, false);
+    assert_eq!(payloads.len(), 1);
+}
+
+#[test]
+fn test_whatsapp_send_payload_forwards_metadata() {
+    let payload = whatsapp_send_payload("447700900123", Some("hi"), &[], false, None);
+    assert!(payload.get("metadata").is_none());
+
+    let metadata = serde_json::json!({"ticket_id": "T-42"});
+    let payload = whatsapp_send_payload("447700900123", Some("hi"), &[], false, Some(&metadata));
+    assert_eq!(payload["metadata"], metadata);
+}
+
+#[test]
+fn test_normalize_phone_number_e164() {
+    assert_eq!(normalize_phone_number("+1 234 567 890"), "+1234567890");
+    assert_eq!(normalize_phone_number("1234567890"), "+1234567890");
+    assert_eq!(normalize_phone_number(" (123) 456-7890 "), "+1234567890");
+    assert_eq!(
+        normalize_phone_number("120363041234567890@g.us"),
+        "120363041234567890@g.us"
+    );
+    assert_eq!(normalize_phone_number("status"), "status");
+}
+
+#[test]
+fn test_differently_formatted_numbers_share_session_key() {
+    let cfg = SessionConfig {
+        dm_scope: "per-channel-peer".to_string(),
+        ..SessionConfig::default()
+    };
+    let keys: Vec<String> = ["+1 234 567 890", "1234567890", "+1-234-567-890"]
+        .into_iter()
+        .map(|peer_id| {
+            let inbound = normalize_whatsapp_inbound(WhatsAppInboundPayload {
+                peer_id: peer_id.to_string(),
+                text: Some("hi".to_string()),
+                message_id: None,
+                thread_id: None,
+                attachments: None,
+                sender_name: None,
+                timestamp: None,
+            });
+            build_session_key(
+                &cfg,
+                None,
+                &inbound.channel,
+                None,
+                &inbound.peer_kind,
+                &inbound.peer_id,
+                None,
+            )
+        })
+        .collect();
+    assert_eq!(keys[0], "agent:main:whatsapp:dm:+1234567890");
+    assert!(keys.iter().all(|key| key == &keys[0]), "{keys:?}");
+}
+
+#[test]
+fn test_whatsapp_error_classification() {
+    let body = serde_json::json!({"error": "not_on_whatsapp"});
+    assert_eq!(
+        whatsapp_error(404, Some(&body)),
+        ChannelError::InvalidRecipient {
+            channel: "whatsapp".to_string(),
+            code: "not_on_whatsapp".to_string()
+        }
+    );
+    assert!(matches!(
+        whatsapp_error(401, None),
+        ChannelError::Auth { .. }
+    ));
+    assert!(matches!(
+        whatsapp_error(400, None),
+        ChannelError::Rejected { .. }
+    ));
+    assert!(matches!(
+        whatsapp_error(503, None),
+        ChannelError::Transient { code, .. } if code == "http 503"
+    ));
+}
+
+#[test]
+fn test_messaging_window_open_and_closed() {
+    let now = chrono::Utc::now();
+    let recent = now - chrono::Duration::hours(23);
+    let (open, expires_at) = messaging_window(Some(recent), now);
+    assert!(open);
+    assert_eq!(
+        expires_at,
+        Some(recent + chrono::Duration::hours(MESSAGING_WINDOW_HOURS))
+    );
+
+    let stale = now - chrono::Duration::hours(25);
+    let (open, expires_at) = messaging_window(Some(stale), now);
+    assert!(!open);
+    assert!(expires_at.unwrap() < now);
+
+    assert_eq!(messaging_window(None, now), (false, None));
+}
+
+#[tokio::test]
+async fn test_unreachable_sidecar_is_transient() {
+    let err = send_whatsapp_message(
+        &reqwest::Client::new(),
+        "http://127.0.0.1:1",
+        "+447700900123",
+        Some("hello"),
+        &[],
+        false,
+        None,
+        None,
+        false,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChannelError>(),
+        Some(ChannelError::Transient { .. })
+    ));
+    assert!(is_retryable(&err));
+}
+
+#[tokio::test]
+async fn test_split_send_failing_after_first_part_is_not_retryable() {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .and(body_partial_json(serde_json::json!({"text": "hello"})))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"message_id": "w1"})),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/send"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    let attachment = |name: &str| Attachment {
+        id: None,
+        url: format!("https://cdn.example.com/{name}"),
+        mime_type: Some("image/jpeg".to_string()),
+        filename: None,
+        size: None,
+    };
+    let attachments = vec![attachment("a.jpg"), attachment("b.jpg")];
+    let err = send_whatsapp_message(
+        &reqwest::Client::new(),
+        &server.uri(),
+        "+447700900123",
+        Some("hello"),
+        &attachments,
+        false,
+        None,
+        None,
+        true,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ChannelError>(),
+        Some(ChannelError::Rejected { code, .. }) if code.starts_with("partially_delivered 1/3")
+    ));
+    assert!(!is_retryable(&err));
+}